@@ -5,8 +5,6 @@ extern crate reqwest;
 extern crate serde;
 extern crate tokio;
 
-use serde::Deserialize;
-
 use colored::*;
 
 #[allow(unused_imports)]
@@ -16,6 +14,10 @@ mod banner;
 use crate::banner::print_banner;
 
 mod models;
+use crate::models::responses::{
+    ActiveStation, ErrorBody, HistoryResponse, QueueChange, QueueResponse, SongScore, Station, StationInfo, TagInfo,
+    TagsChange, UpcomingResponse, UpcomingSong,
+};
 use crate::models::tags_data::parse_tags_data_from_argv;
 use crate::models::tags_data::TagsData;
 
@@ -40,13 +42,19 @@ enum Commands {
     /// Untag the currently playing song
     Untag(UntagArgs),
     /// Skip the currently playing song
-    Skip,
+    Skip(SkipArgs),
+    /// Rate the currently playing song (or --file) from 1 to 5 stars
+    Rate(RateArgs),
     /// List all available jukebox tags
     Tags,
     /// Adjust the jukebox NowPlaying tags
     Playback(PlaybackArgs),
     /// Query the jukebox queue directly
     Queue(QueueArgs),
+    /// Show recently played songs
+    History(HistoryArgs),
+    /// Manage and play named stations
+    Station(StationArgs),
 }
 
 #[derive(Parser)]
@@ -64,12 +72,78 @@ struct UntagArgs {
     tag_name: String,
 }
 
+#[derive(Parser)]
+struct SkipArgs {
+    #[clap(long, help = "Why the song was skipped: dislike, wrong-mood or bad-rip")]
+    reason: Option<String>,
+}
+
+#[derive(Parser)]
+struct RateArgs {
+    #[clap(help = "1 to 5 stars, or `clear`")]
+    rating: String,
+    #[clap(long, help = "Rate this file instead of the playing song")]
+    file: Option<String>,
+}
+
+#[derive(Parser)]
+struct HistoryArgs {
+    #[clap(long, default_value_t = 20, help = "How many songs to show")]
+    limit: usize,
+    #[clap(long, help = "Only songs started at or after this unix timestamp")]
+    since: Option<u64>,
+}
+
 #[derive(Parser)]
 struct PlaybackArgs {
-    #[clap(help = "Tags for playback", required = true)]
-    tags: String,
+    #[clap(help = "Tags for playback", required_unless_present = "expr")]
+    tags: Option<String>,
+    #[clap(help = "Tags to exclude from playback")]
+    not_tags: Option<String>,
+    #[clap(long, help = "Tag expression, e.g. '(chill and instrumental) or ambient and not explicit'")]
+    expr: Option<String>,
+    #[clap(long, help = "Also replace the songs MPD already has lined up after the current one")]
+    flush: bool,
+}
+
+#[derive(Debug, Args)]
+struct StationArgs {
+    #[command(subcommand)]
+    command: StationSubcommand,
+}
+
+#[derive(Subcommand, Debug)]
+enum StationSubcommand {
+    /// List saved stations
+    List,
+    /// Switch playback to a station
+    Play(StationNameArgs),
+    /// Save a station from tags, or from the current playback tags if none are given
+    Save(StationSaveArgs),
+    /// Delete a station
+    Delete(StationNameArgs),
+}
+
+#[derive(Parser, Debug)]
+struct StationNameArgs {
+    #[clap(help = "Name of the station", required = true)]
+    name: String,
+}
+
+#[derive(Parser, Debug)]
+struct StationSaveArgs {
+    #[clap(help = "Name of the station", required = true)]
+    name: String,
+    #[clap(help = "Tags for playback")]
+    tags: Option<String>,
     #[clap(help = "Tags to exclude from playback")]
     not_tags: Option<String>,
+    #[clap(long, help = "Tag expression songs must also match")]
+    expr: Option<String>,
+    #[clap(long, help = "Play whole albums on this station")]
+    album_aware: bool,
+    #[clap(long, help = "Volume (0-100) to switch to")]
+    volume: Option<u32>,
 }
 
 #[derive(Debug, Args)]
@@ -84,6 +158,47 @@ enum QueueSubcommand {
     Head(QueueHeadArgs),
     /// Peek at COUNT from the end of the queue
     Tail(QueueHeadArgs),
+    /// Show what the jukebox will play next, by album when album-aware
+    Upcoming(QueueUpcomingArgs),
+    /// Play a file, album or tag next
+    Next(QueueAddArgs),
+    /// Add a file, album or tag to the end of the queue
+    Add(QueueAddArgs),
+    /// Drop the entry at INDEX (as numbered by `queue upcoming`, from 0)
+    Remove(QueueRemoveArgs),
+    /// Move the entry at FROM to TO
+    Move(QueueMoveArgs),
+    /// Shuffle the queue again
+    Reshuffle,
+}
+
+#[derive(Parser, Debug)]
+struct QueueAddArgs {
+    #[clap(help = "File to queue, as MPD names it", required_unless_present_any = ["album", "tag"])]
+    file: Option<String>,
+    #[clap(long, conflicts_with_all = ["file", "tag"], help = "Queue a whole album")]
+    album: Option<String>,
+    #[clap(long, requires = "album", help = "Only the album by this artist")]
+    artist: Option<String>,
+    #[clap(long, conflicts_with = "file", help = "Queue every song with this tag, shuffled")]
+    tag: Option<String>,
+}
+
+#[derive(Parser, Debug)]
+struct QueueRemoveArgs {
+    index: usize,
+}
+
+#[derive(Parser, Debug)]
+struct QueueMoveArgs {
+    from: usize,
+    to: usize,
+}
+
+#[derive(Parser, Debug)]
+struct QueueUpcomingArgs {
+    #[clap(default_value_t = 10, help = "How many songs (or albums) to show")]
+    count: usize,
 }
 
 #[derive(Parser, Debug)]
@@ -138,9 +253,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             debug!("Untag an item with name: {:?}", args.tag_name);
             untag(&api_hostname, args.tag_name.to_string()).await?;
         }
-        Commands::Skip => {
+        Commands::Skip(args) => {
             // Handle skip subcommand
-            match skip_item(&api_hostname).await {
+            match skip_item(&api_hostname, args.reason.as_deref()).await {
                 Ok(_) => debug!("Skipped item"),
                 Err(err) => eprintln!("[!] Error: {}", err),
             }
@@ -158,13 +273,44 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 None => "".to_string(), // Or use your preferred default value
             };
 
-            let tags_data = parse_tags_data_from_argv(&args.tags, &not_tags);
-            match playback(&api_hostname, &tags_data).await {
+            let tags = args.tags.unwrap_or_default();
+            let tags_data = parse_tags_data_from_argv(&tags, &not_tags, args.expr);
+            match playback(&api_hostname, &tags_data, args.flush).await {
                 Ok(_) => debug!("Playback started with tags: {:?}", tags_data),
                 Err(err) => eprintln!("[!] Error: {}", err),
             }
         }
 
+        Commands::Rate(args) => {
+            let rating = match args.rating.as_str() {
+                "clear" => Ok(None),
+                stars => stars.parse::<u8>().map(Some),
+            };
+            match rating {
+                Ok(rating) => match rate_song(&api_hostname, rating, args.file).await {
+                    Ok(_) => debug!("Rated song"),
+                    Err(err) => eprintln!("[!] Error: {}", err),
+                },
+                Err(_) => eprintln!("[!] Error: rating must be 1 to 5 or `clear`, not `{}`", args.rating),
+            }
+        }
+        Commands::History(args) => {
+            match history(&api_hostname, args.limit, args.since).await {
+                Ok(_) => debug!("Listed history"),
+                Err(err) => eprintln!("[!] Error: {}", err),
+            }
+        }
+        Commands::Station(args) => {
+            let result = match args.command {
+                StationSubcommand::List => list_stations(&api_hostname).await,
+                StationSubcommand::Play(args) => play_station(&api_hostname, &args.name).await,
+                StationSubcommand::Save(args) => save_station(&api_hostname, args).await,
+                StationSubcommand::Delete(args) => delete_station(&api_hostname, &args.name).await,
+            };
+            if let Err(err) = result {
+                eprintln!("[!] Error: {}", err);
+            }
+        }
         Commands::Queue(args) => match args.command {
             QueueSubcommand::Head(args) => {
                 print_banner();
@@ -204,12 +350,33 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     println!("  {}", song.to_string().color(color));
                 }
             }
+            QueueSubcommand::Upcoming(args) => {
+                if let Err(err) = upcoming(&api_hostname, args.count).await {
+                    eprintln!("[!] Error: {}", err);
+                }
+            }
+            command => {
+                if let Err(err) = edit_queue(&api_hostname, command).await {
+                    eprintln!("[!] Error: {}", err);
+                }
+            }
         },
     }
 
     Ok(())
 }
 
+// The server explains failures in an `{error, detail}` body; fall back to
+// the status line for anything else (a proxy's error page, say).
+async fn server_error(response: reqwest::Response) -> String {
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+    match serde_json::from_str::<ErrorBody>(&body) {
+        Ok(error) => format!("{} (HTTP {})", error.detail, status.as_u16()),
+        Err(_) => format!("HTTP {}", status),
+    }
+}
+
 async fn queue_by_count(api_hostname: &str, count: usize) -> Result<QueueResponse, reqwest::Error> {
     // Fetch queue status with a count
     let queue_data = get_queue(api_hostname, Some(count))
@@ -219,13 +386,6 @@ async fn queue_by_count(api_hostname: &str, count: usize) -> Result<QueueRespons
     Ok(queue_data)
 }
 
-#[derive(Debug, Deserialize, Default)]
-struct QueueResponse {
-    length: usize,
-    head: Vec<String>,
-    tail: Vec<String>,
-}
-
 async fn get_queue(api_hostname: &str, count: Option<usize>) -> Option<QueueResponse> {
     let client = reqwest::Client::new();
 
@@ -253,10 +413,7 @@ async fn get_queue(api_hostname: &str, count: Option<usize>) -> Option<QueueResp
             }
         }
     } else {
-        eprintln!(
-            "Error: Failed to fetch queue status (HTTP {})",
-            response.status()
-        );
+        eprintln!("Error: Failed to fetch queue status: {}", server_error(response).await);
         Some(QueueResponse {
             length: 0,
             head: Vec::new(),
@@ -285,8 +442,8 @@ async fn status(api_hostname: &str) -> Result<(), reqwest::Error> {
 
     let client = reqwest::Client::new();
 
-    // Make the first GET request to /tags
-    let url_tags = format!("{}/tags", api_hostname);
+    // Make the first GET request to /tags/active
+    let url_tags = format!("{}/tags/active", api_hostname);
     let response_tags = client.get(&url_tags).send().await?;
 
     if response_tags.status().is_success() {
@@ -302,16 +459,16 @@ async fn status(api_hostname: &str) -> Result<(), reqwest::Error> {
                 println!("{}", "current playback tags:".cyan().bold());
                 println!("    {}: {:?}", "any".green().bold(), tags_data.any);
                 println!("    {}: {:?}", "not".red().bold(), tags_data.not);
+                if let Some(expr) = &tags_data.expr {
+                    println!("    {}: {}", "expr".yellow().bold(), expr);
+                }
             }
             Err(e) => {
                 eprintln!("Error: Failed to deserialize response: {}", e);
             }
         }
     } else {
-        eprintln!(
-            "Error: Failed to fetch status (HTTP {})",
-            response_tags.status()
-        );
+        eprintln!("Error: Failed to fetch status: {}", server_error(response_tags).await);
         return Ok(());
     }
 
@@ -347,19 +504,115 @@ async fn status(api_hostname: &str) -> Result<(), reqwest::Error> {
             }
         }
     } else {
-        eprintln!(
-            "Error: Failed to fetch root (HTTP {})",
-            response_root.status()
-        );
+        eprintln!("Error: Failed to fetch root: {}", server_error(response_root).await);
     }
 
     Ok(())
 }
 
-#[derive(Debug, Deserialize)]
-struct TagInfo {
-    name: String,
-    track_count: usize,
+impl UpcomingSong {
+    fn name(&self) -> String {
+        match (&self.artist, &self.title) {
+            (Some(artist), Some(title)) => format!("{} - {}", artist, title),
+            (None, Some(title)) => title.clone(),
+            _ => self.file.clone(),
+        }
+    }
+}
+
+async fn upcoming(api_hostname: &str, count: usize) -> Result<(), reqwest::Error> {
+    let client = reqwest::Client::new();
+    let url = format!("{}/queue/upcoming?count={}", api_hostname, count);
+
+    let response = client.get(&url).send().await?;
+    if !response.status().is_success() {
+        eprintln!("[!] Error: Failed to fetch upcoming songs: {}", server_error(response).await);
+        return Ok(());
+    }
+    let upcoming: UpcomingResponse = response.json().await?;
+
+    print_banner();
+    let unit = if upcoming.album_aware { "albums" } else { "songs" };
+    println!(
+        "{}{}",
+        format!("{} queued: ", unit).green(),
+        upcoming.length.to_string().green().bold()
+    );
+
+    for (index, song) in upcoming.songs.iter().enumerate() {
+        let color = if index % 2 == 0 { "cyan" } else { "magenta" };
+        println!("  {}", song.name().color(color));
+    }
+    for (index, album) in upcoming.albums.iter().enumerate() {
+        let color = if index % 2 == 0 { "cyan" } else { "magenta" };
+        let title = album.album.as_deref().unwrap_or("(no album)");
+        match &album.artist {
+            Some(artist) => println!("  {} - {}", artist.color(color).bold(), title.color(color).bold()),
+            None => println!("  {}", title.color(color).bold()),
+        }
+        for song in &album.songs {
+            println!("      {}", song.name().color(color));
+        }
+    }
+
+    Ok(())
+}
+
+async fn edit_queue(api_hostname: &str, command: QueueSubcommand) -> Result<(), reqwest::Error> {
+    let client = reqwest::Client::new();
+    let (request, done) = match command {
+        QueueSubcommand::Next(args) => (
+            client.post(format!("{}/queue/next", api_hostname)).json(&queue_request(args)),
+            "[+] playing next:",
+        ),
+        QueueSubcommand::Add(args) => (
+            client.post(format!("{}/queue/enqueue", api_hostname)).json(&queue_request(args)),
+            "[+] queued:",
+        ),
+        QueueSubcommand::Remove(args) => (
+            client.delete(format!("{}/queue/{}", api_hostname, args.index)),
+            "[+] removed:",
+        ),
+        QueueSubcommand::Move(args) => (
+            client
+                .post(format!("{}/queue/move", api_hostname))
+                .json(&serde_json::json!({ "from": args.from, "to": args.to })),
+            "[+] moved:",
+        ),
+        QueueSubcommand::Reshuffle => (
+            client
+                .post(format!("{}/queue/reshuffle", api_hostname))
+                .header(reqwest::header::CONTENT_LENGTH, "0"),
+            "[+] reshuffled the queue",
+        ),
+        QueueSubcommand::Head(_) | QueueSubcommand::Tail(_) | QueueSubcommand::Upcoming(_) => {
+            unreachable!("not a queue edit")
+        }
+    };
+
+    let response = request.send().await?;
+    if !response.status().is_success() {
+        eprintln!("[!] Error: Failed to change the queue: {}", server_error(response).await);
+        return Ok(());
+    }
+    let change: QueueChange = response.json().await?;
+
+    println!("{}", done.green());
+    for song in &change.songs {
+        println!("    {}", song.name().yellow().bold());
+    }
+    println!("{}{}", "queue length: ".cyan(), change.length.to_string().cyan().bold());
+
+    Ok(())
+}
+
+fn queue_request(args: QueueAddArgs) -> serde_json::Value {
+    serde_json::json!({
+        "file": args.file,
+        "album": args.album,
+        "artist": args.artist,
+        "tag": args.tag,
+    })
 }
 
 async fn list_available_tags(api_hostname: &str) -> Result<(), reqwest::Error> {
@@ -382,7 +635,7 @@ async fn list_available_tags(api_hostname: &str) -> Result<(), reqwest::Error> {
             println!("  {}", "no tags found.".red());
         } else {
             // Sort tags by track_count descending
-            tags.sort_by(|a, b| b.track_count.cmp(&a.track_count));
+            tags.sort_by_key(|t| std::cmp::Reverse(t.track_count));
 
             for (index, tag) in tags.iter().enumerate() {
                 let color = if index % 2 == 0 { "green" } else { "yellow" };
@@ -394,25 +647,75 @@ async fn list_available_tags(api_hostname: &str) -> Result<(), reqwest::Error> {
             }
         }
     } else {
-        eprintln!(
-            "Error: Failed to fetch available tags (HTTP {})",
-            response.status()
-        );
+        eprintln!("Error: Failed to fetch available tags: {}", server_error(response).await);
     }
 
     Ok(())
 }
 
-async fn skip_item(api_hostname: &str) -> Result<(), reqwest::Error> {
+// song files have slashes, so they go in the URL as one encoded segment
+fn song_url(api_hostname: &str, file: &str, action: &str) -> Option<reqwest::Url> {
+    let mut url = reqwest::Url::parse(api_hostname).ok()?;
+    url.path_segments_mut().ok()?.pop_if_empty().extend(["song", file, action]);
+    Some(url)
+}
+
+async fn rate_song(api_hostname: &str, rating: Option<u8>, file: Option<String>) -> Result<(), reqwest::Error> {
     let client = reqwest::Client::new();
-    let url = format!("{}/skip", api_hostname);
 
+    let file = match file {
+        Some(file) => file,
+        None => {
+            let now_playing: Option<serde_json::Value> =
+                client.get(format!("{}/song/now", api_hostname)).send().await?.json().await?;
+            match now_playing.and_then(|song| song["file"].as_str().map(str::to_string)) {
+                Some(file) => file,
+                None => {
+                    eprintln!("[!] Error: nothing is playing");
+                    return Ok(());
+                }
+            }
+        }
+    };
+
+    let Some(url) = song_url(api_hostname, &file, "rating") else {
+        eprintln!("[!] Error: can't build a URL from {}", api_hostname);
+        return Ok(());
+    };
     let response = client
-        .post(&url)
-        .header(reqwest::header::CONTENT_LENGTH, "0")
+        .put(url)
+        .json(&serde_json::json!({ "rating": rating }))
         .send()
         .await?;
 
+    if response.status().is_success() {
+        let score: SongScore = response.json().await?;
+        let stars = score.rating.map_or("unrated".to_string(), |r| "*".repeat(r.into()));
+        println!("{} {}", "[+] rated".green(), score.file.green().bold());
+        println!(
+            "    {}  weight {:.2}  ({} plays, {} skips)",
+            stars.yellow().bold(),
+            score.weight,
+            score.plays,
+            score.skips
+        );
+    } else {
+        eprintln!("[!] Error: Failed to rate {}: {}", file, server_error(response).await);
+    }
+
+    Ok(())
+}
+
+async fn skip_item(api_hostname: &str, reason: Option<&str>) -> Result<(), reqwest::Error> {
+    let client = reqwest::Client::new();
+    let url = format!("{}/skip", api_hostname);
+
+    let request = match reason {
+        Some(reason) => client.post(&url).json(&serde_json::json!({ "reason": reason })),
+        None => client.post(&url).header(reqwest::header::CONTENT_LENGTH, "0"),
+    };
+    let response = request.send().await?;
+
     if response.status().is_success() {
         let body = response.text().await?;
         debug!("[+] Item skipped successfully.");
@@ -438,20 +741,219 @@ async fn skip_item(api_hostname: &str) -> Result<(), reqwest::Error> {
             }
         }
     } else {
-        eprintln!(
-            "[!] Error: Failed to skip item (HTTP {})",
-            response.status()
+        eprintln!("[!] Error: Failed to skip item: {}", server_error(response).await);
+    }
+
+    Ok(())
+}
+
+async fn history(api_hostname: &str, limit: usize, since: Option<u64>) -> Result<(), reqwest::Error> {
+    let client = reqwest::Client::new();
+    let mut url = format!("{}/history?limit={}", api_hostname, limit);
+    if let Some(since) = since {
+        url.push_str(&format!("&since={}", since));
+    }
+
+    let response = client.get(&url).send().await?;
+    if !response.status().is_success() {
+        eprintln!("[!] Error: Failed to fetch history: {}", server_error(response).await);
+        return Ok(());
+    }
+
+    let body = response.text().await?;
+    debug!("[?] raw history response body: {}", body);
+    let history = match serde_json::from_str::<HistoryResponse>(&body) {
+        Ok(history) => history,
+        Err(e) => {
+            eprintln!("Error: Failed to parse JSON response: {}", e);
+            return Ok(());
+        }
+    };
+
+    print_banner();
+    println!(
+        "{}{}",
+        "songs in history: ".green(),
+        history.total.to_string().green().bold()
+    );
+
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    for (index, entry) in history.entries.iter().enumerate() {
+        let color = if index % 2 == 0 { "cyan" } else { "magenta" };
+        let name = match (&entry.artist, &entry.title) {
+            (Some(artist), Some(title)) => format!("{} - {}", artist, title),
+            (None, Some(title)) => title.clone(),
+            _ => entry.file.clone(),
+        };
+        let played = format!("{}:{:02}", entry.played_secs / 60, entry.played_secs % 60);
+
+        print!(
+            "  {:>9}  {} ({})",
+            format_ago(now.saturating_sub(entry.started_at)),
+            name.color(color),
+            played
+        );
+        if entry.skipped {
+            print!(" {}", "skipped".red());
+        }
+        println!();
+    }
+
+    Ok(())
+}
+
+fn format_ago(secs: u64) -> String {
+    match secs {
+        0..=59 => format!("{}s ago", secs),
+        60..=3599 => format!("{}m ago", secs / 60),
+        3600..=86399 => format!("{}h ago", secs / 3600),
+        _ => format!("{}d ago", secs / 86400),
+    }
+}
+
+async fn list_stations(api_hostname: &str) -> Result<(), reqwest::Error> {
+    let client = reqwest::Client::new();
+    let response = client.get(format!("{}/stations", api_hostname)).send().await?;
+    if !response.status().is_success() {
+        eprintln!("[!] Error: Failed to fetch stations: {}", server_error(response).await);
+        return Ok(());
+    }
+    let stations: Vec<StationInfo> = response.json().await?;
+
+    let active = client
+        .get(format!("{}/stations/active", api_hostname))
+        .send()
+        .await?
+        .json::<ActiveStation>()
+        .await
+        .ok()
+        .and_then(|active| active.name);
+
+    print_banner();
+    println!("{}", "stations:".cyan().bold());
+    if stations.is_empty() {
+        println!("  {}", "no stations saved.".red());
+    }
+    for info in &stations {
+        let marker = if active.as_deref() == Some(info.name.as_str()) { "▶" } else { " " };
+        print!(
+            "{} {: <20} {}: {:?}",
+            marker.green().bold(),
+            info.name.yellow().bold(),
+            "any".green(),
+            info.station.any
         );
+        if !info.station.not.is_empty() {
+            print!(" {}: {:?}", "not".red(), info.station.not);
+        }
+        if let Some(expr) = &info.station.expr {
+            print!(" {}: {}", "expr".yellow(), expr);
+        }
+        if info.station.album_aware {
+            print!(" {}", "albums".blue());
+        }
+        if let Some(volume) = info.station.volume {
+            print!(" {}", format!("vol {}", volume).white());
+        }
+        println!();
     }
 
     Ok(())
 }
 
-async fn playback(api_hostname: &str, tags_data: &TagsData) -> Result<(), reqwest::Error> {
+async fn play_station(api_hostname: &str, name: &str) -> Result<(), reqwest::Error> {
+    let client = reqwest::Client::new();
+    let url = format!("{}/stations/{}/activate", api_hostname, name);
+
+    let response = client
+        .post(&url)
+        .header(reqwest::header::CONTENT_LENGTH, "0")
+        .send()
+        .await?;
+
+    if response.status().is_success() {
+        println!("{} {}", "[+] now playing station:".green(), name.green().bold());
+    } else {
+        eprintln!("[!] Error: Failed to play station: {}", server_error(response).await);
+    }
+
+    Ok(())
+}
+
+async fn save_station(api_hostname: &str, args: StationSaveArgs) -> Result<(), reqwest::Error> {
+    let client = reqwest::Client::new();
+
+    let station = match args.tags {
+        Some(tags) => {
+            let tags_data = parse_tags_data_from_argv(&tags, args.not_tags.as_deref().unwrap_or(""), args.expr);
+            Station {
+                any: tags_data.any,
+                not: tags_data.not,
+                expr: tags_data.expr,
+                album_aware: args.album_aware,
+                volume: args.volume,
+            }
+        }
+        None => {
+            // no tags given: save whatever is playing now
+            let current: TagsData = client
+                .get(format!("{}/tags/active", api_hostname))
+                .send()
+                .await?
+                .json()
+                .await?;
+            Station {
+                any: current.any,
+                not: current.not,
+                expr: args.expr.or(current.expr),
+                album_aware: current.album_aware || args.album_aware,
+                volume: args.volume,
+            }
+        }
+    };
+
+    let url = format!("{}/stations/{}", api_hostname, args.name);
+    let response = client.put(&url).json(&station).send().await?;
+
+    if response.status().is_success() {
+        println!("{} {}", "[+] saved station:".green(), args.name.green().bold());
+        println!("    {}: {:?}", "any".green().bold(), station.any);
+        println!("    {}: {:?}", "not".red().bold(), station.not);
+        if let Some(expr) = &station.expr {
+            println!("    {}: {}", "expr".yellow().bold(), expr);
+        }
+    } else {
+        eprintln!("[!] Error: Failed to save station: {}", server_error(response).await);
+    }
+
+    Ok(())
+}
+
+async fn delete_station(api_hostname: &str, name: &str) -> Result<(), reqwest::Error> {
+    let client = reqwest::Client::new();
+    let response = client
+        .delete(format!("{}/stations/{}", api_hostname, name))
+        .send()
+        .await?;
+
+    if response.status().is_success() {
+        println!("{}{}", "[+] deleted station: ".red(), name.red().bold());
+    } else {
+        eprintln!("[!] Error: Failed to delete station: {}", server_error(response).await);
+    }
+
+    Ok(())
+}
+
+async fn playback(api_hostname: &str, tags_data: &TagsData, flush: bool) -> Result<(), reqwest::Error> {
     println!("[-] TagsData: {:?}", tags_data);
 
     let client = reqwest::Client::new();
-    let url = format!("{}/tags", api_hostname);
+    let url = format!("{}/tags/active?flush={}", api_hostname, flush);
 
     let response = client
         .post(&url)
@@ -461,12 +963,16 @@ async fn playback(api_hostname: &str, tags_data: &TagsData) -> Result<(), reqwes
         .await?;
 
     if response.status().is_success() {
-        println!("[+] Playback Tags updated successfully.");
-    } else {
-        eprintln!(
-            "[!] Error: Failed to update tags (HTTP {})",
-            response.status()
+        let change: TagsChange = response.json().await?;
+        println!(
+            "[+] Playback Tags updated successfully, {} songs match.",
+            change.matched.to_string().green().bold()
         );
+        if flush {
+            println!("[+] Replaced {} upcoming songs.", change.flushed);
+        }
+    } else {
+        eprintln!("[!] Error: Failed to update tags: {}", server_error(response).await);
     }
 
     Ok(())
@@ -505,27 +1011,19 @@ async fn perform_tagging(
             Ok(strings) => strings.first().map(|song| song.to_owned()),
             Err(e) => {
                 eprintln!("Error: Failed to deserialize root response: {}", e);
-                None
+                return Ok(());
             }
         }
     } else {
-        eprintln!(
-            "Error: Failed to fetch root (HTTP {})",
-            root_response.status()
-        );
-        None
+        eprintln!("Error: Failed to fetch root: {}", server_error(root_response).await);
+        return Ok(());
     };
 
-    // gnarly line just to print, someone tell me why this is stupid and wrong
-    println!(
-        "    {}",
-        now_playing
-            .clone()
-            .expect("REASON")
-            .to_string()
-            .yellow()
-            .bold()
-    );
+    let Some(now_playing) = now_playing else {
+        eprintln!("[!] Error: nothing is playing");
+        return Ok(());
+    };
+    println!("    {}", now_playing.yellow().bold());
 
     // Create a JSON object representing the request body
     let request_body = serde_json::json!({
@@ -546,10 +1044,7 @@ async fn perform_tagging(
     if response.status().is_success() {
         println!("{}", "[+] Tags updated successfully.".green());
     } else {
-        eprintln!(
-            "[!] Error: Failed to update tags (HTTP {})",
-            response.status()
-        );
+        eprintln!("[!] Error: Failed to update tags: {}", server_error(response).await);
     }
 
     Ok(())
@@ -568,10 +1063,7 @@ async fn toggle_album_mode(api_hostname: &str) -> Result<(), reqwest::Error> {
     if response.status().is_success() {
         println!("[+] Album-aware mode toggled");
     } else {
-        eprintln!(
-            "[!] Error: Failed to toggle album mode (HTTP {})",
-            response.status()
-        );
+        eprintln!("[!] Error: Failed to toggle album mode: {}", server_error(response).await);
     }
 
     Ok(())
@@ -1 +1,2 @@
+pub mod responses;
 pub mod tags_data;
@@ -0,0 +1,102 @@
+//! Response bodies of the server routes the CLI calls.
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Deserialize)]
+pub struct ErrorBody {
+    pub detail: String,
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct QueueResponse {
+    pub length: usize,
+    pub head: Vec<String>,
+    pub tail: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpcomingResponse {
+    pub length: usize,
+    pub album_aware: bool,
+    pub songs: Vec<UpcomingSong>,
+    pub albums: Vec<UpcomingAlbum>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpcomingSong {
+    pub file: String,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpcomingAlbum {
+    pub album: Option<String>,
+    pub artist: Option<String>,
+    pub songs: Vec<UpcomingSong>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct QueueChange {
+    pub songs: Vec<UpcomingSong>,
+    pub length: usize,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TagInfo {
+    pub name: String,
+    pub track_count: usize,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SongScore {
+    pub file: String,
+    pub weight: f64,
+    pub rating: Option<u8>,
+    pub plays: u32,
+    pub skips: u32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HistoryResponse {
+    pub total: usize,
+    pub entries: Vec<HistoryEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HistoryEntry {
+    pub file: String,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub started_at: u64,
+    pub played_secs: u64,
+    pub skipped: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Station {
+    pub any: Vec<String>,
+    pub not: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expr: Option<String>,
+    pub album_aware: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub volume: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StationInfo {
+    pub name: String,
+    #[serde(flatten)]
+    pub station: Station,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ActiveStation {
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TagsChange {
+    pub matched: usize,
+    pub flushed: usize,
+}
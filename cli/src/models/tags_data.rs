@@ -9,6 +9,8 @@ use std::fmt;
 pub struct TagsData {
     pub any: Vec<String>,
     pub not: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expr: Option<String>,
     pub album_aware: bool,
 }
 
@@ -17,8 +19,8 @@ impl fmt::Debug for TagsData {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "{{\n    any: {:?},\n    not: {:?},\n    album_aware: {:?}\n}}",
-            self.any, self.not, self.album_aware
+            "{{\n    any: {:?},\n    not: {:?},\n    expr: {:?},\n    album_aware: {:?}\n}}",
+            self.any, self.not, self.expr, self.album_aware
         )
     }
 }
@@ -35,10 +37,11 @@ impl TagsData {
     }
 }
 
-pub fn parse_tags_data_from_argv(tags: &str, not_tags: &str) -> TagsData {
+pub fn parse_tags_data_from_argv(tags: &str, not_tags: &str, expr: Option<String>) -> TagsData {
     TagsData {
         any: tags.split(',').map(|s| s.trim().to_string()).collect(),
         not: not_tags.split(',').map(|s| s.trim().to_string()).collect(),
+        expr,
         album_aware: false,
     }
 }
@@ -12,7 +12,7 @@ fn main() {
     if !lib_src_dir.exists() {
         println!("cargo:warning=Fetching libmpdclient source to OUT_DIR...");
         let status = Command::new("git")
-            .args(&[
+            .args([
                 "clone",
                 "--depth", "1",
                 "--branch", "v2.22",
@@ -48,7 +48,7 @@ fn main() {
     for entry in fs::read_dir(&src_path).expect("Failed to read src dir") {
         let entry = entry.expect("Failed to read entry");
         let path = entry.path();
-        if path.is_file() && path.extension().map_or(false, |ext| ext == "c") {
+        if path.is_file() && path.extension().is_some_and(|ext| ext == "c") {
             let filename = path.file_name().unwrap().to_str().unwrap();
             // Skip example.c and test files if any
             if filename != "example.c" && !filename.starts_with("t_") {
@@ -59,6 +59,12 @@ fn main() {
 
     build.compile("mpdclient");
 
+    // LOCAL-ONLY: offline stub bindings
+    if let Ok(stub) = env::var("STUB_BINDINGS") {
+        fs::copy(stub, out_dir.join("bindings.rs")).unwrap();
+        return;
+    }
+
     // Generate bindings
     let bindings = bindgen::Builder::default()
         .header(include_path.join("mpd/client.h").to_str().unwrap())
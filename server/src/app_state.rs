@@ -1,56 +1,461 @@
+use serde::{Deserialize, Serialize};
 use std::env;
 use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 use tokio::sync::{Mutex, RwLock};
 
-use crate::models::song_queue::SongQueue;
-use crate::models::tags_data::TagsData;
+use crate::models::cooldown::{Cooldown, RecentPlays};
+use crate::models::history::{History, HistoryEntry, DEFAULT_HISTORY_CAPACITY};
+use crate::models::library_index::{IndexedMpd, LibraryIndex, RefreshScope};
+use crate::models::skip_log::SkipLog;
+use crate::models::song_queue::{SongQueue, TagMatchMode};
+use crate::models::song_stats::PlayStats;
+use crate::models::station::{StationInfo, Stations};
+use crate::models::tags_data::{PlaybackTags, TagsChange, TagsData};
+use crate::models::weighting::{SongWeights, Weighting};
+use crate::mpd_conn::mock_mpd::MockMpd;
 use crate::mpd_conn::mpd_pool::MpdPool;
+use crate::mpd_conn::traits::MpdClient;
+use crate::persistence::{self, StateStore};
+use crate::models::tag_cache::TagCache;
+use crate::scheduler::schedule::Schedule;
+use crate::scheduler::EmptyRefill;
+use crate::settings::{ServerArgs, Settings};
 
+/// Runtime-tunable settings, readable and patchable through `/config`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Config {
+    #[serde(rename = "album_aware")]
     pub album_aware_shuffle: bool,
+    pub tag_match_mode: TagMatchMode,
+    /// The scheduler tops MPD up while its queue is shorter than this.
+    pub low_water_mark: usize,
+    /// The internal queue is refilled from the tags once it is down to this
+    /// many entries.
+    pub refill_threshold: usize,
+    /// How often the scheduler checks MPD when `idle` is unavailable.
+    #[serde(rename = "poll_interval_ms", with = "duration_ms")]
+    pub poll_interval: Duration,
+    /// At least this many songs between two by the same artist (0 is off).
+    pub artist_spacing: usize,
+    /// A played song is not queued again for this many hours (0 is off).
+    pub track_cooldown_hours: u64,
+    /// The knobs of the song weighting formula, see `Weighting`.
+    pub rating_boost: f64,
+    pub skip_penalty: f64,
+    pub play_penalty: f64,
+    pub recovery_hours: u64,
 }
 
+mod duration_ms {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::time::Duration;
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(duration.as_millis() as u64)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        u64::deserialize(deserializer).map(Duration::from_millis)
+    }
+}
+
+pub const LOW_WATER_MARK_RANGE: std::ops::RangeInclusive<usize> = 1..=50;
+pub const REFILL_THRESHOLD_RANGE: std::ops::RangeInclusive<usize> = 0..=1000;
+pub const POLL_INTERVAL_RANGE: std::ops::RangeInclusive<Duration> =
+    Duration::from_millis(10)..=Duration::from_secs(300);
+pub const ARTIST_SPACING_RANGE: std::ops::RangeInclusive<usize> = 0..=100;
+pub const TRACK_COOLDOWN_HOURS_RANGE: std::ops::RangeInclusive<u64> = 0..=24 * 30;
+pub const RATING_BOOST_RANGE: std::ops::RangeInclusive<f64> = 1.0..=10.0;
+pub const SKIP_PENALTY_RANGE: std::ops::RangeInclusive<f64> = 0.0..=100.0;
+pub const PLAY_PENALTY_RANGE: std::ops::RangeInclusive<f64> = 0.0..=10.0;
+pub const RECOVERY_HOURS_RANGE: std::ops::RangeInclusive<u64> = 0..=24 * 365;
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            album_aware_shuffle: false,
+            tag_match_mode: TagMatchMode::default(),
+            low_water_mark: 2,
+            refill_threshold: 0,
+            poll_interval: crate::scheduler::POLL_INTERVAL,
+            artist_spacing: 0,
+            track_cooldown_hours: 0,
+            rating_boost: Weighting::default().rating_boost,
+            skip_penalty: Weighting::default().skip_penalty,
+            play_penalty: Weighting::default().play_penalty,
+            recovery_hours: Weighting::default().recovery_hours,
+        }
+    }
+}
+
+impl Config {
+    /// Checks every setting is within its allowed range.
+    pub fn validate(&self) -> Result<(), String> {
+        if !LOW_WATER_MARK_RANGE.contains(&self.low_water_mark) {
+            return Err(format!(
+                "low_water_mark must be between {} and {}",
+                LOW_WATER_MARK_RANGE.start(),
+                LOW_WATER_MARK_RANGE.end()
+            ));
+        }
+        if !REFILL_THRESHOLD_RANGE.contains(&self.refill_threshold) {
+            return Err(format!(
+                "refill_threshold must be between {} and {}",
+                REFILL_THRESHOLD_RANGE.start(),
+                REFILL_THRESHOLD_RANGE.end()
+            ));
+        }
+        if !POLL_INTERVAL_RANGE.contains(&self.poll_interval) {
+            return Err(format!(
+                "poll_interval_ms must be between {} and {}",
+                POLL_INTERVAL_RANGE.start().as_millis(),
+                POLL_INTERVAL_RANGE.end().as_millis()
+            ));
+        }
+        if !ARTIST_SPACING_RANGE.contains(&self.artist_spacing) {
+            return Err(format!(
+                "artist_spacing must be between {} and {}",
+                ARTIST_SPACING_RANGE.start(),
+                ARTIST_SPACING_RANGE.end()
+            ));
+        }
+        if !TRACK_COOLDOWN_HOURS_RANGE.contains(&self.track_cooldown_hours) {
+            return Err(format!(
+                "track_cooldown_hours must be between {} and {}",
+                TRACK_COOLDOWN_HOURS_RANGE.start(),
+                TRACK_COOLDOWN_HOURS_RANGE.end()
+            ));
+        }
+        for (name, value, range) in [
+            ("rating_boost", self.rating_boost, RATING_BOOST_RANGE),
+            ("skip_penalty", self.skip_penalty, SKIP_PENALTY_RANGE),
+            ("play_penalty", self.play_penalty, PLAY_PENALTY_RANGE),
+        ] {
+            if !range.contains(&value) {
+                return Err(format!("{} must be between {} and {}", name, range.start(), range.end()));
+            }
+        }
+        if !RECOVERY_HOURS_RANGE.contains(&self.recovery_hours) {
+            return Err(format!(
+                "recovery_hours must be between {} and {}",
+                RECOVERY_HOURS_RANGE.start(),
+                RECOVERY_HOURS_RANGE.end()
+            ));
+        }
+        Ok(())
+    }
+
+    /// A copy with the keys of `patch` (a JSON object, as served by `/config`)
+    /// laid over it and validated. Omitted or null keys keep their current
+    /// value; unknown keys are an error.
+    pub fn patched(&self, patch: serde_json::Value) -> Result<Config, String> {
+        let serde_json::Value::Object(patch) = patch else {
+            return Err("expected an object of settings".to_string());
+        };
+        let serde_json::Value::Object(mut merged) = serde_json::to_value(self).map_err(|e| e.to_string())? else {
+            unreachable!("Config serializes to an object");
+        };
+
+        for (key, value) in patch {
+            if !merged.contains_key(&key) {
+                return Err(format!("unknown setting `{}`", key));
+            }
+            if !value.is_null() {
+                merged.insert(key, value);
+            }
+        }
+
+        let patched: Config = serde_json::from_value(serde_json::Value::Object(merged)).map_err(|e| e.to_string())?;
+        patched.validate()?;
+        Ok(patched)
+    }
+
+    /// The spacing rules the queue applies when shuffling.
+    pub fn cooldown(&self) -> Cooldown {
+        Cooldown {
+            artist_spacing: self.artist_spacing,
+            track_window: Duration::from_secs(self.track_cooldown_hours * 3600),
+        }
+    }
+
+    /// The weighting formula the queue orders songs by.
+    pub fn weighting(&self) -> Weighting {
+        Weighting {
+            rating_boost: self.rating_boost,
+            skip_penalty: self.skip_penalty,
+            play_penalty: self.play_penalty,
+            recovery_hours: self.recovery_hours,
+        }
+    }
+}
+
+/// The server's shared state. Code that needs more than one of these at a
+/// time takes them in one order, so two tasks never wait on each other:
+/// first an MPD connection from `mpd_pool`, then `queue`, `tags_data` and
+/// `config`, and only after those any of the others.
 #[derive(Clone)]
 pub struct AppState {
     pub mpd_pool: Arc<MpdPool>,
     pub queue: Arc<Mutex<SongQueue>>,
     pub config: Arc<Mutex<Config>>,
     pub tags_data: Arc<RwLock<TagsData>>,
+    pub skip_log: Arc<Mutex<SkipLog>>,
+    pub history: Arc<Mutex<History>>,
+    pub song_stats: Arc<Mutex<PlayStats>>,
+    pub stations: Arc<RwLock<Stations>>,
+    pub schedule: Arc<Mutex<Schedule>>,
+    pub tag_cache: Arc<Mutex<TagCache>>,
+    pub library: Arc<RwLock<LibraryIndex>>,
+    /// The last refill that matched nothing, so the scheduler doesn't
+    /// repeat it every tick.
+    pub empty_refill: Arc<Mutex<Option<EmptyRefill>>>,
+    pub state_store: Arc<StateStore>,
+    /// The config and tags resolved at startup (defaults, file, env and
+    /// flags). Only what differs from these is saved, so a restart still
+    /// picks up new startup values for anything not changed at runtime.
+    pub startup_config: Arc<Config>,
+    pub startup_tags: Arc<TagsData>,
+}
+
+impl AppState {
+    pub fn new(mpd_pool: Arc<MpdPool>, config: Config, tags_data: TagsData) -> Self {
+        AppState {
+            mpd_pool,
+            queue: Arc::new(Mutex::new(SongQueue::new())),
+            startup_config: Arc::new(config.clone()),
+            startup_tags: Arc::new(tags_data.clone()),
+            config: Arc::new(Mutex::new(config)),
+            tags_data: Arc::new(RwLock::new(tags_data)),
+            skip_log: Arc::new(Mutex::new(SkipLog::default())),
+            history: Arc::new(Mutex::new(History::default())),
+            song_stats: Arc::new(Mutex::new(PlayStats::default())),
+            stations: Arc::new(RwLock::new(Stations::default())),
+            schedule: Arc::new(Mutex::new(Schedule::default())),
+            tag_cache: Arc::new(Mutex::new(TagCache::default())),
+            library: Arc::new(RwLock::new(LibraryIndex::default())),
+            empty_refill: Arc::new(Mutex::new(None)),
+            state_store: Arc::new(StateStore::disabled()),
+        }
+    }
+
+    /// Makes `tags` the playback tags and rebuilds the queue from them.
+    /// No station is active afterwards. Fails, leaving the queue and tags
+    /// as they were, if MPD can't be reached.
+    pub async fn switch_tags(&self, tags: TagsData) -> anyhow::Result<TagsChange> {
+        let tags = tags.without_blanks();
+        log::info!("[+] Switching playback tags to {:?}", tags);
+
+        let mut pooled_conn = self.mpd_pool.get_connection().await?;
+        let mut locked_song_queue = self.queue.lock().await;
+        let mut locked_tags_data = self.tags_data.write().await;
+        let album_aware = self.config.lock().await.album_aware_shuffle;
+        self.rebuild_queue(&mut locked_song_queue, &tags, album_aware, &mut pooled_conn.mpd_conn().mpd).await?;
+        *locked_tags_data = tags.clone();
+        let matched = locked_song_queue.song_count();
+
+        self.stations.write().await.set_active(None);
+        self.state_store.mark_dirty();
+        Ok(TagsChange {
+            playback: PlaybackTags { tags, album_aware },
+            matched,
+            flushed: 0,
+        })
+    }
+
+    /// The tags and album mode currently playing.
+    pub async fn playback_tags(&self) -> PlaybackTags {
+        let tags = self.tags_data.read().await.clone();
+        let album_aware = self.config.lock().await.album_aware_shuffle;
+        PlaybackTags { tags, album_aware }
+    }
+
+    /// Tag names in `tags` that are not stored playlists. Always empty when
+    /// tags are matched as substrings, since those need not be playlists.
+    pub async fn unknown_tags(&self, tags: &TagsData) -> anyhow::Result<Vec<String>> {
+        if self.config.lock().await.tag_match_mode == TagMatchMode::Substring {
+            return Ok(Vec::new());
+        }
+        let expr = tags.to_expr().map_err(anyhow::Error::msg)?;
+
+        let mut pooled_conn = self.mpd_pool.get_connection().await?;
+        let library = self.library.read().await;
+        let playlists = IndexedMpd::new(&library, &mut pooled_conn.mpd_conn().mpd).playlists()?;
+        Ok(expr
+            .tag_names()
+            .into_iter()
+            .filter(|name| !playlists.iter().any(|p| p.name == *name))
+            .map(str::to_string)
+            .collect())
+    }
+
+    /// Switches to station `name`: its tags, album mode and volume. `None`
+    /// if there is no such station. Fails, leaving playback as it was, if
+    /// MPD can't be reached.
+    pub async fn activate_station(&self, name: &str) -> anyhow::Result<Option<StationInfo>> {
+        let Some(station) = self.stations.read().await.get(name).cloned() else {
+            return Ok(None);
+        };
+        log::info!("[+] Activating station {}", name);
+
+        let mut pooled_conn = self.mpd_pool.get_connection().await?;
+        let mpd = &mut pooled_conn.mpd_conn().mpd;
+        {
+            let mut locked_song_queue = self.queue.lock().await;
+            let mut locked_tags_data = self.tags_data.write().await;
+            self.rebuild_queue(&mut locked_song_queue, &station.tags, station.album_aware, mpd).await?;
+            *locked_tags_data = station.tags.clone();
+            self.config.lock().await.album_aware_shuffle = station.album_aware;
+        }
+
+        if let Some(volume) = station.volume {
+            if let Err(e) = mpd.set_volume(volume) {
+                log::error!("[!] Failed to set volume for station {}: {}", name, e);
+            }
+        }
+
+        let mut locked_stations = self.stations.write().await;
+        locked_stations.set_active(Some(name));
+        self.state_store.mark_dirty();
+        Ok(locked_stations.active())
+    }
+
+    /// Replaces the queue's contents with a fresh shuffle of `tags`, shaped
+    /// by `album_aware`. Fails, leaving the queue as it was, if MPD's queue
+    /// can't be read.
+    async fn rebuild_queue(
+        &self,
+        queue: &mut SongQueue,
+        tags: &TagsData,
+        album_aware: bool,
+        mpd: &mut dyn MpdClient,
+    ) -> anyhow::Result<()> {
+        let upcoming = mpd.queue()?;
+        let recent = RecentPlays::new(&*self.history.lock().await, &upcoming, &queue.cooldown(), SystemTime::now());
+        let stats = self.song_stats.lock().await;
+        let weights = SongWeights::new(&stats, queue.weighting(), SystemTime::now());
+        let library = self.library.read().await;
+        queue.clear();
+        queue.set_album_aware(album_aware);
+        queue.shuffle_and_add_after(tags, &mut IndexedMpd::new(&library, mpd), &recent, &weights);
+        Ok(())
+    }
+
+    /// What `cooldown` has to keep new songs apart from: the play history
+    /// and MPD's queue, which plays before anything queued now.
+    pub async fn recent_plays(&self, cooldown: Cooldown, mpd: &mut dyn MpdClient) -> RecentPlays {
+        let upcoming = mpd.queue().unwrap_or_else(|e| {
+            log::warn!("[!] Could not read MPD's queue for the cooldown: {}", e);
+            Vec::new()
+        });
+        RecentPlays::new(&*self.history.lock().await, &upcoming, &cooldown, SystemTime::now())
+    }
+
+    /// Notes finished plays in the song stats the weighting reads.
+    pub async fn record_plays(&self, entries: impl IntoIterator<Item = HistoryEntry>) {
+        let mut stats = self.song_stats.lock().await;
+        let mut recorded = false;
+        for entry in entries {
+            stats.record(&entry);
+            recorded = true;
+        }
+        if recorded {
+            self.state_store.mark_dirty();
+        }
+    }
+
+    /// Brings the library index and tag counts up to date after the server
+    /// itself changed the stored playlists `names`, without waiting for
+    /// MPD's change event.
+    pub async fn playlists_changed(&self, mpd: &mut dyn MpdClient, names: &[String]) {
+        let mut library = self.library.write().await;
+        for name in names {
+            library.refresh_playlist(name, mpd, SystemTime::now());
+        }
+        drop(library);
+        self.tag_cache.lock().await.invalidate();
+    }
+
+    /// Reloads the library index from MPD (the songs and any changed stored
+    /// playlists, or just the playlists) and drops the tag counts computed
+    /// from the old one.
+    pub async fn refresh_library(&self, scope: RefreshScope) -> anyhow::Result<()> {
+        let mut pooled_conn = self.mpd_pool.get_connection().await?;
+        let mpd = &mut pooled_conn.mpd_conn().mpd;
+        let now = SystemTime::now();
+
+        match scope {
+            RefreshScope::Full => {
+                // list before locking so reads aren't held up meanwhile
+                let songs = mpd.listall()?;
+                self.library.write().await.reload(songs, mpd, now)?;
+            }
+            RefreshScope::Playlists => {
+                let mut library = self.library.write().await;
+                if library.is_ready() {
+                    library.refresh_playlists(mpd, now)?;
+                } else {
+                    let songs = mpd.listall()?;
+                    library.reload(songs, mpd, now)?;
+                }
+            }
+        }
+
+        let stats = self.library.read().await.stats(now);
+        log::info!(
+            "[+] Library index refreshed ({:?}): {} songs, {} playlists",
+            scope,
+            stats.songs,
+            stats.playlists
+        );
+        self.tag_cache.lock().await.invalidate();
+        Ok(())
+    }
 }
 
+/// Builds the state from `jukectl.toml` and the environment, falling back
+/// to the defaults if they are invalid. The server binary resolves its
+/// settings itself (with flags) and calls `initialize_with`.
 pub async fn initialize() -> AppState {
-    let mpd_host = env::var("MPD_HOST").unwrap_or_else(|_| "127.0.0.1".to_string());
-    let mpd_port: u16 = env::var("MPD_PORT")
-        .unwrap_or_else(|_| "6600".to_string())
-        .parse()
-        .unwrap_or(6600);
-
-    let max_connections = env::var("MPD_MAX_CONNECTIONS")
-        .unwrap_or_else(|_| "5".to_string())
-        .parse()
-        .unwrap_or(5);
-
-    let mpd_pool = Arc::new(
-        MpdPool::new(mpd_host, mpd_port, max_connections)
-            .expect("Failed to create MPD pool"),
-    );
-    
-    let _ = mpd_pool.warm_pool(1).await;
+    let settings = Settings::load(&ServerArgs::default()).unwrap_or_else(|e| {
+        log::error!("[!] Invalid settings, using defaults: {}", e);
+        Settings::default()
+    });
+    initialize_with(&settings).await
+}
 
-    let queue = Arc::new(Mutex::new(SongQueue::new()));
-    let config = Arc::new(Mutex::new(Config {
-        album_aware_shuffle: env::var("ALBUM_AWARE_SHUFFLE").unwrap_or_default() == "1",
-    }));
+pub async fn initialize_with(settings: &Settings) -> AppState {
+    let mpd = &settings.mpd;
+    let mpd_pool = if mpd.dev_mode {
+        log::info!("[!] Dev mode is enabled, using MockMpd");
+        Arc::new(MpdPool::with_mock(MockMpd::new(), mpd.max_connections))
+    } else {
+        Arc::new(
+            MpdPool::with_backend(mpd.host.clone(), mpd.port, mpd.max_connections, mpd.backend)
+                .expect("Failed to create MPD pool"),
+        )
+    };
 
-    let default_tags = load_default_tags();
-    let tags_data = Arc::new(RwLock::new(default_tags));
+    let _ = mpd_pool.warm_pool(1).await;
 
-    AppState {
-        mpd_pool,
-        queue,
-        config,
-        tags_data,
+    let mut state = AppState::new(mpd_pool, settings.config.clone(), settings.default_tags.clone());
+    let mut stations = Stations::new(settings.presets.clone());
+    stations.set_active(settings.preset.as_deref());
+    state.stations = Arc::new(RwLock::new(stations));
+    if let Some(path) = &settings.history_log {
+        log::info!("[+] Appending play history to {}", path.display());
+        state.history = Arc::new(Mutex::new(History::with_log(DEFAULT_HISTORY_CAPACITY, path)));
     }
+
+    if let Some(dir) = &settings.data_dir {
+        state.state_store = Arc::new(StateStore::new(dir));
+    }
+    if let Err(e) = state.refresh_library(RefreshScope::Full).await {
+        log::error!("[!] Failed to build the library index, reading from MPD until it is: {}", e);
+    }
+    persistence::restore(&state).await;
+    state
 }
 
 pub async fn initialize_queue(state: &AppState) {
@@ -69,36 +474,45 @@ pub async fn initialize_queue(state: &AppState) {
     let locked_config = state.config.lock().await;
 
     locked_song_queue.set_album_aware(locked_config.album_aware_shuffle);
+    locked_song_queue.set_tag_match_mode(locked_config.tag_match_mode);
+    locked_song_queue.set_cooldown(locked_config.cooldown());
+    locked_song_queue.set_weighting(locked_config.weighting());
+
+    if !locked_song_queue.is_empty() {
+        log::info!("[+] Resuming saved queue. ({} entries)", locked_song_queue.len());
+        return;
+    }
 
     // Initial queue fill
-    locked_song_queue.shuffle_and_add(&*locked_tags_data, &mut pooled_conn.mpd_conn().mpd);
+    let mpd = &mut pooled_conn.mpd_conn().mpd;
+    let recent = state.recent_plays(locked_config.cooldown(), mpd).await;
+    let stats = state.song_stats.lock().await;
+    let weights = SongWeights::new(&stats, locked_config.weighting(), SystemTime::now());
+    let library = state.library.read().await;
+    locked_song_queue.shuffle_and_add_after(&locked_tags_data, &mut IndexedMpd::new(&library, mpd), &recent, &weights);
     
     log::info!("[+] Queue initialization complete. ({} songs)", locked_song_queue.len());
 }
 
 pub fn load_default_tags() -> TagsData {
-    let fallback = TagsData {
-        any: vec!["jukebox".to_string()],
-        not: vec!["explicit".to_string()],
-    };
+    let fallback = Settings::default().default_tags;
 
     match env::var("JUKECTL_DEFAULT_TAGS_B64") {
-        Ok(b64_tags) => {
-            use base64::{engine::general_purpose, Engine as _};
-            match general_purpose::STANDARD.decode(b64_tags) {
-                Ok(decoded) => match serde_json::from_slice::<TagsData>(&decoded) {
-                    Ok(tags) => tags,
-                    Err(e) => {
-                        log::warn!("[!] Failed to parse JUKECTL_DEFAULT_TAGS_B64 as JSON: {}", e);
-                        fallback
-                    }
-                },
-                Err(e) => {
-                    log::warn!("[!] Failed to decode JUKECTL_DEFAULT_TAGS_B64 as Base64: {}", e);
-                    fallback
-                }
-            }
-        }
+        Ok(b64_tags) => decode_tags_b64(&b64_tags).unwrap_or_else(|e| {
+            log::warn!("[!] Ignoring JUKECTL_DEFAULT_TAGS_B64: {}", e);
+            fallback
+        }),
         Err(_) => fallback,
     }
 }
+
+/// Decodes the base64-encoded `TagsData` JSON of `JUKECTL_DEFAULT_TAGS_B64`.
+pub fn decode_tags_b64(b64_tags: &str) -> Result<TagsData, String> {
+    use base64::{engine::general_purpose, Engine as _};
+    let decoded = general_purpose::STANDARD
+        .decode(b64_tags.trim())
+        .map_err(|e| format!("not valid base64: {}", e))?;
+    let tags = serde_json::from_slice::<TagsData>(&decoded).map_err(|e| format!("not valid tags JSON: {}", e))?;
+    tags.to_expr()?;
+    Ok(tags)
+}
@@ -1,5 +1,11 @@
+#[cfg(not(any(feature = "libmpdclient", feature = "native-mpd")))]
+compile_error!("enable at least one MPD backend: the `libmpdclient` or `native-mpd` feature");
+
 pub mod mpd_conn;
 pub mod models;
 pub mod app_state;
+pub mod persistence;
 pub mod routes;
 pub mod scheduler;
+pub mod settings;
+pub mod tagging;
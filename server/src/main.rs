@@ -1,22 +1,49 @@
 #[macro_use]
 extern crate rocket;
 
-use jukectl_server::app_state;
+use jukectl_server::app_state::{self, AppState};
+use jukectl_server::persistence;
 use jukectl_server::routes;
 use jukectl_server::scheduler;
+use jukectl_server::settings::{ServerArgs, Settings};
+use clap::Parser;
 
 #[launch]
 async fn rocket() -> _ {
-    let state = app_state::initialize().await;
+    let settings = match Settings::load(&ServerArgs::parse()) {
+        Ok(settings) => settings,
+        Err(e) => {
+            eprintln!("[!] Invalid configuration: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let state = app_state::initialize_with(&settings).await;
     let state_for_liftoff = state.clone();
 
-    rocket::build()
+    let figment = rocket::Config::figment()
+        .merge(("address", settings.address))
+        .merge(("port", settings.port));
+
+    rocket::custom(figment)
         .manage(state)
         .mount("/", routes::all_routes())
         .attach(rocket::fairing::AdHoc::on_liftoff("Initialize and Scheduler", |_| {
             Box::pin(async move {
                 app_state::initialize_queue(&state_for_liftoff).await;
+                persistence::spawn_autosave(state_for_liftoff.clone());
+                scheduler::schedule::start_schedule(state_for_liftoff.clone());
                 scheduler::start_scheduler(state_for_liftoff).await;
             })
         }))
+        .attach(rocket::fairing::AdHoc::on_shutdown("Save state", |rocket| {
+            Box::pin(async move {
+                if let Some(state) = rocket.state::<AppState>() {
+                    match state.state_store.save(state).await {
+                        Ok(()) => log::info!("[+] Saved state for next start"),
+                        Err(e) => log::error!("[!] Failed to save state on shutdown: {:#}", e),
+                    }
+                }
+            })
+        }))
 }
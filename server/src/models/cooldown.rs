@@ -0,0 +1,176 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::models::history::History;
+use crate::mpd_conn::traits::Song;
+
+/// How far into the deferred songs `space_artists` looks for one that fits
+/// before pulling more from the shuffle. Keeps a library dominated by one
+/// artist from turning the pass quadratic.
+const DEFERRED_SCAN: usize = 64;
+
+/// How far back `space_artists` looks for a gap to put a leftover item in.
+const BACKFILL_SCAN: usize = 256;
+
+/// Spacing rules applied whenever songs are shuffled into the queue. Both
+/// are off at zero.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Cooldown {
+    /// At least this many other songs (albums when album-aware) between two
+    /// by the same artist.
+    pub artist_spacing: usize,
+    /// Songs played this recently are left out of a refill.
+    pub track_window: Duration,
+}
+
+/// What plays before the songs being queued: the last finished plays and
+/// whatever MPD already has lined up. Lets the cooldown carry across
+/// refills instead of starting fresh each time.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RecentPlays {
+    /// Artists in play order, oldest first.
+    pub artists: Vec<Option<String>>,
+    /// Files still inside the track cooldown window.
+    pub files: HashSet<String>,
+}
+
+impl RecentPlays {
+    /// Gathers what `cooldown` needs from `history` and `upcoming` (MPD's
+    /// queue, playing song first) as of `now`.
+    pub fn new(history: &History, upcoming: &[Song], cooldown: &Cooldown, now: SystemTime) -> Self {
+        let mut recent = RecentPlays::default();
+
+        if cooldown.artist_spacing > 0 {
+            let mut played = history.recent(cooldown.artist_spacing, None);
+            played.reverse();
+            recent.artists = played
+                .into_iter()
+                .map(|entry| entry.artist)
+                .chain(upcoming.iter().map(|song| song.artist.clone()))
+                .collect();
+        }
+
+        if !cooldown.track_window.is_zero() {
+            let since = now
+                .checked_sub(cooldown.track_window)
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |d| d.as_secs());
+            recent.files = history
+                .recent(usize::MAX, Some(since))
+                .into_iter()
+                .map(|entry| entry.file)
+                .chain(history.now_playing().map(|song| song.file.clone()))
+                .chain(upcoming.iter().map(|song| song.file.clone()))
+                .collect();
+        }
+
+        recent
+    }
+
+    /// `songs` without those still cooling down, unless that would leave
+    /// nothing to play, in which case they all stay.
+    pub fn without_recent(&self, songs: Vec<Song>) -> Vec<Song> {
+        if self.files.is_empty() || songs.iter().all(|song| self.files.contains(&song.file)) {
+            return songs;
+        }
+        songs.into_iter().filter(|song| !self.files.contains(&song.file)).collect()
+    }
+}
+
+/// Reorders shuffled `items` so that at least `spacing` others fall between
+/// two by the same artist, counting `before` (the artists playing first,
+/// oldest first) as already placed. Items keep their shuffled order where
+/// they fit. One that fits nowhere at the end goes into the latest earlier
+/// gap that keeps it apart, or last if there is none, so the rule is best
+/// effort when one artist dominates. Items without an artist are never
+/// held back.
+pub fn space_artists<T>(
+    items: Vec<T>,
+    before: &[Option<String>],
+    spacing: usize,
+    artist: impl Fn(&T) -> Option<&str>,
+) -> Vec<T> {
+    if spacing == 0 {
+        return items;
+    }
+
+    let mut last_at: HashMap<String, usize> = HashMap::new();
+    for (pos, name) in before.iter().enumerate() {
+        if let Some(name) = name {
+            last_at.insert(name.clone(), pos);
+        }
+    }
+
+    let mut pending: VecDeque<T> = items.into();
+    let mut deferred: VecDeque<T> = VecDeque::new();
+    let mut spaced: Vec<T> = Vec::with_capacity(pending.len());
+
+    loop {
+        let pos = before.len() + spaced.len();
+        let fits = |item: &T| artist(item).is_none_or(|name| last_at.get(name).is_none_or(|&last| pos - last > spacing));
+
+        let next = match deferred.iter().take(DEFERRED_SCAN).position(&fits) {
+            Some(index) => deferred.remove(index).map(|item| (item, true)),
+            None => loop {
+                match pending.pop_front() {
+                    Some(item) if fits(&item) => break Some((item, true)),
+                    Some(item) => deferred.push_back(item),
+                    None => break deferred.pop_front().map(|item| (item, false)),
+                }
+            },
+        };
+        let Some((item, at_end)) = next else {
+            return spaced;
+        };
+        let Some(name) = artist(&item).map(str::to_string) else {
+            spaced.push(item);
+            continue;
+        };
+
+        let slot = if at_end {
+            None
+        } else {
+            backfill_slot(&spaced, before, spacing, &name, &artist)
+        };
+        match slot {
+            Some(index) => {
+                let inserted_at = before.len() + index;
+                for last in last_at.values_mut() {
+                    if *last >= inserted_at {
+                        *last += 1;
+                    }
+                }
+                let last = last_at.entry(name).or_insert(inserted_at);
+                *last = (*last).max(inserted_at);
+                spaced.insert(index, item);
+            }
+            None => {
+                last_at.insert(name, pos);
+                spaced.push(item);
+            }
+        }
+    }
+}
+
+/// The latest index in `spaced`, among the last `BACKFILL_SCAN`, where an
+/// item by `name` would have `spacing` others by someone else on both sides.
+fn backfill_slot<T>(
+    spaced: &[T],
+    before: &[Option<String>],
+    spacing: usize,
+    name: &str,
+    artist: &impl Fn(&T) -> Option<&str>,
+) -> Option<usize> {
+    let artist_at = |pos: usize| match pos.checked_sub(before.len()) {
+        Some(index) => artist(&spaced[index]),
+        None => before[pos].as_deref(),
+    };
+    let total = before.len() + spaced.len();
+
+    (spaced.len().saturating_sub(BACKFILL_SCAN)..spaced.len()).rev().find(|&index| {
+        let pos = before.len() + index;
+        let clear_before = (pos.saturating_sub(spacing)..pos).all(|p| artist_at(p) != Some(name));
+        let clear_after = (pos..(pos + spacing).min(total)).all(|p| artist_at(p) != Some(name));
+        clear_before && clear_after
+    })
+}
@@ -0,0 +1,194 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::mpd_conn::traits::Song;
+
+/// Default number of plays kept in memory before the oldest are dropped.
+pub const DEFAULT_HISTORY_CAPACITY: usize = 1000;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct HistoryEntry {
+    pub file: String,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    /// Seconds since the unix epoch.
+    pub started_at: u64,
+    /// Seconds between the song starting and the next one taking over.
+    pub played_secs: u64,
+    pub skipped: bool,
+}
+
+/// Bounded record of finished plays, oldest first. A play is recorded once
+/// the next song replaces it at the head of MPD's queue (or it is skipped),
+/// so the entry knows how long it actually played.
+///
+/// With a log path every finished play is also appended to that file as a
+/// JSON line, and the most recent lines are loaded back on startup. The log
+/// is cut back to those lines then, so it never holds much more than
+/// `capacity` plays.
+#[derive(Debug)]
+pub struct History {
+    entries: VecDeque<HistoryEntry>,
+    capacity: usize,
+    now_playing: Option<(Song, SystemTime)>,
+    log_path: Option<PathBuf>,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new(DEFAULT_HISTORY_CAPACITY)
+    }
+}
+
+impl History {
+    pub fn new(capacity: usize) -> Self {
+        History {
+            entries: VecDeque::new(),
+            capacity: capacity.max(1),
+            now_playing: None,
+            log_path: None,
+        }
+    }
+
+    /// Like `new`, but appends finished plays to `path` and starts from the
+    /// newest `capacity` entries already in it. Unreadable lines are skipped,
+    /// and the file is rewritten without them and the older entries.
+    pub fn with_log(capacity: usize, path: impl Into<PathBuf>) -> Self {
+        let mut history = Self::new(capacity);
+        let path = path.into();
+        let mut lines_read = 0;
+
+        match File::open(&path) {
+            Ok(file) => {
+                for line in BufReader::new(file).lines().map_while(Result::ok) {
+                    lines_read += 1;
+                    match serde_json::from_str::<HistoryEntry>(&line) {
+                        Ok(entry) => history.push(entry),
+                        Err(e) => log::warn!("[!] Skipping bad history line in {}: {}", path.display(), e),
+                    }
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => log::warn!("[!] Could not read history log {}: {}", path.display(), e),
+        }
+
+        if lines_read > history.len() {
+            if let Err(e) = rewrite_log(&path, &history.entries) {
+                log::warn!("[!] Could not trim history log {}: {}", path.display(), e);
+            }
+        }
+
+        history.log_path = Some(path);
+        history
+    }
+
+    pub fn log_path(&self) -> Option<&Path> {
+        self.log_path.as_deref()
+    }
+
+    /// Notes what is at the head of MPD's queue. When that differs from the
+    /// song seen last time, the previous song is recorded as played and
+    /// returned.
+    pub fn observe(&mut self, head: Option<&Song>, at: SystemTime) -> Option<HistoryEntry> {
+        let mut finished = None;
+        if let Some((current, _)) = &self.now_playing {
+            if head.is_some_and(|song| song.file == current.file) {
+                return None;
+            }
+            finished = self.finish(at, false);
+        }
+
+        self.now_playing = head.map(|song| (song.clone(), at));
+        finished
+    }
+
+    /// Records `song` as skipped at `at`, whether or not it had been
+    /// observed. Returns the skip, after any play `song` displaced.
+    pub fn skip(&mut self, song: &Song, at: SystemTime) -> Vec<HistoryEntry> {
+        let displaced = self.observe(Some(song), at);
+        displaced.into_iter().chain(self.finish(at, true)).collect()
+    }
+
+    /// The song currently being timed, if any.
+    pub fn now_playing(&self) -> Option<&Song> {
+        self.now_playing.as_ref().map(|(song, _)| song)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Most recent plays first, only those started at or after `since`.
+    pub fn recent(&self, limit: usize, since: Option<u64>) -> Vec<HistoryEntry> {
+        self.entries
+            .iter()
+            .rev()
+            .take_while(|e| since.is_none_or(|since| e.started_at >= since))
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+
+    fn finish(&mut self, at: SystemTime, skipped: bool) -> Option<HistoryEntry> {
+        let (song, started) = self.now_playing.take()?;
+
+        let entry = HistoryEntry {
+            file: song.file,
+            title: song.title,
+            artist: song.artist,
+            album: song.album,
+            started_at: unix_secs(started),
+            played_secs: at.duration_since(started).map(|d| d.as_secs()).unwrap_or(0),
+            skipped,
+        };
+
+        if let Some(path) = &self.log_path {
+            if let Err(e) = append_line(path, &entry) {
+                log::warn!("[!] Could not append to history log {}: {}", path.display(), e);
+            }
+        }
+        self.push(entry.clone());
+        Some(entry)
+    }
+
+    fn push(&mut self, entry: HistoryEntry) {
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+}
+
+fn append_line(path: &Path, entry: &HistoryEntry) -> std::io::Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    let line = serde_json::to_string(entry)?;
+    writeln!(file, "{}", line)
+}
+
+/// Replaces the log at `path` with `entries`, through a temporary file so a
+/// crash part way leaves the old log in place.
+fn rewrite_log(path: &Path, entries: &VecDeque<HistoryEntry>) -> std::io::Result<()> {
+    let mut tmp_name = path.as_os_str().to_owned();
+    tmp_name.push(".tmp");
+    let tmp = PathBuf::from(tmp_name);
+
+    let mut file = File::create(&tmp)?;
+    for entry in entries {
+        writeln!(file, "{}", serde_json::to_string(entry)?)?;
+    }
+    file.sync_all()?;
+    std::fs::rename(&tmp, path)
+}
+
+fn unix_secs(at: SystemTime) -> u64 {
+    at.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
@@ -0,0 +1,371 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::mpd_conn::traits::{MpdClient, Playlist, Query, Song};
+
+/// How often a built index is reloaded even without an MPD change event,
+/// in case one was missed (or `idle` is unavailable).
+pub const LIBRARY_REFRESH_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// What to reload after an MPD change.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RefreshScope {
+    /// Stored playlists only (`stored_playlist`).
+    Playlists,
+    /// The song database and the playlists (`database`).
+    Full,
+}
+
+impl RefreshScope {
+    /// The scope an MPD idle subsystem calls for, if any.
+    pub fn for_subsystem(subsystem: &str) -> Option<RefreshScope> {
+        match subsystem {
+            "database" => Some(RefreshScope::Full),
+            "stored_playlist" => Some(RefreshScope::Playlists),
+            _ => None,
+        }
+    }
+}
+
+/// An in-memory copy of MPD's library so requests and queue rebuilds don't
+/// pull the whole database over the socket: songs by file, the files of
+/// each artist and album, and every stored playlist.
+#[derive(Debug, Default)]
+pub struct LibraryIndex {
+    songs: BTreeMap<String, Song>,
+    by_artist: BTreeMap<String, Vec<String>>,
+    by_album: BTreeMap<String, Vec<String>>,
+    playlists: BTreeMap<String, StoredPlaylist>,
+    built_at: Option<SystemTime>,
+    playlists_at: Option<SystemTime>,
+    revision: u64,
+}
+
+/// A stored playlist as loaded, with MPD's `Last-Modified` for it then.
+#[derive(Debug, Clone)]
+struct StoredPlaylist {
+    songs: Vec<Song>,
+    last_modified: Option<u64>,
+    loaded_at: SystemTime,
+}
+
+impl StoredPlaylist {
+    /// Whether `listed` shows no change since this was loaded. A timestamp
+    /// from the second it was loaded in (or later) could hide a second
+    /// write, so it doesn't count.
+    fn is_current(&self, listed: &Playlist) -> bool {
+        listed
+            .last_modified
+            .is_some_and(|secs| self.last_modified == Some(secs) && secs < unix_secs(self.loaded_at))
+    }
+}
+
+/// Size and age of the index, for `GET /library/stats`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LibraryStats {
+    /// False until the first build; reads go to MPD meanwhile.
+    pub ready: bool,
+    pub songs: usize,
+    pub artists: usize,
+    pub albums: usize,
+    pub playlists: usize,
+    pub playlist_entries: usize,
+    /// Unix seconds of the last full build.
+    pub built_at: Option<u64>,
+    /// Unix seconds the playlists were last reloaded.
+    pub playlists_refreshed_at: Option<u64>,
+    /// Seconds since the last full build.
+    pub age_secs: Option<u64>,
+}
+
+fn unix_secs(at: SystemTime) -> u64 {
+    at.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// Every stored playlist, reusing the ones in `previous` that MPD reports
+/// unchanged. Also says whether anything differs from `previous`.
+fn load_playlists(
+    mpd: &mut dyn MpdClient,
+    previous: &BTreeMap<String, StoredPlaylist>,
+    now: SystemTime,
+) -> Result<(BTreeMap<String, StoredPlaylist>, bool)> {
+    let listed = mpd.playlists()?;
+    let mut changed = listed.len() != previous.len();
+    let mut playlists = BTreeMap::new();
+    for playlist in listed {
+        let stored = match previous.get(&playlist.name) {
+            Some(stored) if stored.is_current(&playlist) => stored.clone(),
+            _ => {
+                changed = true;
+                StoredPlaylist {
+                    songs: mpd.playlist(&playlist.name)?,
+                    last_modified: playlist.last_modified,
+                    loaded_at: now,
+                }
+            }
+        };
+        playlists.insert(playlist.name, stored);
+    }
+    Ok((playlists, changed))
+}
+
+impl LibraryIndex {
+    /// Reads the whole library and every stored playlist from MPD.
+    pub fn load(mpd: &mut dyn MpdClient, now: SystemTime) -> Result<Self> {
+        let mut index = LibraryIndex::default();
+        let songs = mpd.listall()?;
+        index.reload(songs, mpd, now)?;
+        Ok(index)
+    }
+
+    /// Rebuilds the index around `songs` (a fresh `listall`), reloading only
+    /// the stored playlists MPD reports changed. The ones kept take their
+    /// songs' tags from `songs`.
+    pub fn reload(&mut self, songs: Vec<Song>, mpd: &mut dyn MpdClient, now: SystemTime) -> Result<()> {
+        let songs: BTreeMap<String, Song> = songs.into_iter().map(|song| (song.file.clone(), song)).collect();
+        let mut by_artist: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        let mut by_album: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        // in file order, whatever order MPD listed them in
+        for (file, song) in &songs {
+            if let Some(artist) = &song.artist {
+                by_artist.entry(artist.clone()).or_default().push(file.clone());
+            }
+            if let Some(album) = &song.album {
+                by_album.entry(album.clone()).or_default().push(file.clone());
+            }
+        }
+        let (mut playlists, _) = load_playlists(mpd, &self.playlists, now)?;
+        for song in playlists.values_mut().flat_map(|playlist| playlist.songs.iter_mut()) {
+            if let Some(current) = songs.get(&song.file) {
+                *song = current.clone();
+            }
+        }
+
+        self.songs = songs;
+        self.by_artist = by_artist;
+        self.by_album = by_album;
+        self.playlists = playlists;
+        self.built_at = Some(now);
+        self.playlists_at = Some(now);
+        self.revision += 1;
+        Ok(())
+    }
+
+    /// Reloads the stored playlists MPD reports changed, keeping the songs.
+    pub fn refresh_playlists(&mut self, mpd: &mut dyn MpdClient, now: SystemTime) -> Result<()> {
+        let (playlists, changed) = load_playlists(mpd, &self.playlists, now)?;
+        self.playlists = playlists;
+        self.playlists_at = Some(now);
+        if changed {
+            self.revision += 1;
+        }
+        Ok(())
+    }
+
+    /// Reloads one playlist after the server changed it, dropping it if it
+    /// no longer exists. Does nothing before the first build.
+    pub fn refresh_playlist(&mut self, name: &str, mpd: &mut dyn MpdClient, now: SystemTime) {
+        if !self.is_ready() {
+            return;
+        }
+        let listed = mpd.playlists().map(|playlists| playlists.into_iter().find(|p| p.name == name));
+        match listed {
+            Ok(Some(playlist)) => match mpd.playlist(name) {
+                Ok(songs) => {
+                    let stored = StoredPlaylist {
+                        songs,
+                        last_modified: playlist.last_modified,
+                        loaded_at: now,
+                    };
+                    self.playlists.insert(playlist.name, stored);
+                    self.revision += 1;
+                }
+                Err(e) => log::error!("[!] Failed to reload playlist {}: {}", name, e),
+            },
+            Ok(None) => {
+                self.playlists.remove(name);
+                self.revision += 1;
+            }
+            Err(e) => log::error!("[!] Failed to list playlists: {}", e),
+        }
+    }
+
+    /// Goes up with every change to the index, so callers can tell whether
+    /// what they read earlier may be out of date.
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.built_at.is_some()
+    }
+
+    /// Whether a built index is due for its periodic reload.
+    pub fn is_stale(&self, now: SystemTime) -> bool {
+        self.built_at
+            .is_some_and(|at| now.duration_since(at).unwrap_or_default() >= LIBRARY_REFRESH_INTERVAL)
+    }
+
+    pub fn song(&self, file: &str) -> Option<&Song> {
+        self.songs.get(file)
+    }
+
+    /// Every song, by file.
+    pub fn songs(&self) -> impl Iterator<Item = &Song> {
+        self.songs.values()
+    }
+
+    /// Songs by `artist`, in file order.
+    pub fn by_artist(&self, artist: &str) -> Vec<&Song> {
+        self.lookup(self.by_artist.get(artist))
+    }
+
+    /// Songs on `album`, in file order.
+    pub fn by_album(&self, album: &str) -> Vec<&Song> {
+        self.lookup(self.by_album.get(album))
+    }
+
+    fn lookup(&self, files: Option<&Vec<String>>) -> Vec<&Song> {
+        files
+            .into_iter()
+            .flatten()
+            .filter_map(|file| self.songs.get(file))
+            .collect()
+    }
+
+    pub fn playlist(&self, name: &str) -> Option<&[Song]> {
+        self.playlists.get(name).map(|playlist| playlist.songs.as_slice())
+    }
+
+    pub fn playlist_names(&self) -> impl Iterator<Item = &str> {
+        self.playlists.keys().map(String::as_str)
+    }
+
+    pub fn stats(&self, now: SystemTime) -> LibraryStats {
+        LibraryStats {
+            ready: self.is_ready(),
+            songs: self.songs.len(),
+            artists: self.by_artist.len(),
+            albums: self.by_album.len(),
+            playlists: self.playlists.len(),
+            playlist_entries: self.playlists.values().map(|playlist| playlist.songs.len()).sum(),
+            built_at: self.built_at.map(unix_secs),
+            playlists_refreshed_at: self.playlists_at.map(unix_secs),
+            age_secs: self.built_at.map(|at| now.duration_since(at).unwrap_or_default().as_secs()),
+        }
+    }
+
+    /// Songs matching `tag` by artist or album name, in file order.
+    pub fn by_artist_or_album(&self, tag: &str) -> Vec<&Song> {
+        let files: BTreeSet<&String> = self
+            .by_artist
+            .get(tag)
+            .into_iter()
+            .chain(self.by_album.get(tag))
+            .flatten()
+            .collect();
+        files.into_iter().filter_map(|file| self.songs.get(file)).collect()
+    }
+}
+
+/// Answers library reads (`listall`, `playlist`, `playlists`) from a
+/// `LibraryIndex` and passes everything else through to MPD. Until the
+/// index is built, reads go to MPD as well.
+pub struct IndexedMpd<'a> {
+    index: &'a LibraryIndex,
+    mpd: &'a mut dyn MpdClient,
+}
+
+impl<'a> IndexedMpd<'a> {
+    pub fn new(index: &'a LibraryIndex, mpd: &'a mut dyn MpdClient) -> Self {
+        IndexedMpd { index, mpd }
+    }
+}
+
+impl MpdClient for IndexedMpd<'_> {
+    fn ping(&mut self) -> Result<()> {
+        self.mpd.ping()
+    }
+
+    fn playlist(&mut self, name: &str) -> Result<Vec<Song>> {
+        if !self.index.is_ready() {
+            return self.mpd.playlist(name);
+        }
+        self.index
+            .playlist(name)
+            .map(<[Song]>::to_vec)
+            .ok_or_else(|| anyhow!("Playlist {} not found", name))
+    }
+
+    fn playlists(&mut self) -> Result<Vec<Playlist>> {
+        if !self.index.is_ready() {
+            return self.mpd.playlists();
+        }
+        Ok(self
+            .index
+            .playlists
+            .iter()
+            .map(|(name, playlist)| Playlist {
+                name: name.clone(),
+                last_modified: playlist.last_modified,
+            })
+            .collect())
+    }
+
+    fn queue(&mut self) -> Result<Vec<Song>> {
+        self.mpd.queue()
+    }
+
+    fn search(&mut self, query: &Query, window: Option<(u32, u32)>) -> Result<Vec<Song>> {
+        self.mpd.search(query, window)
+    }
+
+    fn consume(&mut self, state: bool) -> Result<()> {
+        self.mpd.consume(state)
+    }
+
+    fn push(&mut self, file: &str) -> Result<u32> {
+        self.mpd.push(file)
+    }
+
+    fn delete(&mut self, pos: u32) -> Result<()> {
+        self.mpd.delete(pos)
+    }
+
+    fn play(&mut self) -> Result<()> {
+        self.mpd.play()
+    }
+
+    fn set_volume(&mut self, volume: u32) -> Result<()> {
+        self.mpd.set_volume(volume)
+    }
+
+    fn pl_push(&mut self, playlist: &str, file: &str) -> Result<()> {
+        self.mpd.pl_push(playlist, file)
+    }
+
+    fn pl_delete(&mut self, playlist: &str, pos: u32) -> Result<()> {
+        self.mpd.pl_delete(playlist, pos)
+    }
+
+    fn pl_clear(&mut self, playlist: &str) -> Result<()> {
+        self.mpd.pl_clear(playlist)
+    }
+
+    fn pl_remove(&mut self, playlist: &str) -> Result<()> {
+        self.mpd.pl_remove(playlist)
+    }
+
+    fn listall(&mut self) -> Result<Vec<Song>> {
+        if !self.index.is_ready() {
+            return self.mpd.listall();
+        }
+        Ok(self.index.songs().cloned().collect())
+    }
+
+    fn idle(&mut self, subsystems: &[&str]) -> Result<Vec<String>> {
+        self.mpd.idle(subsystems)
+    }
+}
@@ -1,3 +1,12 @@
+pub mod cooldown;
 pub mod hashable_song;
+pub mod history;
+pub mod library_index;
+pub mod skip_log;
 pub mod song_queue;
+pub mod song_stats;
+pub mod station;
+pub mod tag_cache;
+pub mod tag_expr;
 pub mod tags_data;
+pub mod weighting;
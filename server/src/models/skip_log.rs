@@ -0,0 +1,106 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Default number of skips kept in memory before the oldest are dropped.
+pub const DEFAULT_SKIP_LOG_CAPACITY: usize = 1000;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "kebab-case")]
+pub enum SkipReason {
+    Dislike,
+    WrongMood,
+    BadRip,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SkipRecord {
+    pub file: String,
+    pub reason: Option<SkipReason>,
+    /// Seconds since the unix epoch.
+    pub at: u64,
+}
+
+/// How often a single track has been skipped, most-skipped first in `/skips`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SkipCount {
+    pub file: String,
+    pub count: usize,
+    pub reasons: HashMap<SkipReason, usize>,
+}
+
+/// Bounded, in-memory record of skipped tracks.
+#[derive(Debug)]
+pub struct SkipLog {
+    records: VecDeque<SkipRecord>,
+    capacity: usize,
+}
+
+impl Default for SkipLog {
+    fn default() -> Self {
+        Self::new(DEFAULT_SKIP_LOG_CAPACITY)
+    }
+}
+
+impl SkipLog {
+    pub fn new(capacity: usize) -> Self {
+        SkipLog {
+            records: VecDeque::new(),
+            capacity: capacity.max(1),
+        }
+    }
+
+    pub fn record(&mut self, file: &str, reason: Option<SkipReason>, at: SystemTime) -> SkipRecord {
+        let record = SkipRecord {
+            file: file.to_string(),
+            reason,
+            at: at.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+        };
+
+        if self.records.len() == self.capacity {
+            self.records.pop_front();
+        }
+        self.records.push_back(record.clone());
+        record
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    /// Most recent skips first, optionally only those with `reason`.
+    pub fn recent(&self, reason: Option<SkipReason>, limit: usize) -> Vec<SkipRecord> {
+        self.records
+            .iter()
+            .rev()
+            .filter(|r| reason.is_none() || r.reason == reason)
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+
+    /// Per-track skip totals, most skipped first, ties broken by file name.
+    pub fn counts(&self, reason: Option<SkipReason>) -> Vec<SkipCount> {
+        let mut by_file: HashMap<&str, SkipCount> = HashMap::new();
+
+        for r in self.records.iter().filter(|r| reason.is_none() || r.reason == reason) {
+            let entry = by_file.entry(&r.file).or_insert_with(|| SkipCount {
+                file: r.file.clone(),
+                count: 0,
+                reasons: HashMap::new(),
+            });
+            entry.count += 1;
+            if let Some(reason) = r.reason {
+                *entry.reasons.entry(reason).or_insert(0) += 1;
+            }
+        }
+
+        let mut counts: Vec<SkipCount> = by_file.into_values().collect();
+        counts.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.file.cmp(&b.file)));
+        counts
+    }
+}
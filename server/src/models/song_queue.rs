@@ -1,9 +1,16 @@
+use anyhow::Result;
 use log::debug;
-use rand::seq::SliceRandom;
-use std::collections::VecDeque;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet, VecDeque};
 
+use crate::models::cooldown::{space_artists, Cooldown, RecentPlays};
 use crate::models::hashable_song::HashableSong;
-use crate::mpd_conn::mpd_pool::PooledMpdConnection;
+use crate::models::tag_expr::TagExpr;
+use crate::models::tags_data::TagsData;
+use crate::models::weighting::{weighted_order, SongWeights, Weighting};
 use crate::mpd_conn::traits::{FilterTerm, MpdClient, Query, Song};
 
 pub enum DequeueMode {
@@ -11,33 +18,207 @@ pub enum DequeueMode {
     Album,
 }
 
+/// Where `SongQueue::insert` puts songs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InsertAt {
+    Front,
+    Back,
+}
+
+/// How `TagsData` names are resolved to songs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TagMatchMode {
+    /// Each tag is an MPD stored playlist; set operations run on membership.
+    #[default]
+    Playlist,
+    /// Legacy behaviour: a tag matches any song whose file, artist or album
+    /// contains it as a substring.
+    Substring,
+}
+
+impl std::str::FromStr for TagMatchMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "playlist" => Ok(TagMatchMode::Playlist),
+            "substring" => Ok(TagMatchMode::Substring),
+            other => Err(format!("unknown tag match mode `{}` (expected playlist or substring)", other)),
+        }
+    }
+}
+
+/// One album in the album-aware queue: the songs that matched the current
+/// tags, in tracklist order. On dequeue it expands to the full tracklist.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AlbumSeed {
+    pub songs: Vec<Song>,
+}
+
+impl AlbumSeed {
+    /// The song that stands for the album in `head`/`tail` listings.
+    pub fn first(&self) -> &Song {
+        &self.songs[0]
+    }
+}
+
+/// (album, AlbumArtist or Artist) per SPEC 0001: "Greatest Hits" by two
+/// bands are different albums, while a compilation's tracks share an
+/// AlbumArtist even though each has its own Artist. Songs without an album
+/// are keyed by file so each stands alone.
+fn album_key(song: &Song) -> (String, Option<String>) {
+    match &song.album {
+        Some(album) => (album.clone(), song.album_identity_artist().map(str::to_string)),
+        None => (song.file.clone(), None),
+    }
+}
+
+/// Disc then track; a missing disc counts as the first and untracked songs
+/// go last, in file order.
+fn sort_tracklist(songs: &mut Vec<Song>) {
+    songs.sort_by(|a, b| {
+        a.disc
+            .unwrap_or(1)
+            .cmp(&b.disc.unwrap_or(1))
+            .then_with(|| a.track.unwrap_or(u32::MAX).cmp(&b.track.unwrap_or(u32::MAX)))
+            .then_with(|| a.file.cmp(&b.file))
+    });
+    songs.dedup_by(|a, b| a.file == b.file);
+}
+
+/// Groups songs into album seeds, ordered by each album's first appearance.
+fn group_albums(songs: impl IntoIterator<Item = Song>) -> Vec<AlbumSeed> {
+    let mut index: HashMap<(String, Option<String>), usize> = HashMap::new();
+    let mut seeds: Vec<AlbumSeed> = Vec::new();
+
+    for song in songs {
+        match index.entry(album_key(&song)) {
+            Entry::Occupied(slot) => seeds[*slot.get()].songs.push(song),
+            Entry::Vacant(slot) => {
+                slot.insert(seeds.len());
+                seeds.push(AlbumSeed { songs: vec![song] });
+            }
+        }
+    }
+
+    for seed in &mut seeds {
+        sort_tracklist(&mut seed.songs);
+    }
+    seeds
+}
+
+/// The remaining queue in order, as saved across restarts.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct QueueSnapshot {
+    pub album_aware: bool,
+    pub songs: Vec<Song>,
+    pub albums: Vec<AlbumSeed>,
+}
+
+/// The internal play queue. In single mode it holds shuffled songs; when
+/// album-aware it holds one seed per album instead, so no album repeats
+/// until every album has been played.
 pub struct SongQueue {
     inner: VecDeque<Song>,
+    albums: VecDeque<AlbumSeed>,
     is_album_aware: bool,
+    tag_match_mode: TagMatchMode,
+    cooldown: Cooldown,
+    weighting: Weighting,
+    rng: StdRng,
+}
+
+impl Default for SongQueue {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl SongQueue {
     pub fn new() -> Self {
         SongQueue {
             inner: VecDeque::new(),
+            albums: VecDeque::new(),
             is_album_aware: false,
+            tag_match_mode: TagMatchMode::default(),
+            cooldown: Cooldown::default(),
+            weighting: Weighting::default(),
+            rng: StdRng::from_os_rng(),
         }
     }
 
+    /// Makes every shuffle from here on repeatable, for tests.
+    pub fn seed_rng(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    /// Switches between song and album-seed shapes, rebuilding whatever is
+    /// queued: songs are grouped into albums, or albums are split back into
+    /// shuffled songs.
     pub fn set_album_aware(&mut self, enabled: bool) {
+        if enabled == self.is_album_aware {
+            return;
+        }
         self.is_album_aware = enabled;
+
+        if enabled {
+            self.albums = group_albums(self.inner.drain(..)).into();
+        } else {
+            let songs: Vec<Song> = self.albums.drain(..).flat_map(|seed| seed.songs).collect();
+            self.add_songs(songs);
+        }
+    }
+
+    pub fn is_album_aware(&self) -> bool {
+        self.is_album_aware
+    }
+
+    pub fn set_tag_match_mode(&mut self, mode: TagMatchMode) {
+        self.tag_match_mode = mode;
     }
 
+    pub fn set_cooldown(&mut self, cooldown: Cooldown) {
+        self.cooldown = cooldown;
+    }
+
+    pub fn cooldown(&self) -> Cooldown {
+        self.cooldown
+    }
+
+    pub fn set_weighting(&mut self, weighting: Weighting) {
+        self.weighting = weighting;
+    }
+
+    pub fn weighting(&self) -> Weighting {
+        self.weighting
+    }
+
+    /// Queued entries: songs, or albums when album-aware.
     pub fn len(&self) -> usize {
-        self.inner.len()
+        if self.is_album_aware {
+            self.albums.len()
+        } else {
+            self.inner.len()
+        }
     }
 
     pub fn is_empty(&self) -> bool {
-        self.inner.is_empty()
+        self.len() == 0
+    }
+
+    /// Queued songs, counting every track of each album seed.
+    pub fn song_count(&self) -> usize {
+        if self.is_album_aware {
+            self.albums.iter().map(|seed| seed.songs.len()).sum()
+        } else {
+            self.inner.len()
+        }
     }
 
     pub fn clear(&mut self) {
         self.inner.clear();
+        self.albums.clear();
     }
 
     pub fn empty_queue(&mut self) {
@@ -45,88 +226,223 @@ impl SongQueue {
     }
 
     pub fn add_songs(&mut self, songs: Vec<Song>) {
-        let mut song_vec: Vec<Song> = songs.into_iter().collect();
-        let mut rng = rand::rng();
-        song_vec.shuffle(&mut rng);
-        for song in song_vec {
-            self.inner.push_back(song);
+        self.add_songs_after(songs, &RecentPlays::default(), &SongWeights::uniform());
+    }
+
+    /// Shuffles `songs` onto the back of the queue, heavier ones tending to
+    /// come first. Leaves out those still in the track cooldown and spaces
+    /// artists apart from each other, from what is already queued and from
+    /// `recent`.
+    pub fn add_songs_after(&mut self, songs: Vec<Song>, recent: &RecentPlays, weights: &SongWeights) {
+        let songs = recent.without_recent(songs);
+        let mut before = recent.artists.clone();
+        before.extend(self.entries().map(|song| song.artist.clone()));
+        let spacing = self.cooldown.artist_spacing;
+
+        if self.is_album_aware {
+            let seeds = weighted_order(group_albums(songs), |seed| weights.of_all(&seed.songs), &mut self.rng);
+            let seeds = space_artists(seeds, &before, spacing, |seed| seed.first().album_identity_artist());
+            self.albums.extend(seeds);
+        } else {
+            let songs = weighted_order(songs, |song| weights.of(song), &mut self.rng);
+            self.inner.extend(space_artists(songs, &before, spacing, |song| song.artist.as_deref()));
         }
     }
 
+    /// Appends `song`; when album-aware it joins its album's seed if one is
+    /// already queued.
     pub fn add(&mut self, song: Song) {
-        self.inner.push_back(song);
+        if !self.is_album_aware {
+            self.inner.push_back(song);
+            return;
+        }
+
+        let key = album_key(&song);
+        match self.albums.iter_mut().find(|seed| album_key(seed.first()) == key) {
+            Some(seed) => {
+                seed.songs.push(song);
+                sort_tracklist(&mut seed.songs);
+            }
+            None => self.albums.push_back(AlbumSeed { songs: vec![song] }),
+        }
     }
 
-    pub fn dequeue(
-        &mut self,
-        mode: DequeueMode,
-        pooled_conn: &mut PooledMpdConnection,
-    ) -> Vec<Song> {
+    /// Puts `songs` at the front or back in the order given, grouped into
+    /// album seeds when album-aware. Songs already queued are moved rather
+    /// than queued twice; when album-aware a queued album is merged into
+    /// the new seed. Returns how many entries were inserted.
+    pub fn insert(&mut self, songs: Vec<Song>, at: InsertAt) -> usize {
+        if self.is_album_aware {
+            let seeds: Vec<AlbumSeed> = group_albums(songs)
+                .into_iter()
+                .map(|mut seed| {
+                    let key = album_key(seed.first());
+                    if let Some(index) = self.albums.iter().position(|queued| album_key(queued.first()) == key) {
+                        let queued = self.albums.remove(index).expect("position is in range");
+                        seed.songs.extend(queued.songs);
+                        sort_tracklist(&mut seed.songs);
+                    }
+                    seed
+                })
+                .collect();
+            let inserted = seeds.len();
+            match at {
+                InsertAt::Front => seeds.into_iter().rev().for_each(|seed| self.albums.push_front(seed)),
+                InsertAt::Back => self.albums.extend(seeds),
+            }
+            return inserted;
+        }
+
+        let mut seen = HashSet::new();
+        let songs: Vec<Song> = songs.into_iter().filter(|s| seen.insert(s.file.clone())).collect();
+        self.inner.retain(|queued| !seen.contains(&queued.file));
+        let inserted = songs.len();
+        match at {
+            InsertAt::Front => songs.into_iter().rev().for_each(|song| self.inner.push_front(song)),
+            InsertAt::Back => self.inner.extend(songs),
+        }
+        inserted
+    }
+
+    /// Removes the entry at `index` (a song, or an album when album-aware)
+    /// and returns its songs.
+    pub fn remove_at(&mut self, index: usize) -> Option<Vec<Song>> {
+        if self.is_album_aware {
+            self.albums.remove(index).map(|seed| seed.songs)
+        } else {
+            self.inner.remove(index).map(|song| vec![song])
+        }
+    }
+
+    /// Moves the entry at `from` so it ends up at `to`, returning its songs.
+    /// `None` if either index is out of range.
+    pub fn move_entry(&mut self, from: usize, to: usize) -> Option<Vec<Song>> {
+        if from >= self.len() || to >= self.len() {
+            return None;
+        }
+        if self.is_album_aware {
+            let seed = self.albums.remove(from)?;
+            let songs = seed.songs.clone();
+            self.albums.insert(to, seed);
+            Some(songs)
+        } else {
+            let song = self.inner.remove(from)?;
+            self.inner.insert(to, song.clone());
+            Some(vec![song])
+        }
+    }
+
+    /// Shuffles what is queued, weighted and with artists kept apart as
+    /// when it was filled; albums keep their tracklists.
+    pub fn reshuffle(&mut self, recent: &RecentPlays, weights: &SongWeights) {
+        let spacing = self.cooldown.artist_spacing;
+        if self.is_album_aware {
+            let seeds: Vec<AlbumSeed> = self.albums.drain(..).collect();
+            let seeds = weighted_order(seeds, |seed| weights.of_all(&seed.songs), &mut self.rng);
+            self.albums = space_artists(seeds, &recent.artists, spacing, |seed| seed.first().album_identity_artist()).into();
+        } else {
+            let songs: Vec<Song> = self.inner.drain(..).collect();
+            let songs = weighted_order(songs, |song| weights.of(song), &mut self.rng);
+            self.inner = space_artists(songs, &recent.artists, spacing, |song| song.artist.as_deref()).into();
+        }
+    }
+
+    /// The tracklist of `album` from MPD, optionally only `artist`'s (by
+    /// AlbumArtist, falling back to Artist).
+    pub fn album_songs(album: &str, artist: Option<&str>, mpd: &mut dyn MpdClient) -> Result<Vec<Song>> {
+        let mut query = Query::new();
+        query.and(FilterTerm::Tag("album".into(), album.to_string()));
+
+        let mut songs: Vec<Song> = mpd
+            .search(&query, None)?
+            .into_iter()
+            .filter(|s| artist.is_none() || s.album_identity_artist() == artist)
+            .collect();
+        sort_tracklist(&mut songs);
+        Ok(songs)
+    }
+
+    pub fn snapshot(&self) -> QueueSnapshot {
+        QueueSnapshot {
+            album_aware: self.is_album_aware,
+            songs: self.inner.iter().cloned().collect(),
+            albums: self.albums.iter().cloned().collect(),
+        }
+    }
+
+    /// Replaces the queue with `snapshot`, keeping its order and shape.
+    pub fn restore(&mut self, snapshot: QueueSnapshot) {
+        self.is_album_aware = snapshot.album_aware;
+        self.inner = snapshot.songs.into();
+        self.albums = snapshot
+            .albums
+            .into_iter()
+            .filter(|seed| !seed.songs.is_empty())
+            .collect();
+    }
+
+    /// The queued album seeds, front first (empty unless album-aware).
+    pub fn albums(&self) -> impl Iterator<Item = &AlbumSeed> {
+        self.albums.iter()
+    }
+
+    pub fn dequeue(&mut self, mode: DequeueMode, mpd: &mut dyn MpdClient) -> Vec<Song> {
         match mode {
             DequeueMode::Single => self.dequeue_single(),
-            DequeueMode::Album => self.dequeue_as_album(pooled_conn),
+            DequeueMode::Album => self.dequeue_as_album(mpd),
         }
     }
 
     pub fn dequeue_single(&mut self) -> Vec<Song> {
-        match self.inner.pop_front() {
+        match self.remove() {
             Some(song) => vec![song],
             None => vec![],
         }
     }
 
+    /// Pops the next song; when album-aware, the next track of the front
+    /// album.
     pub fn remove(&mut self) -> Option<Song> {
-        self.inner.pop_front()
+        if !self.is_album_aware {
+            return self.inner.pop_front();
+        }
+
+        let seed = self.albums.front_mut()?;
+        let song = seed.songs.remove(0);
+        if seed.songs.is_empty() {
+            self.albums.pop_front();
+        }
+        Some(song)
     }
 
-    pub fn dequeue_as_album(
-        &mut self,
-        pooled_conn: &mut PooledMpdConnection,
-    ) -> Vec<Song> {
+    pub fn dequeue_as_album(&mut self, mpd: &mut dyn MpdClient) -> Vec<Song> {
+        if self.is_album_aware {
+            return match self.albums.pop_front() {
+                Some(seed) => {
+                    let tracklist = Self::album_tracklist(seed.first(), mpd);
+                    if tracklist.is_empty() {
+                        seed.songs
+                    } else {
+                        tracklist
+                    }
+                }
+                None => vec![],
+            };
+        }
+
+        // single-shape queue: expand the next song and drop the rest of its
+        // album from the queue
         let first_song = match self.inner.pop_front() {
             Some(s) => s,
             None => return vec![],
         };
 
-        let album_name = match &first_song.album {
-            Some(a) => a,
-            None => return vec![first_song],
-        };
-
-        debug!("Dequeuing album: {}", album_name);
-
-        let album_songs: Vec<Song> = {
-            let mut query = Query::new();
-            query.and(FilterTerm::Tag("album".into(), album_name.clone()));
-
-            let search_results = pooled_conn
-                .mpd_conn()
-                .mpd
-                .search(&query, None)
-                .unwrap_or_default();
-
-            search_results
-                .into_iter()
-                .filter(|s| {
-                    let album_match = s.album.as_deref() == Some(album_name);
-                    let artist_match = if let (Some(ref a1), Some(ref a2)) = (&s.artist, &first_song.artist) {
-                        a1 == a2
-                    } else {
-                        true
-                    };
-                    album_match && artist_match
-                })
-                .collect()
-        };
-
+        let album_songs = Self::album_tracklist(&first_song, mpd);
         if album_songs.is_empty() {
             return vec![first_song];
         }
 
-        let mut album_songs_sorted = album_songs;
-        album_songs_sorted.sort_by(|a, b| a.pos.cmp(&b.pos));
-
-        let hashable_album_songs: Vec<HashableSong> = album_songs_sorted
+        let hashable_album_songs: Vec<HashableSong> = album_songs
             .iter()
             .cloned()
             .map(HashableSong::from)
@@ -137,60 +453,175 @@ impl SongQueue {
             !hashable_album_songs.contains(&hs)
         });
 
-        album_songs_sorted
+        album_songs
+    }
+
+    /// The full, sorted tracklist of `song`'s album from MPD, or nothing if
+    /// the song has no album or the search fails.
+    fn album_tracklist(song: &Song, mpd: &mut dyn MpdClient) -> Vec<Song> {
+        let album_name = match &song.album {
+            Some(a) => a,
+            None => return vec![],
+        };
+
+        debug!("Dequeuing album: {}", album_name);
+
+        let key = album_key(song);
+        let mut query = Query::new();
+        query.and(FilterTerm::Tag("album".into(), album_name.clone()));
+
+        let mut album_songs: Vec<Song> = mpd
+            .search(&query, None)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|s| {
+                let candidate = album_key(s);
+                // an untagged artist on either side is not a mismatch
+                candidate.0 == key.0 && (candidate.1.is_none() || key.1.is_none() || candidate.1 == key.1)
+            })
+            .collect();
+
+        sort_tracklist(&mut album_songs);
+        album_songs
     }
 
     pub fn head(&self, count: Option<usize>) -> Vec<Song> {
         let n = count.unwrap_or(10);
-        self.inner.iter().take(n).cloned().collect()
+        self.entries().take(n).cloned().collect()
     }
 
     pub fn tail(&self, count: Option<usize>) -> Vec<Song> {
         let n = count.unwrap_or(10);
-        let start = if self.inner.len() > n {
-            self.inner.len() - n
+        let start = self.len().saturating_sub(n);
+        self.entries().skip(start).cloned().collect()
+    }
+
+    /// Queued songs, or the first song of each album when album-aware.
+    fn entries(&self) -> Box<dyn Iterator<Item = &Song> + '_> {
+        if self.is_album_aware {
+            Box::new(self.albums.iter().map(AlbumSeed::first))
+        } else {
+            Box::new(self.inner.iter())
+        }
+    }
+
+    pub fn shuffle_and_add(&mut self, tags: &TagsData, mpd: &mut dyn MpdClient) {
+        self.shuffle_and_add_after(tags, mpd, &RecentPlays::default(), &SongWeights::uniform());
+    }
+
+    /// Adds the songs matching `tags`, ordered by `weights` with the
+    /// cooldown applied against `recent` (see `add_songs_after`).
+    pub fn shuffle_and_add_after(
+        &mut self,
+        tags: &TagsData,
+        mpd: &mut dyn MpdClient,
+        recent: &RecentPlays,
+        weights: &SongWeights,
+    ) {
+        let filtered_songs = if tags.expr.is_some() {
+            match tags.to_expr() {
+                Ok(expr) => Self::resolve_expr(&expr, self.tag_match_mode, mpd),
+                Err(e) => {
+                    log::error!("[!] Not queueing anything: {}", e);
+                    Vec::new()
+                }
+            }
         } else {
-            0
+            match self.tag_match_mode {
+                TagMatchMode::Playlist => Self::resolve_playlist_tags(tags, mpd),
+                TagMatchMode::Substring => Self::resolve_substring_tags(tags, mpd),
+            }
         };
-        self.inner.iter().skip(start).cloned().collect()
+
+        debug!("Found {} songs matching tags", filtered_songs.len());
+        self.add_songs_after(filtered_songs, recent, weights);
     }
 
-    pub fn invalidate_cache(&mut self) {
-        // Implementation for cache invalidation if we had one
+    /// (union of `any` playlists) minus (union of `not` playlists).
+    /// An empty `any` list selects the whole library.
+    fn resolve_playlist_tags(tags: &TagsData, mpd: &mut dyn MpdClient) -> Vec<Song> {
+        let included: HashSet<HashableSong> = if tags.any.is_empty() {
+            mpd.listall()
+                .unwrap_or_default()
+                .into_iter()
+                .map(HashableSong::from)
+                .collect()
+        } else {
+            Self::playlist_union(&tags.any, mpd)
+        };
+        let excluded = Self::playlist_union(&tags.not, mpd);
+
+        included
+            .into_iter()
+            .filter(|s| !excluded.contains(s))
+            .map(Song::from)
+            .collect()
     }
 
-    pub fn cache_stats(&self) -> (usize, usize, f64) {
-        (0, 0, 0.0) // Dummy for tests
+    fn playlist_union(names: &[String], mpd: &mut dyn MpdClient) -> HashSet<HashableSong> {
+        let mut songs = HashSet::new();
+        for name in names {
+            match mpd.playlist(name) {
+                Ok(playlist) => songs.extend(playlist.into_iter().map(HashableSong::from)),
+                Err(e) => debug!("Tag {} did not resolve to a playlist: {}", name, e),
+            }
+        }
+        songs
     }
 
-    pub fn shuffle_and_add(&mut self, tags: &crate::models::tags_data::TagsData, mpd: &mut dyn MpdClient) {
+    fn resolve_substring_tags(tags: &TagsData, mpd: &mut dyn MpdClient) -> Vec<Song> {
         let all_songs = mpd.listall().unwrap_or_default();
-        
-        // Filter by tags
-        let filtered_songs: Vec<Song> = all_songs.into_iter().filter(|s| {
+
+        all_songs.into_iter().filter(|s| {
             // Must match ANY of the "any" tags
-            let matches_any = if tags.any.is_empty() {
-                true
-            } else {
-                tags.any.iter().any(|t| {
-                    s.file.contains(t) || 
-                    s.artist.as_ref().map_or(false, |a| a.contains(t)) ||
-                    s.album.as_ref().map_or(false, |a| a.contains(t))
-                })
-            };
+            let matches_any = tags.any.is_empty() || tags.any.iter().any(|t| Self::substring_match(s, t));
 
             // Must NOT match ANY of the "not" tags
-            let matches_not = tags.not.iter().any(|t| {
-                s.file.contains(t) || 
-                s.artist.as_ref().map_or(false, |a| a.contains(t)) ||
-                s.album.as_ref().map_or(false, |a| a.contains(t))
-            });
+            let matches_not = tags.not.iter().any(|t| Self::substring_match(s, t));
 
             matches_any && !matches_not
-        }).collect();
+        }).collect()
+    }
 
-        debug!("Found {} songs matching tags", filtered_songs.len());
-        self.add_songs(filtered_songs);
+    fn substring_match(song: &Song, tag: &str) -> bool {
+        song.file.contains(tag)
+            || song.artist.as_ref().is_some_and(|a| a.contains(tag))
+            || song.album.as_ref().is_some_and(|a| a.contains(tag))
+    }
+
+    /// Evaluates `expr` over the whole library, with tags resolved the
+    /// same way as `any`/`not` in `mode`. Songs that are only in a tag
+    /// playlist (not in the database listing) are considered too.
+    fn resolve_expr(expr: &TagExpr, mode: TagMatchMode, mpd: &mut dyn MpdClient) -> Vec<Song> {
+        let mut songs = mpd.listall().unwrap_or_default();
+        if mode == TagMatchMode::Substring {
+            return songs
+                .into_iter()
+                .filter(|s| expr.matches(s, &|tag, song| Self::substring_match(song, tag)))
+                .collect();
+        }
+
+        let mut members: HashMap<String, HashSet<String>> = HashMap::new();
+        let mut seen: HashSet<String> = songs.iter().map(|s| s.file.clone()).collect();
+        for name in expr.tag_names() {
+            let playlist = match mpd.playlist(name) {
+                Ok(playlist) => playlist,
+                Err(e) => {
+                    debug!("Tag {} did not resolve to a playlist: {}", name, e);
+                    Vec::new()
+                }
+            };
+            let files = playlist.iter().map(|s| s.file.clone()).collect();
+            for song in playlist {
+                if seen.insert(song.file.clone()) {
+                    songs.push(song);
+                }
+            }
+            members.insert(name.to_string(), files);
+        }
+
+        let has_tag = |tag: &str, song: &Song| members.get(tag).is_some_and(|files| files.contains(&song.file));
+        songs.into_iter().filter(|s| expr.matches(s, &has_tag)).collect()
     }
 }
 
@@ -201,12 +632,7 @@ mod tests {
     fn create_test_song(path: &str) -> Song {
         Song {
             file: path.to_string(),
-            title: None,
-            artist: None,
-            album: None,
-            duration: None,
-            pos: None,
-            id: None,
+            ..Default::default()
         }
     }
 
@@ -0,0 +1,75 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+
+use crate::models::history::HistoryEntry;
+
+/// Allowed star ratings.
+pub const RATING_RANGE: RangeInclusive<u8> = 1..=5;
+
+/// What jukectl knows about how well a song goes down: an explicit rating
+/// and how its plays ended.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct SongStats {
+    /// 1 to 5 stars, `None` until rated.
+    pub rating: Option<u8>,
+    /// Plays that ran until the next song took over.
+    pub plays: u32,
+    pub skips: u32,
+    /// When the song last started, in seconds since the unix epoch.
+    pub last_played: Option<u64>,
+}
+
+/// Per-song stats keyed by file. Fed by the play history and saved with the
+/// rest of the state, so the counts outlive the bounded history.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(transparent)]
+pub struct PlayStats {
+    songs: HashMap<String, SongStats>,
+}
+
+impl PlayStats {
+    /// `file`'s stats; all zero for a song never played nor rated.
+    pub fn get(&self, file: &str) -> SongStats {
+        self.songs.get(file).copied().unwrap_or_default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.songs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.songs.is_empty()
+    }
+
+    /// Counts a finished play (or skip) from the history.
+    pub fn record(&mut self, entry: &HistoryEntry) {
+        let stats = self.songs.entry(entry.file.clone()).or_default();
+        if entry.skipped {
+            stats.skips += 1;
+        } else {
+            stats.plays += 1;
+        }
+        stats.last_played = stats.last_played.max(Some(entry.started_at));
+    }
+
+    /// Sets or clears `file`'s rating, returning its stats afterwards.
+    pub fn rate(&mut self, file: &str, rating: Option<u8>) -> Result<SongStats, String> {
+        if let Some(rating) = rating.filter(|r| !RATING_RANGE.contains(r)) {
+            return Err(format!(
+                "rating must be between {} and {}, not {}",
+                RATING_RANGE.start(),
+                RATING_RANGE.end(),
+                rating
+            ));
+        }
+        let stats = self.songs.entry(file.to_string()).or_default();
+        stats.rating = rating;
+        let stats = *stats;
+        if stats == SongStats::default() {
+            self.songs.remove(file);
+        }
+        Ok(stats)
+    }
+}
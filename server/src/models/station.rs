@@ -0,0 +1,140 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::models::tags_data::TagsData;
+
+/// A named mood: the tags to play, whether to play whole albums, and
+/// optionally the volume to switch MPD to.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Station {
+    #[serde(flatten)]
+    pub tags: TagsData,
+    #[serde(default)]
+    pub album_aware: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub volume: Option<u32>,
+}
+
+impl Station {
+    pub fn new(tags: TagsData) -> Self {
+        Station {
+            tags,
+            album_aware: false,
+            volume: None,
+        }
+    }
+
+    /// Drops blank tag names and checks the station can play something.
+    pub fn validated(self) -> Result<Self, String> {
+        let station = Station {
+            tags: self.tags.without_blanks(),
+            ..self
+        };
+        if station.tags.any.is_empty() && station.tags.expr.is_none() {
+            return Err("a station needs at least one tag in `any` or an `expr`".to_string());
+        }
+        station.tags.to_expr()?;
+        if station.volume.is_some_and(|volume| volume > 100) {
+            return Err("volume must be between 0 and 100".to_string());
+        }
+        Ok(station)
+    }
+}
+
+/// A station as listed by the API, with its name.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StationInfo {
+    pub name: String,
+    #[serde(flatten)]
+    pub station: Station,
+}
+
+/// Every known station by name, plus the one last activated. Activating
+/// anything else (such as posting tags directly) clears `active`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Stations {
+    stations: BTreeMap<String, Station>,
+    active: Option<String>,
+    /// Names deleted at runtime, kept so a preset from the config file stays
+    /// deleted after a restart.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    removed: BTreeSet<String>,
+}
+
+impl Stations {
+    pub fn new(stations: BTreeMap<String, Station>) -> Self {
+        Stations {
+            stations,
+            active: None,
+            removed: BTreeSet::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.stations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stations.is_empty()
+    }
+
+    /// All stations, by name.
+    pub fn list(&self) -> Vec<StationInfo> {
+        self.stations
+            .iter()
+            .map(|(name, station)| StationInfo {
+                name: name.clone(),
+                station: station.clone(),
+            })
+            .collect()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Station> {
+        self.stations.get(name)
+    }
+
+    /// Adds or replaces `name`, returning the previous station if any.
+    pub fn insert(&mut self, name: &str, station: Station) -> Result<Option<Station>, String> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err("station name must not be empty".to_string());
+        }
+        let station = station.validated()?;
+        self.removed.remove(name);
+        Ok(self.stations.insert(name.to_string(), station))
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Station> {
+        let removed = self.stations.remove(name)?;
+        self.removed.insert(name.to_string());
+        if self.active.as_deref() == Some(name) {
+            self.active = None;
+        }
+        Some(removed)
+    }
+
+    pub fn active(&self) -> Option<StationInfo> {
+        let name = self.active.as_ref()?;
+        self.stations.get(name).map(|station| StationInfo {
+            name: name.clone(),
+            station: station.clone(),
+        })
+    }
+
+    /// Marks `name` as playing; `None` (or an unknown name) clears it.
+    pub fn set_active(&mut self, name: Option<&str>) {
+        self.active = name.filter(|name| self.stations.contains_key(*name)).map(str::to_string);
+    }
+
+    /// Layers stations saved at runtime over these (the config file's), so
+    /// API edits and deletions win and presets added to the file still show
+    /// up.
+    pub fn merge_saved(&mut self, saved: Stations) {
+        for name in &saved.removed {
+            self.stations.remove(name);
+        }
+        self.removed.extend(saved.removed);
+        self.stations.extend(saved.stations);
+        self.set_active(saved.active.as_deref());
+    }
+}
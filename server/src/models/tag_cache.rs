@@ -0,0 +1,75 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::models::tags_data::{TagInfo, TagsResponse};
+use crate::mpd_conn::traits::MpdClient;
+
+/// Longest a snapshot is trusted, in case a change is missed (for example
+/// while the scheduler polls because `idle` is unavailable).
+pub const TAG_CACHE_MAX_AGE: Duration = Duration::from_secs(10 * 60);
+
+/// What `GET /tags` and `GET /tags/available` report, computed together
+/// since both need every playlist's track count.
+#[derive(Debug, Clone)]
+pub struct TagSnapshot {
+    pub tags: TagsResponse,
+    /// By track count, most first.
+    pub available: Vec<TagInfo>,
+}
+
+impl TagSnapshot {
+    /// Reads the whole library and every playlist from MPD.
+    pub fn load(mpd: &mut dyn MpdClient) -> anyhow::Result<Self> {
+        let songs = mpd.listall()?;
+        let mut available: Vec<TagInfo> = mpd
+            .playlists()?
+            .into_iter()
+            .map(|p| {
+                let track_count = mpd.playlist(&p.name).map(|s| s.len()).unwrap_or(0);
+                TagInfo { name: p.name, track_count }
+            })
+            .collect();
+        available.sort_by(|a, b| b.track_count.cmp(&a.track_count).then_with(|| a.name.cmp(&b.name)));
+
+        Ok(TagSnapshot {
+            tags: TagsResponse::to_api_response(songs, &available),
+            available,
+        })
+    }
+}
+
+/// The last `TagSnapshot`, kept until the library index is refreshed after
+/// MPD reports a change to the database or stored playlists. `generation`
+/// guards against storing a snapshot that was being computed while such a
+/// change came in.
+#[derive(Debug, Default)]
+pub struct TagCache {
+    generation: u64,
+    snapshot: Option<(Arc<TagSnapshot>, Instant)>,
+}
+
+impl TagCache {
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub fn get(&self) -> Option<Arc<TagSnapshot>> {
+        match &self.snapshot {
+            Some((snapshot, at)) if at.elapsed() < TAG_CACHE_MAX_AGE => Some(snapshot.clone()),
+            _ => None,
+        }
+    }
+
+    /// Keeps `snapshot` unless the cache was invalidated since
+    /// `generation` was read.
+    pub fn store(&mut self, generation: u64, snapshot: Arc<TagSnapshot>) {
+        if generation == self.generation {
+            self.snapshot = Some((snapshot, Instant::now()));
+        }
+    }
+
+    pub fn invalidate(&mut self) {
+        self.generation += 1;
+        self.snapshot = None;
+    }
+}
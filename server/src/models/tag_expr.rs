@@ -0,0 +1,470 @@
+use std::collections::BTreeSet;
+use std::fmt;
+
+use crate::mpd_conn::traits::{parse_tag_number, Song};
+
+/// A boolean expression over tags and song metadata, such as
+/// `(chill and instrumental) or ambient and not explicit` or
+/// `artist:"Pink Floyd" and year>=1990`.
+///
+/// `not` binds tighter than `and`, which binds tighter than `or`. Keywords
+/// are case-insensitive; quote a tag whose name is a keyword or contains
+/// spaces or operator characters.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TagExpr {
+    /// Matches every song.
+    All,
+    /// Membership of a tag (in playlist mode, an MPD stored playlist).
+    Tag(String),
+    Field(FieldTest),
+    Not(Box<TagExpr>),
+    And(Vec<TagExpr>),
+    Or(Vec<TagExpr>),
+}
+
+/// A song metadata field usable in a predicate.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Field {
+    Artist,
+    AlbumArtist,
+    Album,
+    Title,
+    Genre,
+    Composer,
+    Year,
+    Track,
+    Disc,
+}
+
+const FIELD_NAMES: [(&str, Field); 9] = [
+    ("artist", Field::Artist),
+    ("albumartist", Field::AlbumArtist),
+    ("album", Field::Album),
+    ("title", Field::Title),
+    ("genre", Field::Genre),
+    ("composer", Field::Composer),
+    ("year", Field::Year),
+    ("track", Field::Track),
+    ("disc", Field::Disc),
+];
+
+impl Field {
+    fn named(name: &str) -> Option<Field> {
+        FIELD_NAMES
+            .iter()
+            .find(|(field_name, _)| name.eq_ignore_ascii_case(field_name))
+            .map(|(_, field)| *field)
+    }
+
+    fn name(self) -> &'static str {
+        FIELD_NAMES
+            .iter()
+            .find(|(_, field)| *field == self)
+            .map(|(name, _)| *name)
+            .expect("every field is named")
+    }
+
+    fn is_numeric(self) -> bool {
+        matches!(self, Field::Year | Field::Track | Field::Disc)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompareOp {
+    /// `:` or `=`; text compares ignoring case.
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl CompareOp {
+    fn symbol(self) -> &'static str {
+        match self {
+            CompareOp::Eq => ":",
+            CompareOp::Ne => "!=",
+            CompareOp::Lt => "<",
+            CompareOp::Le => "<=",
+            CompareOp::Gt => ">",
+            CompareOp::Ge => ">=",
+        }
+    }
+}
+
+/// `field op value`. Text fields support `:`, `=` and `!=`; numeric ones
+/// (year, track, disc) all comparisons.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FieldTest {
+    pub field: Field,
+    pub op: CompareOp,
+    pub value: String,
+}
+
+impl FieldTest {
+    fn matches(&self, song: &Song) -> bool {
+        if self.field.is_numeric() {
+            let actual = match self.field {
+                Field::Year => song.date.as_deref().and_then(parse_tag_number),
+                Field::Track => song.track,
+                _ => song.disc,
+            };
+            let (Some(actual), Ok(wanted)) = (actual, self.value.parse::<u32>()) else {
+                return false;
+            };
+            return match self.op {
+                CompareOp::Eq => actual == wanted,
+                CompareOp::Ne => actual != wanted,
+                CompareOp::Lt => actual < wanted,
+                CompareOp::Le => actual <= wanted,
+                CompareOp::Gt => actual > wanted,
+                CompareOp::Ge => actual >= wanted,
+            };
+        }
+
+        let actual = match self.field {
+            Field::Artist => song.artist.as_deref(),
+            Field::AlbumArtist => song.album_identity_artist(),
+            Field::Album => song.album.as_deref(),
+            Field::Title => song.title.as_deref(),
+            Field::Genre => song.genre.as_deref(),
+            _ => song.composer.as_deref(),
+        };
+        let equal = actual.is_some_and(|actual| actual.eq_ignore_ascii_case(&self.value));
+        match self.op {
+            CompareOp::Ne => !equal,
+            _ => equal,
+        }
+    }
+}
+
+impl TagExpr {
+    pub fn parse(input: &str) -> Result<TagExpr, String> {
+        let tokens = tokenize(input)?;
+        if tokens.is_empty() {
+            return Err("expression is empty".to_string());
+        }
+        let mut parser = Parser { tokens, pos: 0, len: input.chars().count() };
+        let expr = parser.or()?;
+        match parser.peek() {
+            None => Ok(expr),
+            Some(token) => Err(format!("column {}: unexpected {}", token.column, token.kind)),
+        }
+    }
+
+    /// Every tag name the expression refers to.
+    pub fn tag_names(&self) -> BTreeSet<&str> {
+        let mut names = BTreeSet::new();
+        self.collect_tag_names(&mut names);
+        names
+    }
+
+    fn collect_tag_names<'a>(&'a self, names: &mut BTreeSet<&'a str>) {
+        match self {
+            TagExpr::Tag(name) => {
+                names.insert(name);
+            }
+            TagExpr::Not(inner) => inner.collect_tag_names(names),
+            TagExpr::And(terms) | TagExpr::Or(terms) => {
+                terms.iter().for_each(|term| term.collect_tag_names(names))
+            }
+            TagExpr::All | TagExpr::Field(_) => {}
+        }
+    }
+
+    /// Whether `song` matches, with `has_tag` deciding tag membership.
+    pub fn matches(&self, song: &Song, has_tag: &dyn Fn(&str, &Song) -> bool) -> bool {
+        match self {
+            TagExpr::All => true,
+            TagExpr::Tag(name) => has_tag(name, song),
+            TagExpr::Field(test) => test.matches(song),
+            TagExpr::Not(inner) => !inner.matches(song, has_tag),
+            TagExpr::And(terms) => terms.iter().all(|term| term.matches(song, has_tag)),
+            TagExpr::Or(terms) => terms.iter().any(|term| term.matches(song, has_tag)),
+        }
+    }
+
+    /// `(a or b) and not (c or d)`: what `any`/`not` lists mean.
+    pub fn from_lists(any: &[String], not: &[String]) -> TagExpr {
+        let union = |names: &[String]| -> TagExpr {
+            let mut terms: Vec<TagExpr> = names.iter().map(|name| TagExpr::Tag(name.clone())).collect();
+            if terms.len() == 1 {
+                terms.remove(0)
+            } else {
+                TagExpr::Or(terms)
+            }
+        };
+
+        match (any.is_empty(), not.is_empty()) {
+            (true, true) => TagExpr::All,
+            (false, true) => union(any),
+            (true, false) => TagExpr::Not(Box::new(union(not))),
+            (false, false) => TagExpr::And(vec![union(any), TagExpr::Not(Box::new(union(not)))]),
+        }
+    }
+}
+
+fn write_term(f: &mut fmt::Formatter<'_>, term: &TagExpr) -> fmt::Result {
+    match term {
+        TagExpr::And(_) | TagExpr::Or(_) => write!(f, "({})", term),
+        _ => write!(f, "{}", term),
+    }
+}
+
+fn write_name(f: &mut fmt::Formatter<'_>, name: &str) -> fmt::Result {
+    let bare = !name.is_empty()
+        && name.chars().all(|c| !c.is_whitespace() && !SPECIAL.contains(c))
+        && !KEYWORDS.iter().any(|keyword| name.eq_ignore_ascii_case(keyword));
+    if bare {
+        write!(f, "{}", name)
+    } else {
+        write!(f, "\"{}\"", name.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+}
+
+impl fmt::Display for TagExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TagExpr::All => write!(f, "*"),
+            TagExpr::Tag(name) => write_name(f, name),
+            TagExpr::Field(test) => {
+                write!(f, "{}{}", test.field.name(), test.op.symbol())?;
+                write_name(f, &test.value)
+            }
+            TagExpr::Not(inner) => {
+                write!(f, "not ")?;
+                write_term(f, inner)
+            }
+            TagExpr::And(terms) | TagExpr::Or(terms) => {
+                let joiner = if matches!(self, TagExpr::And(_)) { " and " } else { " or " };
+                for (i, term) in terms.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "{}", joiner)?;
+                    }
+                    write_term(f, term)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+const KEYWORDS: [&str; 3] = ["and", "or", "not"];
+
+/// Characters that end a bare word.
+const SPECIAL: &str = "()\":=!<>*";
+
+#[derive(Clone, Debug, PartialEq)]
+enum TokenKind {
+    Word(String),
+    Quoted(String),
+    Op(CompareOp),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+    Star,
+}
+
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenKind::Word(word) => write!(f, "`{}`", word),
+            TokenKind::Quoted(text) => write!(f, "\"{}\"", text),
+            TokenKind::Op(op) => write!(f, "`{}`", op.symbol()),
+            TokenKind::And => write!(f, "`and`"),
+            TokenKind::Or => write!(f, "`or`"),
+            TokenKind::Not => write!(f, "`not`"),
+            TokenKind::Open => write!(f, "`(`"),
+            TokenKind::Close => write!(f, "`)`"),
+            TokenKind::Star => write!(f, "`*`"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Token {
+    kind: TokenKind,
+    /// 1-based, in characters.
+    column: usize,
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let column = i + 1;
+        let kind = match c {
+            c if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            '(' => TokenKind::Open,
+            ')' => TokenKind::Close,
+            '*' => TokenKind::Star,
+            ':' | '=' => TokenKind::Op(CompareOp::Eq),
+            '!' | '<' | '>' => {
+                let with_equals = chars.get(i + 1) == Some(&'=');
+                let op = match (c, with_equals) {
+                    ('!', true) => CompareOp::Ne,
+                    ('<', true) => CompareOp::Le,
+                    ('<', false) => CompareOp::Lt,
+                    ('>', true) => CompareOp::Ge,
+                    ('>', false) => CompareOp::Gt,
+                    _ => return Err(format!("column {}: expected `!=`, use `not` to negate", column)),
+                };
+                if with_equals {
+                    i += 1;
+                }
+                TokenKind::Op(op)
+            }
+            '"' => {
+                let mut text = String::new();
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        None => return Err(format!("column {}: unterminated quote", column)),
+                        Some('"') => break,
+                        Some('\\') if i + 1 < chars.len() => {
+                            text.push(chars[i + 1]);
+                            i += 2;
+                        }
+                        Some(c) => {
+                            text.push(*c);
+                            i += 1;
+                        }
+                    }
+                }
+                TokenKind::Quoted(text)
+            }
+            _ => {
+                let start = i;
+                while i < chars.len() && !chars[i].is_whitespace() && !SPECIAL.contains(chars[i]) {
+                    i += 1;
+                }
+                let word: String = chars[start..i].iter().collect();
+                tokens.push(Token {
+                    kind: match word.to_lowercase().as_str() {
+                        "and" => TokenKind::And,
+                        "or" => TokenKind::Or,
+                        "not" => TokenKind::Not,
+                        _ => TokenKind::Word(word),
+                    },
+                    column,
+                });
+                continue;
+            }
+        };
+        tokens.push(Token { kind, column });
+        i += 1;
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    /// Input length, for errors at the end.
+    len: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat(&mut self, kind: &TokenKind) -> bool {
+        if self.peek().is_some_and(|token| token.kind == *kind) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    fn or(&mut self) -> Result<TagExpr, String> {
+        let mut terms = vec![self.and()?];
+        while self.eat(&TokenKind::Or) {
+            terms.push(self.and()?);
+        }
+        Ok(if terms.len() == 1 { terms.remove(0) } else { TagExpr::Or(terms) })
+    }
+
+    fn and(&mut self) -> Result<TagExpr, String> {
+        let mut terms = vec![self.unary()?];
+        while self.eat(&TokenKind::And) {
+            terms.push(self.unary()?);
+        }
+        Ok(if terms.len() == 1 { terms.remove(0) } else { TagExpr::And(terms) })
+    }
+
+    fn unary(&mut self) -> Result<TagExpr, String> {
+        if self.eat(&TokenKind::Not) {
+            return Ok(TagExpr::Not(Box::new(self.unary()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<TagExpr, String> {
+        let Some(token) = self.next() else {
+            return Err(format!("column {}: expression ends early, expected a tag", self.len + 1));
+        };
+        match token.kind {
+            TokenKind::Open => {
+                let inner = self.or()?;
+                match self.next() {
+                    Some(Token { kind: TokenKind::Close, .. }) => Ok(inner),
+                    Some(other) => Err(format!("column {}: expected `)`, found {}", other.column, other.kind)),
+                    None => Err(format!("column {}: missing `)` for `(` at column {}", self.len + 1, token.column)),
+                }
+            }
+            TokenKind::Star => Ok(TagExpr::All),
+            TokenKind::Quoted(name) => Ok(TagExpr::Tag(name)),
+            TokenKind::Word(word) => match self.peek().map(|token| token.kind.clone()) {
+                Some(TokenKind::Op(op)) => {
+                    self.pos += 1;
+                    self.field_test(&word, token.column, op)
+                }
+                _ => Ok(TagExpr::Tag(word)),
+            },
+            other => Err(format!("column {}: expected a tag, found {}", token.column, other)),
+        }
+    }
+
+    fn field_test(&mut self, name: &str, column: usize, op: CompareOp) -> Result<TagExpr, String> {
+        let field = Field::named(name).ok_or_else(|| {
+            let known: Vec<&str> = FIELD_NAMES.iter().map(|(name, _)| *name).collect();
+            format!("column {}: unknown field `{}` (expected one of {})", column, name, known.join(", "))
+        })?;
+        let value = match self.next() {
+            Some(Token { kind: TokenKind::Word(value) | TokenKind::Quoted(value), .. }) => value,
+            Some(other) => {
+                return Err(format!("column {}: expected a value for `{}`, found {}", other.column, name, other.kind))
+            }
+            None => return Err(format!("column {}: expected a value for `{}`", self.len + 1, name)),
+        };
+
+        if field.is_numeric() {
+            if value.parse::<u32>().is_err() {
+                return Err(format!("column {}: `{}` needs a number, got `{}`", column, field.name(), value));
+            }
+        } else if !matches!(op, CompareOp::Eq | CompareOp::Ne) {
+            return Err(format!(
+                "column {}: `{}` only works on year, track and disc",
+                column,
+                op.symbol()
+            ));
+        }
+        Ok(TagExpr::Field(FieldTest { field, op, value }))
+    }
+}
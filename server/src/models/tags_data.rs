@@ -1,10 +1,79 @@
 use serde::{Deserialize, Serialize};
-use crate::mpd_conn::traits::{Playlist, Song};
+use crate::models::tag_expr::TagExpr;
+use crate::mpd_conn::traits::Song;
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
 pub struct TagsData {
+    #[serde(default)]
     pub any: Vec<String>,
+    #[serde(default)]
     pub not: Vec<String>,
+    /// A boolean expression (see `TagExpr`) that songs must also match.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expr: Option<String>,
+}
+
+impl TagsData {
+    /// Drops blank tag names. The CLI sends `not: [""]` when no exclusions
+    /// are given, and an empty name would otherwise match every song.
+    pub fn without_blanks(self) -> Self {
+        let keep = |tags: Vec<String>| -> Vec<String> {
+            tags.into_iter()
+                .map(|t| t.trim().to_string())
+                .filter(|t| !t.is_empty())
+                .collect()
+        };
+
+        TagsData {
+            any: keep(self.any),
+            not: keep(self.not),
+            expr: self.expr.map(|e| e.trim().to_string()).filter(|e| !e.is_empty()),
+        }
+    }
+
+    /// The whole selection as one expression: `any`/`not` are sugar for
+    /// `(any...) and not (not...)`, and-ed with `expr` when both are given.
+    pub fn to_expr(&self) -> Result<TagExpr, String> {
+        let lists = TagExpr::from_lists(&self.any, &self.not);
+        let Some(expr) = &self.expr else {
+            return Ok(lists);
+        };
+        let parsed = TagExpr::parse(expr).map_err(|e| format!("invalid expr: {}", e))?;
+        Ok(match lists {
+            TagExpr::All => parsed,
+            TagExpr::And(mut terms) => {
+                terms.insert(0, parsed);
+                TagExpr::And(terms)
+            }
+            lists => TagExpr::And(vec![parsed, lists]),
+        })
+    }
+}
+
+/// The active playback tags as reported to clients, alongside the mode
+/// the scheduler is dequeuing in.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PlaybackTags {
+    #[serde(flatten)]
+    pub tags: TagsData,
+    pub album_aware: bool,
+}
+
+/// The outcome of `POST /tags/active`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TagsChange {
+    #[serde(flatten)]
+    pub playback: PlaybackTags,
+    /// Songs the new tags select, counted after the queue was rebuilt.
+    pub matched: usize,
+    /// Upcoming MPD queue entries dropped because `flush` was asked for.
+    pub flushed: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TagInfo {
+    pub name: String,
+    pub track_count: usize,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -21,6 +90,12 @@ pub struct TagsResponse {
     pub playlists: Vec<TagValue>,
 }
 
+impl Default for TagsResponse {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl TagsResponse {
     pub fn new() -> Self {
         TagsResponse {
@@ -31,10 +106,13 @@ impl TagsResponse {
         }
     }
 
-    pub fn to_api_response(songs: Vec<Song>, playlists: Vec<Playlist>) -> Self {
+    /// Counts songs per artist, album and genre; `playlists` carry their
+    /// own track counts.
+    pub fn to_api_response(songs: Vec<Song>, playlists: &[TagInfo]) -> Self {
         let mut response = TagsResponse::new();
         let mut artists_map = std::collections::HashMap::new();
         let mut albums_map = std::collections::HashMap::new();
+        let mut genres_map = std::collections::HashMap::new();
 
         for song in songs {
             if let Some(artist) = song.artist {
@@ -43,6 +121,9 @@ impl TagsResponse {
             if let Some(album) = song.album {
                 *albums_map.entry(album).or_insert(0) += 1;
             }
+            if let Some(genre) = song.genre {
+                *genres_map.entry(genre).or_insert(0) += 1;
+            }
         }
 
         response.artists = artists_map
@@ -53,17 +134,22 @@ impl TagsResponse {
             .into_iter()
             .map(|(name, count)| TagValue { name, count })
             .collect();
+        response.genres = genres_map
+            .into_iter()
+            .map(|(name, count)| TagValue { name, count })
+            .collect();
 
         response.playlists = playlists
-            .into_iter()
+            .iter()
             .map(|p| TagValue {
-                name: p.name,
-                count: 0,
+                name: p.name.clone(),
+                count: p.track_count,
             })
             .collect();
 
         response.artists.sort_by(|a, b| a.name.cmp(&b.name));
         response.albums.sort_by(|a, b| a.name.cmp(&b.name));
+        response.genres.sort_by(|a, b| a.name.cmp(&b.name));
         response.playlists.sort_by(|a, b| a.name.cmp(&b.name));
 
         response
@@ -0,0 +1,145 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::models::song_stats::{PlayStats, SongStats};
+use crate::mpd_conn::traits::Song;
+
+/// The neutral rating: three stars weigh the same as no rating.
+const NEUTRAL_RATING: i32 = 3;
+
+/// A song played a moment ago keeps this share of its weight, so it stays
+/// possible rather than impossible.
+const MIN_RECENCY: f64 = 0.05;
+
+/// The knobs of the weighting formula. A song's weight is the product of
+/// its `ScoreFactors`; an unrated, never played song weighs 1.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Weighting {
+    /// Each star above three multiplies the weight by this, each star below
+    /// divides it.
+    pub rating_boost: f64,
+    /// How hard a song's skip ratio pulls it down: at 1, a song that is
+    /// always skipped weighs half.
+    pub skip_penalty: f64,
+    /// How hard heavy rotation pulls a song down, per log of its plays.
+    pub play_penalty: f64,
+    /// Hours for a played song to recover its full weight; 0 turns this off.
+    pub recovery_hours: u64,
+}
+
+impl Default for Weighting {
+    fn default() -> Self {
+        Weighting {
+            rating_boost: 1.5,
+            skip_penalty: 1.0,
+            play_penalty: 0.1,
+            recovery_hours: 48,
+        }
+    }
+}
+
+/// The parts of a song's weight, as shown by `/song/<file>/score`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct ScoreFactors {
+    pub rating: f64,
+    pub skips: f64,
+    pub plays: f64,
+    pub recency: f64,
+}
+
+impl ScoreFactors {
+    pub fn weight(&self) -> f64 {
+        self.rating * self.skips * self.plays * self.recency
+    }
+}
+
+impl Weighting {
+    /// How `stats` bear on a song's weight at `now` (unix seconds).
+    pub fn factors(&self, stats: &SongStats, now: u64) -> ScoreFactors {
+        let rating = match stats.rating {
+            Some(stars) => self.rating_boost.powi(i32::from(stars) - NEUTRAL_RATING),
+            None => 1.0,
+        };
+
+        let finished = stats.plays + stats.skips;
+        let skips = if finished == 0 {
+            1.0
+        } else {
+            1.0 / (1.0 + self.skip_penalty * f64::from(stats.skips) / f64::from(finished))
+        };
+
+        let plays = 1.0 / (1.0 + self.play_penalty * f64::from(stats.plays).ln_1p());
+
+        let recency = match stats.last_played {
+            Some(last) if self.recovery_hours > 0 => {
+                let hours = now.saturating_sub(last) as f64 / 3600.0;
+                (hours / self.recovery_hours as f64).clamp(MIN_RECENCY, 1.0)
+            }
+            _ => 1.0,
+        };
+
+        ScoreFactors { rating, skips, plays, recency }
+    }
+}
+
+/// Weights for the songs being queued: `weighting` applied to `stats` as of
+/// one moment.
+pub struct SongWeights<'a> {
+    stats: Option<&'a PlayStats>,
+    weighting: Weighting,
+    now: u64,
+}
+
+impl<'a> SongWeights<'a> {
+    pub fn new(stats: &'a PlayStats, weighting: Weighting, now: SystemTime) -> Self {
+        SongWeights {
+            stats: Some(stats),
+            weighting,
+            now: now.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+        }
+    }
+
+    /// Every song weighs the same, which makes the order a plain shuffle.
+    pub fn uniform() -> Self {
+        SongWeights {
+            stats: None,
+            weighting: Weighting::default(),
+            now: 0,
+        }
+    }
+
+    pub fn of(&self, song: &Song) -> f64 {
+        match self.stats {
+            Some(stats) => self.weighting.factors(&stats.get(&song.file), self.now).weight(),
+            None => 1.0,
+        }
+    }
+
+    /// The mean weight of `songs`, so an album counts as well liked as its
+    /// tracks are on average.
+    pub fn of_all(&self, songs: &[Song]) -> f64 {
+        if songs.is_empty() {
+            return 1.0;
+        }
+        songs.iter().map(|song| self.of(song)).sum::<f64>() / songs.len() as f64
+    }
+}
+
+/// A random order of `items` in which heavier ones tend to come first: each
+/// item is drawn ahead of the rest with probability proportional to its
+/// weight (Efraimidis-Spirakis keys). Items weighing nothing go last.
+pub fn weighted_order<T>(items: Vec<T>, weight: impl Fn(&T) -> f64, rng: &mut impl Rng) -> Vec<T> {
+    let mut keyed: Vec<(f64, T)> = items
+        .into_iter()
+        .map(|item| {
+            let weight = weight(&item);
+            // ln(u) / w for u in (0, 1] orders the same as u^(1/w)
+            let u = 1.0 - rng.random::<f64>();
+            let key = if weight > 0.0 { u.ln() / weight } else { f64::NEG_INFINITY };
+            (key, item)
+        })
+        .collect();
+    keyed.sort_by(|a, b| b.0.total_cmp(&a.0));
+    keyed.into_iter().map(|(_, item)| item).collect()
+}
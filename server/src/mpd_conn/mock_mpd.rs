@@ -1,29 +1,135 @@
 use crate::mpd_conn::traits::{FilterTerm, MpdClient, Playlist, Query, Song};
 use anyhow::{anyhow, Result};
 use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Condvar, Mutex};
+
+/// Per-subsystem change counters backing `idle`.
+struct IdleEvents {
+    counters: HashMap<String, u64>,
+    supported: bool,
+}
 
-#[derive(Clone)]
 pub struct MockMpd {
     playlists: Arc<Mutex<HashMap<String, Vec<Song>>>>,
+    // stands in for each playlist's `Last-Modified`: the `stored_playlist`
+    // change count when it was last written
+    playlists_modified: Arc<Mutex<HashMap<String, u64>>>,
+    playlist_reads: Arc<Mutex<HashMap<String, usize>>>,
     queue: Arc<Mutex<Vec<Song>>>,
     is_consuming: Arc<Mutex<bool>>,
+    volume: Arc<Mutex<Option<u32>>>,
     connection_state: Arc<Mutex<bool>>, // true if connected
+    playlist_edits_fail: Arc<Mutex<bool>>,
+    idle_events: Arc<(Mutex<IdleEvents>, Condvar)>,
+    // counters as of this handle's last `idle`; not shared between clones,
+    // so each clone behaves like a separate MPD client
+    idle_seen: HashMap<String, u64>,
+}
+
+impl Clone for MockMpd {
+    fn clone(&self) -> Self {
+        MockMpd {
+            playlists: self.playlists.clone(),
+            playlists_modified: self.playlists_modified.clone(),
+            playlist_reads: self.playlist_reads.clone(),
+            queue: self.queue.clone(),
+            is_consuming: self.is_consuming.clone(),
+            volume: self.volume.clone(),
+            connection_state: self.connection_state.clone(),
+            playlist_edits_fail: self.playlist_edits_fail.clone(),
+            idle_events: self.idle_events.clone(),
+            idle_seen: self.idle_events.0.lock().unwrap().counters.clone(),
+        }
+    }
 }
 
 impl MockMpd {
     pub fn new() -> Self {
         MockMpd {
             playlists: Arc::new(Mutex::new(HashMap::new())),
+            playlists_modified: Arc::new(Mutex::new(HashMap::new())),
+            playlist_reads: Arc::new(Mutex::new(HashMap::new())),
             queue: Arc::new(Mutex::new(Vec::new())),
             is_consuming: Arc::new(Mutex::new(false)),
+            volume: Arc::new(Mutex::new(None)),
             connection_state: Arc::new(Mutex::new(true)),
+            playlist_edits_fail: Arc::new(Mutex::new(false)),
+            idle_events: Arc::new((
+                Mutex::new(IdleEvents {
+                    counters: HashMap::new(),
+                    supported: true,
+                }),
+                Condvar::new(),
+            )),
+            idle_seen: HashMap::new(),
         }
     }
 
     pub fn add_playlist(&self, name: &str, songs: Vec<Song>) {
         let mut playlists = self.playlists.lock().unwrap();
         playlists.insert(name.to_string(), songs);
+        drop(playlists);
+        self.playlist_changed(name);
+    }
+
+    /// How many times `name` has been read with `playlist`, across clones.
+    pub fn playlist_reads(&self, name: &str) -> usize {
+        self.playlist_reads.lock().unwrap().get(name).copied().unwrap_or(0)
+    }
+
+    /// Stamps `name` as just written and tells `idle` clients.
+    fn playlist_changed(&self, name: &str) {
+        self.emit_idle_event("stored_playlist");
+        let changes = self.idle_events.0.lock().unwrap().counters["stored_playlist"];
+        self.playlists_modified.lock().unwrap().insert(name.to_string(), changes);
+    }
+
+    /// Records a change in `subsystem`, waking clients blocked in `idle`.
+    pub fn emit_idle_event(&self, subsystem: &str) {
+        let (events, changed) = &*self.idle_events;
+        let mut events = events.lock().unwrap();
+        *events.counters.entry(subsystem.to_string()).or_insert(0) += 1;
+        changed.notify_all();
+    }
+
+    /// Makes `idle` fail as it would against a server without the command.
+    pub fn disable_idle(&self) {
+        let (events, changed) = &*self.idle_events;
+        events.lock().unwrap().supported = false;
+        changed.notify_all();
+    }
+
+    /// Non-blocking `idle`: the subsystems that changed since this handle
+    /// last looked. An empty `subsystems` means all of them.
+    pub fn idle_changes(&mut self, subsystems: &[&str]) -> Result<Vec<String>> {
+        let events = self.idle_events.0.lock().unwrap();
+        if !events.supported {
+            return Err(anyhow!("unknown command \"idle\""));
+        }
+        Ok(Self::take_changes(&events, &mut self.idle_seen, subsystems))
+    }
+
+    fn take_changes(events: &IdleEvents, seen: &mut HashMap<String, u64>, subsystems: &[&str]) -> Vec<String> {
+        let mut changed: Vec<String> = events
+            .counters
+            .iter()
+            .filter(|(name, count)| {
+                (subsystems.is_empty() || subsystems.contains(&name.as_str()))
+                    && seen.get(*name).copied().unwrap_or(0) < **count
+            })
+            .map(|(name, _)| name.clone())
+            .collect();
+        changed.sort();
+
+        for name in &changed {
+            seen.insert(name.clone(), events.counters[name]);
+        }
+        changed
+    }
+
+    /// The last volume set, if any.
+    pub fn volume(&self) -> Option<u32> {
+        *self.volume.lock().unwrap()
     }
 
     pub fn simulate_disconnect(&self) {
@@ -36,6 +142,32 @@ impl MockMpd {
         *state = true;
     }
 
+    /// Makes every stored playlist edit fail, as MPD does when its playlist
+    /// directory is read-only.
+    pub fn fail_playlist_edits(&self, fail: bool) {
+        *self.playlist_edits_fail.lock().unwrap() = fail;
+    }
+
+    fn check_playlist_edit(&self) -> Result<()> {
+        self.check_connection()?;
+        if *self.playlist_edits_fail.lock().unwrap() {
+            return Err(anyhow!("Failed to write playlist: Read-only file system"));
+        }
+        Ok(())
+    }
+
+    /// Every song in any playlist, once each, like MPD's database.
+    fn library(&self) -> Vec<Song> {
+        let playlists = self.playlists.lock().unwrap();
+        let mut seen = std::collections::HashSet::new();
+        playlists
+            .values()
+            .flatten()
+            .filter(|song| seen.insert(song.file.clone()))
+            .cloned()
+            .collect()
+    }
+
     fn check_connection(&self) -> Result<()> {
         let state = self.connection_state.lock().unwrap();
         if !*state {
@@ -45,6 +177,12 @@ impl MockMpd {
     }
 }
 
+impl Default for MockMpd {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl MpdClient for MockMpd {
     fn ping(&mut self) -> Result<()> {
         self.check_connection()
@@ -52,6 +190,7 @@ impl MpdClient for MockMpd {
 
     fn playlist(&mut self, name: &str) -> Result<Vec<Song>> {
         self.check_connection()?;
+        *self.playlist_reads.lock().unwrap().entry(name.to_string()).or_insert(0) += 1;
         let playlists = self.playlists.lock().unwrap();
         match playlists.get(name) {
             Some(songs) => Ok(songs.clone()),
@@ -64,7 +203,10 @@ impl MpdClient for MockMpd {
         let playlists = self.playlists.lock().unwrap();
         Ok(playlists
             .keys()
-            .map(|name| Playlist { name: name.clone() })
+            .map(|name| Playlist {
+                name: name.clone(),
+                last_modified: self.playlists_modified.lock().unwrap().get(name).copied(),
+            })
             .collect())
     }
 
@@ -77,11 +219,7 @@ impl MpdClient for MockMpd {
     fn search(&mut self, query: &Query, _window: Option<(u32, u32)>) -> Result<Vec<Song>> {
         self.check_connection()?;
 
-        let playlists = self.playlists.lock().unwrap();
-        let mut all_songs = Vec::new();
-        for playlist in playlists.values() {
-            all_songs.extend(playlist.clone());
-        }
+        let all_songs = self.library();
 
         let filtered_songs = all_songs
             .into_iter()
@@ -89,14 +227,18 @@ impl MpdClient for MockMpd {
                 query.terms.iter().all(|term| match term {
                     FilterTerm::Any(val) => {
                         song.file.contains(val)
-                            || song.title.as_ref().map_or(false, |t| t.contains(val))
-                            || song.artist.as_ref().map_or(false, |a| a.contains(val))
-                            || song.album.as_ref().map_or(false, |a| a.contains(val))
+                            || song.title.as_ref().is_some_and(|t| t.contains(val))
+                            || song.artist.as_ref().is_some_and(|a| a.contains(val))
+                            || song.album.as_ref().is_some_and(|a| a.contains(val))
                     }
                     FilterTerm::Tag(tag, val) => match tag.to_lowercase().as_str() {
-                        "artist" => song.artist.as_ref().map_or(false, |a| a == val),
-                        "album" => song.album.as_ref().map_or(false, |a| a == val),
-                        "title" => song.title.as_ref().map_or(false, |t| t == val),
+                        "artist" => song.artist.as_ref().is_some_and(|a| a == val),
+                        "album" => song.album.as_ref().is_some_and(|a| a == val),
+                        "title" => song.title.as_ref().is_some_and(|t| t == val),
+                        "albumartist" => song.album_artist.as_ref().is_some_and(|a| a == val),
+                        "genre" => song.genre.as_ref().is_some_and(|g| g == val),
+                        "composer" => song.composer.as_ref().is_some_and(|c| c == val),
+                        "date" => song.date.as_ref().is_some_and(|d| d == val),
                         _ => false,
                     },
                 })
@@ -110,22 +252,34 @@ impl MpdClient for MockMpd {
         self.check_connection()?;
         let mut consume_state = self.is_consuming.lock().unwrap();
         *consume_state = state;
+        drop(consume_state);
+        self.emit_idle_event("options");
         Ok(())
     }
 
     fn push(&mut self, file: &str) -> Result<u32> {
         self.check_connection()?;
+        // like MPD, queue entries carry the library's tags for the file
+        let known = self.library().into_iter().find(|s| s.file == file);
+
         let mut queue = self.queue.lock().unwrap();
         let id = queue.len() as u32;
-        queue.push(Song {
-            file: file.to_string(),
-            title: None,
-            artist: None,
-            album: None,
-            duration: None,
-            pos: Some(id),
-            id: Some(id),
-        });
+        let song = match known {
+            Some(song) => Song {
+                pos: Some(id),
+                id: Some(id),
+                ..song
+            },
+            None => Song {
+                file: file.to_string(),
+                pos: Some(id),
+                id: Some(id),
+                ..Default::default()
+            },
+        };
+        queue.push(song);
+        drop(queue);
+        self.emit_idle_event("playlist");
         Ok(id)
     }
 
@@ -136,59 +290,101 @@ impl MpdClient for MockMpd {
             return Err(anyhow!("Position out of bounds"));
         }
         queue.remove(pos as usize);
+        drop(queue);
+        self.emit_idle_event("playlist");
         Ok(())
     }
 
     fn play(&mut self) -> Result<()> {
-        self.check_connection()
+        self.check_connection()?;
+        self.emit_idle_event("player");
+        Ok(())
     }
 
-    fn pl_push(&mut self, playlist_name: &str, file: &str) -> Result<()> {
+    fn set_volume(&mut self, volume: u32) -> Result<()> {
         self.check_connection()?;
+        if volume > 100 {
+            return Err(anyhow!("Invalid volume value"));
+        }
+        *self.volume.lock().unwrap() = Some(volume);
+        self.emit_idle_event("mixer");
+        Ok(())
+    }
+
+    fn pl_push(&mut self, playlist_name: &str, file: &str) -> Result<()> {
+        self.check_playlist_edit()?;
         let mut playlists = self.playlists.lock().unwrap();
         playlists
             .entry(playlist_name.to_string())
-            .or_insert_with(Vec::new)
+            .or_default()
             .push(Song {
                 file: file.to_string(),
-                title: None,
-                artist: None,
-                album: None,
-                duration: None,
-                pos: None,
-                id: None,
+                ..Default::default()
             });
+        drop(playlists);
+        self.playlist_changed(playlist_name);
         Ok(())
     }
 
     fn pl_delete(&mut self, playlist_name: &str, pos: u32) -> Result<()> {
-        self.check_connection()?;
+        self.check_playlist_edit()?;
         let mut playlists = self.playlists.lock().unwrap();
         if let Some(playlist) = playlists.get_mut(playlist_name) {
             if pos as usize >= playlist.len() {
                 return Err(anyhow!("Position out of bounds"));
             }
             playlist.remove(pos as usize);
+            drop(playlists);
+            self.playlist_changed(playlist_name);
             Ok(())
         } else {
             Err(anyhow!("Playlist {} not found", playlist_name))
         }
     }
 
-    fn pl_remove(&mut self, playlist: &str) -> Result<()> {
-        self.check_connection()?;
+    fn pl_clear(&mut self, playlist: &str) -> Result<()> {
+        self.check_playlist_edit()?;
         let mut playlists = self.playlists.lock().unwrap();
-        playlists.remove(playlist);
+        // like MPD, clearing a playlist that does not exist creates it empty
+        playlists.entry(playlist.to_string()).or_default().clear();
+        drop(playlists);
+        self.playlist_changed(playlist);
         Ok(())
     }
 
+    fn pl_remove(&mut self, playlist: &str) -> Result<()> {
+        self.check_playlist_edit()?;
+        let mut playlists = self.playlists.lock().unwrap();
+        match playlists.remove(playlist) {
+            Some(_) => {
+                drop(playlists);
+                self.playlists_modified.lock().unwrap().remove(playlist);
+                self.emit_idle_event("stored_playlist");
+                Ok(())
+            }
+            None => Err(anyhow!("Playlist {} not found", playlist)),
+        }
+    }
+
     fn listall(&mut self) -> Result<Vec<Song>> {
         self.check_connection()?;
-        let playlists = self.playlists.lock().unwrap();
-        let mut all_songs = Vec::new();
-        for playlist in playlists.values() {
-            all_songs.extend(playlist.clone());
+        Ok(self.library())
+    }
+
+    fn idle(&mut self, subsystems: &[&str]) -> Result<Vec<String>> {
+        self.check_connection()?;
+        let (events, changed) = &*self.idle_events;
+        let mut events = events.lock().unwrap();
+
+        loop {
+            if !events.supported {
+                return Err(anyhow!("unknown command \"idle\""));
+            }
+            let found = Self::take_changes(&events, &mut self.idle_seen, subsystems);
+            if !found.is_empty() {
+                return Ok(found);
+            }
+            events = changed.wait(events).unwrap();
         }
-        Ok(all_songs)
     }
 }
@@ -1,5 +1,11 @@
 pub mod mock_mpd;
+#[allow(clippy::module_inception)]
 pub mod mpd_conn;
 pub mod mpd_pool;
 pub mod traits;
+#[cfg(feature = "libmpdclient")]
 pub mod raw_client;
+#[cfg(feature = "native-mpd")]
+pub mod native_client;
+#[cfg(feature = "native-mpd")]
+pub mod protocol;
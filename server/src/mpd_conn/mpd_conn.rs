@@ -1,173 +1,267 @@
 use anyhow::Result;
-use std::env;
 
 use crate::mpd_conn::mock_mpd::MockMpd;
 use crate::mpd_conn::traits::{MpdClient, Playlist, Query, Song};
+#[cfg(feature = "native-mpd")]
+use crate::mpd_conn::native_client::NativeMpdClient;
+#[cfg(feature = "libmpdclient")]
 use crate::mpd_conn::raw_client::RawMpdClient;
-use log::{debug, info};
+use log::debug;
 
 pub enum MpdBackend {
+    #[cfg(feature = "libmpdclient")]
     Real(RawMpdClient),
+    #[cfg(feature = "native-mpd")]
+    Native(NativeMpdClient),
     Mock(MockMpd),
 }
 
+/// Which client library a real (non-mock) connection is made with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BackendKind {
+    #[cfg(feature = "libmpdclient")]
+    Libmpdclient,
+    #[cfg(feature = "native-mpd")]
+    Native,
+}
+
+impl BackendKind {
+    fn connect(self, host: &str, port: u16) -> Result<MpdBackend> {
+        let mut mpd = match self {
+            #[cfg(feature = "libmpdclient")]
+            BackendKind::Libmpdclient => MpdBackend::Real(RawMpdClient::connect(host, port)?),
+            #[cfg(feature = "native-mpd")]
+            BackendKind::Native => MpdBackend::Native(NativeMpdClient::connect(host, port)?),
+        };
+        mpd.consume(true)?;
+        Ok(mpd)
+    }
+}
+
+impl Default for BackendKind {
+    #[cfg(feature = "libmpdclient")]
+    fn default() -> Self {
+        BackendKind::Libmpdclient
+    }
+
+    #[cfg(not(feature = "libmpdclient"))]
+    fn default() -> Self {
+        BackendKind::Native
+    }
+}
+
+impl std::str::FromStr for BackendKind {
+    type Err = String;
+
+    /// Accepts the backends compiled into this build.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            #[cfg(feature = "libmpdclient")]
+            "libmpdclient" => Ok(BackendKind::Libmpdclient),
+            #[cfg(feature = "native-mpd")]
+            "native" => Ok(BackendKind::Native),
+            other => Err(format!("unknown or disabled MPD backend `{}`", other)),
+        }
+    }
+}
+
 impl MpdClient for MpdBackend {
     fn ping(&mut self) -> Result<()> {
         match self {
+            #[cfg(feature = "libmpdclient")]
             MpdBackend::Real(c) => c.ping(),
+            #[cfg(feature = "native-mpd")]
+            MpdBackend::Native(n) => n.ping(),
             MpdBackend::Mock(m) => m.ping(),
         }
     }
 
     fn playlist(&mut self, name: &str) -> Result<Vec<Song>> {
         match self {
+            #[cfg(feature = "libmpdclient")]
             MpdBackend::Real(c) => c.get_playlist_songs(name),
+            #[cfg(feature = "native-mpd")]
+            MpdBackend::Native(n) => n.playlist(name),
             MpdBackend::Mock(m) => m.playlist(name),
         }
     }
 
     fn playlists(&mut self) -> Result<Vec<Playlist>> {
         match self {
+            #[cfg(feature = "libmpdclient")]
             MpdBackend::Real(c) => c.list_playlists(),
+            #[cfg(feature = "native-mpd")]
+            MpdBackend::Native(n) => n.playlists(),
             MpdBackend::Mock(m) => m.playlists(),
         }
     }
 
     fn queue(&mut self) -> Result<Vec<Song>> {
         match self {
+            #[cfg(feature = "libmpdclient")]
             MpdBackend::Real(c) => c.get_queue(),
+            #[cfg(feature = "native-mpd")]
+            MpdBackend::Native(n) => n.queue(),
             MpdBackend::Mock(m) => m.queue(),
         }
     }
 
     fn search(&mut self, query: &Query, _window: Option<(u32, u32)>) -> Result<Vec<Song>> {
         match self {
+            #[cfg(feature = "libmpdclient")]
             MpdBackend::Real(c) => c.search(query),
+            #[cfg(feature = "native-mpd")]
+            MpdBackend::Native(n) => n.search(query, _window),
             MpdBackend::Mock(m) => m.search(query, _window),
         }
     }
 
     fn consume(&mut self, state: bool) -> Result<()> {
         match self {
+            #[cfg(feature = "libmpdclient")]
             MpdBackend::Real(c) => c.set_consume(state),
+            #[cfg(feature = "native-mpd")]
+            MpdBackend::Native(n) => n.consume(state),
             MpdBackend::Mock(m) => m.consume(state),
         }
     }
 
     fn push(&mut self, file: &str) -> Result<u32> {
         match self {
+            #[cfg(feature = "libmpdclient")]
             MpdBackend::Real(c) => {
                 c.queue_add(file)?;
                 Ok(0)
             }
+            #[cfg(feature = "native-mpd")]
+            MpdBackend::Native(n) => n.push(file),
             MpdBackend::Mock(m) => m.push(file),
         }
     }
 
     fn delete(&mut self, pos: u32) -> Result<()> {
         match self {
+            #[cfg(feature = "libmpdclient")]
             MpdBackend::Real(c) => c.queue_delete(pos),
+            #[cfg(feature = "native-mpd")]
+            MpdBackend::Native(n) => n.delete(pos),
             MpdBackend::Mock(m) => m.delete(pos),
         }
     }
 
     fn play(&mut self) -> Result<()> {
         match self {
+            #[cfg(feature = "libmpdclient")]
             MpdBackend::Real(c) => c.play(),
+            #[cfg(feature = "native-mpd")]
+            MpdBackend::Native(n) => n.play(),
             MpdBackend::Mock(m) => m.play(),
         }
     }
 
+    fn set_volume(&mut self, volume: u32) -> Result<()> {
+        match self {
+            #[cfg(feature = "libmpdclient")]
+            MpdBackend::Real(c) => c.set_volume(volume),
+            #[cfg(feature = "native-mpd")]
+            MpdBackend::Native(n) => n.set_volume(volume),
+            MpdBackend::Mock(m) => m.set_volume(volume),
+        }
+    }
+
     fn pl_push(&mut self, playlist: &str, file: &str) -> Result<()> {
         match self {
+            #[cfg(feature = "libmpdclient")]
             MpdBackend::Real(c) => c.playlist_add(playlist, file),
+            #[cfg(feature = "native-mpd")]
+            MpdBackend::Native(n) => n.pl_push(playlist, file),
             MpdBackend::Mock(m) => m.pl_push(playlist, file),
         }
     }
 
     fn pl_delete(&mut self, playlist: &str, pos: u32) -> Result<()> {
         match self {
+            #[cfg(feature = "libmpdclient")]
             MpdBackend::Real(c) => c.playlist_delete(playlist, pos),
+            #[cfg(feature = "native-mpd")]
+            MpdBackend::Native(n) => n.pl_delete(playlist, pos),
             MpdBackend::Mock(m) => m.pl_delete(playlist, pos),
         }
     }
 
-    fn pl_remove(&mut self, playlist: &str) -> Result<()> {
+    fn pl_clear(&mut self, playlist: &str) -> Result<()> {
         match self {
+            #[cfg(feature = "libmpdclient")]
             MpdBackend::Real(c) => c.playlist_clear(playlist),
+            #[cfg(feature = "native-mpd")]
+            MpdBackend::Native(n) => n.pl_clear(playlist),
+            MpdBackend::Mock(m) => m.pl_clear(playlist),
+        }
+    }
+
+    fn pl_remove(&mut self, playlist: &str) -> Result<()> {
+        match self {
+            #[cfg(feature = "libmpdclient")]
+            MpdBackend::Real(c) => c.playlist_remove(playlist),
+            #[cfg(feature = "native-mpd")]
+            MpdBackend::Native(n) => n.pl_remove(playlist),
             MpdBackend::Mock(m) => m.pl_remove(playlist),
         }
     }
 
     fn listall(&mut self) -> Result<Vec<Song>> {
         match self {
+            #[cfg(feature = "libmpdclient")]
             MpdBackend::Real(c) => c.list_all_songs(),
+            #[cfg(feature = "native-mpd")]
+            MpdBackend::Native(n) => n.listall(),
             MpdBackend::Mock(m) => m.listall(),
         }
     }
+
+    fn idle(&mut self, subsystems: &[&str]) -> Result<Vec<String>> {
+        match self {
+            #[cfg(feature = "libmpdclient")]
+            MpdBackend::Real(c) => c.idle(subsystems),
+            #[cfg(feature = "native-mpd")]
+            MpdBackend::Native(n) => n.idle(subsystems),
+            MpdBackend::Mock(m) => m.idle(subsystems),
+        }
+    }
 }
 
 pub struct MpdConn {
     pub mpd: MpdBackend,
     address: String,
     port: u16,
-    is_dev_mode: bool,
+    backend: BackendKind,
+    is_mock: bool,
 }
 
 impl MpdConn {
-    pub fn new() -> Result<Self> {
-        let is_dev_mode = env::var("JUKECTL_DEV_MODE").unwrap_or_default() == "1";
-
-        if is_dev_mode {
-            info!("[!] JUKECTL_DEV_MODE is enabled, using MockMpd");
-            return Ok(MpdConn {
-                mpd: MpdBackend::Mock(MockMpd::new()),
-                address: "mock".to_string(),
-                port: 0,
-                is_dev_mode: true,
-            });
-        }
-
-        debug!("[!] connecting to mpd...");
-        let host = env::var("MPD_HOST").unwrap_or_else(|_| "127.0.0.1".to_string());
-        let port: u16 = env::var("MPD_PORT")
-            .unwrap_or_else(|_| "6600".to_string())
-            .parse()
-            .unwrap_or(6600);
-
-        let mpd = RawMpdClient::connect(&host, port)?;
-        mpd.set_consume(true)?;
+    /// Connects to MPD at `host:port` with the `backend` client library.
+    /// Dev mode never gets here: its pool hands out `from_mock` connections.
+    pub fn new_with_backend(host: &str, port: u16, backend: BackendKind) -> Result<Self> {
+        debug!("[!] connecting to mpd at {}:{}...", host, port);
+        let mpd = backend.connect(host, port)?;
 
         Ok(MpdConn {
-            mpd: MpdBackend::Real(mpd),
-            address: host,
+            mpd,
+            address: host.to_string(),
             port,
-            is_dev_mode: false,
+            backend,
+            is_mock: false,
         })
     }
 
-    pub fn new_with_host(host: &str, port: u16) -> Result<Self> {
-        let is_dev_mode = env::var("JUKECTL_DEV_MODE").unwrap_or_default() == "1";
-
-        if is_dev_mode {
-            return Ok(MpdConn {
-                mpd: MpdBackend::Mock(MockMpd::new()),
-                address: "mock".to_string(),
-                port: 0,
-                is_dev_mode: true,
-            });
+    pub fn from_mock(mock: MockMpd) -> Self {
+        MpdConn {
+            mpd: MpdBackend::Mock(mock),
+            address: "mock".to_string(),
+            port: 0,
+            backend: BackendKind::default(),
+            is_mock: true,
         }
-
-        debug!("[!] connecting to mpd at {}:{}...", host, port);
-        let mpd = RawMpdClient::connect(host, port)?;
-        mpd.set_consume(true)?;
-
-        Ok(MpdConn {
-            mpd: MpdBackend::Real(mpd),
-            address: host.to_string(),
-            port,
-            is_dev_mode: false,
-        })
     }
 
     pub fn get_host_info(&self) -> (String, u16) {
@@ -175,21 +269,19 @@ impl MpdConn {
     }
 
     pub fn reconnect(&mut self) -> Result<()> {
-        if self.is_dev_mode {
+        if self.is_mock {
             return Ok(());
         }
 
         if !self.is_connected() {
             debug!("[!] Reconnecting to mpd...");
-            let mpd = RawMpdClient::connect(&self.address, self.port)?;
-            mpd.set_consume(true)?;
-            self.mpd = MpdBackend::Real(mpd);
+            self.mpd = self.backend.connect(&self.address, self.port)?;
         }
         Ok(())
     }
 
     fn is_connected(&mut self) -> bool {
-        if self.is_dev_mode {
+        if self.is_mock {
             return true;
         }
         self.ping().is_ok()
@@ -197,7 +289,10 @@ impl MpdConn {
 
     pub fn ping(&mut self) -> Result<()> {
         match &mut self.mpd {
+            #[cfg(feature = "libmpdclient")]
             MpdBackend::Real(c) => c.ping(),
+            #[cfg(feature = "native-mpd")]
+            MpdBackend::Native(n) => n.ping(),
             MpdBackend::Mock(m) => m.ping(),
         }
     }
@@ -2,13 +2,16 @@ use anyhow::{anyhow, Result};
 use std::sync::Arc;
 use tokio::sync::{Mutex, Semaphore};
 
-use crate::mpd_conn::mpd_conn::MpdConn;
+use crate::mpd_conn::mock_mpd::MockMpd;
+use crate::mpd_conn::mpd_conn::{BackendKind, MpdConn};
 
 pub struct MpdPool {
     connections: Arc<Mutex<Vec<MpdConn>>>,
     semaphore: Arc<Semaphore>,
     host: String,
     port: u16,
+    backend: BackendKind,
+    mock: Option<MockMpd>,
 }
 
 pub struct PooledMpdConnection {
@@ -35,15 +38,30 @@ impl Drop for PooledMpdConnection {
 }
 
 impl MpdPool {
-    pub fn new(host: String, port: u16, max_connections: usize) -> Result<Self> {
+    pub fn with_backend(host: String, port: u16, max_connections: usize, backend: BackendKind) -> Result<Self> {
         Ok(MpdPool {
             connections: Arc::new(Mutex::new(Vec::with_capacity(max_connections))),
             semaphore: Arc::new(Semaphore::new(max_connections)),
             host,
             port,
+            backend,
+            mock: None,
         })
     }
 
+    /// Builds a pool whose connections all share one `MockMpd`, so state
+    /// written through one connection is visible through the others.
+    pub fn with_mock(mock: MockMpd, max_connections: usize) -> Self {
+        MpdPool {
+            connections: Arc::new(Mutex::new(Vec::with_capacity(max_connections))),
+            semaphore: Arc::new(Semaphore::new(max_connections)),
+            host: "mock".to_string(),
+            port: 0,
+            backend: BackendKind::default(),
+            mock: Some(mock),
+        }
+    }
+
     pub async fn warm_pool(&self, count: usize) -> Result<()> {
         let mut conns = Vec::with_capacity(count);
         for _ in 0..count {
@@ -61,7 +79,7 @@ impl MpdPool {
         
         let mut pool_lock = self.connections.lock().await;
         if let Some(mut conn) = pool_lock.pop() {
-            if let Err(_) = conn.reconnect() {
+            if conn.reconnect().is_err() {
                 drop(pool_lock);
                 let new_conn = self.create_new_connection().await?;
                 return Ok(PooledMpdConnection {
@@ -83,10 +101,24 @@ impl MpdPool {
         })
     }
 
+    /// Opens a connection outside the pool, for long-lived blocking work such
+    /// as `idle` that would otherwise tie up a pooled connection.
+    pub fn open_dedicated(&self) -> Result<MpdConn> {
+        match &self.mock {
+            Some(mock) => Ok(MpdConn::from_mock(mock.clone())),
+            None => MpdConn::new_with_backend(&self.host, self.port, self.backend),
+        }
+    }
+
     async fn create_new_connection(&self) -> Result<MpdConn> {
+        if let Some(mock) = &self.mock {
+            return Ok(MpdConn::from_mock(mock.clone()));
+        }
+
         let host = self.host.clone();
         let port = self.port;
-        tokio::task::spawn_blocking(move || MpdConn::new_with_host(&host, port))
+        let backend = self.backend;
+        tokio::task::spawn_blocking(move || MpdConn::new_with_backend(&host, port, backend))
             .await
             .map_err(|e| anyhow!("Blocking task join error: {}", e))?
     }
@@ -0,0 +1,473 @@
+// A pure-Rust MPD client speaking the text protocol over tokio, plus a
+// blocking handle so it can sit behind the synchronous `MpdClient` trait.
+use anyhow::{anyhow, Result};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::mpsc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::runtime::RuntimeFlavor;
+
+use crate::mpd_conn::protocol::{self, AckError, Command, Response};
+use crate::mpd_conn::traits::{FilterTerm, MpdClient, Playlist, Query, Song};
+
+/// Matches the 30s timeout `RawMpdClient` hands to libmpdclient.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub struct AsyncMpdClient<S = TcpStream> {
+    stream: BufReader<S>,
+    version: String,
+    timeout: Duration,
+    // set once a read or write failed or timed out mid-response; the
+    // stream position is unknown from then on
+    broken: bool,
+}
+
+impl AsyncMpdClient<TcpStream> {
+    pub async fn connect(host: &str, port: u16) -> Result<Self> {
+        let stream = tokio::time::timeout(DEFAULT_TIMEOUT, TcpStream::connect((host, port)))
+            .await
+            .map_err(|_| anyhow!("Timed out connecting to MPD at {}:{}", host, port))??;
+        Self::from_stream(stream).await
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> AsyncMpdClient<S> {
+    /// Wraps an already connected stream and consumes the `OK MPD <version>` greeting.
+    pub async fn from_stream(stream: S) -> Result<Self> {
+        let mut client = AsyncMpdClient {
+            stream: BufReader::new(stream),
+            version: String::new(),
+            timeout: DEFAULT_TIMEOUT,
+            broken: false,
+        };
+
+        let greeting = tokio::time::timeout(client.timeout, client.read_line())
+            .await
+            .map_err(|_| anyhow!("Timed out waiting for the MPD greeting"))??;
+        client.version = greeting
+            .strip_prefix("OK MPD ")
+            .ok_or_else(|| anyhow!("Unexpected MPD greeting: {:?}", greeting))?
+            .to_string();
+
+        Ok(client)
+    }
+
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    pub fn is_broken(&self) -> bool {
+        self.broken
+    }
+
+    /// Sends one command and reads its response. An ACK comes back as an
+    /// `AckError` and leaves the connection usable.
+    pub async fn execute(&mut self, command: &Command) -> Result<Response> {
+        let line = command.to_line()?;
+        let mut responses = self.exchange(line, false).await?;
+        Ok(responses.pop().unwrap_or_default())
+    }
+
+    /// Sends the commands as one `command_list_ok_begin` block and returns
+    /// one response per command. MPD stops at the first failing command;
+    /// its position is the `index` of the returned `AckError`.
+    pub async fn execute_list(&mut self, commands: &[Command]) -> Result<Vec<Response>> {
+        if commands.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut block = String::from("command_list_ok_begin\n");
+        for command in commands {
+            block.push_str(&command.to_line()?);
+        }
+        block.push_str("command_list_end\n");
+
+        self.exchange(block, true).await
+    }
+
+    async fn exchange(&mut self, request: String, list: bool) -> Result<Vec<Response>> {
+        if self.broken {
+            return Err(anyhow!("MPD connection is closed"));
+        }
+
+        let result = match tokio::time::timeout(self.timeout, self.send_and_read(&request, list)).await {
+            Ok(result) => result,
+            Err(_) => Err(anyhow!("Timed out waiting for MPD after {:?}", self.timeout)),
+        };
+
+        if let Err(e) = &result {
+            if e.downcast_ref::<AckError>().is_none() {
+                self.broken = true;
+            }
+        }
+        result
+    }
+
+    async fn send_and_read(&mut self, request: &str, list: bool) -> Result<Vec<Response>> {
+        self.stream.get_mut().write_all(request.as_bytes()).await?;
+        self.stream.get_mut().flush().await?;
+
+        let mut responses = Vec::new();
+        let mut current = Response::default();
+
+        loop {
+            let line = self.read_line().await?;
+
+            if line == "OK" {
+                if !list {
+                    responses.push(current);
+                }
+                return Ok(responses);
+            }
+            if list && line == "list_OK" {
+                responses.push(std::mem::take(&mut current));
+                continue;
+            }
+            if let Some(ack) = AckError::parse(&line) {
+                return Err(ack.into());
+            }
+
+            let (key, value) = protocol::parse_pair(&line)?;
+            if key == "binary" {
+                let len: usize = value
+                    .parse()
+                    .map_err(|_| anyhow!("Invalid binary length: {:?}", value))?;
+                let mut data = vec![0u8; len];
+                self.stream.read_exact(&mut data).await?;
+                // the payload is followed by a bare newline
+                let mut newline = [0u8; 1];
+                self.stream.read_exact(&mut newline).await?;
+                current.binary = Some(data);
+            } else {
+                current.pairs.push((key, value));
+            }
+        }
+    }
+
+    async fn read_line(&mut self) -> Result<String> {
+        let mut buf = Vec::new();
+        let n = self.stream.read_until(b'\n', &mut buf).await?;
+        if n == 0 || buf.last() != Some(&b'\n') {
+            return Err(anyhow!("MPD closed the connection"));
+        }
+        buf.pop();
+        Ok(String::from_utf8(buf)?)
+    }
+
+    pub async fn ping(&mut self) -> Result<()> {
+        self.execute(&Command::new("ping")).await?;
+        Ok(())
+    }
+
+    pub async fn queue(&mut self) -> Result<Vec<Song>> {
+        let response = self.execute(&Command::new("playlistinfo")).await?;
+        Ok(protocol::songs_from_pairs(&response.pairs))
+    }
+
+    pub async fn playlist(&mut self, name: &str) -> Result<Vec<Song>> {
+        let response = self.execute(&Command::new("listplaylistinfo").arg(name)).await?;
+        Ok(protocol::songs_from_pairs(&response.pairs))
+    }
+
+    pub async fn playlists(&mut self) -> Result<Vec<Playlist>> {
+        let response = self.execute(&Command::new("listplaylists")).await?;
+        Ok(protocol::playlists_from_pairs(&response.pairs))
+    }
+
+    pub async fn listall(&mut self) -> Result<Vec<Song>> {
+        let response = self.execute(&Command::new("listallinfo")).await?;
+        Ok(protocol::songs_from_pairs(&response.pairs))
+    }
+
+    /// Exact-match search, like `RawMpdClient::search` (`find` in MPD terms).
+    pub async fn search(&mut self, query: &Query, window: Option<(u32, u32)>) -> Result<Vec<Song>> {
+        let mut command = Command::new("find");
+        for term in &query.terms {
+            command = match term {
+                FilterTerm::Any(value) => command.arg("any").arg(value),
+                FilterTerm::Tag(tag, value) => command.arg(tag.to_lowercase()).arg(value),
+            };
+        }
+        if let Some((start, end)) = window {
+            command = command.arg("window").arg(format!("{}:{}", start, end));
+        }
+
+        let response = self.execute(&command).await?;
+        Ok(protocol::songs_from_pairs(&response.pairs))
+    }
+
+    pub async fn consume(&mut self, state: bool) -> Result<()> {
+        self.execute(&Command::new("consume").arg(if state { 1 } else { 0 })).await?;
+        Ok(())
+    }
+
+    pub async fn push(&mut self, file: &str) -> Result<u32> {
+        let response = self.execute(&Command::new("addid").arg(file)).await?;
+        response
+            .get("Id")
+            .and_then(|id| id.parse().ok())
+            .ok_or_else(|| anyhow!("MPD did not return an Id for {}", file))
+    }
+
+    pub async fn delete(&mut self, pos: u32) -> Result<()> {
+        self.execute(&Command::new("delete").arg(pos)).await?;
+        Ok(())
+    }
+
+    pub async fn play(&mut self) -> Result<()> {
+        self.execute(&Command::new("play")).await?;
+        Ok(())
+    }
+
+    pub async fn set_volume(&mut self, volume: u32) -> Result<()> {
+        self.execute(&Command::new("setvol").arg(volume)).await?;
+        Ok(())
+    }
+
+    pub async fn pl_push(&mut self, playlist: &str, file: &str) -> Result<()> {
+        self.execute(&Command::new("playlistadd").arg(playlist).arg(file)).await?;
+        Ok(())
+    }
+
+    pub async fn pl_delete(&mut self, playlist: &str, pos: u32) -> Result<()> {
+        self.execute(&Command::new("playlistdelete").arg(playlist).arg(pos)).await?;
+        Ok(())
+    }
+
+    pub async fn pl_clear(&mut self, playlist: &str) -> Result<()> {
+        self.execute(&Command::new("playlistclear").arg(playlist)).await?;
+        Ok(())
+    }
+
+    pub async fn pl_remove(&mut self, playlist: &str) -> Result<()> {
+        self.execute(&Command::new("rm").arg(playlist)).await?;
+        Ok(())
+    }
+
+    /// Waits for one of `subsystems` to change, with no timeout. If the
+    /// future is dropped mid-wait the connection is left marked broken,
+    /// since MPD still considers it idle.
+    pub async fn idle(&mut self, subsystems: &[&str]) -> Result<Vec<String>> {
+        if self.broken {
+            return Err(anyhow!("MPD connection is closed"));
+        }
+
+        let mut command = Command::new("idle");
+        for subsystem in subsystems {
+            command = command.arg(subsystem);
+        }
+
+        let line = command.to_line()?;
+        self.broken = true;
+        let response = match self.send_and_read(&line, false).await {
+            Ok(mut responses) => responses.pop().unwrap_or_default(),
+            Err(e) => {
+                if e.downcast_ref::<AckError>().is_some() {
+                    self.broken = false;
+                }
+                return Err(e);
+            }
+        };
+        self.broken = false;
+
+        Ok(response
+            .pairs
+            .into_iter()
+            .filter(|(k, _)| k == "changed")
+            .map(|(_, v)| v)
+            .collect())
+    }
+
+    /// Fetches the cover art stored next to `uri`, one `albumart` chunk at a time.
+    pub async fn album_art(&mut self, uri: &str) -> Result<Vec<u8>> {
+        let mut data = Vec::new();
+
+        loop {
+            let response = self
+                .execute(&Command::new("albumart").arg(uri).arg(data.len()))
+                .await?;
+            let size: usize = response
+                .get("size")
+                .and_then(|s| s.parse().ok())
+                .ok_or_else(|| anyhow!("albumart response is missing its size"))?;
+            let chunk = response.binary.unwrap_or_default();
+
+            if chunk.is_empty() && data.len() < size {
+                return Err(anyhow!("albumart returned an empty chunk at offset {}", data.len()));
+            }
+            data.extend(chunk);
+
+            if data.len() >= size {
+                return Ok(data);
+            }
+        }
+    }
+}
+
+type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+type Job = Box<dyn for<'a> FnOnce(&'a mut AsyncMpdClient) -> BoxFuture<'a, ()> + Send>;
+
+fn job<F>(f: F) -> Job
+where
+    F: for<'a> FnOnce(&'a mut AsyncMpdClient) -> BoxFuture<'a, ()> + Send + 'static,
+{
+    Box::new(f)
+}
+
+/// Blocking handle to an `AsyncMpdClient` that lives on its own thread and
+/// runtime, so it can be driven from both sync and async callers.
+pub struct NativeMpdClient {
+    jobs: mpsc::Sender<Job>,
+}
+
+impl NativeMpdClient {
+    pub fn connect(host: &str, port: u16) -> Result<Self> {
+        let (jobs, job_rx) = mpsc::channel::<Job>();
+        let (ready_tx, ready_rx) = mpsc::channel::<Result<()>>();
+        let host = host.to_string();
+
+        std::thread::Builder::new()
+            .name(format!("mpd-{}:{}", host, port))
+            .spawn(move || {
+                let runtime = match tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()
+                {
+                    Ok(rt) => rt,
+                    Err(e) => {
+                        let _ = ready_tx.send(Err(e.into()));
+                        return;
+                    }
+                };
+
+                let mut client = match runtime.block_on(AsyncMpdClient::connect(&host, port)) {
+                    Ok(client) => client,
+                    Err(e) => {
+                        let _ = ready_tx.send(Err(e));
+                        return;
+                    }
+                };
+                let _ = ready_tx.send(Ok(()));
+
+                // runs until the handle is dropped
+                while let Ok(job) = job_rx.recv() {
+                    runtime.block_on(job(&mut client));
+                }
+            })?;
+
+        ready_rx
+            .recv()
+            .map_err(|_| anyhow!("MPD connection thread exited during connect"))??;
+
+        Ok(NativeMpdClient { jobs })
+    }
+
+    fn call<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: for<'a> FnOnce(&'a mut AsyncMpdClient) -> BoxFuture<'a, Result<T>> + Send + 'static,
+    {
+        let (tx, rx) = mpsc::channel();
+        self.jobs
+            .send(job(move |client| {
+                Box::pin(async move {
+                    let _ = tx.send(f(client).await);
+                })
+            }))
+            .map_err(|_| anyhow!("MPD connection thread has exited"))?;
+
+        let wait = move || rx.recv().map_err(|_| anyhow!("MPD connection thread has exited"))?;
+        // On the server's multi-threaded runtime, hand the worker's other
+        // tasks off while this one waits on MPD. A current-thread runtime
+        // (tests, the connection's own thread) cannot, and just waits.
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => tokio::task::block_in_place(wait),
+            _ => wait(),
+        }
+    }
+}
+
+impl MpdClient for NativeMpdClient {
+    fn ping(&mut self) -> Result<()> {
+        self.call(|c| Box::pin(c.ping()))
+    }
+
+    fn playlist(&mut self, name: &str) -> Result<Vec<Song>> {
+        let name = name.to_string();
+        self.call(move |c| Box::pin(async move { c.playlist(&name).await }))
+    }
+
+    fn playlists(&mut self) -> Result<Vec<Playlist>> {
+        self.call(|c| Box::pin(c.playlists()))
+    }
+
+    fn queue(&mut self) -> Result<Vec<Song>> {
+        self.call(|c| Box::pin(c.queue()))
+    }
+
+    fn search(&mut self, query: &Query, window: Option<(u32, u32)>) -> Result<Vec<Song>> {
+        let query = query.clone();
+        self.call(move |c| Box::pin(async move { c.search(&query, window).await }))
+    }
+
+    fn consume(&mut self, state: bool) -> Result<()> {
+        self.call(move |c| Box::pin(c.consume(state)))
+    }
+
+    fn push(&mut self, file: &str) -> Result<u32> {
+        let file = file.to_string();
+        self.call(move |c| Box::pin(async move { c.push(&file).await }))
+    }
+
+    fn delete(&mut self, pos: u32) -> Result<()> {
+        self.call(move |c| Box::pin(c.delete(pos)))
+    }
+
+    fn play(&mut self) -> Result<()> {
+        self.call(|c| Box::pin(c.play()))
+    }
+
+    fn set_volume(&mut self, volume: u32) -> Result<()> {
+        self.call(move |c| Box::pin(c.set_volume(volume)))
+    }
+
+    fn pl_push(&mut self, playlist: &str, file: &str) -> Result<()> {
+        let (playlist, file) = (playlist.to_string(), file.to_string());
+        self.call(move |c| Box::pin(async move { c.pl_push(&playlist, &file).await }))
+    }
+
+    fn pl_delete(&mut self, playlist: &str, pos: u32) -> Result<()> {
+        let playlist = playlist.to_string();
+        self.call(move |c| Box::pin(async move { c.pl_delete(&playlist, pos).await }))
+    }
+
+    fn pl_clear(&mut self, playlist: &str) -> Result<()> {
+        let playlist = playlist.to_string();
+        self.call(move |c| Box::pin(async move { c.pl_clear(&playlist).await }))
+    }
+
+    fn pl_remove(&mut self, playlist: &str) -> Result<()> {
+        let playlist = playlist.to_string();
+        self.call(move |c| Box::pin(async move { c.pl_remove(&playlist).await }))
+    }
+
+    fn listall(&mut self) -> Result<Vec<Song>> {
+        self.call(|c| Box::pin(c.listall()))
+    }
+
+    fn idle(&mut self, subsystems: &[&str]) -> Result<Vec<String>> {
+        let subsystems: Vec<String> = subsystems.iter().map(|s| s.to_string()).collect();
+        self.call(move |c| {
+            Box::pin(async move {
+                let subsystems: Vec<&str> = subsystems.iter().map(|s| s.as_str()).collect();
+                c.idle(&subsystems).await
+            })
+        })
+    }
+}
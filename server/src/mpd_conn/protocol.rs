@@ -0,0 +1,206 @@
+// Encoding and decoding for the MPD text protocol, independent of any
+// transport. See https://mpd.readthedocs.io/en/latest/protocol.html
+use anyhow::{anyhow, Result};
+use std::fmt;
+
+use crate::mpd_conn::traits::{parse_tag_number, Playlist, Song};
+
+/// A single protocol command and its arguments. Arguments are always sent
+/// quoted, so values with spaces or quotes need no special handling. Line
+/// breaks and NULs cannot be quoted and are refused by `to_line`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Command {
+    pub name: String,
+    pub args: Vec<String>,
+}
+
+impl Command {
+    pub fn new(name: &str) -> Self {
+        Command {
+            name: name.to_string(),
+            args: Vec::new(),
+        }
+    }
+
+    pub fn arg(mut self, arg: impl ToString) -> Self {
+        self.args.push(arg.to_string());
+        self
+    }
+
+    /// The request line, including the trailing newline. Fails if an
+    /// argument holds a line break or NUL, which would end the line early
+    /// and let the rest run as a command of its own.
+    pub fn to_line(&self) -> Result<String> {
+        let mut line = self.name.clone();
+        for arg in &self.args {
+            if arg.contains(['\n', '\r', '\0']) {
+                return Err(anyhow!(
+                    "{} argument {:?} contains a line break or NUL",
+                    self.name,
+                    arg
+                ));
+            }
+            line.push(' ');
+            line.push_str(&quote(arg));
+        }
+        line.push('\n');
+        Ok(line)
+    }
+}
+
+pub fn quote(arg: &str) -> String {
+    let mut quoted = String::with_capacity(arg.len() + 2);
+    quoted.push('"');
+    for c in arg.chars() {
+        if c == '"' || c == '\\' {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// An `ACK [code@index] {command} message` error line.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AckError {
+    pub code: u32,
+    /// Position of the failing command within a command list, 0 otherwise.
+    pub index: u32,
+    pub command: String,
+    pub message: String,
+}
+
+impl AckError {
+    pub const NOT_LIST: u32 = 1;
+    pub const ARG: u32 = 2;
+    pub const PASSWORD: u32 = 3;
+    pub const PERMISSION: u32 = 4;
+    pub const UNKNOWN: u32 = 5;
+    pub const NO_EXIST: u32 = 50;
+    pub const PLAYLIST_MAX: u32 = 51;
+    pub const SYSTEM: u32 = 52;
+    pub const PLAYLIST_LOAD: u32 = 53;
+    pub const UPDATE_ALREADY: u32 = 54;
+    pub const PLAYER_SYNC: u32 = 55;
+    pub const EXIST: u32 = 56;
+
+    pub fn parse(line: &str) -> Option<AckError> {
+        let rest = line.strip_prefix("ACK [")?;
+        let (code_index, rest) = rest.split_once(']')?;
+        let (code, index) = code_index.split_once('@')?;
+        let rest = rest.trim_start().strip_prefix('{')?;
+        let (command, message) = rest.split_once('}')?;
+
+        Some(AckError {
+            code: code.parse().ok()?,
+            index: index.parse().ok()?,
+            command: command.to_string(),
+            message: message.trim().to_string(),
+        })
+    }
+}
+
+impl fmt::Display for AckError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "MPD ACK [{}@{}] {{{}}} {}", self.code, self.index, self.command, self.message)
+    }
+}
+
+impl std::error::Error for AckError {}
+
+/// Everything MPD sent back for one command, up to its `OK` / `list_OK`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Response {
+    pub pairs: Vec<(String, String)>,
+    /// The payload of a `binary: N` section, if the command returned one.
+    pub binary: Option<Vec<u8>>,
+}
+
+impl Response {
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.pairs
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+pub fn parse_pair(line: &str) -> Result<(String, String)> {
+    line.split_once(": ")
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .ok_or_else(|| anyhow!("Malformed MPD response line: {:?}", line))
+}
+
+/// Splits a song listing into songs. Each `file` key starts a new song;
+/// `directory` and `playlist` entries (as sent by `listallinfo`) are skipped.
+pub fn songs_from_pairs(pairs: &[(String, String)]) -> Vec<Song> {
+    let mut songs = Vec::new();
+    let mut current: Option<Song> = None;
+
+    for (key, value) in pairs {
+        match key.as_str() {
+            "file" => {
+                songs.extend(current.take());
+                current = Some(Song {
+                    file: value.clone(),
+                    ..Default::default()
+                });
+            }
+            "directory" | "playlist" => songs.extend(current.take()),
+            _ => {
+                if let Some(song) = current.as_mut() {
+                    apply_song_field(song, key, value);
+                }
+            }
+        }
+    }
+
+    songs.extend(current);
+    songs
+}
+
+fn apply_song_field(song: &mut Song, key: &str, value: &str) {
+    match key {
+        "Title" => song.title = Some(value.to_string()),
+        "Artist" => song.artist = Some(value.to_string()),
+        "Album" => song.album = Some(value.to_string()),
+        "AlbumArtist" => song.album_artist = Some(value.to_string()),
+        "Track" => song.track = parse_tag_number(value),
+        "Disc" => song.disc = parse_tag_number(value),
+        "Date" => song.date = Some(value.to_string()),
+        // multi-valued tags repeat; keep the first value
+        "Genre" if song.genre.is_none() => song.genre = Some(value.to_string()),
+        "Composer" if song.composer.is_none() => song.composer = Some(value.to_string()),
+        // `duration` is fractional and newer than the integral `Time`
+        "duration" => {
+            if let Ok(secs) = value.parse::<f64>() {
+                song.duration = Some(secs.round() as u32);
+            }
+        }
+        "Time" if song.duration.is_none() => song.duration = value.parse().ok(),
+        "Pos" => song.pos = value.parse().ok(),
+        "Id" => song.id = value.parse().ok(),
+        _ => {}
+    }
+}
+
+pub fn playlists_from_pairs(pairs: &[(String, String)]) -> Vec<Playlist> {
+    let mut playlists: Vec<Playlist> = Vec::new();
+    for (key, value) in pairs {
+        match (key.as_str(), playlists.last_mut()) {
+            ("playlist", _) => playlists.push(Playlist {
+                name: value.clone(),
+                last_modified: None,
+            }),
+            ("Last-Modified", Some(playlist)) => {
+                playlist.last_modified = value
+                    .parse::<jiff::Timestamp>()
+                    .ok()
+                    .and_then(|at| u64::try_from(at.as_second()).ok());
+            }
+            _ => {}
+        }
+    }
+    playlists
+}
@@ -2,7 +2,18 @@ use jukectl_mpdclient_sys::*;
 use std::ffi::{CStr, CString};
 use std::ptr;
 use anyhow::{anyhow, Result};
-use crate::mpd_conn::traits::{Song, Playlist, Query, FilterTerm};
+use crate::mpd_conn::traits::{parse_tag_number, Song, Playlist, Query, FilterTerm};
+
+const IDLE_SUBSYSTEMS: &[(&str, mpd_idle)] = &[
+    ("database", mpd_idle_MPD_IDLE_DATABASE),
+    ("stored_playlist", mpd_idle_MPD_IDLE_STORED_PLAYLIST),
+    ("playlist", mpd_idle_MPD_IDLE_QUEUE),
+    ("player", mpd_idle_MPD_IDLE_PLAYER),
+    ("mixer", mpd_idle_MPD_IDLE_MIXER),
+    ("output", mpd_idle_MPD_IDLE_OUTPUT),
+    ("options", mpd_idle_MPD_IDLE_OPTIONS),
+    ("update", mpd_idle_MPD_IDLE_UPDATE),
+];
 
 pub struct RawMpdClient {
     conn: *mut mpd_connection,
@@ -35,7 +46,11 @@ impl RawMpdClient {
                 } else {
                     CStr::from_ptr(msg).to_str().unwrap_or("Invalid UTF-8 error message")
                 };
-                return Err(anyhow!("MPD Error ({}): {}", error, msg_str));
+                let err = anyhow!("MPD Error ({}): {}", error, msg_str);
+                // libmpdclient keeps failing every call until a recoverable
+                // error (e.g. a server ACK) is cleared
+                mpd_connection_clear_error(self.conn);
+                return Err(err);
             }
         }
         Ok(())
@@ -72,6 +87,15 @@ impl RawMpdClient {
         Ok(())
     }
 
+    pub fn set_volume(&self, volume: u32) -> Result<()> {
+        unsafe {
+            if !mpd_run_set_volume(self.conn, volume) {
+                self.check_error()?;
+            }
+        }
+        Ok(())
+    }
+
     pub fn queue_add(&self, file: &str) -> Result<()> {
         let file_c = CString::new(file)?;
         unsafe {
@@ -141,8 +165,11 @@ impl RawMpdClient {
                 }
                 let name = mpd_playlist_get_path(pl);
                 if !name.is_null() {
+                    // 0 when the server didn't send one
+                    let last_modified = u64::try_from(mpd_playlist_get_last_modified(pl)).ok().filter(|&secs| secs > 0);
                     playlists.push(Playlist {
                         name: CStr::from_ptr(name).to_string_lossy().into_owned(),
+                        last_modified,
                     });
                 }
                 mpd_playlist_free(pl);
@@ -205,6 +232,16 @@ impl RawMpdClient {
         Ok(())
     }
 
+    pub fn playlist_remove(&self, playlist: &str) -> Result<()> {
+        let pl_c = CString::new(playlist)?;
+        unsafe {
+            if !mpd_run_rm(self.conn, pl_c.as_ptr()) {
+                self.check_error()?;
+            }
+        }
+        Ok(())
+    }
+
     pub fn search(&self, query: &Query) -> Result<Vec<Song>> {
         unsafe {
             if !mpd_search_db_songs(self.conn, true) {
@@ -246,6 +283,25 @@ impl RawMpdClient {
         }
     }
 
+    /// Waits (without a timeout) for one of `subsystems` to change.
+    pub fn idle(&self, subsystems: &[&str]) -> Result<Vec<String>> {
+        let mask = subsystems
+            .iter()
+            .filter_map(|name| IDLE_SUBSYSTEMS.iter().find(|(n, _)| n == name))
+            .fold(0, |mask, (_, bit)| mask | bit);
+
+        let changed = unsafe { mpd_run_idle_mask(self.conn, mask) };
+        if changed == 0 {
+            self.check_error()?;
+        }
+
+        Ok(IDLE_SUBSYSTEMS
+            .iter()
+            .filter(|(_, bit)| changed & bit != 0)
+            .map(|(name, _)| name.to_string())
+            .collect())
+    }
+
     fn recv_song(&self) -> Result<Option<Song>> {
         unsafe {
             let song_ptr = mpd_recv_song(self.conn);
@@ -258,6 +314,12 @@ impl RawMpdClient {
             let title = self.get_tag(song_ptr, mpd_tag_type_MPD_TAG_TITLE);
             let artist = self.get_tag(song_ptr, mpd_tag_type_MPD_TAG_ARTIST);
             let album = self.get_tag(song_ptr, mpd_tag_type_MPD_TAG_ALBUM);
+            let album_artist = self.get_tag(song_ptr, mpd_tag_type_MPD_TAG_ALBUM_ARTIST);
+            let track = self.get_tag(song_ptr, mpd_tag_type_MPD_TAG_TRACK);
+            let disc = self.get_tag(song_ptr, mpd_tag_type_MPD_TAG_DISC);
+            let date = self.get_tag(song_ptr, mpd_tag_type_MPD_TAG_DATE);
+            let genre = self.get_tag(song_ptr, mpd_tag_type_MPD_TAG_GENRE);
+            let composer = self.get_tag(song_ptr, mpd_tag_type_MPD_TAG_COMPOSER);
             let duration = mpd_song_get_duration(song_ptr);
             let pos = mpd_song_get_pos(song_ptr);
             let id = mpd_song_get_id(song_ptr);
@@ -272,6 +334,12 @@ impl RawMpdClient {
                 duration: if duration > 0 { Some(duration) } else { None },
                 pos: if pos != u32::MAX { Some(pos) } else { None },
                 id: if id != u32::MAX { Some(id) } else { None },
+                album_artist,
+                track: track.as_deref().and_then(parse_tag_number),
+                disc: disc.as_deref().and_then(parse_tag_number),
+                date,
+                genre,
+                composer,
             }))
         }
     }
@@ -293,6 +361,11 @@ impl RawMpdClient {
             "album" => mpd_tag_type_MPD_TAG_ALBUM,
             "title" => mpd_tag_type_MPD_TAG_TITLE,
             "genre" => mpd_tag_type_MPD_TAG_GENRE,
+            "albumartist" => mpd_tag_type_MPD_TAG_ALBUM_ARTIST,
+            "composer" => mpd_tag_type_MPD_TAG_COMPOSER,
+            "date" => mpd_tag_type_MPD_TAG_DATE,
+            "track" => mpd_tag_type_MPD_TAG_TRACK,
+            "disc" => mpd_tag_type_MPD_TAG_DISC,
             _ => mpd_tag_type_MPD_TAG_UNKNOWN,
         }
     }
@@ -1,7 +1,7 @@
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct Song {
     pub file: String,
     pub title: Option<String>,
@@ -10,11 +10,34 @@ pub struct Song {
     pub duration: Option<u32>,
     pub pos: Option<u32>,
     pub id: Option<u32>,
+    pub album_artist: Option<String>,
+    /// Track number within its disc, from a tag such as `3` or `3/12`.
+    pub track: Option<u32>,
+    pub disc: Option<u32>,
+    pub date: Option<String>,
+    pub genre: Option<String>,
+    pub composer: Option<String>,
+}
+
+impl Song {
+    /// The artist an album belongs to: `AlbumArtist`, falling back to
+    /// `Artist`, so compilation tracks stay together (SPEC 0001).
+    pub fn album_identity_artist(&self) -> Option<&str> {
+        self.album_artist.as_deref().or(self.artist.as_deref())
+    }
+}
+
+/// Parses the leading number of a `Track`/`Disc` tag (`"03"`, `"3/12"`).
+pub fn parse_tag_number(value: &str) -> Option<u32> {
+    let digits: String = value.trim().chars().take_while(|c| c.is_ascii_digit()).collect();
+    digits.parse().ok()
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Playlist {
     pub name: String,
+    /// Unix seconds MPD last saved it, when it says.
+    pub last_modified: Option<u64>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -49,8 +72,14 @@ pub trait MpdClient: Send {
     fn push(&mut self, file: &str) -> Result<u32>;
     fn delete(&mut self, pos: u32) -> Result<()>;
     fn play(&mut self) -> Result<()>;
+    /// Sets the output volume, 0 to 100.
+    fn set_volume(&mut self, volume: u32) -> Result<()>;
     fn pl_push(&mut self, playlist: &str, file: &str) -> Result<()>;
     fn pl_delete(&mut self, playlist: &str, pos: u32) -> Result<()>;
+    fn pl_clear(&mut self, playlist: &str) -> Result<()>;
     fn pl_remove(&mut self, playlist: &str) -> Result<()>;
     fn listall(&mut self) -> Result<Vec<Song>>;
+    /// Blocks until one of `subsystems` (MPD idle names such as `player` or
+    /// `stored_playlist`) changes and returns the ones that did.
+    fn idle(&mut self, subsystems: &[&str]) -> Result<Vec<String>>;
 }
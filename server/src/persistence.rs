@@ -0,0 +1,276 @@
+use anyhow::{Context, Result};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::sync::{Mutex, Notify};
+
+use crate::app_state::{AppState, Config};
+use crate::models::song_queue::QueueSnapshot;
+use crate::models::song_stats::PlayStats;
+use crate::models::station::Stations;
+use crate::scheduler::schedule::{EngineState, ScheduleEntry};
+use crate::models::tags_data::TagsData;
+
+pub const TAGS_FILE: &str = "tags.json";
+pub const CONFIG_FILE: &str = "config.json";
+pub const QUEUE_FILE: &str = "queue.json";
+pub const STATIONS_FILE: &str = "stations.json";
+pub const SCHEDULE_FILE: &str = "schedule.json";
+pub const SCHEDULE_STATE_FILE: &str = "schedule_state.json";
+pub const STATS_FILE: &str = "stats.json";
+
+/// How long autosave waits after a change, so a burst of changes (a refill
+/// followed by a dequeue) is written once.
+const SAVE_DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Snapshots of the active tags, config and remaining queue as JSON files
+/// under a data dir, so a restart resumes where the jukebox left off.
+/// Without a data dir every operation is a no-op.
+pub struct StateStore {
+    dir: Option<PathBuf>,
+    dirty: Notify,
+    // held for a whole save, so the autosave and the shutdown save never
+    // write the same temp files at once
+    saving: Mutex<()>,
+}
+
+impl StateStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        StateStore {
+            dir: Some(dir.into()),
+            dirty: Notify::new(),
+            saving: Mutex::new(()),
+        }
+    }
+
+    pub fn disabled() -> Self {
+        StateStore {
+            dir: None,
+            dirty: Notify::new(),
+            saving: Mutex::new(()),
+        }
+    }
+
+    pub fn dir(&self) -> Option<&Path> {
+        self.dir.as_deref()
+    }
+
+    /// Asks the autosave task to write a fresh snapshot soon.
+    pub fn mark_dirty(&self) {
+        if self.dir.is_some() {
+            self.dirty.notify_one();
+        }
+    }
+
+    pub fn load_tags(&self) -> Option<TagsData> {
+        self.read_json(TAGS_FILE)
+    }
+
+    /// `base` with the saved settings laid over it, or `None` if the file is
+    /// missing, unreadable or out of range.
+    pub fn load_config(&self, base: &Config) -> Option<Config> {
+        let changed: serde_json::Value = self.read_json(CONFIG_FILE)?;
+        match base.patched(changed) {
+            Ok(config) => Some(config),
+            Err(e) => {
+                log::warn!("[!] Ignoring saved config: {}", e);
+                None
+            }
+        }
+    }
+
+    pub fn load_queue(&self) -> Option<QueueSnapshot> {
+        self.read_json(QUEUE_FILE)
+    }
+
+    pub fn load_stations(&self) -> Option<Stations> {
+        self.read_json(STATIONS_FILE)
+    }
+
+    pub fn load_schedule(&self) -> Option<Vec<ScheduleEntry>> {
+        self.read_json(SCHEDULE_FILE)
+    }
+
+    pub fn load_schedule_state(&self) -> Option<EngineState> {
+        self.read_json(SCHEDULE_STATE_FILE)
+    }
+
+    pub fn load_stats(&self) -> Option<PlayStats> {
+        self.read_json(STATS_FILE)
+    }
+
+    /// Writes the current queue, stations, schedule (and how far its engine
+    /// got) and song stats, plus whatever tags and config settings differ
+    /// from the startup ones.
+    pub async fn save(&self, state: &AppState) -> Result<()> {
+        if self.dir.is_none() {
+            return Ok(());
+        }
+        let _saving = self.saving.lock().await;
+
+        let (queue, tags, config, stations) = {
+            let locked_song_queue = state.queue.lock().await;
+            let locked_tags_data = state.tags_data.read().await;
+            let locked_config = state.config.lock().await;
+            let locked_stations = state.stations.read().await;
+            (
+                locked_song_queue.snapshot(),
+                locked_tags_data.clone(),
+                locked_config.clone(),
+                locked_stations.clone(),
+            )
+        };
+
+        if tags == *state.startup_tags {
+            self.remove(TAGS_FILE)?;
+        } else {
+            self.write_json(TAGS_FILE, &tags)?;
+        }
+        self.write_json(CONFIG_FILE, &changed_settings(&state.startup_config, &config)?)?;
+        self.write_json(QUEUE_FILE, &queue)?;
+        self.write_json(STATIONS_FILE, &stations)?;
+        let (schedule, engine) = {
+            let schedule = state.schedule.lock().await;
+            (schedule.rules().to_vec(), schedule.engine_state())
+        };
+        self.write_json(SCHEDULE_FILE, &schedule)?;
+        self.write_json(SCHEDULE_STATE_FILE, &engine)?;
+        let stats = state.song_stats.lock().await.clone();
+        self.write_json(STATS_FILE, &stats)?;
+        Ok(())
+    }
+
+    fn read_json<T: DeserializeOwned>(&self, name: &str) -> Option<T> {
+        let path = self.dir.as_ref()?.join(name);
+        let raw = match fs::read_to_string(&path) {
+            Ok(raw) => raw,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return None,
+            Err(e) => {
+                log::warn!("[!] Could not read {}: {}", path.display(), e);
+                return None;
+            }
+        };
+
+        match serde_json::from_str(&raw) {
+            Ok(value) => Some(value),
+            Err(e) => {
+                log::warn!("[!] Ignoring corrupt {}: {}", path.display(), e);
+                None
+            }
+        }
+    }
+
+    // write-then-rename so a crash mid-save never leaves a truncated file
+    fn write_json<T: Serialize>(&self, name: &str, value: &T) -> Result<()> {
+        let Some(dir) = &self.dir else {
+            return Ok(());
+        };
+        fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+
+        let path = dir.join(name);
+        let tmp = dir.join(format!("{}.tmp", name));
+        fs::write(&tmp, serde_json::to_vec_pretty(value)?).with_context(|| format!("writing {}", tmp.display()))?;
+        fs::rename(&tmp, &path).with_context(|| format!("replacing {}", path.display()))?;
+        Ok(())
+    }
+
+    fn remove(&self, name: &str) -> Result<()> {
+        let Some(dir) = &self.dir else {
+            return Ok(());
+        };
+        let path = dir.join(name);
+        match fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(e).with_context(|| format!("removing {}", path.display()))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The settings of `config` that differ from `startup`, as a partial config
+/// object.
+fn changed_settings(startup: &Config, config: &Config) -> Result<serde_json::Map<String, serde_json::Value>> {
+    let serde_json::Value::Object(startup) = serde_json::to_value(startup)? else {
+        unreachable!("Config serializes to an object");
+    };
+    let serde_json::Value::Object(config) = serde_json::to_value(config)? else {
+        unreachable!("Config serializes to an object");
+    };
+    Ok(config
+        .into_iter()
+        .filter(|(key, value)| startup.get(key) != Some(value))
+        .collect())
+}
+
+/// Loads whatever the store has saved into `state`. Tags and config
+/// settings are only saved once changed at runtime, so anything else keeps
+/// the value resolved at startup, as does anything missing or corrupt.
+pub async fn restore(state: &AppState) {
+    let store = &state.state_store;
+    let Some(dir) = store.dir() else {
+        return;
+    };
+    log::info!("[+] Restoring state from {}", dir.display());
+
+    let mut locked_song_queue = state.queue.lock().await;
+    let mut locked_tags_data = state.tags_data.write().await;
+    let mut locked_config = state.config.lock().await;
+    let mut locked_stations = state.stations.write().await;
+
+    let saved_tags = store.load_tags();
+    let tags_restored = saved_tags.is_some();
+    if let Some(tags) = saved_tags {
+        log::info!("[+] Restored tags {:?}", tags);
+        *locked_tags_data = tags;
+    }
+    if let Some(config) = store.load_config(&locked_config) {
+        *locked_config = config;
+    }
+    if let Some(snapshot) = store.load_queue() {
+        locked_song_queue.restore(snapshot);
+    }
+    if let Some(stations) = store.load_stations() {
+        let startup_station = locked_stations.active().map(|info| info.name);
+        locked_stations.merge_saved(stations);
+        // the saved station only matters alongside the saved tags it played
+        if !tags_restored {
+            locked_stations.set_active(startup_station.as_deref());
+        }
+    }
+    if let Some(rules) = store.load_schedule() {
+        state.schedule.lock().await.restore(rules);
+    }
+    if let Some(engine) = store.load_schedule_state() {
+        state.schedule.lock().await.restore_engine(engine);
+    }
+    if let Some(stats) = store.load_stats() {
+        log::info!("[+] Restored stats for {} songs", stats.len());
+        *state.song_stats.lock().await = stats;
+    }
+
+    locked_song_queue.set_album_aware(locked_config.album_aware_shuffle);
+    locked_song_queue.set_tag_match_mode(locked_config.tag_match_mode);
+    locked_song_queue.set_cooldown(locked_config.cooldown());
+    locked_song_queue.set_weighting(locked_config.weighting());
+}
+
+/// Saves `state` shortly after each `mark_dirty`, for as long as the
+/// runtime lives.
+pub fn spawn_autosave(state: AppState) {
+    if state.state_store.dir().is_none() {
+        return;
+    }
+
+    tokio::spawn(async move {
+        loop {
+            state.state_store.dirty.notified().await;
+            tokio::time::sleep(SAVE_DEBOUNCE).await;
+            if let Err(e) = state.state_store.save(&state).await {
+                log::error!("[!] Failed to save state: {:#}", e);
+            }
+        }
+    });
+}
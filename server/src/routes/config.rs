@@ -0,0 +1,57 @@
+use rocket::serde::json::{self, Json};
+use rocket::{get, patch, post, State, routes};
+use crate::app_state::{AppState, Config};
+use crate::models::tags_data::PlaybackTags;
+use crate::routes::error::ApiError;
+
+pub fn routes() -> Vec<rocket::Route> {
+    routes![toggle_album_mode, get_config, patch_config]
+}
+
+#[post("/album-mode/toggle")]
+pub async fn toggle_album_mode(app_state: &State<AppState>) -> Json<PlaybackTags> {
+    let mut locked_song_queue = app_state.queue.lock().await;
+    let tags = app_state.tags_data.read().await.clone();
+    let mut locked_config = app_state.config.lock().await;
+
+    locked_config.album_aware_shuffle = !locked_config.album_aware_shuffle;
+    locked_song_queue.set_album_aware(locked_config.album_aware_shuffle);
+    log::info!("[+] Album-aware mode is now {}", locked_config.album_aware_shuffle);
+    app_state.state_store.mark_dirty();
+
+    Json(PlaybackTags {
+        tags,
+        album_aware: locked_config.album_aware_shuffle,
+    })
+}
+
+#[get("/config")]
+pub async fn get_config(app_state: &State<AppState>) -> Json<Config> {
+    Json(app_state.config.lock().await.clone())
+}
+
+/// Takes any subset of the keys `GET /config` returns; the rest keep their
+/// current value.
+#[patch("/config", data = "<body>")]
+pub async fn patch_config(
+    app_state: &State<AppState>,
+    body: Result<Json<serde_json::Value>, json::Error<'_>>,
+) -> Result<Json<Config>, ApiError> {
+    let Json(patch) = body?;
+
+    let mut locked_song_queue = app_state.queue.lock().await;
+    let mut locked_config = app_state.config.lock().await;
+
+    let patched = locked_config.patched(patch).map_err(ApiError::BadRequest)?;
+
+    // the scheduler re-reads the rest on its next pass
+    locked_song_queue.set_album_aware(patched.album_aware_shuffle);
+    locked_song_queue.set_tag_match_mode(patched.tag_match_mode);
+    locked_song_queue.set_cooldown(patched.cooldown());
+    locked_song_queue.set_weighting(patched.weighting());
+    *locked_config = patched;
+    log::info!("[+] Config updated: {:?}", *locked_config);
+    app_state.state_store.mark_dirty();
+
+    Ok(Json(locked_config.clone()))
+}
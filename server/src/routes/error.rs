@@ -0,0 +1,88 @@
+use rocket::http::Status;
+use rocket::response::{self, status, Responder};
+use rocket::serde::json::{self, Json};
+use rocket::Request;
+use serde::{Deserialize, Serialize};
+
+/// Why a request failed. Responds with the matching status and an
+/// `ErrorBody`, so clients can tell "MPD is down" from an empty result.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ApiError {
+    /// MPD could not be reached, or failed a command (503).
+    Unavailable(String),
+    /// MPD was reached but refused to make a change, such as editing a
+    /// stored playlist (500).
+    Failed(String),
+    /// The named tag, station or rule does not exist (404).
+    NotFound(String),
+    /// The request body or parameters were invalid (400).
+    BadRequest(String),
+}
+
+/// The JSON body of every error response.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ErrorBody {
+    /// `mpd_unavailable`, `mpd_error`, `not_found` or `bad_request`.
+    pub error: String,
+    pub detail: String,
+}
+
+impl ApiError {
+    pub fn status(&self) -> Status {
+        match self {
+            ApiError::Unavailable(_) => Status::ServiceUnavailable,
+            ApiError::Failed(_) => Status::InternalServerError,
+            ApiError::NotFound(_) => Status::NotFound,
+            ApiError::BadRequest(_) => Status::BadRequest,
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ApiError::Unavailable(_) => "mpd_unavailable",
+            ApiError::Failed(_) => "mpd_error",
+            ApiError::NotFound(_) => "not_found",
+            ApiError::BadRequest(_) => "bad_request",
+        }
+    }
+
+    pub fn detail(&self) -> &str {
+        match self {
+            ApiError::Unavailable(detail)
+            | ApiError::Failed(detail)
+            | ApiError::NotFound(detail)
+            | ApiError::BadRequest(detail) => detail,
+        }
+    }
+}
+
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.kind(), self.detail())
+    }
+}
+
+// pool checkouts and MPD commands both fail with `anyhow::Error`, so `?`
+// on either reports MPD as unavailable
+impl From<anyhow::Error> for ApiError {
+    fn from(e: anyhow::Error) -> Self {
+        ApiError::Unavailable(format!("{:#}", e))
+    }
+}
+
+impl From<json::Error<'_>> for ApiError {
+    fn from(e: json::Error<'_>) -> Self {
+        ApiError::BadRequest(e.to_string())
+    }
+}
+
+impl<'r> Responder<'r, 'static> for ApiError {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        log::error!("[!] {} {}: {}", request.method(), request.uri(), self);
+        let body = ErrorBody {
+            error: self.kind().to_string(),
+            detail: self.detail().to_string(),
+        };
+        status::Custom(self.status(), Json(body)).respond_to(request)
+    }
+}
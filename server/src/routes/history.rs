@@ -0,0 +1,31 @@
+use rocket::serde::json::Json;
+use rocket::{get, State, routes};
+use serde::{Deserialize, Serialize};
+use crate::app_state::AppState;
+use crate::models::history::HistoryEntry;
+
+pub fn routes() -> Vec<rocket::Route> {
+    routes![history]
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HistoryResponse {
+    /// Plays currently held in memory, before `since`/`limit` filtering.
+    pub total: usize,
+    /// Most recent first.
+    pub entries: Vec<HistoryEntry>,
+}
+
+// `since` is in seconds since the unix epoch
+#[get("/history?<limit>&<since>")]
+pub async fn history(
+    app_state: &State<AppState>,
+    limit: Option<usize>,
+    since: Option<u64>,
+) -> Json<HistoryResponse> {
+    let history = app_state.history.lock().await;
+    Json(HistoryResponse {
+        total: history.len(),
+        entries: history.recent(limit.unwrap_or(50), since),
+    })
+}
@@ -2,6 +2,7 @@ use rocket::serde::json::Json;
 use rocket::{get, State, routes};
 use crate::app_state::AppState;
 use crate::mpd_conn::traits::{MpdClient, Song};
+use crate::routes::error::ApiError;
 
 pub fn routes() -> Vec<rocket::Route> {
     routes![index]
@@ -12,16 +13,10 @@ fn queue_to_filenames(song_array: Vec<Song>) -> Vec<String> {
 }
 
 #[get("/")]
-pub async fn index(app_state: &State<AppState>) -> Json<Vec<String>> {
-    let mut pooled_conn = match app_state.mpd_pool.get_connection().await {
-        Ok(c) => c,
-        Err(_) => return Json(vec![]),
-    };
-    
-    let song_array: Vec<Song> = match pooled_conn.mpd_conn().mpd.queue() {
-        Ok(s) => s,
-        Err(_) => vec![],
-    };
-    
-    Json(queue_to_filenames(song_array))
+pub async fn index(app_state: &State<AppState>) -> Result<Json<Vec<String>>, ApiError> {
+    let mut pooled_conn = app_state.mpd_pool.get_connection().await?;
+
+    let song_array: Vec<Song> = pooled_conn.mpd_conn().mpd.queue()?;
+
+    Ok(Json(queue_to_filenames(song_array)))
 }
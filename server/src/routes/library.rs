@@ -0,0 +1,14 @@
+use rocket::serde::json::Json;
+use rocket::{get, State, routes};
+use std::time::SystemTime;
+use crate::app_state::AppState;
+use crate::models::library_index::LibraryStats;
+
+pub fn routes() -> Vec<rocket::Route> {
+    routes![library_stats]
+}
+
+#[get("/library/stats")]
+pub async fn library_stats(app_state: &State<AppState>) -> Json<LibraryStats> {
+    Json(app_state.library.read().await.stats(SystemTime::now()))
+}
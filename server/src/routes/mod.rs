@@ -1,6 +1,13 @@
+mod config;
+pub mod error;
+mod history;
 mod index;
+mod library;
 mod queue;
+mod schedule;
+mod skip;
 mod song;
+mod stations;
 mod tags;
 
 pub fn all_routes() -> Vec<rocket::Route> {
@@ -10,5 +17,11 @@ pub fn all_routes() -> Vec<rocket::Route> {
     routes.extend(queue::routes());
     routes.extend(song::routes());
     routes.extend(tags::routes());
+    routes.extend(skip::routes());
+    routes.extend(config::routes());
+    routes.extend(history::routes());
+    routes.extend(stations::routes());
+    routes.extend(schedule::routes());
+    routes.extend(library::routes());
     routes
 }
@@ -1,33 +1,280 @@
-use rocket::serde::json::Json;
-use rocket::{get, State, routes};
+use rand::seq::SliceRandom;
+use rocket::serde::json::{self, Json};
+use rocket::{delete, get, post, State, routes};
+use serde::{Deserialize, Serialize};
 use crate::app_state::AppState;
 use crate::mpd_conn::traits::{MpdClient, Song};
 use crate::mpd_conn::mpd_pool::PooledMpdConnection;
-use crate::models::song_queue::SongQueue;
+use crate::models::cooldown::RecentPlays;
+use crate::models::library_index::IndexedMpd;
+use crate::models::song_queue::{AlbumSeed, InsertAt, SongQueue};
+use crate::models::weighting::SongWeights;
+use crate::routes::error::ApiError;
+use crate::tagging;
+use std::time::SystemTime;
 use tokio::sync::MutexGuard;
 
 pub fn routes() -> Vec<rocket::Route> {
-    routes![get_queue, clear_queue]
+    routes![
+        get_queue,
+        clear_queue,
+        queue_summary,
+        upcoming,
+        play_next,
+        enqueue,
+        remove_entry,
+        move_entry,
+        reshuffle
+    ]
+}
+
+/// What to queue: exactly one of a file, an album (optionally narrowed to
+/// one artist) or a tag.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct QueueRequest {
+    pub file: Option<String>,
+    pub album: Option<String>,
+    /// Only with `album`: matched against AlbumArtist, then Artist.
+    pub artist: Option<String>,
+    pub tag: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct MoveRequest {
+    pub from: usize,
+    pub to: usize,
+}
+
+/// The songs a change queued, removed or moved, and the queue's new length
+/// in entries (songs, or albums when album-aware).
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct QueueChange {
+    pub songs: Vec<Song>,
+    pub length: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct QueueResponse {
+    pub length: usize,
+    pub head: Vec<String>,
+    pub tail: Vec<String>,
+}
+
+/// What the jukebox means to play next, from its own queue rather than
+/// what MPD already has buffered.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct UpcomingResponse {
+    /// Queued entries: songs, or albums when album-aware.
+    pub length: usize,
+    pub album_aware: bool,
+    /// The next `count` songs. Empty when album-aware.
+    pub songs: Vec<Song>,
+    /// The next `count` albums with their queued tracks. Empty unless
+    /// album-aware.
+    pub albums: Vec<UpcomingAlbum>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct UpcomingAlbum {
+    pub album: Option<String>,
+    /// AlbumArtist, or Artist when that is missing.
+    pub artist: Option<String>,
+    /// The tracks that matched the tags; the whole album plays.
+    pub songs: Vec<Song>,
+}
+
+impl From<&AlbumSeed> for UpcomingAlbum {
+    fn from(seed: &AlbumSeed) -> Self {
+        let first = seed.first();
+        UpcomingAlbum {
+            album: first.album.clone(),
+            artist: first.album_identity_artist().map(str::to_string),
+            songs: seed.songs.clone(),
+        }
+    }
+}
+
+fn filenames(songs: Vec<Song>) -> Vec<String> {
+    songs.into_iter().map(|s| s.file).collect()
+}
+
+#[get("/queue?<count>")]
+pub async fn queue_summary(app_state: &State<AppState>, count: Option<usize>) -> Json<QueueResponse> {
+    let internal_queue: MutexGuard<SongQueue> = app_state.queue.lock().await;
+
+    Json(QueueResponse {
+        length: internal_queue.len(),
+        head: filenames(internal_queue.head(count)),
+        tail: filenames(internal_queue.tail(count)),
+    })
+}
+
+#[get("/queue/upcoming?<count>")]
+pub async fn upcoming(app_state: &State<AppState>, count: Option<usize>) -> Json<UpcomingResponse> {
+    let internal_queue: MutexGuard<SongQueue> = app_state.queue.lock().await;
+    let album_aware = internal_queue.is_album_aware();
+
+    Json(UpcomingResponse {
+        length: internal_queue.len(),
+        album_aware,
+        songs: if album_aware { Vec::new() } else { internal_queue.head(count) },
+        albums: internal_queue
+            .albums()
+            .take(count.unwrap_or(10))
+            .map(UpcomingAlbum::from)
+            .collect(),
+    })
 }
 
 #[get("/queue/all")]
-pub async fn get_queue(app_state: &State<AppState>) -> Json<Vec<Song>> {
-    let mut pooled_conn: PooledMpdConnection = match app_state.mpd_pool.get_connection().await {
-        Ok(c) => c,
-        Err(_) => return Json(vec![]),
-    };
-    
-    let queue = match pooled_conn.mpd_conn().mpd.queue() {
-        Ok(q) => q,
-        Err(_) => vec![],
-    };
-    
-    Json(queue)
+pub async fn get_queue(app_state: &State<AppState>) -> Result<Json<Vec<Song>>, ApiError> {
+    let mut pooled_conn: PooledMpdConnection = app_state.mpd_pool.get_connection().await?;
+
+    let queue = pooled_conn.mpd_conn().mpd.queue()?;
+
+    Ok(Json(queue))
 }
 
 #[get("/queue/clear")]
 pub async fn clear_queue(app_state: &State<AppState>) -> Json<bool> {
     let mut internal_queue: MutexGuard<SongQueue> = app_state.queue.lock().await;
     internal_queue.clear();
+    app_state.state_store.mark_dirty();
     Json(true)
 }
+
+/// The songs `request` names: a file, an album in tracklist order, or a
+/// tag's songs shuffled.
+async fn resolve_request(app_state: &AppState, request: QueueRequest) -> Result<Vec<Song>, ApiError> {
+    let mut pooled_conn: PooledMpdConnection = app_state.mpd_pool.get_connection().await?;
+    let library = app_state.library.read().await;
+    let mpd = &mut IndexedMpd::new(&library, &mut pooled_conn.mpd_conn().mpd);
+
+    match request {
+        QueueRequest { file: Some(file), album: None, artist: None, tag: None } => {
+            let song = match library.song(&file) {
+                Some(song) => Some(song.clone()),
+                None => mpd.listall()?.into_iter().find(|s| s.file == file),
+            };
+            song.map(|song| vec![song])
+                .ok_or_else(|| ApiError::NotFound(format!("no song `{}`", file)))
+        }
+        QueueRequest { file: None, album: Some(album), artist, tag: None } => {
+            let songs = SongQueue::album_songs(&album, artist.as_deref(), mpd)?;
+            if songs.is_empty() {
+                return Err(ApiError::NotFound(match artist {
+                    Some(artist) => format!("no album `{}` by `{}`", album, artist),
+                    None => format!("no album `{}`", album),
+                }));
+            }
+            Ok(songs)
+        }
+        QueueRequest { file: None, album: None, artist: None, tag: Some(tag) } => {
+            if !tagging::tag_exists(mpd, &tag)? {
+                return Err(ApiError::NotFound(format!("no tag named `{}`", tag)));
+            }
+            let mut songs = mpd.playlist(&tag)?;
+            songs.shuffle(&mut rand::rng());
+            Ok(songs)
+        }
+        _ => Err(ApiError::BadRequest(
+            "give exactly one of `file`, `album` or `tag` (`artist` only goes with `album`)".to_string(),
+        )),
+    }
+}
+
+async fn insert(
+    app_state: &AppState,
+    body: Result<Json<QueueRequest>, json::Error<'_>>,
+    at: InsertAt,
+) -> Result<Json<QueueChange>, ApiError> {
+    let Json(request) = body?;
+    let songs = resolve_request(app_state, request).await?;
+
+    let mut internal_queue: MutexGuard<SongQueue> = app_state.queue.lock().await;
+    internal_queue.insert(songs.clone(), at);
+    log::info!("[+] Queued {} songs ({:?})", songs.len(), at);
+    app_state.state_store.mark_dirty();
+
+    Ok(Json(QueueChange {
+        songs,
+        length: internal_queue.len(),
+    }))
+}
+
+#[post("/queue/next", data = "<body>")]
+pub async fn play_next(
+    app_state: &State<AppState>,
+    body: Result<Json<QueueRequest>, json::Error<'_>>,
+) -> Result<Json<QueueChange>, ApiError> {
+    insert(app_state, body, InsertAt::Front).await
+}
+
+#[post("/queue/enqueue", data = "<body>")]
+pub async fn enqueue(
+    app_state: &State<AppState>,
+    body: Result<Json<QueueRequest>, json::Error<'_>>,
+) -> Result<Json<QueueChange>, ApiError> {
+    insert(app_state, body, InsertAt::Back).await
+}
+
+// `index` counts entries as `/queue/upcoming` lists them: songs, or albums
+// when album-aware
+#[delete("/queue/<index>")]
+pub async fn remove_entry(app_state: &State<AppState>, index: usize) -> Result<Json<QueueChange>, ApiError> {
+    let mut internal_queue: MutexGuard<SongQueue> = app_state.queue.lock().await;
+    let songs = internal_queue
+        .remove_at(index)
+        .ok_or_else(|| ApiError::NotFound(format!("no queue entry {}", index)))?;
+    log::info!("[+] Removed queue entry {} ({} songs)", index, songs.len());
+    app_state.state_store.mark_dirty();
+
+    Ok(Json(QueueChange {
+        songs,
+        length: internal_queue.len(),
+    }))
+}
+
+#[post("/queue/move", data = "<body>")]
+pub async fn move_entry(
+    app_state: &State<AppState>,
+    body: Result<Json<MoveRequest>, json::Error<'_>>,
+) -> Result<Json<QueueChange>, ApiError> {
+    let Json(MoveRequest { from, to }) = body?;
+
+    let mut internal_queue: MutexGuard<SongQueue> = app_state.queue.lock().await;
+    let length = internal_queue.len();
+    let songs = internal_queue.move_entry(from, to).ok_or_else(|| {
+        ApiError::BadRequest(format!("can't move {} to {}, the queue has {} entries", from, to, length))
+    })?;
+    log::info!("[+] Moved queue entry {} to {}", from, to);
+    app_state.state_store.mark_dirty();
+
+    Ok(Json(QueueChange { songs, length }))
+}
+
+#[post("/queue/reshuffle")]
+pub async fn reshuffle(app_state: &State<AppState>) -> Json<QueueChange> {
+    // the connection before the queue lock, see `AppState`
+    let mut pooled_conn = app_state.mpd_pool.get_connection().await.ok();
+    let mut internal_queue: MutexGuard<SongQueue> = app_state.queue.lock().await;
+    // with MPD down the queue is only spaced within itself
+    let recent = match pooled_conn.as_mut() {
+        Some(pooled_conn) => {
+            app_state.recent_plays(internal_queue.cooldown(), &mut pooled_conn.mpd_conn().mpd).await
+        }
+        None => RecentPlays::default(),
+    };
+    let stats = app_state.song_stats.lock().await;
+    let weights = SongWeights::new(&stats, internal_queue.weighting(), SystemTime::now());
+    internal_queue.reshuffle(&recent, &weights);
+    log::info!("[+] Reshuffled the queue");
+    app_state.state_store.mark_dirty();
+
+    Json(QueueChange {
+        songs: Vec::new(),
+        length: internal_queue.len(),
+    })
+}
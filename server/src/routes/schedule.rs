@@ -0,0 +1,122 @@
+use rocket::serde::json::{self, Json};
+use rocket::{delete, get, post, put, State, routes};
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::app_state::AppState;
+use crate::routes::error::ApiError;
+use crate::scheduler::schedule::{Schedule, ScheduleEntry, ScheduleRule, ScheduleTarget};
+
+pub fn routes() -> Vec<rocket::Route> {
+    routes![get_schedule, add_rule, replace_schedule, delete_rule]
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ScheduledRule {
+    #[serde(flatten)]
+    pub entry: ScheduleEntry,
+    /// Unix seconds of the next time the rule fires.
+    pub next_run: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RevertInfo {
+    /// Unix seconds.
+    pub at: u64,
+    pub to: ScheduleTarget,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ScheduleResponse {
+    /// The zone rule times are read in.
+    pub time_zone: String,
+    pub rules: Vec<ScheduledRule>,
+    pub pending_revert: Option<RevertInfo>,
+}
+
+fn unix_secs(at: SystemTime) -> u64 {
+    at.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+fn scheduled(schedule: &Schedule, entry: ScheduleEntry, now: SystemTime) -> ScheduledRule {
+    let next_run = schedule.next_run(&entry.rule.when, now).map(unix_secs);
+    ScheduledRule { entry, next_run }
+}
+
+fn response(schedule: &Schedule) -> ScheduleResponse {
+    let now = SystemTime::now();
+    ScheduleResponse {
+        time_zone: schedule.time_zone().iana_name().unwrap_or("local").to_string(),
+        rules: schedule
+            .rules()
+            .iter()
+            .map(|entry| scheduled(schedule, entry.clone(), now))
+            .collect(),
+        pending_revert: schedule.pending_revert().map(|revert| RevertInfo {
+            at: unix_secs(revert.at),
+            to: revert.restore.clone(),
+        }),
+    }
+}
+
+/// Rejects rules naming a station that does not exist (yet).
+async fn check_stations(app_state: &AppState, rules: &[ScheduleRule]) -> Result<(), ApiError> {
+    let stations = app_state.stations.read().await;
+    for rule in rules {
+        if let Some(name) = &rule.station {
+            if stations.get(name).is_none() {
+                return Err(ApiError::BadRequest(format!("no station named `{}`", name)));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[get("/schedule")]
+pub async fn get_schedule(app_state: &State<AppState>) -> Json<ScheduleResponse> {
+    Json(response(&*app_state.schedule.lock().await))
+}
+
+#[post("/schedule", data = "<body>")]
+pub async fn add_rule(
+    app_state: &State<AppState>,
+    body: Result<Json<ScheduleRule>, json::Error<'_>>,
+) -> Result<Json<ScheduledRule>, ApiError> {
+    let Json(rule) = body?;
+    check_stations(app_state, std::slice::from_ref(&rule)).await?;
+
+    let mut schedule = app_state.schedule.lock().await;
+    let entry = schedule.add(rule).map_err(ApiError::BadRequest)?;
+    log::info!("[+] Added schedule rule {} ({})", entry.id, entry.rule.when);
+    app_state.state_store.mark_dirty();
+
+    Ok(Json(scheduled(&schedule, entry, SystemTime::now())))
+}
+
+#[put("/schedule", data = "<body>")]
+pub async fn replace_schedule(
+    app_state: &State<AppState>,
+    body: Result<Json<Vec<ScheduleRule>>, json::Error<'_>>,
+) -> Result<Json<ScheduleResponse>, ApiError> {
+    let Json(rules) = body?;
+    check_stations(app_state, &rules).await?;
+
+    let mut schedule = app_state.schedule.lock().await;
+    schedule.replace(rules).map_err(ApiError::BadRequest)?;
+    log::info!("[+] Replaced schedule ({} rules)", schedule.rules().len());
+    app_state.state_store.mark_dirty();
+
+    Ok(Json(response(&schedule)))
+}
+
+#[delete("/schedule/<id>")]
+pub async fn delete_rule(app_state: &State<AppState>, id: u64) -> Result<Json<ScheduleEntry>, ApiError> {
+    let entry = app_state
+        .schedule
+        .lock()
+        .await
+        .remove(id)
+        .ok_or_else(|| ApiError::NotFound(format!("no schedule rule {}", id)))?;
+    log::info!("[+] Deleted schedule rule {}", id);
+    app_state.state_store.mark_dirty();
+    Ok(Json(entry))
+}
@@ -0,0 +1,113 @@
+use rocket::serde::json::{self, Json};
+use rocket::{get, post, State, routes};
+use serde::{Deserialize, Serialize};
+use std::time::SystemTime;
+use crate::app_state::AppState;
+use crate::models::skip_log::{SkipCount, SkipReason, SkipRecord};
+use crate::mpd_conn::traits::{MpdClient, Song};
+use crate::mpd_conn::mpd_pool::PooledMpdConnection;
+use crate::routes::error::ApiError;
+use crate::scheduler::clock::SystemClock;
+use crate::scheduler::scheduler_tick;
+
+pub fn routes() -> Vec<rocket::Route> {
+    routes![skip, list_skips]
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct SkipRequest {
+    #[serde(default)]
+    pub reason: Option<SkipReason>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SkipResponse {
+    pub skipped: String,
+    pub new: String,
+    pub reason: Option<SkipReason>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SkipsResponse {
+    /// Skips currently held in the log, before `reason`/`limit` filtering.
+    pub total: usize,
+    pub recent: Vec<SkipRecord>,
+    pub most_skipped: Vec<SkipCount>,
+}
+
+// The CLI posts an empty body; only a non-empty body that fails to parse
+// (e.g. an unknown reason) is rejected.
+#[post("/skip", data = "<body>")]
+pub async fn skip(
+    app_state: &State<AppState>,
+    body: Result<Json<SkipRequest>, json::Error<'_>>,
+) -> Result<Json<SkipResponse>, ApiError> {
+    let request = match body {
+        Ok(Json(request)) => request,
+        Err(json::Error::Parse(raw, _)) if raw.trim().is_empty() => SkipRequest::default(),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut pooled_conn: PooledMpdConnection = app_state.mpd_pool.get_connection().await?;
+    let mpd = &mut pooled_conn.mpd_conn().mpd;
+
+    let queue: Vec<Song> = mpd.queue()?;
+
+    let head = match queue.first() {
+        Some(song) => song.clone(),
+        None => return Err(ApiError::NotFound("nothing is playing".to_string())),
+    };
+    let skipped = head.file.clone();
+
+    mpd.delete(0)?;
+
+    let now = SystemTime::now();
+    app_state.skip_log.lock().await.record(&skipped, request.reason, now);
+    let finished = app_state.history.lock().await.skip(&head, now);
+    app_state.record_plays(finished).await;
+
+    // top up now rather than waiting for the next scheduler pass, so MPD
+    // never sits idle when the last queued song was skipped
+    if let Err(e) = scheduler_tick(app_state.inner(), mpd, &SystemClock).await {
+        log::error!("[!] Scheduler top-up after skip failed: {}", e);
+    }
+    let _ = mpd.play();
+
+    let new = mpd
+        .queue()
+        .ok()
+        .and_then(|q| q.first().map(|s| s.file.clone()))
+        .unwrap_or_default();
+    log::info!("[+] Skipped {} ({:?})", skipped, request.reason);
+
+    Ok(Json(SkipResponse {
+        skipped,
+        new,
+        reason: request.reason,
+    }))
+}
+
+/// The latest skips and the most skipped files, each capped at `limit`
+/// (default 50).
+#[get("/skips?<limit>&<reason>")]
+pub async fn list_skips(
+    app_state: &State<AppState>,
+    limit: Option<usize>,
+    reason: Option<&str>,
+) -> Result<Json<SkipsResponse>, ApiError> {
+    let reason = match reason {
+        Some(r) => match serde_json::from_value::<SkipReason>(serde_json::Value::String(r.to_string())) {
+            Ok(reason) => Some(reason),
+            Err(e) => return Err(ApiError::BadRequest(e.to_string())),
+        },
+        None => None,
+    };
+
+    let limit = limit.unwrap_or(50);
+    let log = app_state.skip_log.lock().await;
+    Ok(Json(SkipsResponse {
+        total: log.len(),
+        recent: log.recent(reason, limit),
+        most_skipped: log.counts(reason).into_iter().take(limit).collect(),
+    }))
+}
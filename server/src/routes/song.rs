@@ -1,39 +1,194 @@
-use rocket::serde::json::Json;
-use rocket::{get, State, routes};
+use rocket::serde::json::{self, Json};
+use rocket::{get, post, put, State, routes};
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
 use crate::app_state::AppState;
+use crate::models::song_stats::SongStats;
+use crate::models::weighting::ScoreFactors;
 use crate::mpd_conn::traits::{MpdClient, Song};
 use crate::mpd_conn::mpd_pool::PooledMpdConnection;
+use crate::routes::error::ApiError;
+use crate::tagging;
 
 pub fn routes() -> Vec<rocket::Route> {
-    routes![now_playing, list_all]
+    routes![now_playing, list_all, update_song_tags, song_score, rate_song]
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SongTagsRequest {
+    /// Defaults to the currently playing song when omitted.
+    pub filename: Option<String>,
+    #[serde(default)]
+    pub add: Vec<String>,
+    #[serde(default)]
+    pub remove: Vec<String>,
+}
+
+/// The tags that actually changed; ones the song already had (or lacked)
+/// are left out.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SongTagsResponse {
+    pub filename: String,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+/// How likely a song is to come up, from `GET /song/<file>/score`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SongScore {
+    pub file: String,
+    /// The product of `factors`; 1 for an unrated song never played.
+    pub weight: f64,
+    pub factors: ScoreFactors,
+    #[serde(flatten)]
+    pub stats: SongStats,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RatingRequest {
+    /// 1 to 5 stars, or null to clear the rating.
+    pub rating: Option<u8>,
+}
+
+// null when nothing is queued; an error only when MPD can't be asked
 #[get("/song/now")]
-pub async fn now_playing(app_state: &State<AppState>) -> Json<Option<Song>> {
-    let mut pooled_conn: PooledMpdConnection = match app_state.mpd_pool.get_connection().await {
-        Ok(c) => c,
-        Err(_) => return Json(None),
-    };
-    
-    let queue: Vec<Song> = match pooled_conn.mpd_conn().mpd.queue() {
-        Ok(q) => q,
-        Err(_) => return Json(None),
-    };
-    
-    Json(queue.first().cloned())
+pub async fn now_playing(app_state: &State<AppState>) -> Result<Json<Option<Song>>, ApiError> {
+    let mut pooled_conn: PooledMpdConnection = app_state.mpd_pool.get_connection().await?;
+
+    let queue: Vec<Song> = pooled_conn.mpd_conn().mpd.queue()?;
+
+    Ok(Json(queue.first().cloned()))
 }
 
 #[get("/song/all")]
-pub async fn list_all(app_state: &State<AppState>) -> Json<Vec<Song>> {
-    let mut pooled_conn: PooledMpdConnection = match app_state.mpd_pool.get_connection().await {
-        Ok(c) => c,
-        Err(_) => return Json(vec![]),
+pub async fn list_all(app_state: &State<AppState>) -> Result<Json<Vec<Song>>, ApiError> {
+    let library = app_state.library.read().await;
+    if library.is_ready() {
+        return Ok(Json(library.songs().cloned().collect()));
+    }
+    drop(library);
+
+    let mut pooled_conn: PooledMpdConnection = app_state.mpd_pool.get_connection().await?;
+
+    let songs = pooled_conn.mpd_conn().mpd.listall()?;
+
+    Ok(Json(songs))
+}
+
+#[post("/song/tags", data = "<request>")]
+pub async fn update_song_tags(
+    app_state: &State<AppState>,
+    request: Result<Json<SongTagsRequest>, json::Error<'_>>,
+) -> Result<Json<SongTagsResponse>, ApiError> {
+    let Json(request) = request?;
+    if let Some(file) = &request.filename {
+        check_song_exists(app_state, file).await?;
+    }
+    let mut pooled_conn: PooledMpdConnection = app_state.mpd_pool.get_connection().await?;
+
+    let filename = match request.filename {
+        Some(f) => f,
+        None => match pooled_conn.mpd_conn().mpd.queue()?.first() {
+            Some(song) => song.file.clone(),
+            None => return Err(ApiError::NotFound("nothing is playing".to_string())),
+        },
     };
-    
-    let songs = match pooled_conn.mpd_conn().mpd.listall() {
-        Ok(s) => s,
-        Err(_) => vec![],
+
+    let mpd = &mut pooled_conn.mpd_conn().mpd;
+    let mut response = SongTagsResponse {
+        filename,
+        added: Vec::new(),
+        removed: Vec::new(),
     };
-    
-    Json(songs)
+    let edited = edit_song_tags(mpd, &request.add, &request.remove, &mut response);
+
+    // refresh whatever did change, even if a later edit failed
+    let changed: Vec<String> = response.added.iter().chain(&response.removed).cloned().collect();
+    if !changed.is_empty() {
+        app_state.playlists_changed(mpd, &changed).await;
+    }
+    edited?;
+
+    log::info!("[+] Tagged {}: +{:?} -{:?}", response.filename, response.added, response.removed);
+    Ok(Json(response))
+}
+
+/// Applies `add` and `remove` to `response.filename`, noting each tag that
+/// changed in `response`. Stops at the first edit MPD refuses.
+fn edit_song_tags(
+    mpd: &mut dyn MpdClient,
+    add: &[String],
+    remove: &[String],
+    response: &mut SongTagsResponse,
+) -> Result<(), ApiError> {
+    for tag in add.iter().filter(|t| !t.is_empty()) {
+        let added = tagging::tag_song(mpd, tag, &response.filename)
+            .map_err(|e| ApiError::Failed(format!("failed to add `{}` to `{}`: {:#}", response.filename, tag, e)))?;
+        if added {
+            response.added.push(tag.clone());
+        }
+    }
+
+    for tag in remove.iter().filter(|t| !t.is_empty()) {
+        let removed = tagging::untag_song(mpd, tag, &response.filename)
+            .map_err(|e| ApiError::Failed(format!("failed to remove `{}` from `{}`: {:#}", response.filename, tag, e)))?;
+        if removed > 0 {
+            response.removed.push(tag.clone());
+        }
+    }
+    Ok(())
+}
+
+async fn check_song_exists(app_state: &AppState, file: &str) -> Result<(), ApiError> {
+    if app_state.library.read().await.song(file).is_some() {
+        return Ok(());
+    }
+    let mut pooled_conn: PooledMpdConnection = app_state.mpd_pool.get_connection().await?;
+    if pooled_conn.mpd_conn().mpd.listall()?.iter().any(|s| s.file == file) {
+        return Ok(());
+    }
+    Err(ApiError::NotFound(format!("no song `{}`", file)))
+}
+
+async fn score(app_state: &AppState, file: String, stats: SongStats) -> SongScore {
+    let weighting = app_state.config.lock().await.weighting();
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let factors = weighting.factors(&stats, now);
+    SongScore {
+        file,
+        weight: factors.weight(),
+        factors,
+        stats,
+    }
+}
+
+// files have slashes in them, so clients send them percent-encoded as one
+// path segment: /song/Artist%2FAlbum%2F01.mp3/score
+#[get("/song/<file>/score")]
+pub async fn song_score(app_state: &State<AppState>, file: String) -> Result<Json<SongScore>, ApiError> {
+    check_song_exists(app_state, &file).await?;
+    let stats = app_state.song_stats.lock().await.get(&file);
+    Ok(Json(score(app_state, file, stats).await))
+}
+
+#[put("/song/<file>/rating", data = "<body>")]
+pub async fn rate_song(
+    app_state: &State<AppState>,
+    file: String,
+    body: Result<Json<RatingRequest>, json::Error<'_>>,
+) -> Result<Json<SongScore>, ApiError> {
+    let Json(request) = body?;
+    check_song_exists(app_state, &file).await?;
+
+    let stats = app_state
+        .song_stats
+        .lock()
+        .await
+        .rate(&file, request.rating)
+        .map_err(ApiError::BadRequest)?;
+    log::info!("[+] Rated {}: {:?}", file, request.rating);
+    app_state.state_store.mark_dirty();
+
+    Ok(Json(score(app_state, file, stats).await))
 }
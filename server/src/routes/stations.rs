@@ -0,0 +1,84 @@
+use rocket::serde::json::{self, Json};
+use rocket::{delete, get, post, put, State, routes};
+use serde::{Deserialize, Serialize};
+use crate::app_state::AppState;
+use crate::models::station::{Station, StationInfo};
+use crate::routes::error::ApiError;
+
+pub fn routes() -> Vec<rocket::Route> {
+    routes![list_stations, active_station, get_station, save_station, delete_station, activate_station]
+}
+
+/// The station last activated; both fields are null once the tags have
+/// been changed some other way.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ActiveStationResponse {
+    pub name: Option<String>,
+    pub station: Option<Station>,
+}
+
+#[get("/stations")]
+pub async fn list_stations(app_state: &State<AppState>) -> Json<Vec<StationInfo>> {
+    Json(app_state.stations.read().await.list())
+}
+
+#[get("/stations/active")]
+pub async fn active_station(app_state: &State<AppState>) -> Json<ActiveStationResponse> {
+    let active = app_state.stations.read().await.active();
+    Json(ActiveStationResponse {
+        name: active.as_ref().map(|info| info.name.clone()),
+        station: active.map(|info| info.station),
+    })
+}
+
+fn no_such_station(name: &str) -> ApiError {
+    ApiError::NotFound(format!("no station named `{}`", name))
+}
+
+#[get("/stations/<name>")]
+pub async fn get_station(app_state: &State<AppState>, name: &str) -> Result<Json<StationInfo>, ApiError> {
+    let station = app_state.stations.read().await.get(name).cloned().ok_or_else(|| no_such_station(name))?;
+    Ok(Json(StationInfo {
+        name: name.to_string(),
+        station,
+    }))
+}
+
+#[put("/stations/<name>", data = "<body>")]
+pub async fn save_station(
+    app_state: &State<AppState>,
+    name: &str,
+    body: Result<Json<Station>, json::Error<'_>>,
+) -> Result<Json<StationInfo>, ApiError> {
+    let Json(station) = body?;
+
+    let mut locked_stations = app_state.stations.write().await;
+    let replaced = locked_stations
+        .insert(name, station)
+        .map_err(ApiError::BadRequest)?;
+    log::info!("[+] {} station {}", if replaced.is_some() { "Updated" } else { "Saved" }, name);
+    app_state.state_store.mark_dirty();
+
+    let station = locked_stations.get(name.trim()).cloned().expect("station was just saved");
+    Ok(Json(StationInfo {
+        name: name.trim().to_string(),
+        station,
+    }))
+}
+
+#[delete("/stations/<name>")]
+pub async fn delete_station(app_state: &State<AppState>, name: &str) -> Result<Json<StationInfo>, ApiError> {
+    let station = app_state.stations.write().await.remove(name).ok_or_else(|| no_such_station(name))?;
+    log::info!("[+] Deleted station {}", name);
+    app_state.state_store.mark_dirty();
+
+    Ok(Json(StationInfo {
+        name: name.to_string(),
+        station,
+    }))
+}
+
+#[post("/stations/<name>/activate")]
+pub async fn activate_station(app_state: &State<AppState>, name: &str) -> Result<Json<StationInfo>, ApiError> {
+    app_state.activate_station(name).await?.map(Json).ok_or_else(|| no_such_station(name))
+}
@@ -1,44 +1,176 @@
-use rocket::serde::json::Json;
-use rocket::{get, State, routes};
+use rocket::serde::json::{self, Json};
+use rocket::{delete, get, post, put, State, routes};
+use std::sync::Arc;
 use crate::app_state::AppState;
 use crate::mpd_conn::traits::{MpdClient, Song};
-use crate::models::tags_data::TagsResponse;
+use crate::models::tags_data::{PlaybackTags, TagInfo, TagsChange, TagsData, TagsResponse};
+use crate::models::library_index::IndexedMpd;
+use crate::models::tag_cache::TagSnapshot;
 use crate::mpd_conn::mpd_pool::PooledMpdConnection;
+use crate::routes::error::ApiError;
+use crate::scheduler::clock::SystemClock;
+use crate::scheduler::scheduler_tick;
+use crate::tagging;
 
 pub fn routes() -> Vec<rocket::Route> {
-    routes![get_tags, get_tag_songs]
+    routes![get_tags, get_tag_songs, set_tags, active_tags, set_active_tags, available_tags, create_tag, delete_tag]
 }
 
-#[get("/tags")]
-pub async fn get_tags(app_state: &State<AppState>) -> Json<TagsResponse> {
-    let mut pooled_conn: PooledMpdConnection = match app_state.mpd_pool.get_connection().await {
-        Ok(c) => c,
-        Err(_) => return Json(TagsResponse::new()),
+/// The cached tag counts, loading them from MPD if the cache is empty.
+async fn tag_snapshot(app_state: &AppState) -> Result<Arc<TagSnapshot>, ApiError> {
+    let generation = {
+        let cache = app_state.tag_cache.lock().await;
+        if let Some(snapshot) = cache.get() {
+            return Ok(snapshot);
+        }
+        cache.generation()
     };
-    
-    let songs = pooled_conn.mpd_conn().mpd.listall().unwrap_or_default();
-    let playlists = pooled_conn.mpd_conn().mpd.playlists().unwrap_or_default();
-    
-    Json(TagsResponse::to_api_response(songs, playlists))
+
+    let mut pooled_conn: PooledMpdConnection = app_state.mpd_pool.get_connection().await?;
+    let library = app_state.library.read().await;
+    let snapshot = Arc::new(TagSnapshot::load(&mut IndexedMpd::new(&library, &mut pooled_conn.mpd_conn().mpd))?);
+    app_state.tag_cache.lock().await.store(generation, snapshot.clone());
+    Ok(snapshot)
 }
 
+fn no_such_tag(tag: &str) -> ApiError {
+    ApiError::NotFound(format!("no tag, artist or album named `{}`", tag))
+}
+
+#[get("/tags")]
+pub async fn get_tags(app_state: &State<AppState>) -> Result<Json<TagsResponse>, ApiError> {
+    Ok(Json(tag_snapshot(app_state).await?.tags.clone()))
+}
+
+// A playlist's songs, or failing that the songs of an artist or album by
+// that name. An existing but empty playlist is an empty list, not a 404.
 #[get("/tags/<tag>")]
-pub async fn get_tag_songs(app_state: &State<AppState>, tag: String) -> Json<Vec<Song>> {
-    let mut pooled_conn: PooledMpdConnection = match app_state.mpd_pool.get_connection().await {
-        Ok(c) => c,
-        Err(_) => return Json(vec![]),
-    };
-    
-    if let Ok(songs) = pooled_conn.mpd_conn().mpd.playlist(&tag) {
+pub async fn get_tag_songs(app_state: &State<AppState>, tag: String) -> Result<Json<Vec<Song>>, ApiError> {
+    let library = app_state.library.read().await;
+    if library.is_ready() {
+        let playlist = library.playlist(&tag);
+        if let Some(songs) = playlist.filter(|songs| !songs.is_empty()) {
+            return Ok(Json(songs.to_vec()));
+        }
+        let songs: Vec<Song> = library.by_artist_or_album(&tag).into_iter().cloned().collect();
+        if songs.is_empty() && playlist.is_none() {
+            return Err(no_such_tag(&tag));
+        }
+        return Ok(Json(songs));
+    }
+    drop(library);
+
+    let mut pooled_conn: PooledMpdConnection = app_state.mpd_pool.get_connection().await?;
+    let mpd = &mut pooled_conn.mpd_conn().mpd;
+
+    let exists = tagging::tag_exists(mpd, &tag)?;
+    if exists {
+        let songs = mpd.playlist(&tag)?;
         if !songs.is_empty() {
-            return Json(songs);
+            return Ok(Json(songs));
         }
     }
-    
-    let songs = pooled_conn.mpd_conn().mpd.listall().unwrap_or_default();
-    let filtered: Vec<Song> = songs.into_iter().filter(|s| {
+
+    let filtered: Vec<Song> = mpd.listall()?.into_iter().filter(|s| {
         s.artist.as_deref() == Some(&tag) || s.album.as_deref() == Some(&tag)
     }).collect();
-    
-    Json(filtered)
+    if filtered.is_empty() && !exists {
+        return Err(no_such_tag(&tag));
+    }
+
+    Ok(Json(filtered))
+}
+
+#[post("/tags", data = "<body>")]
+pub async fn set_tags(
+    app_state: &State<AppState>,
+    body: Result<Json<TagsData>, json::Error<'_>>,
+) -> Result<Json<PlaybackTags>, ApiError> {
+    let Json(tags) = body?;
+    let tags = tags.without_blanks();
+    tags.to_expr().map_err(ApiError::BadRequest)?;
+    Ok(Json(app_state.switch_tags(tags).await?.playback))
+}
+
+#[get("/tags/active")]
+pub async fn active_tags(app_state: &State<AppState>) -> Json<PlaybackTags> {
+    Json(app_state.playback_tags().await)
+}
+
+/// Drops everything in MPD's queue after the playing song, then tops it up
+/// from the internal queue. Returns how many entries were dropped.
+async fn flush_upcoming(app_state: &AppState) -> Result<usize, ApiError> {
+    let mut pooled_conn: PooledMpdConnection = app_state.mpd_pool.get_connection().await?;
+    let mpd = &mut pooled_conn.mpd_conn().mpd;
+
+    let upcoming = mpd.queue()?.len().saturating_sub(1);
+    // from the back so earlier positions stay valid
+    for pos in (1..=upcoming as u32).rev() {
+        mpd.delete(pos)?;
+    }
+    scheduler_tick(app_state, mpd, &SystemClock).await?;
+    Ok(upcoming)
+}
+
+// Like `POST /tags`, but every tag must name a stored playlist (unless tags
+// are matched as substrings), and `?flush=true` also replaces what MPD
+// already has lined up, so the change is heard after the current song.
+#[post("/tags/active?<flush>", data = "<body>")]
+pub async fn set_active_tags(
+    app_state: &State<AppState>,
+    flush: Option<bool>,
+    body: Result<Json<TagsData>, json::Error<'_>>,
+) -> Result<Json<TagsChange>, ApiError> {
+    let Json(tags) = body?;
+    let tags = tags.without_blanks();
+    tags.to_expr().map_err(ApiError::BadRequest)?;
+
+    let unknown = app_state.unknown_tags(&tags).await?;
+    match unknown.as_slice() {
+        [] => {}
+        [tag] => return Err(ApiError::BadRequest(format!("no tag named `{}`", tag))),
+        tags => return Err(ApiError::BadRequest(format!("no tags named `{}`", tags.join("`, `")))),
+    }
+
+    let mut change = app_state.switch_tags(tags).await?;
+    if flush.unwrap_or(false) {
+        change.flushed = flush_upcoming(app_state).await?;
+        log::info!("[+] Flushed {} upcoming songs", change.flushed);
+    }
+    Ok(Json(change))
+}
+
+#[get("/tags/available")]
+pub async fn available_tags(app_state: &State<AppState>) -> Result<Json<Vec<TagInfo>>, ApiError> {
+    Ok(Json(tag_snapshot(app_state).await?.available.clone()))
+}
+
+#[put("/tags/<tag>")]
+pub async fn create_tag(app_state: &State<AppState>, tag: String) -> Result<Json<TagInfo>, ApiError> {
+    let mut pooled_conn: PooledMpdConnection = app_state.mpd_pool.get_connection().await?;
+    let mpd = &mut pooled_conn.mpd_conn().mpd;
+
+    if tagging::create_tag(mpd, &tag)? {
+        log::info!("[+] Created tag {}", tag);
+        app_state.playlists_changed(mpd, std::slice::from_ref(&tag)).await;
+    }
+
+    let track_count = mpd.playlist(&tag).map(|s| s.len()).unwrap_or(0);
+    Ok(Json(TagInfo { name: tag, track_count }))
+}
+
+#[delete("/tags/<tag>")]
+pub async fn delete_tag(app_state: &State<AppState>, tag: String) -> Result<Json<TagInfo>, ApiError> {
+    let mut pooled_conn: PooledMpdConnection = app_state.mpd_pool.get_connection().await?;
+
+    let mpd = &mut pooled_conn.mpd_conn().mpd;
+
+    match tagging::delete_tag(mpd, &tag)? {
+        Some(track_count) => {
+            log::info!("[+] Deleted tag {} ({} tracks)", tag, track_count);
+            app_state.playlists_changed(mpd, std::slice::from_ref(&tag)).await;
+            Ok(Json(TagInfo { name: tag, track_count }))
+        }
+        None => Err(ApiError::NotFound(format!("no tag named `{}`", tag))),
+    }
 }
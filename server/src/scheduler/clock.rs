@@ -0,0 +1,44 @@
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+/// Source of "now" for the scheduler, so simulations can step time by hand.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> SystemTime;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// A clock that only moves when told to.
+pub struct ManualClock {
+    now: Mutex<SystemTime>,
+}
+
+impl ManualClock {
+    pub fn new(start: SystemTime) -> Self {
+        ManualClock {
+            now: Mutex::new(start),
+        }
+    }
+
+    pub fn advance(&self, by: Duration) {
+        let mut now = self.now.lock().unwrap();
+        *now += by;
+    }
+
+    pub fn set(&self, to: SystemTime) {
+        let mut now = self.now.lock().unwrap();
+        *now = to;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> SystemTime {
+        *self.now.lock().unwrap()
+    }
+}
@@ -1,12 +1,77 @@
+pub mod clock;
+pub mod schedule;
+
+use tokio::sync::mpsc;
 use tokio::time::Duration;
-use std::io::Write;
 use std::sync::Arc;
+use std::time::SystemTime;
 
 use crate::app_state::AppState;
+use crate::mpd_conn::mpd_pool::MpdPool;
 use crate::mpd_conn::traits::{MpdClient, Song};
+use crate::models::cooldown::RecentPlays;
 use crate::models::song_queue::DequeueMode;
+use crate::models::weighting::SongWeights;
+use crate::models::library_index::{IndexedMpd, RefreshScope};
+use crate::models::tags_data::TagsData;
+use crate::scheduler::clock::{Clock, SystemClock};
+
+use log::{debug, info, trace, warn, error};
+
+/// MPD subsystems whose changes can leave the queue short or stale.
+pub const IDLE_SUBSYSTEMS: &[&str] = &["player", "playlist", "database", "stored_playlist"];
+
+/// How often the scheduler checks MPD when `idle` is unavailable.
+pub const POLL_INTERVAL: Duration = Duration::from_secs(3);
+
+/// Upper bound between ticks while idle-driven, in case an event is missed.
+const IDLE_SAFETY_INTERVAL: Duration = Duration::from_secs(60);
 
-use log::{debug, info, trace, error};
+/// Longest wait before the idle watcher retries a failed connection.
+const MAX_IDLE_RETRY: Duration = Duration::from_secs(300);
+
+/// How long a refill that matched nothing is trusted while the tags and
+/// library stay the same, since cooldowns and config can still change what
+/// it finds.
+pub const EMPTY_REFILL_RETRY: Duration = Duration::from_secs(60);
+
+/// A refill that matched nothing, and what it was run against.
+#[derive(Debug, Clone)]
+pub struct EmptyRefill {
+    tags: TagsData,
+    library_revision: u64,
+    at: SystemTime,
+}
+
+impl EmptyRefill {
+    /// Whether refilling from `tags` at `now` would find nothing again.
+    fn still_holds(&self, tags: &TagsData, library_revision: u64, now: SystemTime) -> bool {
+        self.tags == *tags
+            && self.library_revision == library_revision
+            && now.duration_since(self.at).is_ok_and(|waited| waited < EMPTY_REFILL_RETRY)
+    }
+}
+
+/// What a single scheduler tick did.
+#[derive(Debug, Clone)]
+pub struct TickOutcome {
+    pub at: SystemTime,
+    /// Entries the internal queue was refilled with (0 if no refill): songs,
+    /// or albums when album-aware.
+    pub refilled: usize,
+    /// Songs pushed onto the MPD queue this tick.
+    pub pushed: Vec<Song>,
+}
+
+/// Messages from the idle watcher thread to the scheduler loop.
+#[derive(Debug)]
+enum IdleEvent {
+    /// The dedicated connection is up and waiting in `idle`.
+    Ready,
+    Changed(Vec<String>),
+    /// `idle` failed or is unsupported; the scheduler polls until `Ready`.
+    Lost(String),
+}
 
 pub async fn start_scheduler(app_state: AppState) {
     info!("[+] Starting scheduler...");
@@ -15,64 +80,230 @@ pub async fn start_scheduler(app_state: AppState) {
 }
 
 async fn scheduler_mainbody(app_state: Arc<AppState>) {
+    let clock = SystemClock;
     let mut scheduler_cycle = 0u64;
+    let mut idling = false;
+
+    let (events_tx, mut events) = mpsc::unbounded_channel();
+    let retry_interval = app_state.config.lock().await.poll_interval;
+    spawn_idle_watcher(app_state.mpd_pool.clone(), events_tx, retry_interval);
 
     loop {
+        // re-read every cycle so PATCH /config applies without a restart
+        let poll_interval = app_state.config.lock().await.poll_interval;
         scheduler_cycle += 1;
+        trace!("[-] scheduler cycle #{}", scheduler_cycle);
 
-        if scheduler_cycle % 20 == 0 {
-            trace!("[-] scheduler cycle #{}", scheduler_cycle);
-        } else {
-            print!(".");
-            let _ = std::io::stdout().flush();
+        if app_state.library.read().await.is_stale(clock.now()) {
+            if let Err(e) = app_state.refresh_library(RefreshScope::Full).await {
+                error!("[!] Failed to refresh the library index: {}", e);
+            }
+        }
+
+        match app_state.mpd_pool.get_connection().await {
+            Ok(mut pooled_conn) => {
+                if let Err(err) = scheduler_tick(&app_state, &mut pooled_conn.mpd_conn().mpd, &clock).await {
+                    error!("[!] Error getting MPD queue: {}", err);
+                }
+            }
+            Err(e) => error!("[!] Error getting MPD connection from pool: {}", e),
+        }
+
+        // while idle-driven MPD wakes us up; the timeout is only a safety net
+        let wait = if idling { IDLE_SAFETY_INTERVAL } else { poll_interval };
+        match tokio::time::timeout(wait, events.recv()).await {
+            Ok(Some(event)) => {
+                let mut refresh = library_refresh(&event);
+                handle_idle_event(event, &mut idling, poll_interval);
+                // coalesce a burst of changes into a single tick
+                while let Ok(event) = events.try_recv() {
+                    refresh = refresh.max(library_refresh(&event));
+                    handle_idle_event(event, &mut idling, poll_interval);
+                }
+                if let Some(scope) = refresh {
+                    if let Err(e) = app_state.refresh_library(scope).await {
+                        error!("[!] Failed to refresh the library index: {}", e);
+                    }
+                }
+            }
+            // the watcher thread is gone, so poll from here on
+            Ok(None) => {
+                idling = false;
+                tokio::time::sleep(poll_interval).await;
+            }
+            Err(_) => {}
         }
+    }
+}
+
+/// How much of the library index `event` makes stale. A (re)connected
+/// watcher may have missed changes while it was down.
+fn library_refresh(event: &IdleEvent) -> Option<RefreshScope> {
+    match event {
+        IdleEvent::Changed(subsystems) => subsystems.iter().filter_map(|s| RefreshScope::for_subsystem(s)).max(),
+        IdleEvent::Ready => Some(RefreshScope::Full),
+        IdleEvent::Lost(_) => None,
+    }
+}
 
-        let mut pooled_conn = match app_state.mpd_pool.get_connection().await {
-            Ok(conn) => conn,
-            Err(e) => {
-                error!("[!] Error getting MPD connection from pool: {}", e);
-                tokio::time::sleep(Duration::from_secs(3)).await;
-                continue;
+fn handle_idle_event(event: IdleEvent, idling: &mut bool, poll_interval: Duration) {
+    match event {
+        IdleEvent::Ready => {
+            debug!("[+] MPD idle connection ready");
+            *idling = true;
+        }
+        IdleEvent::Changed(subsystems) => {
+            debug!("[+] MPD changed: {:?}", subsystems);
+            *idling = true;
+        }
+        IdleEvent::Lost(reason) => {
+            if *idling {
+                warn!("[!] MPD idle unavailable ({}), polling every {:?}", reason, poll_interval);
             }
-        };
-
-        let mpd_queue_result: anyhow::Result<Vec<Song>> = pooled_conn.mpd_conn().mpd.queue();
-
-        match mpd_queue_result {
-            Ok(queue) => {
-                if queue.len() < 2 {
-                    let mut locked_song_queue = app_state.queue.lock().await;
-                    
-                    if !locked_song_queue.is_empty() {
-                        let mode = if app_state.config.lock().await.album_aware_shuffle {
-                            DequeueMode::Album
-                        } else {
-                            DequeueMode::Single
-                        };
-                        
-                        let songs = locked_song_queue.dequeue(mode, &mut pooled_conn);
-
-                        if !songs.is_empty() {
-                            info!("[+] Scheduler adding {} song(s) to MPD queue", songs.len());
-
-                            for song in songs {
-                                if let Err(err) = pooled_conn.mpd_conn().mpd.push(&song.file) {
-                                    error!("[!] Error pushing song to MPD: {}", err);
-                                } else {
-                                    debug!("[+] Added: {}", song.file);
+            *idling = false;
+        }
+    }
+}
+
+/// Holds a dedicated connection blocked in `idle` on its own thread and
+/// forwards each change to the scheduler, reconnecting with backoff.
+fn spawn_idle_watcher(pool: Arc<MpdPool>, events: mpsc::UnboundedSender<IdleEvent>, retry_interval: Duration) {
+    let spawned = std::thread::Builder::new()
+        .name("mpd-idle".to_string())
+        .spawn(move || {
+            let mut retry = retry_interval;
+            loop {
+                match pool.open_dedicated() {
+                    Ok(mut conn) => {
+                        if events.send(IdleEvent::Ready).is_err() {
+                            return;
+                        }
+                        loop {
+                            match conn.mpd.idle(IDLE_SUBSYSTEMS) {
+                                Ok(changed) => {
+                                    retry = retry_interval;
+                                    if events.send(IdleEvent::Changed(changed)).is_err() {
+                                        return;
+                                    }
+                                }
+                                Err(e) => {
+                                    if events.send(IdleEvent::Lost(e.to_string())).is_err() {
+                                        return;
+                                    }
+                                    break;
                                 }
                             }
-
-                            let _ = pooled_conn.mpd_conn().mpd.play();
+                        }
+                    }
+                    Err(e) => {
+                        if events.send(IdleEvent::Lost(e.to_string())).is_err() {
+                            return;
                         }
                     }
                 }
+
+                std::thread::sleep(retry);
+                retry = (retry * 2).min(MAX_IDLE_RETRY);
             }
-            Err(err) => {
-                error!("[!] Error getting MPD queue: {}", err);
+        });
+
+    if let Err(e) = spawned {
+        error!("[!] Could not start the MPD idle watcher, polling instead: {}", e);
+    }
+}
+
+/// One pass of the scheduler: when MPD is below the configured low-water
+/// mark, refill the internal queue from the current tags if it is down to
+/// the refill threshold, then dequeue the next song (or album) onto MPD.
+pub async fn scheduler_tick(
+    app_state: &AppState,
+    mpd: &mut dyn MpdClient,
+    clock: &dyn Clock,
+) -> anyhow::Result<TickOutcome> {
+    let mut outcome = TickOutcome {
+        at: clock.now(),
+        refilled: 0,
+        pushed: Vec::new(),
+    };
+
+    let queue = mpd.queue()?;
+    // with consume on, the head of MPD's queue is the song playing now
+    let finished = app_state.history.lock().await.observe(queue.first(), outcome.at);
+    app_state.record_plays(finished).await;
+
+    let (album_aware, low_water_mark, refill_threshold, cooldown, weighting) = {
+        let config = app_state.config.lock().await;
+        (
+            config.album_aware_shuffle,
+            config.low_water_mark,
+            config.refill_threshold,
+            config.cooldown(),
+            config.weighting(),
+        )
+    };
+
+    if queue.len() >= low_water_mark {
+        return Ok(outcome);
+    }
+
+    let mut locked_song_queue = app_state.queue.lock().await;
+    // keep the queue's shape in step with the config before refilling
+    locked_song_queue.set_album_aware(album_aware);
+
+    if locked_song_queue.len() <= refill_threshold {
+        let locked_tags_data = app_state.tags_data.read().await;
+        let library = app_state.library.read().await;
+        let mut empty_refill = app_state.empty_refill.lock().await;
+        if empty_refill
+            .as_ref()
+            .is_some_and(|empty| empty.still_holds(&locked_tags_data, library.revision(), outcome.at))
+        {
+            trace!("[-] Tags {:?} matched nothing last time, not refilling yet", *locked_tags_data);
+        } else {
+            info!("[+] Internal queue is running low, refilling from tags {:?}", *locked_tags_data);
+            let before = locked_song_queue.len();
+            let recent = RecentPlays::new(&*app_state.history.lock().await, &queue, &cooldown, outcome.at);
+            let stats = app_state.song_stats.lock().await;
+            let weights = SongWeights::new(&stats, weighting, outcome.at);
+            locked_song_queue.set_cooldown(cooldown);
+            locked_song_queue.set_weighting(weighting);
+            locked_song_queue.shuffle_and_add_after(&locked_tags_data, &mut IndexedMpd::new(&library, mpd), &recent, &weights);
+            outcome.refilled = locked_song_queue.len() - before;
+            *empty_refill = (outcome.refilled == 0).then(|| EmptyRefill {
+                tags: locked_tags_data.clone(),
+                library_revision: library.revision(),
+                at: outcome.at,
+            });
+        }
+    }
+
+    if locked_song_queue.is_empty() {
+        return Ok(outcome);
+    }
+
+    let mode = if album_aware {
+        DequeueMode::Album
+    } else {
+        DequeueMode::Single
+    };
+
+    let songs = locked_song_queue.dequeue(mode, mpd);
+    app_state.state_store.mark_dirty();
+
+    if !songs.is_empty() {
+        info!("[+] Scheduler adding {} song(s) to MPD queue", songs.len());
+
+        for song in songs {
+            if let Err(err) = mpd.push(&song.file) {
+                error!("[!] Error pushing song to MPD: {}", err);
+            } else {
+                debug!("[+] Added: {}", song.file);
+                outcome.pushed.push(song);
             }
         }
 
-        tokio::time::sleep(Duration::from_secs(3)).await;
+        let _ = mpd.play();
     }
+
+    Ok(outcome)
 }
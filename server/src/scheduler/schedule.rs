@@ -0,0 +1,517 @@
+use jiff::civil::{Date, Weekday};
+use jiff::tz::TimeZone;
+use jiff::Timestamp;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+
+use crate::app_state::AppState;
+use crate::models::tags_data::TagsData;
+use crate::scheduler::clock::{Clock, SystemClock};
+
+/// How often the schedule engine looks for rules that came due.
+pub const SCHEDULE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// How far back the engine looks for a rule to apply after a restart or a
+/// long gap (say the host was asleep).
+const MAX_CATCH_UP: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Short and long day names, Monday first.
+const DAY_NAMES: [(&str, &str); 7] = [
+    ("mon", "monday"),
+    ("tue", "tuesday"),
+    ("wed", "wednesday"),
+    ("thu", "thursday"),
+    ("fri", "friday"),
+    ("sat", "saturday"),
+    ("sun", "sunday"),
+];
+
+/// When a rule fires: a set of weekdays and a local time of day. Written
+/// as `"<days> HH:MM"`, where days is `daily`, `weekdays`, `weekends` or a
+/// list such as `mon,wed,fri` or `mon-thu`; or cron-style as
+/// `"MM HH * * <dow>"` with a numeric (0 or 7 = Sunday) day-of-week list.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct When {
+    /// Monday first.
+    days: [bool; 7],
+    hour: i8,
+    minute: i8,
+}
+
+impl When {
+    pub fn runs_on(&self, day: Weekday) -> bool {
+        self.days[day.to_monday_zero_offset() as usize]
+    }
+
+    fn parse_days(spec: &str) -> Result<[bool; 7], String> {
+        match spec {
+            "daily" | "everyday" | "*" => return Ok([true; 7]),
+            "weekdays" => return Ok([true, true, true, true, true, false, false]),
+            "weekends" => return Ok([false, false, false, false, false, true, true]),
+            _ => {}
+        }
+
+        let day = |name: &str| -> Result<usize, String> {
+            let name = name.trim();
+            DAY_NAMES
+                .iter()
+                .position(|(short, long)| name == *short || name == *long)
+                .ok_or_else(|| format!("unknown day `{}`", name))
+        };
+
+        let mut days = [false; 7];
+        for part in spec.split(',') {
+            match part.split_once('-') {
+                Some((from, to)) => {
+                    let (from, to) = (day(from)?, day(to)?);
+                    let mut i = from;
+                    loop {
+                        days[i] = true;
+                        if i == to {
+                            break;
+                        }
+                        i = (i + 1) % 7;
+                    }
+                }
+                None => days[day(part)?] = true,
+            }
+        }
+        Ok(days)
+    }
+
+    fn parse_cron_days(spec: &str) -> Result<[bool; 7], String> {
+        if spec == "*" {
+            return Ok([true; 7]);
+        }
+        // cron counts from Sunday = 0 (or 7)
+        let day = |n: &str| -> Result<usize, String> {
+            match n.trim().parse::<usize>() {
+                Ok(n @ 0..=7) => Ok((n + 6) % 7),
+                _ => Err(format!("bad day-of-week `{}`", n)),
+            }
+        };
+
+        let mut days = [false; 7];
+        for part in spec.split(',') {
+            match part.split_once('-') {
+                Some((from, to)) => {
+                    let (from, to) = (from.trim().parse::<usize>(), to.trim().parse::<usize>());
+                    let (Ok(from), Ok(to)) = (from, to) else {
+                        return Err(format!("bad day-of-week range `{}`", part));
+                    };
+                    if from > to || to > 7 {
+                        return Err(format!("bad day-of-week range `{}`", part));
+                    }
+                    for n in from..=to {
+                        days[(n + 6) % 7] = true;
+                    }
+                }
+                None => days[day(part)?] = true,
+            }
+        }
+        Ok(days)
+    }
+}
+
+fn parse_clock(hour: &str, minute: &str) -> Result<(i8, i8), String> {
+    let hour: i8 = hour.trim().parse().map_err(|_| format!("bad hour `{}`", hour))?;
+    let minute: i8 = minute.trim().parse().map_err(|_| format!("bad minute `{}`", minute))?;
+    if !(0..24).contains(&hour) || !(0..60).contains(&minute) {
+        return Err(format!("{:02}:{:02} is not a time of day", hour, minute));
+    }
+    Ok((hour, minute))
+}
+
+impl FromStr for When {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_lowercase();
+        let fields: Vec<&str> = s.split_whitespace().collect();
+        match fields.as_slice() {
+            [days, time] => {
+                let (hour, minute) = time
+                    .split_once(':')
+                    .ok_or_else(|| format!("expected HH:MM, got `{}`", time))?;
+                let (hour, minute) = parse_clock(hour, minute)?;
+                Ok(When {
+                    days: Self::parse_days(days)?,
+                    hour,
+                    minute,
+                })
+            }
+            [minute, hour, "*", "*", days] => {
+                let (hour, minute) = parse_clock(hour, minute)?;
+                Ok(When {
+                    days: Self::parse_cron_days(days)?,
+                    hour,
+                    minute,
+                })
+            }
+            [_, _, _, _, _] => Err("only `*` is supported for cron day-of-month and month".to_string()),
+            _ => Err(format!("expected \"<days> HH:MM\" or \"MM HH * * <dow>\", got `{}`", s)),
+        }
+    }
+}
+
+impl fmt::Display for When {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let days = match self.days {
+            [true, true, true, true, true, true, true] => "daily".to_string(),
+            [true, true, true, true, true, false, false] => "weekdays".to_string(),
+            [false, false, false, false, false, true, true] => "weekends".to_string(),
+            days => DAY_NAMES
+                .iter()
+                .zip(days)
+                .filter(|(_, on)| *on)
+                .map(|((name, _), _)| *name)
+                .collect::<Vec<_>>()
+                .join(","),
+        };
+        write!(f, "{} {:02}:{:02}", days, self.hour, self.minute)
+    }
+}
+
+impl Serialize for When {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for When {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// What a rule switches playback to.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ScheduleTarget {
+    Station(String),
+    Tags(TagsData),
+}
+
+/// One schedule rule as sent by clients.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ScheduleRule {
+    pub when: When,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub station: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tags: Option<TagsData>,
+    /// Switch back to whatever was playing before, this many minutes
+    /// after the rule fired, unless something else changed it meanwhile.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub revert_after_minutes: Option<u32>,
+}
+
+impl ScheduleRule {
+    /// The station or tags to switch to; exactly one must be given.
+    pub fn target(&self) -> Result<ScheduleTarget, String> {
+        match (&self.station, &self.tags) {
+            (Some(station), None) => Ok(ScheduleTarget::Station(station.clone())),
+            (None, Some(tags)) => {
+                let tags = tags.clone().without_blanks();
+                if tags.any.is_empty() && tags.expr.is_none() {
+                    return Err("`tags` needs at least one tag in `any` or an `expr`".to_string());
+                }
+                tags.to_expr()?;
+                Ok(ScheduleTarget::Tags(tags))
+            }
+            _ => Err("give either `station` or `tags`".to_string()),
+        }
+    }
+}
+
+/// A rule with the id it is listed and deleted by.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ScheduleEntry {
+    pub id: u64,
+    #[serde(flatten)]
+    pub rule: ScheduleRule,
+}
+
+/// A revert waiting for its time.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PendingRevert {
+    pub at: SystemTime,
+    /// What the rule switched to; the revert is dropped if this is no
+    /// longer playing.
+    pub applied: ScheduleTarget,
+    pub restore: ScheduleTarget,
+}
+
+/// How far the engine got, saved alongside the rules so a restart only
+/// catches up on what came due while the server was down.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct EngineState {
+    pub last_checked: Option<SystemTime>,
+    pub pending_revert: Option<PendingRevert>,
+}
+
+/// The time-of-day rules and the engine state deciding when they fire.
+/// Times are matched in `time_zone` (the system's by default).
+#[derive(Debug)]
+pub struct Schedule {
+    rules: Vec<ScheduleEntry>,
+    next_id: u64,
+    time_zone: TimeZone,
+    last_checked: Option<SystemTime>,
+    pending_revert: Option<PendingRevert>,
+}
+
+impl Default for Schedule {
+    fn default() -> Self {
+        Self::with_time_zone(TimeZone::system())
+    }
+}
+
+impl Schedule {
+    pub fn with_time_zone(time_zone: TimeZone) -> Self {
+        Schedule {
+            rules: Vec::new(),
+            next_id: 1,
+            time_zone,
+            last_checked: None,
+            pending_revert: None,
+        }
+    }
+
+    pub fn time_zone(&self) -> &TimeZone {
+        &self.time_zone
+    }
+
+    pub fn rules(&self) -> &[ScheduleEntry] {
+        &self.rules
+    }
+
+    pub fn add(&mut self, rule: ScheduleRule) -> Result<ScheduleEntry, String> {
+        rule.target()?;
+        let entry = ScheduleEntry { id: self.next_id, rule };
+        self.next_id += 1;
+        self.rules.push(entry.clone());
+        Ok(entry)
+    }
+
+    /// Swaps in a whole new rule list; nothing changes if any rule is
+    /// invalid.
+    pub fn replace(&mut self, rules: Vec<ScheduleRule>) -> Result<&[ScheduleEntry], String> {
+        for (i, rule) in rules.iter().enumerate() {
+            rule.target().map_err(|e| format!("rule {}: {}", i + 1, e))?;
+        }
+        self.rules.clear();
+        for rule in rules {
+            self.add(rule)?;
+        }
+        Ok(&self.rules)
+    }
+
+    pub fn remove(&mut self, id: u64) -> Option<ScheduleEntry> {
+        let index = self.rules.iter().position(|entry| entry.id == id)?;
+        Some(self.rules.remove(index))
+    }
+
+    /// Restores saved rules, keeping their ids.
+    pub fn restore(&mut self, rules: Vec<ScheduleEntry>) {
+        self.next_id = rules.iter().map(|entry| entry.id + 1).max().unwrap_or(1);
+        self.rules = rules;
+    }
+
+    pub fn engine_state(&self) -> EngineState {
+        EngineState {
+            last_checked: self.last_checked,
+            pending_revert: self.pending_revert.clone(),
+        }
+    }
+
+    /// Resumes from a saved `engine_state`.
+    pub fn restore_engine(&mut self, state: EngineState) {
+        self.last_checked = state.last_checked;
+        self.pending_revert = state.pending_revert;
+    }
+
+    pub fn pending_revert(&self) -> Option<&PendingRevert> {
+        self.pending_revert.as_ref()
+    }
+
+    pub fn set_pending_revert(&mut self, revert: Option<PendingRevert>) {
+        self.pending_revert = revert;
+    }
+
+    /// Takes the pending revert if it is due at `now`.
+    pub fn take_due_revert(&mut self, now: SystemTime) -> Option<PendingRevert> {
+        if self.pending_revert.as_ref()?.at <= now {
+            return self.pending_revert.take();
+        }
+        None
+    }
+
+    /// The first time after `after` that `when` fires.
+    pub fn next_run(&self, when: &When, after: SystemTime) -> Option<SystemTime> {
+        let start = self.date_of(after)?;
+        (0..8)
+            .filter_map(|offset| start.checked_add(jiff::Span::new().days(offset)).ok())
+            .filter_map(|date| self.occurrence(when, date))
+            .find(|at| *at > after)
+    }
+
+    /// The rule whose time came most recently between the previous call and
+    /// `now`, with that time, unless its revert is also past. Anything that
+    /// came due before it in the same gap would be replaced straight away,
+    /// so it is skipped. A restored engine state counts as the previous
+    /// call; without one the first call looks back `MAX_CATCH_UP`, so a
+    /// fresh start still applies the rule that should be in force.
+    pub fn due(&mut self, now: SystemTime) -> Option<(SystemTime, ScheduleEntry)> {
+        let lookback = now.checked_sub(MAX_CATCH_UP).unwrap_or(SystemTime::UNIX_EPOCH);
+        let since = match self.last_checked.replace(now) {
+            Some(last) if now <= last => return None,
+            Some(last) => last.max(lookback),
+            None => lookback,
+        };
+
+        let (first_day, last_day) = (self.date_of(since)?, self.date_of(now)?);
+        let mut due: Option<(SystemTime, ScheduleEntry)> = None;
+        let mut date = first_day;
+        while date <= last_day {
+            for entry in &self.rules {
+                let Some(at) = self.occurrence(&entry.rule.when, date) else {
+                    continue;
+                };
+                // a rule whose revert time has also passed is no longer in force
+                let reverted = entry.rule.revert_after_minutes.is_some_and(|minutes| {
+                    at + Duration::from_secs(u64::from(minutes) * 60) <= now
+                });
+                // ties go to the rule listed last
+                if at > since && at <= now && !reverted && due.as_ref().is_none_or(|(latest, _)| at >= *latest) {
+                    due = Some((at, entry.clone()));
+                }
+            }
+            match date.tomorrow() {
+                Ok(next) => date = next,
+                Err(_) => break,
+            }
+        }
+        due
+    }
+
+    fn date_of(&self, at: SystemTime) -> Option<Date> {
+        let timestamp = Timestamp::try_from(at).ok()?;
+        Some(timestamp.to_zoned(self.time_zone.clone()).date())
+    }
+
+    fn occurrence(&self, when: &When, date: Date) -> Option<SystemTime> {
+        if !when.runs_on(date.weekday()) {
+            return None;
+        }
+        let zoned = date.at(when.hour, when.minute, 0, 0).to_zoned(self.time_zone.clone()).ok()?;
+        Some(SystemTime::from(zoned.timestamp()))
+    }
+}
+
+/// What one pass of the engine did.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ScheduleOutcome {
+    /// Ids of the rules that fired.
+    pub fired: Vec<u64>,
+    pub reverted: bool,
+}
+
+/// Fires the latest rule that came due since the last pass and any revert
+/// whose time has come.
+pub async fn schedule_tick(state: &AppState, clock: &dyn Clock) -> ScheduleOutcome {
+    let now = clock.now();
+    let (due, revert) = {
+        let mut schedule = state.schedule.lock().await;
+        let due = schedule.due(now);
+        (due, schedule.take_due_revert(now))
+    };
+
+    let mut outcome = ScheduleOutcome::default();
+    if let Some(revert) = revert {
+        if current_target(state).await == revert.applied {
+            log::info!("[+] Schedule reverting to {:?}", revert.restore);
+            outcome.reverted = apply(state, &revert.restore).await;
+        } else {
+            log::info!("[-] Skipping schedule revert, playback changed since");
+        }
+    }
+
+    if let Some((at, entry)) = due {
+        if fire(state, at, &entry).await {
+            outcome.fired.push(entry.id);
+        }
+    }
+
+    if !outcome.fired.is_empty() || outcome.reverted {
+        state.state_store.mark_dirty();
+    }
+    outcome
+}
+
+/// Applies `entry`'s target as of `at`, arming its revert if it has one.
+async fn fire(state: &AppState, at: SystemTime, entry: &ScheduleEntry) -> bool {
+    let Ok(target) = entry.rule.target() else {
+        return false;
+    };
+    let previous = current_target(state).await;
+    log::info!("[+] Schedule rule {} ({}) firing", entry.id, entry.rule.when);
+    if !apply(state, &target).await {
+        return false;
+    }
+
+    let pending = entry.rule.revert_after_minutes.map(|minutes| PendingRevert {
+        at: at + Duration::from_secs(u64::from(minutes) * 60),
+        applied: target,
+        restore: previous,
+    });
+    state.schedule.lock().await.set_pending_revert(pending);
+    true
+}
+
+/// The active station, or else the playback tags.
+async fn current_target(state: &AppState) -> ScheduleTarget {
+    if let Some(active) = state.stations.read().await.active() {
+        return ScheduleTarget::Station(active.name);
+    }
+    ScheduleTarget::Tags(state.tags_data.read().await.clone())
+}
+
+async fn apply(state: &AppState, target: &ScheduleTarget) -> bool {
+    match target {
+        ScheduleTarget::Station(name) => match state.activate_station(name).await {
+            Ok(Some(_)) => true,
+            Ok(None) => {
+                log::error!("[!] Scheduled station {} does not exist", name);
+                false
+            }
+            Err(e) => {
+                log::error!("[!] Failed to activate scheduled station {}: {}", name, e);
+                false
+            }
+        },
+        ScheduleTarget::Tags(tags) => match state.switch_tags(tags.clone()).await {
+            Ok(_) => true,
+            Err(e) => {
+                log::error!("[!] Failed to switch to scheduled tags: {}", e);
+                false
+            }
+        },
+    }
+}
+
+/// Runs the schedule engine on the system clock for as long as the
+/// runtime lives.
+pub fn start_schedule(state: AppState) {
+    log::info!("[+] Starting schedule engine...");
+    tokio::spawn(async move {
+        let clock = SystemClock;
+        loop {
+            schedule_tick(&state, &clock).await;
+            tokio::time::sleep(SCHEDULE_INTERVAL).await;
+        }
+    });
+}
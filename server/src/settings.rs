@@ -0,0 +1,497 @@
+use clap::Parser;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+use toml::Spanned;
+
+use crate::app_state::{decode_tags_b64, Config};
+use crate::models::song_queue::TagMatchMode;
+use crate::models::station::Station;
+use crate::models::tags_data::TagsData;
+use crate::mpd_conn::mpd_conn::BackendKind;
+
+/// Read from the working directory when neither `--config` nor
+/// `JUKECTL_CONFIG` names a file.
+pub const DEFAULT_CONFIG_FILE: &str = "jukectl.toml";
+
+/// Command-line flags of `jukectl-server`. Every flag overrides the same
+/// setting from the config file and the environment.
+#[derive(Parser, Debug, Default, Clone)]
+#[command(name = "jukectl-server", version, about = "jukebox daemon driving MPD")]
+pub struct ServerArgs {
+    #[arg(long, short, value_name = "PATH", help = "Config file (default: ./jukectl.toml if present)")]
+    pub config: Option<PathBuf>,
+    #[arg(long, help = "Address to bind the HTTP API to")]
+    pub address: Option<IpAddr>,
+    #[arg(long, short, help = "Port to bind the HTTP API to")]
+    pub port: Option<u16>,
+    #[arg(long)]
+    pub mpd_host: Option<String>,
+    #[arg(long)]
+    pub mpd_port: Option<u16>,
+    #[arg(long)]
+    pub mpd_max_connections: Option<usize>,
+    #[arg(long, help = "MPD client library: libmpdclient or native")]
+    pub mpd_backend: Option<BackendKind>,
+    #[arg(long, num_args = 0..=1, default_missing_value = "true", help = "Use an in-memory mock instead of MPD")]
+    pub dev_mode: Option<bool>,
+    #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+    pub album_aware: Option<bool>,
+    #[arg(long, help = "playlist or substring")]
+    pub tag_match_mode: Option<TagMatchMode>,
+    #[arg(long)]
+    pub low_water_mark: Option<usize>,
+    #[arg(long)]
+    pub refill_threshold: Option<usize>,
+    #[arg(long)]
+    pub poll_interval_ms: Option<u64>,
+    #[arg(long, help = "Songs between two by the same artist (0 is off)")]
+    pub artist_spacing: Option<usize>,
+    #[arg(long, help = "Hours before a played song is queued again (0 is off)")]
+    pub track_cooldown_hours: Option<u64>,
+    #[arg(long, help = "Weight multiplier per star above three")]
+    pub rating_boost: Option<f64>,
+    #[arg(long, help = "How much a song's skip ratio lowers its weight")]
+    pub skip_penalty: Option<f64>,
+    #[arg(long, help = "How much a song's play count lowers its weight")]
+    pub play_penalty: Option<f64>,
+    #[arg(long, help = "Hours for a played song to regain its full weight (0 is off)")]
+    pub recovery_hours: Option<u64>,
+    #[arg(long, help = "Start with this preset's station")]
+    pub preset: Option<String>,
+    #[arg(long, value_name = "DIR")]
+    pub data_dir: Option<PathBuf>,
+    #[arg(long, value_name = "PATH")]
+    pub history_log: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MpdSettings {
+    pub host: String,
+    pub port: u16,
+    pub max_connections: usize,
+    pub backend: BackendKind,
+    /// Serve from an in-memory `MockMpd` instead of connecting.
+    pub dev_mode: bool,
+}
+
+/// Everything the server needs at startup, resolved from the defaults,
+/// then `jukectl.toml`, then environment variables, then flags.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Settings {
+    pub address: IpAddr,
+    pub port: u16,
+    pub mpd: MpdSettings,
+    /// The tags playing until a client sets others.
+    pub default_tags: TagsData,
+    pub config: Config,
+    /// Stations defined as `[presets.<name>]`.
+    pub presets: BTreeMap<String, Station>,
+    /// The preset chosen to start with; its tags and album mode are
+    /// already in `default_tags` and `config`.
+    pub preset: Option<String>,
+    pub data_dir: Option<PathBuf>,
+    pub history_log: Option<PathBuf>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            port: 8000,
+            mpd: MpdSettings {
+                host: "127.0.0.1".to_string(),
+                port: 6600,
+                max_connections: 5,
+                backend: BackendKind::default(),
+                dev_mode: false,
+            },
+            default_tags: TagsData {
+                any: vec!["jukebox".to_string()],
+                not: vec!["explicit".to_string()],
+                expr: None,
+            },
+            config: Config::default(),
+            presets: BTreeMap::new(),
+            preset: None,
+            data_dir: None,
+            history_log: None,
+        }
+    }
+}
+
+/// A setting that could not be used, with where it came from: a file and
+/// line, an environment variable or a flag.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SettingsError {
+    pub origin: String,
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl SettingsError {
+    fn new(origin: impl Into<String>, message: impl Into<String>) -> Self {
+        SettingsError {
+            origin: origin.into(),
+            line: None,
+            message: message.into(),
+        }
+    }
+
+    fn in_file(path: &Path, text: &str, span: Option<Range<usize>>, message: impl Into<String>) -> Self {
+        SettingsError {
+            origin: path.display().to_string(),
+            line: span.map(|span| line_of(text, span.start)),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}: {}", self.origin, line, self.message),
+            None => write!(f, "{}: {}", self.origin, self.message),
+        }
+    }
+}
+
+impl std::error::Error for SettingsError {}
+
+fn line_of(text: &str, offset: usize) -> usize {
+    text[..offset.min(text.len())].matches('\n').count() + 1
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct FileSettings {
+    server: ServerSection,
+    mpd: MpdSection,
+    tags: TagsSection,
+    scheduler: SchedulerSection,
+    presets: BTreeMap<String, Spanned<PresetSection>>,
+    storage: StorageSection,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct ServerSection {
+    address: Option<IpAddr>,
+    port: Option<u16>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct MpdSection {
+    host: Option<String>,
+    port: Option<u16>,
+    max_connections: Option<Spanned<usize>>,
+    backend: Option<Spanned<String>>,
+    dev_mode: Option<bool>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct TagsSection {
+    any: Option<Vec<String>>,
+    not: Option<Vec<String>>,
+    expr: Option<Spanned<String>>,
+    preset: Option<Spanned<String>>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct SchedulerSection {
+    album_aware: Option<bool>,
+    tag_match_mode: Option<TagMatchMode>,
+    low_water_mark: Option<Spanned<usize>>,
+    refill_threshold: Option<Spanned<usize>>,
+    poll_interval_ms: Option<Spanned<u64>>,
+    artist_spacing: Option<Spanned<usize>>,
+    track_cooldown_hours: Option<Spanned<u64>>,
+    rating_boost: Option<Spanned<f64>>,
+    skip_penalty: Option<Spanned<f64>>,
+    play_penalty: Option<Spanned<f64>>,
+    recovery_hours: Option<Spanned<u64>>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct PresetSection {
+    any: Vec<String>,
+    not: Vec<String>,
+    expr: Option<String>,
+    album_aware: bool,
+    volume: Option<u32>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct StorageSection {
+    data_dir: Option<PathBuf>,
+    history_log: Option<PathBuf>,
+}
+
+impl Settings {
+    /// Resolves the settings for `args`, reading the config file they (or
+    /// `JUKECTL_CONFIG`) point at, or `./jukectl.toml` when it exists.
+    pub fn load(args: &ServerArgs) -> Result<Self, SettingsError> {
+        let path = match (&args.config, env::var("JUKECTL_CONFIG")) {
+            (Some(path), _) => Some(path.clone()),
+            (None, Ok(path)) if !path.trim().is_empty() => Some(PathBuf::from(path)),
+            _ => Some(PathBuf::from(DEFAULT_CONFIG_FILE)).filter(|path| path.exists()),
+        };
+
+        let mut settings = Settings::default();
+        if let Some(path) = path {
+            let text = fs::read_to_string(&path)
+                .map_err(|e| SettingsError::new(path.display().to_string(), e.to_string()))?;
+            settings.apply_file(&path, &text)?;
+        }
+        settings.apply_env()?;
+        settings.apply_args(args)?;
+        Ok(settings)
+    }
+
+    /// Settings from a config file's contents alone, on top of the defaults.
+    pub fn from_toml(path: &Path, text: &str) -> Result<Self, SettingsError> {
+        let mut settings = Settings::default();
+        settings.apply_file(path, text)?;
+        Ok(settings)
+    }
+
+    fn apply_file(&mut self, path: &Path, text: &str) -> Result<(), SettingsError> {
+        let file: FileSettings = toml::from_str(text)
+            .map_err(|e| SettingsError::in_file(path, text, e.span(), e.message()))?;
+        let error_at = |span: Range<usize>, message: String| SettingsError::in_file(path, text, Some(span), message);
+
+        set(&mut self.address, file.server.address);
+        set(&mut self.port, file.server.port);
+
+        set(&mut self.mpd.host, file.mpd.host);
+        set(&mut self.mpd.port, file.mpd.port);
+        if let Some(max) = file.mpd.max_connections {
+            self.mpd.max_connections = checked_max_connections(*max.get_ref()).map_err(|e| error_at(max.span(), e))?;
+        }
+        if let Some(backend) = file.mpd.backend {
+            self.mpd.backend = backend.get_ref().parse().map_err(|e| error_at(backend.span(), e))?;
+        }
+        set(&mut self.mpd.dev_mode, file.mpd.dev_mode);
+
+        for (name, preset) in file.presets {
+            let span = preset.span();
+            let preset = preset.into_inner();
+            let station = Station {
+                tags: TagsData { any: preset.any, not: preset.not, expr: preset.expr },
+                album_aware: preset.album_aware,
+                volume: preset.volume,
+            };
+            let station = station
+                .validated()
+                .map_err(|e| error_at(span, format!("preset `{}`: {}", name, e)))?;
+            self.presets.insert(name, station);
+        }
+
+        if let Some(name) = file.tags.preset {
+            if file.tags.any.is_some() || file.tags.not.is_some() || file.tags.expr.is_some() {
+                return Err(error_at(name.span(), "set either `preset` or `any`/`not`/`expr` in [tags], not both".to_string()));
+            }
+            self.use_preset(name.get_ref()).map_err(|e| error_at(name.span(), e))?;
+        } else if file.tags.any.is_some() || file.tags.not.is_some() || file.tags.expr.is_some() {
+            let span = file.tags.expr.as_ref().map(|expr| expr.span());
+            self.default_tags = TagsData {
+                any: file.tags.any.unwrap_or_default(),
+                not: file.tags.not.unwrap_or_default(),
+                expr: file.tags.expr.map(Spanned::into_inner),
+            }
+            .without_blanks();
+            if let (Some(span), Err(e)) = (span, self.default_tags.to_expr()) {
+                return Err(error_at(span, e));
+            }
+        }
+
+        let scheduler = file.scheduler;
+        set(&mut self.config.album_aware_shuffle, scheduler.album_aware);
+        set(&mut self.config.tag_match_mode, scheduler.tag_match_mode);
+        if let Some(mark) = scheduler.low_water_mark {
+            self.config.low_water_mark = *mark.get_ref();
+            self.config.validate().map_err(|e| error_at(mark.span(), e))?;
+        }
+        if let Some(threshold) = scheduler.refill_threshold {
+            self.config.refill_threshold = *threshold.get_ref();
+            self.config.validate().map_err(|e| error_at(threshold.span(), e))?;
+        }
+        if let Some(interval) = scheduler.poll_interval_ms {
+            self.config.poll_interval = Duration::from_millis(*interval.get_ref());
+            self.config.validate().map_err(|e| error_at(interval.span(), e))?;
+        }
+        if let Some(spacing) = scheduler.artist_spacing {
+            self.config.artist_spacing = *spacing.get_ref();
+            self.config.validate().map_err(|e| error_at(spacing.span(), e))?;
+        }
+        if let Some(hours) = scheduler.track_cooldown_hours {
+            self.config.track_cooldown_hours = *hours.get_ref();
+            self.config.validate().map_err(|e| error_at(hours.span(), e))?;
+        }
+        if let Some(boost) = scheduler.rating_boost {
+            self.config.rating_boost = *boost.get_ref();
+            self.config.validate().map_err(|e| error_at(boost.span(), e))?;
+        }
+        if let Some(penalty) = scheduler.skip_penalty {
+            self.config.skip_penalty = *penalty.get_ref();
+            self.config.validate().map_err(|e| error_at(penalty.span(), e))?;
+        }
+        if let Some(penalty) = scheduler.play_penalty {
+            self.config.play_penalty = *penalty.get_ref();
+            self.config.validate().map_err(|e| error_at(penalty.span(), e))?;
+        }
+        if let Some(hours) = scheduler.recovery_hours {
+            self.config.recovery_hours = *hours.get_ref();
+            self.config.validate().map_err(|e| error_at(hours.span(), e))?;
+        }
+
+        set_some(&mut self.data_dir, file.storage.data_dir);
+        set_some(&mut self.history_log, file.storage.history_log);
+        Ok(())
+    }
+
+    /// `ROCKET_ADDRESS` and `ROCKET_PORT` are read here too, so they keep
+    /// overriding the file like the other variables.
+    fn apply_env(&mut self) -> Result<(), SettingsError> {
+        set(&mut self.address, env_value("ROCKET_ADDRESS")?);
+        set(&mut self.port, env_value("ROCKET_PORT")?);
+
+        set(&mut self.mpd.host, env_value("MPD_HOST")?);
+        set(&mut self.mpd.port, env_value("MPD_PORT")?);
+        if let Some(max) = env_value("MPD_MAX_CONNECTIONS")? {
+            self.mpd.max_connections =
+                checked_max_connections(max).map_err(|e| SettingsError::new("MPD_MAX_CONNECTIONS", e))?;
+        }
+        set(&mut self.mpd.backend, env_value("MPD_BACKEND")?);
+        set(&mut self.mpd.dev_mode, env_flag("JUKECTL_DEV_MODE")?);
+
+        if let Some(name) = env_value::<String>("JUKECTL_PRESET")? {
+            self.use_preset(&name).map_err(|e| SettingsError::new("JUKECTL_PRESET", e))?;
+        }
+        if let Some(b64) = env_value::<String>("JUKECTL_DEFAULT_TAGS_B64")? {
+            self.default_tags = decode_tags_b64(&b64).map_err(|e| SettingsError::new("JUKECTL_DEFAULT_TAGS_B64", e))?;
+            self.preset = None;
+        }
+
+        set(&mut self.config.album_aware_shuffle, env_flag("ALBUM_AWARE_SHUFFLE")?);
+        set(&mut self.config.tag_match_mode, env_value("TAG_MATCH_MODE")?);
+        set(&mut self.config.low_water_mark, env_value("JUKECTL_LOW_WATER_MARK")?);
+        set(&mut self.config.refill_threshold, env_value("JUKECTL_REFILL_THRESHOLD")?);
+        set(&mut self.config.poll_interval, env_value("JUKECTL_POLL_INTERVAL_MS")?.map(Duration::from_millis));
+        set(&mut self.config.artist_spacing, env_value("JUKECTL_ARTIST_SPACING")?);
+        set(&mut self.config.track_cooldown_hours, env_value("JUKECTL_TRACK_COOLDOWN_HOURS")?);
+        set(&mut self.config.rating_boost, env_value("JUKECTL_RATING_BOOST")?);
+        set(&mut self.config.skip_penalty, env_value("JUKECTL_SKIP_PENALTY")?);
+        set(&mut self.config.play_penalty, env_value("JUKECTL_PLAY_PENALTY")?);
+        set(&mut self.config.recovery_hours, env_value("JUKECTL_RECOVERY_HOURS")?);
+        self.config.validate().map_err(|e| SettingsError::new("environment", e))?;
+
+        set_some(&mut self.data_dir, env_value("JUKECTL_DATA_DIR")?);
+        set_some(&mut self.history_log, env_value("JUKECTL_HISTORY_LOG")?);
+        Ok(())
+    }
+
+    fn apply_args(&mut self, args: &ServerArgs) -> Result<(), SettingsError> {
+        set(&mut self.address, args.address);
+        set(&mut self.port, args.port);
+
+        set(&mut self.mpd.host, args.mpd_host.clone());
+        set(&mut self.mpd.port, args.mpd_port);
+        if let Some(max) = args.mpd_max_connections {
+            self.mpd.max_connections =
+                checked_max_connections(max).map_err(|e| SettingsError::new("--mpd-max-connections", e))?;
+        }
+        set(&mut self.mpd.backend, args.mpd_backend);
+        set(&mut self.mpd.dev_mode, args.dev_mode);
+
+        if let Some(name) = &args.preset {
+            self.use_preset(name).map_err(|e| SettingsError::new("--preset", e))?;
+        }
+
+        set(&mut self.config.album_aware_shuffle, args.album_aware);
+        set(&mut self.config.tag_match_mode, args.tag_match_mode);
+        set(&mut self.config.low_water_mark, args.low_water_mark);
+        set(&mut self.config.refill_threshold, args.refill_threshold);
+        set(&mut self.config.poll_interval, args.poll_interval_ms.map(Duration::from_millis));
+        set(&mut self.config.artist_spacing, args.artist_spacing);
+        set(&mut self.config.track_cooldown_hours, args.track_cooldown_hours);
+        set(&mut self.config.rating_boost, args.rating_boost);
+        set(&mut self.config.skip_penalty, args.skip_penalty);
+        set(&mut self.config.play_penalty, args.play_penalty);
+        set(&mut self.config.recovery_hours, args.recovery_hours);
+        self.config.validate().map_err(|e| SettingsError::new("command line", e))?;
+
+        set_some(&mut self.data_dir, args.data_dir.clone());
+        set_some(&mut self.history_log, args.history_log.clone());
+        Ok(())
+    }
+
+    fn use_preset(&mut self, name: &str) -> Result<(), String> {
+        let Some(station) = self.presets.get(name) else {
+            let known: Vec<&str> = self.presets.keys().map(String::as_str).collect();
+            return Err(format!("unknown preset `{}` (defined: {})", name, known.join(", ")));
+        };
+        self.default_tags = station.tags.clone();
+        self.config.album_aware_shuffle = station.album_aware;
+        self.preset = Some(name.to_string());
+        Ok(())
+    }
+}
+
+fn set<T>(setting: &mut T, value: Option<T>) {
+    if let Some(value) = value {
+        *setting = value;
+    }
+}
+
+fn set_some<T>(setting: &mut Option<T>, value: Option<T>) {
+    if value.is_some() {
+        *setting = value;
+    }
+}
+
+fn checked_max_connections(max: usize) -> Result<usize, String> {
+    if max == 0 {
+        return Err("max_connections must be at least 1".to_string());
+    }
+    Ok(max)
+}
+
+/// A variable's parsed value, `None` when unset or blank.
+fn env_value<T>(name: &str) -> Result<Option<T>, SettingsError>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    match env::var(name) {
+        Ok(value) if !value.trim().is_empty() => value
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|e| SettingsError::new(name, format!("invalid value `{}`: {}", value, e))),
+        _ => Ok(None),
+    }
+}
+
+/// Like `env_value`, but also accepting the `1`/`0` the variables have
+/// always used.
+fn env_flag(name: &str) -> Result<Option<bool>, SettingsError> {
+    match env_value::<String>(name)?.as_deref().map(str::to_lowercase).as_deref() {
+        None => Ok(None),
+        Some("1" | "true" | "yes" | "on") => Ok(Some(true)),
+        Some("0" | "false" | "no" | "off") => Ok(Some(false)),
+        Some(other) => Err(SettingsError::new(name, format!("expected 1 or 0, got `{}`", other))),
+    }
+}
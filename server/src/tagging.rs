@@ -0,0 +1,65 @@
+// Editing tags. A tag is an MPD stored playlist, so tagging a song appends
+// it to that playlist and untagging deletes its entries by position.
+use anyhow::Result;
+
+use crate::mpd_conn::traits::MpdClient;
+
+pub fn tag_exists(mpd: &mut dyn MpdClient, tag: &str) -> Result<bool> {
+    Ok(mpd.playlists()?.iter().any(|p| p.name == tag))
+}
+
+/// Adds `file` to `tag`, creating the tag if needed. Returns false if the
+/// song was already tagged.
+pub fn tag_song(mpd: &mut dyn MpdClient, tag: &str, file: &str) -> Result<bool> {
+    if tag_exists(mpd, tag)? && mpd.playlist(tag)?.iter().any(|s| s.file == file) {
+        return Ok(false);
+    }
+
+    mpd.pl_push(tag, file)?;
+    Ok(true)
+}
+
+/// Removes every entry for `file` from `tag`. Returns how many were removed.
+pub fn untag_song(mpd: &mut dyn MpdClient, tag: &str, file: &str) -> Result<usize> {
+    if !tag_exists(mpd, tag)? {
+        return Ok(0);
+    }
+
+    let positions: Vec<u32> = mpd
+        .playlist(tag)?
+        .iter()
+        .enumerate()
+        .filter(|(_, s)| s.file == file)
+        .map(|(i, _)| i as u32)
+        .collect();
+
+    // delete from the back so earlier positions stay valid
+    for pos in positions.iter().rev() {
+        mpd.pl_delete(tag, *pos)?;
+    }
+
+    Ok(positions.len())
+}
+
+/// Creates an empty tag. Returns false if it already existed, in which case
+/// it is left untouched.
+pub fn create_tag(mpd: &mut dyn MpdClient, tag: &str) -> Result<bool> {
+    if tag_exists(mpd, tag)? {
+        return Ok(false);
+    }
+
+    mpd.pl_clear(tag)?;
+    Ok(true)
+}
+
+/// Deletes a tag outright. Returns the number of songs it held, or `None`
+/// if there was no such tag.
+pub fn delete_tag(mpd: &mut dyn MpdClient, tag: &str) -> Result<Option<usize>> {
+    if !tag_exists(mpd, tag)? {
+        return Ok(None);
+    }
+
+    let track_count = mpd.playlist(tag)?.len();
+    mpd.pl_remove(tag)?;
+    Ok(Some(track_count))
+}
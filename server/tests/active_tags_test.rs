@@ -0,0 +1,112 @@
+mod fixtures;
+
+use fixtures::{get_json, mock_library, post_json, song, spawn_server, SongBuilder};
+use jukectl_server::models::song_queue::TagMatchMode;
+use jukectl_server::mpd_conn::mock_mpd::MockMpd;
+use jukectl_server::mpd_conn::traits::MpdClient;
+use serde_json::{json, Value};
+
+fn library() -> MockMpd {
+    let track = |path: &str, album: &str| song(path).by("Artist").on(album);
+    mock_library(vec![
+        ("jukebox", vec![track("j1.mp3", "Loud"), track("j2.mp3", "Loud"), track("j3.mp3", "Louder")]),
+        (
+            "chill",
+            vec![track("c1.mp3", "Calm"), track("c2.mp3", "Calm"), track("c3.mp3", "Still"), track("j1.mp3", "Loud")],
+        ),
+        ("explicit", vec![track("j1.mp3", "Loud")]),
+    ])
+}
+
+async fn post_active(base: &str, query: &str, body: Value) -> reqwest::Response {
+    post_json(format!("{}/tags/active{}", base, query), body).await
+}
+
+#[tokio::test]
+async fn test_active_tags_are_read_and_changed_separately_from_the_catalogue() {
+    let (base, state) = spawn_server(library()).await;
+
+    let active = get_json(format!("{}/tags/active", base)).await;
+    assert_eq!(active, json!({ "any": ["jukebox"], "not": [], "album_aware": false }));
+
+    let response = post_active(&base, "", json!({ "any": ["chill"], "not": ["explicit", ""] })).await;
+    assert!(response.status().is_success());
+    let change: Value = response.json().await.unwrap();
+    assert_eq!(change["any"], json!(["chill"]));
+    assert_eq!(change["not"], json!(["explicit"]));
+    assert_eq!(change["matched"], 3);
+    assert_eq!(change["flushed"], 0);
+    assert_eq!(state.queue.lock().await.len(), 3);
+
+    assert_eq!(get_json(format!("{}/tags/active", base)).await["any"], json!(["chill"]));
+    // `/tags` is still the library catalogue
+    let catalogue = get_json(format!("{}/tags", base)).await;
+    assert!(catalogue["playlists"].is_array());
+    assert!(catalogue.get("any").is_none());
+
+    // album-aware queues hold albums, but `matched` counts songs
+    state.config.lock().await.album_aware_shuffle = true;
+    state.queue.lock().await.set_album_aware(true);
+    let change: Value = post_active(&base, "", json!({ "any": ["chill"] })).await.json().await.unwrap();
+    assert_eq!(change["matched"], 4);
+    assert_eq!(change["album_aware"], true);
+    assert_eq!(state.queue.lock().await.len(), 3);
+}
+
+#[tokio::test]
+async fn test_unknown_tags_are_rejected_before_anything_changes() {
+    let (base, state) = spawn_server(library()).await;
+
+    let response = post_active(&base, "", json!({ "any": ["chill", "nope"] })).await;
+    assert_eq!(response.status(), 400);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["detail"], "no tag named `nope`");
+
+    let response = post_active(&base, "", json!({ "not": ["gone"], "expr": "chill or missing" })).await;
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["detail"], "no tags named `gone`, `missing`");
+
+    let response = post_active(&base, "", json!({ "expr": "chill and" })).await;
+    assert_eq!(response.status(), 400);
+
+    assert_eq!(state.tags_data.read().await.any, vec!["jukebox"]);
+
+    // field predicates name no playlist, and substring tags need not either
+    let response = post_active(&base, "", json!({ "expr": "artist:Artist and chill" })).await;
+    assert!(response.status().is_success());
+    state.config.lock().await.tag_match_mode = TagMatchMode::Substring;
+    state.queue.lock().await.set_tag_match_mode(TagMatchMode::Substring);
+    let change: Value = post_active(&base, "", json!({ "any": ["Cal"] })).await.json().await.unwrap();
+    assert_eq!(change["matched"], 2);
+
+    // the old endpoint keeps its lenient behaviour
+    let response = post_json(format!("{}/tags", base), json!({ "any": ["nope"] })).await;
+    assert!(response.status().is_success());
+}
+
+#[tokio::test]
+async fn test_flush_replaces_upcoming_mpd_entries() {
+    let mut mock = library();
+    for file in ["j1.mp3", "j2.mp3", "j3.mp3"] {
+        mock.push(file).unwrap();
+    }
+    let (base, _state) = spawn_server(mock.clone()).await;
+
+    // without flush, MPD keeps what it already had lined up
+    let change: Value = post_active(&base, "", json!({ "any": ["chill"] })).await.json().await.unwrap();
+    assert_eq!(change["flushed"], 0);
+    assert_eq!(mock.queue().unwrap().len(), 3);
+
+    let change: Value = post_active(&base, "?flush=true", json!({ "any": ["chill"], "not": ["explicit"] }))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(change["flushed"], 2);
+
+    let queue = mock.queue().unwrap();
+    // the playing song is left alone and the rest comes from the new tags
+    assert_eq!(queue[0].file, "j1.mp3");
+    assert!(queue.len() >= 2);
+    assert!(queue[1..].iter().all(|s| s.file.starts_with('c')), "{:?}", queue);
+}
@@ -1,7 +1,31 @@
-use jukectl_server::models::song_queue::{DequeueMode, SongQueue};
+mod fixtures;
+
+use fixtures::realish_library;
+use jukectl_server::models::song_queue::SongQueue;
+use jukectl_server::models::tags_data::TagsData;
 use jukectl_server::mpd_conn::mock_mpd::MockMpd;
-use jukectl_server::mpd_conn::traits::Song;
-use jukectl_server::mpd_conn::mpd_pool::MpdPool;
+use jukectl_server::mpd_conn::traits::{parse_tag_number, Song};
+use std::collections::HashSet;
+
+fn library_mpd() -> MockMpd {
+    let mock = MockMpd::new();
+    mock.add_playlist("jukebox", realish_library());
+    mock
+}
+
+fn library_song(file: &str) -> Song {
+    realish_library().into_iter().find(|s| s.file == file).unwrap()
+}
+
+fn expand(seed: &str) -> Vec<String> {
+    let mut queue = SongQueue::new();
+    queue.add(library_song(seed));
+    queue
+        .dequeue_as_album(&mut library_mpd())
+        .into_iter()
+        .map(|s| s.file)
+        .collect()
+}
 
 #[tokio::test]
 async fn test_album_aware_shuffle_basic() {
@@ -16,8 +40,168 @@ async fn test_album_aware_shuffle_basic() {
         duration: Some(180),
         pos: Some(0),
         id: Some(1),
+        ..Default::default()
     };
     
     queue.add(song1);
     assert_eq!(queue.len(), 1);
 }
+
+#[test]
+fn test_album_plays_disc_then_track_order() {
+    let files = expand("music/pink_floyd/the_wall/cd2/02_is_there_anybody_out_there.mp3");
+    assert_eq!(
+        files,
+        vec![
+            "music/pink_floyd/the_wall/cd1/01_in_the_flesh.mp3",
+            "music/pink_floyd/the_wall/cd1/02_the_thin_ice.mp3",
+            "music/pink_floyd/the_wall/cd2/01_hey_you.mp3",
+            "music/pink_floyd/the_wall/cd2/02_is_there_anybody_out_there.mp3",
+        ]
+    );
+}
+
+#[test]
+fn test_compilation_groups_by_album_artist() {
+    let files = expand("music/soundtracks/guardians/02_go_all_the_way.mp3");
+    assert_eq!(
+        files,
+        vec![
+            "music/soundtracks/guardians/01_hookman.mp3",
+            "music/soundtracks/guardians/02_go_all_the_way.mp3",
+        ]
+    );
+}
+
+#[test]
+fn test_same_album_name_different_artist_stays_apart() {
+    let files = expand("music/the_band/greatest_hits/02_the_weight.mp3");
+    assert_eq!(
+        files,
+        vec![
+            "music/the_band/greatest_hits/01_up_on_cripple_creek.mp3",
+            "music/the_band/greatest_hits/02_the_weight.mp3",
+        ]
+    );
+}
+
+#[test]
+fn test_missing_track_numbers_sort_last() {
+    let mock = MockMpd::new();
+    let mut untracked = library_song("music/led_zeppelin/iv/black_dog.mp3");
+    untracked.file = "music/led_zeppelin/iv/bonus.mp3".to_string();
+    untracked.track = None;
+    let mut songs = realish_library();
+    songs.insert(0, untracked);
+    mock.add_playlist("jukebox", songs);
+
+    let mut queue = SongQueue::new();
+    queue.add(library_song("music/led_zeppelin/iv/stairway_to_heaven.mp3"));
+    let files: Vec<String> = queue
+        .dequeue_as_album(&mut mock.clone())
+        .into_iter()
+        .map(|s| s.file)
+        .collect();
+    assert_eq!(
+        files,
+        vec![
+            "music/led_zeppelin/iv/black_dog.mp3",
+            "music/led_zeppelin/iv/rock_and_roll.mp3",
+            "music/led_zeppelin/iv/stairway_to_heaven.mp3",
+            "music/led_zeppelin/iv/bonus.mp3",
+        ]
+    );
+}
+
+#[test]
+fn test_parse_tag_number() {
+    assert_eq!(parse_tag_number("3"), Some(3));
+    assert_eq!(parse_tag_number("03/12"), Some(3));
+    assert_eq!(parse_tag_number(" 7 "), Some(7));
+    assert_eq!(parse_tag_number("A1"), None);
+    assert_eq!(parse_tag_number(""), None);
+}
+
+fn jukebox_tags() -> TagsData {
+    TagsData {
+        any: vec!["jukebox".to_string()],
+        not: vec![],
+        ..Default::default()
+    }
+}
+
+fn album_count(songs: &[Song]) -> usize {
+    songs
+        .iter()
+        .map(|s| (s.album.clone(), s.album_identity_artist().map(str::to_string)))
+        .collect::<HashSet<_>>()
+        .len()
+}
+
+#[test]
+fn test_album_aware_queue_holds_one_seed_per_album() {
+    let library = realish_library();
+    let mut mpd = library_mpd();
+    let mut queue = SongQueue::new();
+    queue.set_album_aware(true);
+    queue.shuffle_and_add(&jukebox_tags(), &mut mpd);
+
+    assert_eq!(queue.len(), album_count(&library));
+    assert!(queue.albums().all(|seed| album_count(&seed.songs) == 1));
+
+    let mut played = Vec::new();
+    let mut albums = HashSet::new();
+    while !queue.is_empty() {
+        let songs = queue.dequeue_as_album(&mut mpd);
+        assert!(albums.insert((songs[0].album.clone(), songs[0].album_identity_artist().map(str::to_string))));
+        played.extend(songs.into_iter().map(|s| s.file));
+    }
+
+    played.sort();
+    let mut expected: Vec<String> = library.into_iter().map(|s| s.file).collect();
+    expected.sort();
+    assert_eq!(played, expected);
+}
+
+#[test]
+fn test_toggling_album_aware_rebuilds_the_queue() {
+    let library = realish_library();
+    let mut mpd = library_mpd();
+    let mut queue = SongQueue::new();
+    queue.shuffle_and_add(&jukebox_tags(), &mut mpd);
+    assert_eq!(queue.len(), library.len());
+
+    queue.set_album_aware(true);
+    assert!(queue.is_album_aware());
+    assert_eq!(queue.len(), album_count(&library));
+    // listings show one song per album
+    assert_eq!(queue.head(Some(100)).len(), album_count(&library));
+
+    queue.set_album_aware(false);
+    assert_eq!(queue.len(), library.len());
+    let mut files: Vec<String> = queue.head(Some(100)).into_iter().map(|s| s.file).collect();
+    files.sort();
+    let mut expected: Vec<String> = library.into_iter().map(|s| s.file).collect();
+    expected.sort();
+    assert_eq!(files, expected);
+}
+
+#[test]
+fn test_album_seed_plays_its_tracks_in_order_one_at_a_time() {
+    let mut queue = SongQueue::new();
+    queue.set_album_aware(true);
+    queue.add(library_song("music/pink_floyd/the_wall/cd2/01_hey_you.mp3"));
+    queue.add(library_song("music/led_zeppelin/iv/black_dog.mp3"));
+    queue.add(library_song("music/pink_floyd/the_wall/cd1/02_the_thin_ice.mp3"));
+    assert_eq!(queue.len(), 2);
+
+    let order: Vec<String> = std::iter::from_fn(|| queue.remove()).map(|s| s.file).collect();
+    assert_eq!(
+        order,
+        vec![
+            "music/pink_floyd/the_wall/cd1/02_the_thin_ice.mp3",
+            "music/pink_floyd/the_wall/cd2/01_hey_you.mp3",
+            "music/led_zeppelin/iv/black_dog.mp3",
+        ]
+    );
+}
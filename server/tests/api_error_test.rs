@@ -0,0 +1,110 @@
+mod fixtures;
+
+use fixtures::{get_json, patch_json, post_empty, post_json, song, spawn_server, SongBuilder};
+use jukectl_server::mpd_conn::mock_mpd::MockMpd;
+use jukectl_server::mpd_conn::traits::MpdClient;
+use jukectl_server::routes::error::ErrorBody;
+use serde_json::json;
+
+async fn error(response: reqwest::Response, status: u16) -> ErrorBody {
+    assert_eq!(response.status(), status);
+    response.json().await.unwrap()
+}
+
+#[tokio::test]
+async fn test_mpd_down_is_503_not_an_empty_result() {
+    let mock = MockMpd::new();
+    mock.add_playlist("jukebox", vec![song("a.mp3").by("Artist")]);
+    let (base, state) = spawn_server(mock.clone()).await;
+    let client = reqwest::Client::new();
+
+    // an empty queue is still a plain empty list
+    assert_eq!(get_json(format!("{}/", base)).await, json!([]));
+    assert!(get_json(format!("{}/song/now", base)).await.is_null());
+
+    mock.simulate_disconnect();
+    for path in ["/", "/queue/all", "/song/now", "/song/all", "/tags", "/tags/available", "/tags/jukebox"] {
+        let body = error(reqwest::get(format!("{}{}", base, path)).await.unwrap(), 503).await;
+        assert_eq!(body.error, "mpd_unavailable", "{}", path);
+        assert!(body.detail.contains("Not connected"), "{}: {}", path, body.detail);
+    }
+    let response = post_empty(format!("{}/skip", base)).await;
+    assert_eq!(error(response, 503).await.error, "mpd_unavailable");
+    let response = client.put(format!("{}/tags/morning", base)).send().await.unwrap();
+    assert_eq!(error(response, 503).await.error, "mpd_unavailable");
+
+    // switching tags fails rather than matching nothing, and keeps the old ones
+    let before = state.tags_data.read().await.clone();
+    for path in ["/tags", "/tags/active"] {
+        let response = post_json(format!("{}{}", base, path), json!({ "any": ["jukebox"] })).await;
+        assert_eq!(error(response, 503).await.error, "mpd_unavailable", "{}", path);
+    }
+    assert_eq!(*state.tags_data.read().await, before);
+
+    // routes that never touch MPD keep working
+    assert!(reqwest::get(format!("{}/queue", base)).await.unwrap().status().is_success());
+    assert!(reqwest::get(format!("{}/config", base)).await.unwrap().status().is_success());
+
+    mock.simulate_reconnect();
+    assert!(reqwest::get(format!("{}/", base)).await.unwrap().status().is_success());
+}
+
+#[tokio::test]
+async fn test_missing_things_are_404() {
+    let mut mock = MockMpd::new();
+    mock.add_playlist("jukebox", vec![song("a.mp3").by("Artist")]);
+    mock.pl_clear("empty").unwrap();
+    let (base, _state) = spawn_server(mock).await;
+    let client = reqwest::Client::new();
+
+    let body = error(reqwest::get(format!("{}/tags/nope", base)).await.unwrap(), 404).await;
+    assert_eq!(
+        body,
+        ErrorBody {
+            error: "not_found".to_string(),
+            detail: "no tag, artist or album named `nope`".to_string(),
+        }
+    );
+    // an artist, or a tag that exists but is empty, is not missing
+    assert_eq!(get_json(format!("{}/tags/Artist", base)).await.as_array().unwrap().len(), 1);
+    assert_eq!(get_json(format!("{}/tags/empty", base)).await, json!([]));
+
+    let response = client.delete(format!("{}/tags/nope", base)).send().await.unwrap();
+    assert_eq!(error(response, 404).await.detail, "no tag named `nope`");
+    let response = client.get(format!("{}/stations/nope", base)).send().await.unwrap();
+    assert_eq!(error(response, 404).await.detail, "no station named `nope`");
+    let response = client.delete(format!("{}/schedule/7", base)).send().await.unwrap();
+    assert_eq!(error(response, 404).await.detail, "no schedule rule 7");
+
+    // skipping or tagging with nothing playing
+    let response = post_empty(format!("{}/skip", base)).await;
+    assert_eq!(error(response, 404).await.detail, "nothing is playing");
+    let response = post_json(format!("{}/song/tags", base), json!({ "add": ["jukebox"] })).await;
+    assert_eq!(error(response, 404).await.detail, "nothing is playing");
+}
+
+#[tokio::test]
+async fn test_bad_input_is_400_with_detail() {
+    let (base, _state) = spawn_server(MockMpd::new()).await;
+    let client = reqwest::Client::new();
+
+    let response = patch_json(format!("{}/config", base), json!({ "low_water_mark": 0 })).await;
+    let body = error(response, 400).await;
+    assert_eq!(body.error, "bad_request");
+    assert!(body.detail.contains("low_water_mark"), "{}", body.detail);
+
+    let response = client
+        .post(format!("{}/song/tags", base))
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body("{\"add\": \"not a list\"}")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(error(response, 400).await.error, "bad_request");
+
+    let response = client.get(format!("{}/skips?reason=bored", base)).send().await.unwrap();
+    assert!(error(response, 400).await.detail.contains("bored"));
+
+    let response = post_json(format!("{}/schedule", base), json!({ "when": "daily 07:00", "station": "ghost" })).await;
+    assert_eq!(error(response, 400).await.detail, "no station named `ghost`");
+}
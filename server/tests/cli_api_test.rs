@@ -0,0 +1,283 @@
+// Drives the HTTP calls made by the `jukectl` CLI against a real Rocket
+// listener backed by `MockMpd`, parsing responses with the CLI's own types.
+mod fixtures;
+
+// the CLI is a binary, so its models are compiled in from its source
+#[allow(dead_code)]
+#[path = "../../cli/src/models/mod.rs"]
+mod cli_models;
+
+use cli_models::responses::{ActiveStation, HistoryResponse, QueueResponse, SongScore, StationInfo, TagInfo, TagsChange};
+use cli_models::tags_data::TagsData;
+use fixtures::{song, spawn_server};
+use jukectl_server::mpd_conn::mock_mpd::MockMpd;
+use jukectl_server::mpd_conn::traits::MpdClient;
+
+#[tokio::test]
+async fn test_cli_queue_head_tail() {
+    let (base, state) = spawn_server(MockMpd::new()).await;
+    {
+        let mut queue = state.queue.lock().await;
+        for i in 0..5 {
+            queue.add(song(&format!("song{}.mp3", i)));
+        }
+    }
+
+    let queue: QueueResponse = reqwest::get(format!("{}/queue?count=2", base))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    assert_eq!(queue.length, 5);
+    assert_eq!(queue.head, vec!["song0.mp3", "song1.mp3"]);
+    assert_eq!(queue.tail, vec!["song3.mp3", "song4.mp3"]);
+}
+
+#[tokio::test]
+async fn test_cli_skip() {
+    let mut mock = MockMpd::new();
+    MpdClient::push(&mut mock, "first.mp3").unwrap();
+    MpdClient::push(&mut mock, "second.mp3").unwrap();
+    let (base, _state) = spawn_server(mock.clone()).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/skip", base))
+        .header(reqwest::header::CONTENT_LENGTH, "0")
+        .send()
+        .await
+        .unwrap();
+    assert!(response.status().is_success());
+
+    let json: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(json["skipped"].as_str(), Some("first.mp3"));
+    assert_eq!(json["new"].as_str(), Some("second.mp3"));
+
+    let queue = MpdClient::queue(&mut mock).unwrap();
+    assert_eq!(queue.len(), 1);
+    assert_eq!(queue[0].file, "second.mp3");
+}
+
+#[tokio::test]
+async fn test_cli_playback_sets_tags() {
+    let (base, state) = spawn_server(MockMpd::new()).await;
+
+    // what `jukectl playback rock` sends: empty not-tags and album_aware=false
+    let body = r#"{"any":["rock"],"not":[""],"album_aware":false}"#;
+    let response = reqwest::Client::new()
+        .post(format!("{}/tags", base))
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(body)
+        .send()
+        .await
+        .unwrap();
+    assert!(response.status().is_success());
+
+    let tags: TagsData = response.json().await.unwrap();
+    assert_eq!(tags.any, vec!["rock"]);
+    assert!(tags.not.is_empty());
+    assert!(!tags.album_aware);
+
+    let active = state.tags_data.read().await;
+    assert_eq!(active.any, vec!["rock"]);
+    assert!(active.not.is_empty());
+}
+
+#[tokio::test]
+async fn test_cli_playback_and_status_use_active_tags() {
+    let mock = MockMpd::new();
+    mock.add_playlist("rock", vec![song("a.mp3"), song("b.mp3")]);
+    let (base, _state) = spawn_server(mock).await;
+    let client = reqwest::Client::new();
+
+    // what `jukectl playback rock` sends now
+    let body = r#"{"any":["rock"],"not":[""],"album_aware":false}"#;
+    let response = client
+        .post(format!("{}/tags/active?flush=false", base))
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(body)
+        .send()
+        .await
+        .unwrap();
+    assert!(response.status().is_success());
+    let change: TagsChange = response.json().await.unwrap();
+    assert_eq!(change.matched, 2);
+    assert_eq!(change.flushed, 0);
+
+    // and what `jukectl status` reads back
+    let tags: TagsData = reqwest::get(format!("{}/tags/active", base)).await.unwrap().json().await.unwrap();
+    assert_eq!(tags.any, vec!["rock"]);
+    assert!(tags.not.is_empty());
+    assert!(!tags.album_aware);
+}
+
+#[tokio::test]
+async fn test_cli_available_tags() {
+    let mock = MockMpd::new();
+    mock.add_playlist("jukebox", vec![song("a.mp3"), song("b.mp3"), song("c.mp3")]);
+    mock.add_playlist("chill", vec![song("a.mp3")]);
+    let (base, _state) = spawn_server(mock).await;
+
+    let tags: Vec<TagInfo> = reqwest::get(format!("{}/tags/available", base))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    assert_eq!(tags.len(), 2);
+    assert_eq!(tags[0].name, "jukebox");
+    assert_eq!(tags[0].track_count, 3);
+    assert_eq!(tags[1].name, "chill");
+    assert_eq!(tags[1].track_count, 1);
+}
+
+#[tokio::test]
+async fn test_cli_tag_and_untag() {
+    let mut mock = MockMpd::new();
+    mock.add_playlist("jukebox", vec![song("a.mp3"), song("b.mp3")]);
+    MpdClient::push(&mut mock, "a.mp3").unwrap();
+    let (base, _state) = spawn_server(mock.clone()).await;
+
+    let request_body = serde_json::json!({
+        "filename": "a.mp3",
+        "add": ["favorites"],
+        "remove": ["jukebox"]
+    });
+    let response = reqwest::Client::new()
+        .post(format!("{}/song/tags", base))
+        .json(&request_body)
+        .send()
+        .await
+        .unwrap();
+    assert!(response.status().is_success());
+
+    let favorites = MpdClient::playlist(&mut mock, "favorites").unwrap();
+    assert_eq!(favorites.len(), 1);
+    assert_eq!(favorites[0].file, "a.mp3");
+
+    let jukebox = MpdClient::playlist(&mut mock, "jukebox").unwrap();
+    assert_eq!(jukebox.len(), 1);
+    assert_eq!(jukebox[0].file, "b.mp3");
+}
+
+#[tokio::test]
+async fn test_cli_album_mode_toggle() {
+    let (base, state) = spawn_server(MockMpd::new()).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/album-mode/toggle", base))
+        .header(reqwest::header::CONTENT_LENGTH, "0")
+        .send()
+        .await
+        .unwrap();
+    assert!(response.status().is_success());
+
+    let tags: TagsData = response.json().await.unwrap();
+    assert!(tags.album_aware);
+    assert!(state.config.lock().await.album_aware_shuffle);
+}
+
+#[tokio::test]
+async fn test_cli_history() {
+    let (base, state) = spawn_server(MockMpd::new()).await;
+    {
+        let mut history = state.history.lock().await;
+        let now = std::time::SystemTime::now();
+        history.observe(Some(&song("a.mp3")), now);
+        history.skip(&song("b.mp3"), now);
+    }
+
+    let history: HistoryResponse = reqwest::get(format!("{}/history?limit=20", base))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    assert_eq!(history.total, 2);
+    let entry = &history.entries[0];
+    assert_eq!(entry.file, "b.mp3");
+    assert!(entry.skipped);
+    assert_eq!((entry.title.as_deref(), entry.artist.as_deref()), (None, None));
+    assert!(entry.started_at > 0 && entry.played_secs == 0);
+}
+
+#[tokio::test]
+async fn test_cli_station_save_play_list_delete() {
+    let mock = MockMpd::new();
+    mock.add_playlist("morning", vec![song("m.mp3")]);
+    let (base, state) = spawn_server(mock).await;
+    let client = reqwest::Client::new();
+
+    // what `jukectl station save morning morning --volume 40` sends
+    let body = r#"{"any":["morning"],"not":[""],"album_aware":false,"volume":40}"#;
+    let response = client
+        .put(format!("{}/stations/morning", base))
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(body)
+        .send()
+        .await
+        .unwrap();
+    assert!(response.status().is_success());
+
+    // `jukectl station play morning`
+    let response = client
+        .post(format!("{}/stations/morning/activate", base))
+        .header(reqwest::header::CONTENT_LENGTH, "0")
+        .send()
+        .await
+        .unwrap();
+    assert!(response.status().is_success());
+    assert_eq!(state.tags_data.read().await.any, vec!["morning"]);
+
+    // `jukectl station list`
+    let stations: Vec<StationInfo> = reqwest::get(format!("{}/stations", base)).await.unwrap().json().await.unwrap();
+    assert_eq!(stations.len(), 1);
+    assert_eq!(stations[0].name, "morning");
+    assert!(stations[0].station.not.is_empty());
+    assert!(!stations[0].station.album_aware);
+    assert_eq!(stations[0].station.volume, Some(40));
+    assert_eq!(stations[0].station.any, vec!["morning"]);
+    let active: ActiveStation = reqwest::get(format!("{}/stations/active", base)).await.unwrap().json().await.unwrap();
+    assert_eq!(active.name.as_deref(), Some("morning"));
+
+    // `jukectl station delete morning`, then playing it again is a 404
+    let response = client.delete(format!("{}/stations/morning", base)).send().await.unwrap();
+    assert!(response.status().is_success());
+    let response = client
+        .post(format!("{}/stations/morning/activate", base))
+        .header(reqwest::header::CONTENT_LENGTH, "0")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_cli_rate_encodes_the_file_into_the_path() {
+    let mock = MockMpd::new();
+    mock.add_playlist("jukebox", vec![song("Artist/Album/01 Song.mp3")]);
+    let (base, _state) = spawn_server(mock).await;
+
+    // what `jukectl rate 4 --file 'Artist/Album/01 Song.mp3'` builds
+    let mut url = reqwest::Url::parse(&base).unwrap();
+    url.path_segments_mut()
+        .unwrap()
+        .pop_if_empty()
+        .extend(["song", "Artist/Album/01 Song.mp3", "rating"]);
+    let response = reqwest::Client::new()
+        .put(url)
+        .json(&serde_json::json!({ "rating": 4 }))
+        .send()
+        .await
+        .unwrap();
+    assert!(response.status().is_success());
+
+    let score: SongScore = response.json().await.unwrap();
+    assert_eq!(score.file, "Artist/Album/01 Song.mp3");
+    assert_eq!(score.rating, Some(4));
+    assert_eq!(score.weight, 1.5);
+    assert_eq!((score.plays, score.skips), (0, 0));
+}
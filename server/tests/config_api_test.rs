@@ -0,0 +1,107 @@
+mod fixtures;
+
+use fixtures::{get_json, patch_json, post_empty, song, spawn_server, SongBuilder};
+use jukectl_server::mpd_conn::mock_mpd::MockMpd;
+use serde_json::{json, Value};
+use std::time::Duration;
+
+async fn patch(base: &str, body: Value) -> reqwest::Response {
+    patch_json(format!("{}/config", base), body).await
+}
+
+#[tokio::test]
+async fn test_get_config_defaults() {
+    let (base, _state) = spawn_server(MockMpd::new()).await;
+
+    let config = get_json(format!("{}/config", base)).await;
+    assert_eq!(
+        config,
+        json!({
+            "album_aware": false,
+            "tag_match_mode": "playlist",
+            "low_water_mark": 2,
+            "refill_threshold": 0,
+            "poll_interval_ms": 3000,
+            "artist_spacing": 0,
+            "track_cooldown_hours": 0,
+            "rating_boost": 1.5,
+            "skip_penalty": 1.0,
+            "play_penalty": 0.1,
+            "recovery_hours": 48,
+        })
+    );
+}
+
+#[tokio::test]
+async fn test_patch_config_updates_only_given_fields() {
+    let (base, state) = spawn_server(MockMpd::new()).await;
+
+    let response = patch(&base, json!({ "low_water_mark": 5, "poll_interval_ms": 500 })).await;
+    assert!(response.status().is_success());
+    let config: Value = response.json().await.unwrap();
+    assert_eq!(config["low_water_mark"], 5);
+    assert_eq!(config["poll_interval_ms"], 500);
+    assert_eq!(config["album_aware"], false);
+
+    let stored = state.config.lock().await.clone();
+    assert_eq!(stored.low_water_mark, 5);
+    assert_eq!(stored.poll_interval, Duration::from_millis(500));
+
+    // null leaves a setting as it is
+    let config: Value = patch(&base, json!({ "low_water_mark": null, "artist_spacing": 2 }))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(config["low_water_mark"], 5);
+    assert_eq!(config["artist_spacing"], 2);
+}
+
+#[tokio::test]
+async fn test_patch_config_rejects_invalid_values() {
+    let (base, state) = spawn_server(MockMpd::new()).await;
+
+    for body in [
+        json!({ "low_water_mark": 0 }),
+        json!({ "refill_threshold": 5000 }),
+        json!({ "poll_interval_ms": 1 }),
+        json!({ "tag_match_mode": "regex" }),
+        json!({ "no_such_setting": true }),
+    ] {
+        let response = patch(&base, body.clone()).await;
+        assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST, "{}", body);
+    }
+
+    let response = patch(&base, json!({ "no_such_setting": true })).await;
+    assert!(response.text().await.unwrap().contains("unknown setting `no_such_setting`"));
+
+    // a rejected patch changes nothing, even its valid fields
+    let response = patch(&base, json!({ "album_aware": true, "low_water_mark": 99 })).await;
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+    assert!(response.text().await.unwrap().contains("low_water_mark"));
+    assert!(!state.config.lock().await.album_aware_shuffle);
+}
+
+#[tokio::test]
+async fn test_patch_album_aware_rebuilds_queue() {
+    let (base, state) = spawn_server(MockMpd::new()).await;
+    {
+        let mut queue = state.queue.lock().await;
+        queue.add(song("a1.mp3").by("Artist").on("A"));
+        queue.add(song("b1.mp3").by("Artist").on("B"));
+        queue.add(song("a2.mp3").by("Artist").on("A"));
+    }
+
+    let response = patch(&base, json!({ "album_aware": true, "tag_match_mode": "substring" })).await;
+    assert!(response.status().is_success());
+    {
+        let queue = state.queue.lock().await;
+        assert!(queue.is_album_aware());
+        assert_eq!(queue.len(), 2);
+    }
+
+    let response = post_empty(format!("{}/album-mode/toggle", base)).await;
+    let toggled: Value = response.json().await.unwrap();
+    assert_eq!(toggled["album_aware"], false);
+    assert_eq!(state.queue.lock().await.len(), 3);
+}
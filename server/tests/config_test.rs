@@ -1,21 +1,23 @@
 use jukectl_server::app_state::{initialize, load_default_tags};
 use std::env;
-use std::sync::Mutex;
+use tokio::sync::Mutex;
 use base64::{engine::general_purpose, Engine as _};
 
 // Use a global mutex to prevent environment variable race conditions during tests
-static ENV_MUTEX: Mutex<()> = Mutex::new(());
+static ENV_MUTEX: Mutex<()> = Mutex::const_new(());
 
 #[tokio::test]
 async fn test_initialize_basic() {
-    let _lock = ENV_MUTEX.lock().unwrap();
+    let _lock = ENV_MUTEX.lock().await;
     let state = initialize().await;
-    assert!(state.mpd_pool.get_connection().await.is_ok() || true);
+    // MPD may not be reachable here; initialize() must still hand back a usable state
+    let _ = state.mpd_pool.get_connection().await;
+    assert!(state.queue.lock().await.is_empty());
 }
 
 #[test]
 fn test_load_default_tags_fallback() {
-    let _lock = ENV_MUTEX.lock().unwrap();
+    let _lock = ENV_MUTEX.blocking_lock();
     env::remove_var("JUKECTL_DEFAULT_TAGS_B64");
     let tags = load_default_tags();
     assert_eq!(tags.any, vec!["jukebox".to_string()]);
@@ -24,7 +26,7 @@ fn test_load_default_tags_fallback() {
 
 #[test]
 fn test_load_default_tags_valid_b64() {
-    let _lock = ENV_MUTEX.lock().unwrap();
+    let _lock = ENV_MUTEX.blocking_lock();
     let json = r#"{"any": ["tag1", "tag2"], "not": ["tag3"]}"#;
     let b64 = general_purpose::STANDARD.encode(json);
     env::set_var("JUKECTL_DEFAULT_TAGS_B64", b64);
@@ -38,7 +40,7 @@ fn test_load_default_tags_valid_b64() {
 
 #[test]
 fn test_load_default_tags_invalid_b64() {
-    let _lock = ENV_MUTEX.lock().unwrap();
+    let _lock = ENV_MUTEX.blocking_lock();
     env::set_var("JUKECTL_DEFAULT_TAGS_B64", "!!!not-base64!!!");
     let tags = load_default_tags();
     assert_eq!(tags.any, vec!["jukebox".to_string()]);
@@ -47,7 +49,7 @@ fn test_load_default_tags_invalid_b64() {
 
 #[test]
 fn test_load_default_tags_invalid_json() {
-    let _lock = ENV_MUTEX.lock().unwrap();
+    let _lock = ENV_MUTEX.blocking_lock();
     let invalid_json = r#"{"any": ["tag1"], "not": "#;
     let b64 = general_purpose::STANDARD.encode(invalid_json);
     env::set_var("JUKECTL_DEFAULT_TAGS_B64", b64);
@@ -0,0 +1,147 @@
+mod fixtures;
+
+use fixtures::{song, SongBuilder};
+use jukectl_server::app_state::{AppState, Config};
+use jukectl_server::models::cooldown::{space_artists, Cooldown, RecentPlays};
+use jukectl_server::models::history::History;
+use jukectl_server::models::song_queue::SongQueue;
+use jukectl_server::models::tags_data::TagsData;
+use jukectl_server::mpd_conn::mock_mpd::MockMpd;
+use jukectl_server::mpd_conn::mpd_pool::MpdPool;
+use jukectl_server::mpd_conn::traits::{MpdClient, Song};
+use jukectl_server::scheduler::clock::ManualClock;
+use jukectl_server::scheduler::scheduler_tick;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Three songs each by A, B and C: `a1.mp3` is by A.
+fn library() -> Vec<Song> {
+    ["A", "B", "C"]
+        .iter()
+        .flat_map(|artist| (1..=3).map(move |i| song(&format!("{}{}.mp3", artist.to_lowercase(), i)).by(artist)))
+        .collect()
+}
+
+fn at(secs: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(secs)
+}
+
+fn files(songs: &[Song]) -> Vec<String> {
+    songs.iter().map(|s| s.file.clone()).collect()
+}
+
+fn artists(songs: &[Song]) -> Vec<String> {
+    songs.iter().map(|s| s.artist.clone().unwrap_or_default()).collect()
+}
+
+/// Panics if two entries closer than `spacing` apart share an artist.
+fn assert_spaced(sequence: &[String], spacing: usize) {
+    for (i, artist) in sequence.iter().enumerate() {
+        for other in sequence.iter().skip(i + 1).take(spacing) {
+            assert_ne!(artist, other, "{:?}", sequence);
+        }
+    }
+}
+
+#[test]
+fn test_spacing_keeps_artists_apart_and_is_seeded() {
+    let fill = |seed: u64| {
+        let mut queue = SongQueue::new();
+        queue.seed_rng(seed);
+        queue.set_cooldown(Cooldown { artist_spacing: 2, ..Cooldown::default() });
+        queue.add_songs(library());
+        queue.head(Some(queue.len()))
+    };
+
+    for seed in 0..20 {
+        let queued = fill(seed);
+        assert_eq!(queued.len(), 9);
+        assert_spaced(&artists(&queued), 2);
+        assert_eq!(files(&queued), files(&fill(seed)));
+    }
+}
+
+#[test]
+fn test_spacing_counts_what_plays_first_and_gives_way_when_impossible() {
+    let songs = vec![song("a1.mp3").by("A"), song("b1.mp3").by("B"), song("a2.mp3").by("A")];
+    let before = vec![Some("A".to_string())];
+    let spaced = space_artists(songs, &before, 1, |s| s.artist.as_deref());
+    assert_eq!(artists(&spaced), vec!["B", "A", "A"]);
+
+    // one artist only: everything is still queued, in shuffled order
+    let songs: Vec<Song> = (0..4).map(|i| song(&format!("a{}.mp3", i)).by("A")).collect();
+    let spaced = space_artists(songs.clone(), &[], 3, |s| s.artist.as_deref());
+    assert_eq!(files(&spaced), files(&songs));
+}
+
+#[test]
+fn test_recent_plays_come_from_history_inside_the_window() {
+    let mut history = History::new(10);
+    history.observe(Some(&song("a1.mp3").by("A")), at(1_000));
+    history.observe(Some(&song("b1.mp3").by("B")), at(5_000));
+    history.observe(Some(&song("c1.mp3").by("C")), at(5_200));
+    let upcoming = vec![song("c1.mp3").by("C"), song("a2.mp3").by("A")];
+    let cooldown = Cooldown {
+        artist_spacing: 1,
+        track_window: Duration::from_secs(3_600),
+    };
+
+    let recent = RecentPlays::new(&history, &upcoming, &cooldown, at(5_300));
+    // a1 was played more than an hour ago
+    let mut cooling: Vec<&str> = recent.files.iter().map(String::as_str).collect();
+    cooling.sort();
+    assert_eq!(cooling, vec!["a2.mp3", "b1.mp3", "c1.mp3"]);
+    let recent_artists: Vec<Option<&str>> = recent.artists.iter().map(Option::as_deref).collect();
+    assert_eq!(recent_artists, vec![Some("B"), Some("C"), Some("A")]);
+
+    let kept = recent.without_recent(library());
+    assert_eq!(kept.len(), 6);
+    assert!(kept.iter().all(|s| !recent.files.contains(&s.file)));
+    // everything cooling down is better than silence
+    let all_recent = vec![song("b1.mp3").by("B")];
+    assert_eq!(files(&recent.without_recent(all_recent.clone())), files(&all_recent));
+
+    let off = RecentPlays::new(&history, &upcoming, &Cooldown::default(), at(5_300));
+    assert_eq!(off, RecentPlays::default());
+}
+
+#[tokio::test]
+async fn test_scheduler_refill_carries_the_cooldown_from_history() {
+    for seed in 0..10 {
+        let mut mock = MockMpd::new();
+        mock.add_playlist("jukebox", library());
+        let config = Config {
+            artist_spacing: 1,
+            track_cooldown_hours: 1,
+            ..Config::default()
+        };
+        let tags = TagsData {
+            any: vec!["jukebox".to_string()],
+            not: vec![],
+            ..Default::default()
+        };
+        let state = AppState::new(Arc::new(MpdPool::with_mock(mock.clone(), 2)), config, tags);
+        state.queue.lock().await.seed_rng(seed);
+
+        // a1 played, b1 is playing now
+        mock.push("b1.mp3").unwrap();
+        {
+            let mut history = state.history.lock().await;
+            history.observe(Some(&song("a1.mp3").by("A")), at(10_000));
+            history.observe(Some(&song("b1.mp3").by("B")), at(10_200));
+        }
+
+        let clock = ManualClock::new(at(10_300));
+        let outcome = scheduler_tick(&state, &mut mock, &clock).await.unwrap();
+        assert_eq!(outcome.refilled, 7);
+
+        let queue = state.queue.lock().await;
+        let mut sequence = vec!["A".to_string(), "B".to_string()];
+        sequence.extend(artists(&outcome.pushed));
+        sequence.extend(artists(&queue.head(Some(queue.len()))));
+        assert_spaced(&sequence, 1);
+
+        let queued: Vec<Song> = outcome.pushed.iter().cloned().chain(queue.head(Some(queue.len()))).collect();
+        assert!(queued.iter().all(|s| s.file != "a1.mp3" && s.file != "b1.mp3"), "{:?}", queued);
+    }
+}
@@ -0,0 +1,314 @@
+// End-to-end tests of the real MPD backends, `MpdConn` and `MpdPool`
+// against `FakeMpd`. Each scenario runs once per compiled-in backend.
+mod fixtures;
+
+use fixtures::fake_mpd::FakeMpd;
+use fixtures::{mock_library, song, SongBuilder};
+use jukectl_server::app_state::{AppState, Config};
+use jukectl_server::models::tags_data::TagsData;
+use jukectl_server::mpd_conn::mock_mpd::MockMpd;
+use jukectl_server::mpd_conn::mpd_conn::{BackendKind, MpdConn};
+use jukectl_server::mpd_conn::mpd_pool::MpdPool;
+use jukectl_server::mpd_conn::traits::{FilterTerm, MpdClient, Query};
+use jukectl_server::scheduler::clock::SystemClock;
+use jukectl_server::scheduler::scheduler_tick;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+fn library() -> MockMpd {
+    let track = |path: &str, artist: &str| song(path).titled(path).by(artist).on("Album").lasting(180);
+    mock_library(vec![
+        ("jukebox", vec![track("a.mp3", "Band"), track("b.mp3", "Band"), track("c.mp3", "Other")]),
+        ("chill", vec![track("c.mp3", "Other")]),
+    ])
+}
+
+fn connect(fake: &FakeMpd, backend: BackendKind) -> MpdConn {
+    MpdConn::new_with_backend(&fake.host(), fake.port(), backend).unwrap()
+}
+
+fn queue_round_trip(backend: BackendKind) {
+    let fake = FakeMpd::start(library());
+    let mut conn = connect(&fake, backend);
+
+    conn.mpd.push("a.mp3").unwrap();
+    conn.mpd.push("b.mp3").unwrap();
+    conn.mpd.play().unwrap();
+
+    let queue = conn.mpd.queue().unwrap();
+    let files: Vec<&str> = queue.iter().map(|s| s.file.as_str()).collect();
+    assert_eq!(files, vec!["a.mp3", "b.mp3"]);
+    assert_eq!(queue[1].pos, Some(1));
+
+    conn.mpd.delete(0).unwrap();
+    assert_eq!(MpdClient::queue(&mut fake.mock.clone()).unwrap()[0].file, "b.mp3");
+
+    // connecting turns consume mode on
+    assert!(fake.received().contains(&"consume".to_string()));
+}
+
+fn library_listing(backend: BackendKind) {
+    let fake = FakeMpd::start(library());
+    let mut conn = connect(&fake, backend);
+
+    let mut names: Vec<String> = conn.mpd.playlists().unwrap().into_iter().map(|p| p.name).collect();
+    names.sort();
+    assert_eq!(names, vec!["chill", "jukebox"]);
+
+    let jukebox = conn.mpd.playlist("jukebox").unwrap();
+    assert_eq!(jukebox.len(), 3);
+    assert_eq!(jukebox[0].artist.as_deref(), Some("Band"));
+    assert_eq!(jukebox[0].duration, Some(180));
+
+    // c.mp3 is in both playlists but only once in the library
+    assert_eq!(conn.mpd.listall().unwrap().len(), 3);
+
+    let mut query = Query::new();
+    query.and(FilterTerm::Tag("artist".to_string(), "Other".to_string()));
+    let found = conn.mpd.search(&query, None).unwrap();
+    assert!(found.iter().all(|s| s.file == "c.mp3"));
+    assert!(!found.is_empty());
+}
+
+fn playlist_edits(backend: BackendKind) {
+    let fake = FakeMpd::start(library());
+    let mut conn = connect(&fake, backend);
+    let mut mock = fake.mock.clone();
+
+    conn.mpd.pl_push("favorites", "a.mp3").unwrap();
+    conn.mpd.pl_push("favorites", "b.mp3").unwrap();
+    conn.mpd.pl_delete("favorites", 0).unwrap();
+    let favorites = MpdClient::playlist(&mut mock, "favorites").unwrap();
+    assert_eq!(favorites.len(), 1);
+    assert_eq!(favorites[0].file, "b.mp3");
+
+    conn.mpd.pl_clear("jukebox").unwrap();
+    assert!(MpdClient::playlist(&mut mock, "jukebox").unwrap().is_empty());
+
+    conn.mpd.pl_remove("chill").unwrap();
+    assert!(MpdClient::playlist(&mut mock, "chill").is_err());
+}
+
+fn ack_errors_surface(backend: BackendKind) {
+    let fake = FakeMpd::start(library());
+    let mut conn = connect(&fake, backend);
+
+    assert!(conn.mpd.playlist("missing").is_err());
+    assert!(conn.mpd.delete(99).is_err());
+
+    fake.fail_next("playlistinfo", 52, "simulated failure");
+    let err = conn.mpd.queue().unwrap_err();
+    assert!(err.to_string().contains("simulated failure"), "{}", err);
+
+    // an ACK does not cost the connection
+    conn.mpd.queue().unwrap();
+    assert_eq!(fake.connections_accepted(), 1);
+}
+
+fn latency_is_applied(backend: BackendKind) {
+    let fake = FakeMpd::start(library());
+    let mut conn = connect(&fake, backend);
+
+    fake.set_latency(Duration::from_millis(100));
+    let started = Instant::now();
+    conn.mpd.ping().unwrap();
+    assert!(started.elapsed() >= Duration::from_millis(100));
+}
+
+fn reconnect_after_drop(backend: BackendKind) {
+    let fake = FakeMpd::start(library());
+    let mut conn = connect(&fake, backend);
+    conn.mpd.push("a.mp3").unwrap();
+
+    fake.drop_all_connections();
+    // give the server a moment to close the socket
+    std::thread::sleep(Duration::from_millis(50));
+    assert!(conn.ping().is_err());
+
+    conn.reconnect().unwrap();
+    assert_eq!(fake.connections_accepted(), 2);
+    assert_eq!(conn.mpd.queue().unwrap().len(), 1);
+}
+
+fn dropped_mid_command(backend: BackendKind) {
+    let fake = FakeMpd::start(library());
+    let mut conn = connect(&fake, backend);
+
+    fake.drop_next("listplaylists");
+    assert!(conn.mpd.playlists().is_err());
+
+    conn.reconnect().unwrap();
+    assert_eq!(conn.mpd.playlists().unwrap().len(), 2);
+}
+
+fn idle_reports_changes(backend: BackendKind) {
+    let fake = FakeMpd::start(library());
+    let mut conn = connect(&fake, backend);
+
+    let waiter = std::thread::spawn(move || {
+        let changed = conn.mpd.idle(&["playlist", "stored_playlist"]);
+        (conn, changed)
+    });
+    std::thread::sleep(Duration::from_millis(100));
+    fake.mock.clone().push("a.mp3").unwrap();
+
+    let (mut conn, changed) = waiter.join().unwrap();
+    assert_eq!(changed.unwrap(), vec!["playlist"]);
+    assert_eq!(conn.mpd.queue().unwrap().len(), 1);
+}
+
+fn idle_unsupported(backend: BackendKind) {
+    let fake = FakeMpd::start(library());
+    let mut conn = connect(&fake, backend);
+
+    fake.mock.disable_idle();
+    assert!(conn.mpd.idle(&["player"]).is_err());
+    // the connection stays usable for the polling fallback
+    assert_eq!(conn.mpd.playlists().unwrap().len(), 2);
+}
+
+fn volume_is_set(backend: BackendKind) {
+    let fake = FakeMpd::start(library());
+    let mut conn = connect(&fake, backend);
+
+    conn.mpd.set_volume(42).unwrap();
+    assert_eq!(fake.mock.volume(), Some(42));
+    assert!(conn.mpd.set_volume(150).is_err());
+    assert_eq!(fake.mock.volume(), Some(42));
+}
+
+async fn pool_replaces_dead_connections(backend: BackendKind) {
+    let fake = FakeMpd::start(library());
+    let pool = MpdPool::with_backend(fake.host(), fake.port(), 2, backend).unwrap();
+    pool.warm_pool(1).await.unwrap();
+    assert_eq!(fake.connections_accepted(), 1);
+
+    fake.drop_all_connections();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let mut pooled = pool.get_connection().await.unwrap();
+    assert_eq!(pooled.mpd_conn().mpd.playlists().unwrap().len(), 2);
+    assert_eq!(fake.connections_accepted(), 2);
+}
+
+async fn scheduler_fills_real_queue(backend: BackendKind) {
+    let fake = FakeMpd::start(library());
+    let pool = Arc::new(MpdPool::with_backend(fake.host(), fake.port(), 2, backend).unwrap());
+    let state = AppState::new(
+        pool.clone(),
+        Config::default(),
+        TagsData {
+            any: vec!["jukebox".to_string()],
+            not: vec!["chill".to_string()],
+            ..Default::default()
+        },
+    );
+
+    let mut pooled = pool.get_connection().await.unwrap();
+    let outcome = scheduler_tick(&state, &mut pooled.mpd_conn().mpd, &SystemClock)
+        .await
+        .unwrap();
+
+    assert_eq!(outcome.refilled, 2);
+    let queue = MpdClient::queue(&mut fake.mock.clone()).unwrap();
+    assert_eq!(queue.len(), 1);
+    assert_ne!(queue[0].file, "c.mp3");
+}
+
+macro_rules! backend_suite {
+    ($module:ident, $backend:expr) => {
+        mod $module {
+            use super::*;
+
+            #[test]
+            fn test_queue_round_trip() {
+                queue_round_trip($backend);
+            }
+
+            #[test]
+            fn test_library_listing() {
+                library_listing($backend);
+            }
+
+            #[test]
+            fn test_playlist_edits() {
+                playlist_edits($backend);
+            }
+
+            #[test]
+            fn test_ack_errors_surface() {
+                ack_errors_surface($backend);
+            }
+
+            #[test]
+            fn test_latency_is_applied() {
+                latency_is_applied($backend);
+            }
+
+            #[test]
+            fn test_reconnect_after_drop() {
+                reconnect_after_drop($backend);
+            }
+
+            #[test]
+            fn test_dropped_mid_command() {
+                dropped_mid_command($backend);
+            }
+
+            #[test]
+            fn test_idle_reports_changes() {
+                idle_reports_changes($backend);
+            }
+
+            #[test]
+            fn test_idle_unsupported() {
+                idle_unsupported($backend);
+            }
+
+            #[test]
+            fn test_volume_is_set() {
+                volume_is_set($backend);
+            }
+
+            #[tokio::test]
+            async fn test_pool_replaces_dead_connections() {
+                pool_replaces_dead_connections($backend).await;
+            }
+
+            #[tokio::test]
+            async fn test_scheduler_fills_real_queue() {
+                scheduler_fills_real_queue($backend).await;
+            }
+        }
+    };
+}
+
+#[cfg(feature = "libmpdclient")]
+backend_suite!(libmpdclient, BackendKind::Libmpdclient);
+#[cfg(feature = "native-mpd")]
+backend_suite!(native, BackendKind::Native);
+
+#[cfg(feature = "native-mpd")]
+#[tokio::test]
+async fn test_command_list_stops_at_first_ack() {
+    use jukectl_server::mpd_conn::native_client::AsyncMpdClient;
+    use jukectl_server::mpd_conn::protocol::{AckError, Command};
+
+    let fake = FakeMpd::start(library());
+    let mut client = AsyncMpdClient::connect(&fake.host(), fake.port()).await.unwrap();
+
+    let err = client
+        .execute_list(&[
+            Command::new("addid").arg("a.mp3"),
+            Command::new("listplaylistinfo").arg("missing"),
+            Command::new("addid").arg("b.mp3"),
+        ])
+        .await
+        .unwrap_err();
+    let ack = err.downcast_ref::<AckError>().unwrap();
+    assert_eq!((ack.code, ack.index), (AckError::NO_EXIST, 1));
+
+    let queue = client.queue().await.unwrap();
+    assert_eq!(queue.len(), 1);
+    assert_eq!(queue[0].file, "a.mp3");
+}
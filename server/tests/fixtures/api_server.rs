@@ -0,0 +1,47 @@
+use jukectl_server::app_state::{AppState, Config};
+use jukectl_server::models::tags_data::TagsData;
+use jukectl_server::mpd_conn::mock_mpd::MockMpd;
+use jukectl_server::mpd_conn::mpd_pool::MpdPool;
+use jukectl_server::routes;
+use std::sync::Arc;
+
+/// Launches the full route table on a free localhost port, backed by `mock`.
+/// Returns the base URL and the state the server is managing.
+pub async fn spawn_server(mock: MockMpd) -> (String, AppState) {
+    let state = AppState::new(
+        Arc::new(MpdPool::with_mock(mock, 5)),
+        Config::default(),
+        TagsData {
+            any: vec!["jukebox".to_string()],
+            not: vec![],
+            ..Default::default()
+        },
+    );
+
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+
+    let config = rocket::Config {
+        port,
+        log_level: rocket::config::LogLevel::Off,
+        ..rocket::Config::debug_default()
+    };
+
+    let rocket = rocket::custom(config)
+        .manage(state.clone())
+        .mount("/", routes::all_routes());
+    tokio::spawn(rocket.launch());
+
+    let base = format!("http://127.0.0.1:{}", port);
+    for _ in 0..100 {
+        if tokio::net::TcpStream::connect(("127.0.0.1", port)).await.is_ok() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+
+    (base, state)
+}
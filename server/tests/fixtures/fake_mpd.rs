@@ -0,0 +1,478 @@
+use jukectl_server::mpd_conn::mock_mpd::MockMpd;
+use jukectl_server::mpd_conn::traits::{FilterTerm, MpdClient, Playlist, Query, Song};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::net::tcp::OwnedReadHalf;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{oneshot, watch};
+
+pub const GREETING: &str = "OK MPD 0.23.5\n";
+
+const ACK_ARG: u32 = 2;
+const ACK_UNKNOWN: u32 = 5;
+const ACK_NO_EXIST: u32 = 50;
+const ACK_SYSTEM: u32 = 52;
+
+#[derive(Default)]
+struct Faults {
+    latency: Duration,
+    acks: HashMap<String, VecDeque<(u32, String)>>,
+    drops: HashSet<String>,
+}
+
+struct Shared {
+    mock: MockMpd,
+    faults: Mutex<Faults>,
+    accepted: AtomicUsize,
+    received: Mutex<Vec<String>>,
+    kill: watch::Sender<u64>,
+}
+
+type Request = (String, Vec<String>);
+
+enum Failure {
+    Ack(u32, String),
+    Hangup,
+}
+
+/// A localhost MPD server backed by a `MockMpd`, for driving the real
+/// client backends, `MpdConn` and `MpdPool` end to end. It runs on its own
+/// thread and runtime so blocking clients can be used from any test.
+pub struct FakeMpd {
+    pub mock: MockMpd,
+    port: u16,
+    shared: Arc<Shared>,
+    shutdown: Option<oneshot::Sender<()>>,
+}
+
+impl FakeMpd {
+    pub fn start(mock: MockMpd) -> FakeMpd {
+        let (kill, _) = watch::channel(0u64);
+        let shared = Arc::new(Shared {
+            mock: mock.clone(),
+            faults: Mutex::new(Faults::default()),
+            accepted: AtomicUsize::new(0),
+            received: Mutex::new(Vec::new()),
+            kill,
+        });
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (shutdown, shutdown_rx) = oneshot::channel();
+
+        let server_shared = shared.clone();
+        std::thread::spawn(move || {
+            let runtime = tokio::runtime::Builder::new_multi_thread()
+                .worker_threads(2)
+                .enable_all()
+                .build()
+                .unwrap();
+            runtime.block_on(async move {
+                let listener = TcpListener::from_std(listener).unwrap();
+                tokio::select! {
+                    _ = accept_loop(listener, server_shared) => {}
+                    _ = shutdown_rx => {}
+                }
+            });
+        });
+
+        FakeMpd {
+            mock,
+            port,
+            shared,
+            shutdown: Some(shutdown),
+        }
+    }
+
+    pub fn host(&self) -> String {
+        "127.0.0.1".to_string()
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Delay applied before every response.
+    pub fn set_latency(&self, latency: Duration) {
+        self.shared.faults.lock().unwrap().latency = latency;
+    }
+
+    /// The next `command` is answered with `ACK [code@..] {command} message`.
+    pub fn fail_next(&self, command: &str, code: u32, message: &str) {
+        self.shared
+            .faults
+            .lock()
+            .unwrap()
+            .acks
+            .entry(command.to_string())
+            .or_default()
+            .push_back((code, message.to_string()));
+    }
+
+    /// The next `command` closes its connection instead of being answered.
+    pub fn drop_next(&self, command: &str) {
+        self.shared.faults.lock().unwrap().drops.insert(command.to_string());
+    }
+
+    /// Closes every open client connection, as an MPD restart would.
+    pub fn drop_all_connections(&self) {
+        self.shared.kill.send_modify(|generation| *generation += 1);
+    }
+
+    pub fn connections_accepted(&self) -> usize {
+        self.shared.accepted.load(Ordering::SeqCst)
+    }
+
+    /// Names of every command received so far, in order.
+    pub fn received(&self) -> Vec<String> {
+        self.shared.received.lock().unwrap().clone()
+    }
+}
+
+impl Drop for FakeMpd {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+    }
+}
+
+async fn accept_loop(listener: TcpListener, shared: Arc<Shared>) {
+    while let Ok((socket, _)) = listener.accept().await {
+        shared.accepted.fetch_add(1, Ordering::SeqCst);
+        tokio::spawn(serve_connection(socket, shared.clone()));
+    }
+}
+
+async fn serve_connection(socket: TcpStream, shared: Arc<Shared>) {
+    let mut kill = shared.kill.subscribe();
+    let (reader, mut writer) = socket.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    // tracks which changes this client has already been told about by `idle`
+    let mut watcher = shared.mock.clone();
+
+    if writer.write_all(GREETING.as_bytes()).await.is_err() {
+        return;
+    }
+
+    // (list_OK after each command?, queued commands) while inside a command list
+    let mut list: Option<(bool, Vec<Request>)> = None;
+
+    loop {
+        let line = tokio::select! {
+            line = lines.next_line() => match line {
+                Ok(Some(line)) => line,
+                _ => return,
+            },
+            _ = kill.changed() => return,
+        };
+
+        let (name, args) = match tokenize(&line) {
+            Some(parsed) => parsed,
+            None => {
+                let reply = format!("ACK [{}@0] {{}} Invalid argument\n", ACK_ARG);
+                if writer.write_all(reply.as_bytes()).await.is_err() {
+                    return;
+                }
+                continue;
+            }
+        };
+
+        let reply = match (name.as_str(), list.as_mut()) {
+            ("command_list_begin", None) => {
+                list = Some((false, Vec::new()));
+                continue;
+            }
+            ("command_list_ok_begin", None) => {
+                list = Some((true, Vec::new()));
+                continue;
+            }
+            ("command_list_end", Some(_)) => {
+                let (list_ok, commands) = list.take().unwrap();
+                run_list(&shared, list_ok, commands)
+            }
+            (_, Some((_, commands))) => {
+                commands.push((name, args));
+                continue;
+            }
+            ("idle", None) => idle(&shared, &mut watcher, &args, &mut lines, &mut kill).await,
+            _ => run_list(&shared, false, vec![(name, args)]),
+        };
+
+        let latency = shared.faults.lock().unwrap().latency;
+        if !latency.is_zero() {
+            tokio::time::sleep(latency).await;
+        }
+
+        match reply {
+            Some(reply) => {
+                if writer.write_all(reply.as_bytes()).await.is_err() {
+                    return;
+                }
+            }
+            None => return,
+        }
+    }
+}
+
+/// Runs a batch of commands and renders the full reply, or `None` when the
+/// connection should be dropped.
+fn run_list(shared: &Shared, list_ok: bool, commands: Vec<Request>) -> Option<String> {
+    let mut reply = String::new();
+
+    for (index, (name, args)) in commands.iter().enumerate() {
+        shared.received.lock().unwrap().push(name.clone());
+
+        match execute(shared, name, args) {
+            Ok(body) => {
+                reply.push_str(&body);
+                if list_ok {
+                    reply.push_str("list_OK\n");
+                }
+            }
+            Err(Failure::Ack(code, message)) => {
+                reply.push_str(&format!("ACK [{}@{}] {{{}}} {}\n", code, index, name, message));
+                return Some(reply);
+            }
+            Err(Failure::Hangup) => return None,
+        }
+    }
+
+    reply.push_str("OK\n");
+    Some(reply)
+}
+
+/// Waits until one of the requested subsystems changes or the client sends
+/// `noidle`, like MPD does.
+async fn idle(
+    shared: &Shared,
+    watcher: &mut MockMpd,
+    args: &[String],
+    lines: &mut Lines<BufReader<OwnedReadHalf>>,
+    kill: &mut watch::Receiver<u64>,
+) -> Option<String> {
+    shared.received.lock().unwrap().push("idle".to_string());
+    match take_fault(shared, "idle") {
+        Some(Failure::Ack(code, message)) => return Some(format!("ACK [{}@0] {{idle}} {}\n", code, message)),
+        Some(Failure::Hangup) => return None,
+        None => {}
+    }
+
+    let subsystems: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+    let mut changed = Vec::new();
+    loop {
+        match watcher.idle_changes(&subsystems) {
+            Ok(found) if !found.is_empty() => {
+                changed = found;
+                break;
+            }
+            Ok(_) => {}
+            Err(e) => return Some(format!("ACK [{}@0] {{idle}} {}\n", ACK_UNKNOWN, e)),
+        }
+
+        tokio::select! {
+            line = lines.next_line() => match line {
+                Ok(Some(line)) if line.trim_end() == "noidle" => break,
+                // anything but noidle while idling is a protocol error
+                _ => return None,
+            },
+            _ = kill.changed() => return None,
+            _ = tokio::time::sleep(Duration::from_millis(10)) => {}
+        }
+    }
+
+    let mut reply: String = changed.iter().map(|name| format!("changed: {}\n", name)).collect();
+    reply.push_str("OK\n");
+    Some(reply)
+}
+
+fn take_fault(shared: &Shared, name: &str) -> Option<Failure> {
+    let mut faults = shared.faults.lock().unwrap();
+    if faults.drops.remove(name) {
+        return Some(Failure::Hangup);
+    }
+    faults
+        .acks
+        .get_mut(name)
+        .and_then(|q| q.pop_front())
+        .map(|(code, message)| Failure::Ack(code, message))
+}
+
+fn execute(shared: &Shared, name: &str, args: &[String]) -> Result<String, Failure> {
+    if let Some(failure) = take_fault(shared, name) {
+        return Err(failure);
+    }
+
+    let mut mpd = shared.mock.clone();
+    let arg = |i: usize| -> Result<&str, Failure> {
+        args.get(i)
+            .map(|s| s.as_str())
+            .ok_or_else(|| Failure::Ack(ACK_ARG, "wrong number of arguments".to_string()))
+    };
+    let number = |i: usize| -> Result<u32, Failure> {
+        arg(i)?
+            .parse()
+            .map_err(|_| Failure::Ack(ACK_ARG, format!("Integer expected: {}", args[i])))
+    };
+
+    match name {
+        "ping" => mpd.ping().map(|_| String::new()).map_err(ack),
+        "close" => Err(Failure::Hangup),
+        "playlistinfo" => {
+            let queue = mpd.queue().map_err(ack)?;
+            Ok(queue
+                .iter()
+                .enumerate()
+                .map(|(pos, song)| format_song(song, Some(pos as u32)))
+                .collect())
+        }
+        "listallinfo" => Ok(mpd.listall().map_err(ack)?.iter().map(|s| format_song(s, None)).collect()),
+        "listplaylists" => Ok(mpd
+            .playlists()
+            .map_err(ack)?
+            .iter()
+            .map(|p| format!("playlist: {}\nLast-Modified: {}\n", p.name, last_modified(p)))
+            .collect()),
+        "listplaylistinfo" => Ok(mpd
+            .playlist(arg(0)?)
+            .map_err(|_| Failure::Ack(ACK_NO_EXIST, "No such playlist".to_string()))?
+            .iter()
+            .map(|s| format_song(s, None))
+            .collect()),
+        "add" => mpd.push(arg(0)?).map(|_| String::new()).map_err(ack),
+        "addid" => mpd.push(arg(0)?).map(|id| format!("Id: {}\n", id)).map_err(ack),
+        "delete" => mpd
+            .delete(number(0)?)
+            .map(|_| String::new())
+            .map_err(|_| Failure::Ack(ACK_ARG, "Bad song index".to_string())),
+        "consume" => mpd.consume(arg(0)? == "1").map(|_| String::new()).map_err(ack),
+        "play" => mpd.play().map(|_| String::new()).map_err(ack),
+        "setvol" => mpd.set_volume(number(0)?).map(|_| String::new()).map_err(ack),
+        "search" | "find" => {
+            let query = parse_query(args)?;
+            Ok(mpd.search(&query, None).map_err(ack)?.iter().map(|s| format_song(s, None)).collect())
+        }
+        "playlistadd" => mpd.pl_push(arg(0)?, arg(1)?).map(|_| String::new()).map_err(ack),
+        "playlistdelete" => mpd.pl_delete(arg(0)?, number(1)?).map(|_| String::new()).map_err(ack),
+        "playlistclear" => mpd.pl_clear(arg(0)?).map(|_| String::new()).map_err(ack),
+        "rm" => mpd.pl_remove(arg(0)?).map(|_| String::new()).map_err(ack),
+        _ => Err(Failure::Ack(ACK_UNKNOWN, format!("unknown command \"{}\"", name))),
+    }
+}
+
+/// The mock's change count as seconds after 2024-01-01, so each write
+/// moves the playlist's `Last-Modified` on.
+fn last_modified(playlist: &Playlist) -> jiff::Timestamp {
+    let secs = 1_704_067_200 + playlist.last_modified.unwrap_or(0) as i64;
+    jiff::Timestamp::from_second(secs).unwrap()
+}
+
+fn ack(e: anyhow::Error) -> Failure {
+    let message = e.to_string();
+    let code = if message.contains("not found") {
+        ACK_NO_EXIST
+    } else if message.contains("out of bounds") {
+        ACK_ARG
+    } else {
+        ACK_SYSTEM
+    };
+    Failure::Ack(code, message)
+}
+
+// legacy `find TAG VALUE [TAG VALUE...] [window START:END]` syntax
+fn parse_query(args: &[String]) -> Result<Query, Failure> {
+    if !args.len().is_multiple_of(2) {
+        return Err(Failure::Ack(ACK_ARG, "Incorrect arguments".to_string()));
+    }
+
+    let mut query = Query::new();
+    for pair in args.chunks(2) {
+        match pair[0].to_lowercase().as_str() {
+            "window" => {}
+            "any" => {
+                query.and(FilterTerm::Any(pair[1].clone()));
+            }
+            tag => {
+                query.and(FilterTerm::Tag(tag.to_string(), pair[1].clone()));
+            }
+        }
+    }
+    Ok(query)
+}
+
+fn format_song(song: &Song, pos: Option<u32>) -> String {
+    let mut out = format!("file: {}\n", song.file);
+    if let Some(title) = &song.title {
+        out.push_str(&format!("Title: {}\n", title));
+    }
+    if let Some(artist) = &song.artist {
+        out.push_str(&format!("Artist: {}\n", artist));
+    }
+    if let Some(album) = &song.album {
+        out.push_str(&format!("Album: {}\n", album));
+    }
+    if let Some(album_artist) = &song.album_artist {
+        out.push_str(&format!("AlbumArtist: {}\n", album_artist));
+    }
+    if let Some(track) = song.track {
+        out.push_str(&format!("Track: {}\n", track));
+    }
+    if let Some(disc) = song.disc {
+        out.push_str(&format!("Disc: {}\n", disc));
+    }
+    for (key, value) in [("Date", &song.date), ("Genre", &song.genre), ("Composer", &song.composer)] {
+        if let Some(value) = value {
+            out.push_str(&format!("{}: {}\n", key, value));
+        }
+    }
+    if let Some(duration) = song.duration {
+        out.push_str(&format!("Time: {}\nduration: {}.000\n", duration, duration));
+    }
+    if let Some(pos) = pos {
+        out.push_str(&format!("Pos: {}\nId: {}\n", pos, song.id.unwrap_or(pos)));
+    }
+    out
+}
+
+/// Splits a request line into the command and its arguments, honouring
+/// double quotes and backslash escapes inside them.
+fn tokenize(line: &str) -> Option<Request> {
+    let mut tokens = Vec::new();
+    let mut chars = line.trim_end_matches('\r').chars().peekable();
+
+    loop {
+        while chars.peek() == Some(&' ') || chars.peek() == Some(&'\t') {
+            chars.next();
+        }
+        let Some(&first) = chars.peek() else { break };
+
+        let mut token = String::new();
+        if first == '"' {
+            chars.next();
+            loop {
+                match chars.next()? {
+                    '"' => break,
+                    '\\' => token.push(chars.next()?),
+                    c => token.push(c),
+                }
+            }
+        } else {
+            while let Some(&c) = chars.peek() {
+                if c == ' ' || c == '\t' {
+                    break;
+                }
+                token.push(c);
+                chars.next();
+            }
+        }
+        tokens.push(token);
+    }
+
+    let mut tokens = tokens.into_iter();
+    let name = tokens.next()?;
+    Some((name, tokens.collect()))
+}
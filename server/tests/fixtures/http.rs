@@ -0,0 +1,32 @@
+use reqwest::{Method, Response};
+use serde_json::Value;
+
+pub async fn get_json(url: String) -> Value {
+    reqwest::get(url).await.unwrap().json().await.unwrap()
+}
+
+/// Sends `body` as JSON, or an empty body when there is none.
+pub async fn send(method: Method, url: String, body: Option<Value>) -> Response {
+    let request = reqwest::Client::new().request(method, url);
+    let request = match body {
+        Some(body) => request.json(&body),
+        None => request.header(reqwest::header::CONTENT_LENGTH, "0"),
+    };
+    request.send().await.unwrap()
+}
+
+pub async fn post_json(url: String, body: Value) -> Response {
+    send(Method::POST, url, Some(body)).await
+}
+
+pub async fn post_empty(url: String) -> Response {
+    send(Method::POST, url, None).await
+}
+
+pub async fn put_json(url: String, body: Value) -> Response {
+    send(Method::PUT, url, Some(body)).await
+}
+
+pub async fn patch_json(url: String, body: Value) -> Response {
+    send(Method::PATCH, url, Some(body)).await
+}
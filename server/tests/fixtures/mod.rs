@@ -1,5 +1,16 @@
+// Shared by several test binaries; not every binary uses every fixture.
+#![allow(dead_code, unused_imports)]
+
+pub mod api_server;
+pub mod fake_mpd;
+pub mod http;
 pub mod music_library;
 pub mod scheduler_simulation;
+pub mod scripted_mpd;
+pub mod songs;
 
+pub use api_server::spawn_server;
+pub use http::{get_json, patch_json, post_empty, post_json, put_json, send};
 pub use music_library::realish_library;
 pub use scheduler_simulation::*;
+pub use songs::{mock_library, song, SongBuilder};
@@ -1,4 +1,4 @@
-use mpd::Song;
+use jukectl_server::mpd_conn::traits::Song;
 
 pub fn realish_library() -> Vec<Song> {
     vec![
@@ -77,6 +77,47 @@ pub fn realish_library() -> Vec<Song> {
             "Eclipse",
             10,
         ),
+        // Pink Floyd - The Wall (two discs, listed out of order)
+        on_disc(
+            2,
+            mk_song(
+                "music/pink_floyd/the_wall/cd2/01_hey_you.mp3",
+                "Pink Floyd",
+                "The Wall",
+                "Hey You",
+                1,
+            ),
+        ),
+        on_disc(
+            1,
+            mk_song(
+                "music/pink_floyd/the_wall/cd1/02_the_thin_ice.mp3",
+                "Pink Floyd",
+                "The Wall",
+                "The Thin Ice",
+                2,
+            ),
+        ),
+        on_disc(
+            2,
+            mk_song(
+                "music/pink_floyd/the_wall/cd2/02_is_there_anybody_out_there.mp3",
+                "Pink Floyd",
+                "The Wall",
+                "Is There Anybody Out There?",
+                2,
+            ),
+        ),
+        on_disc(
+            1,
+            mk_song(
+                "music/pink_floyd/the_wall/cd1/01_in_the_flesh.mp3",
+                "Pink Floyd",
+                "The Wall",
+                "In the Flesh?",
+                1,
+            ),
+        ),
         // Led Zeppelin IV (track 2 is missing from folder!)
         mk_song(
             "music/led_zeppelin/iv/black_dog.mp3",
@@ -104,43 +145,43 @@ pub fn realish_library() -> Vec<Song> {
         // ============================================
 
         // NOW That's What I Call Music! series
-        mk_song(
+        compilation(mk_song(
             "music/compilations/now01/01_adele_hello.mp3",
             "Adele",
             "Now That's What I Call Music! 63",
             "Hello",
             1,
-        ),
-        mk_song(
+        )),
+        compilation(mk_song(
             "music/compilations/now01/02_ed_sheeran_shape.mp3",
             "Ed Sheeran",
             "Now That's What I Call Music! 63",
             "Shape of You",
             2,
-        ),
+        )),
         // Another NOW album
-        mk_song(
+        compilation(mk_song(
             "music/compilations/now02/01_drake_hotline_bling.mp3",
             "Drake",
             "Now That's What I Call Music! 64",
             "Hotline Bling",
             1,
-        ),
+        )),
         // Soundtracks with multiple artists
-        mk_song(
+        compilation(mk_song(
             "music/soundtracks/guardians/01_hookman.mp3",
             "Blue Swede",
             "Guardians of the Galaxy: Awesome Mix Vol. 1",
             "Hooked on a Feeling",
             1,
-        ),
-        mk_song(
+        )),
+        compilation(mk_song(
             "music/soundtracks/guardians/02_go_all_the_way.mp3",
             "The Raspberries",
             "Guardians of the Galaxy: Awesome Mix Vol. 1",
             "Go All the Way",
             2,
-        ),
+        )),
         // ============================================
         // SAME ALBUM NAME, DIFFERENT ARTISTS - CRITICAL
         // ============================================
@@ -294,18 +335,30 @@ pub fn realish_library() -> Vec<Song> {
     ]
 }
 
-fn mk_song(path: &str, artist: &str, album: &str, title: &str, track: Option<u32>) -> Song {
-    let mut song = Song::default();
-    song.file = path.to_string();
-    song.tags = vec![
-        ("Artist".to_string(), artist.to_string()),
-        ("Album".to_string(), album.to_string()),
-        ("Title".to_string(), title.to_string()),
-    ];
-    if let Some(t) = track {
-        song.tags.push(("Track".to_string(), t.to_string()));
+fn mk_song(path: &str, artist: &str, album: &str, title: &str, track: impl Into<Option<u32>>) -> Song {
+    let non_empty = |v: &str| (!v.is_empty()).then(|| v.to_string());
+    Song {
+        file: path.to_string(),
+        title: non_empty(title),
+        artist: non_empty(artist),
+        album: non_empty(album),
+        track: track.into(),
+        ..Default::default()
+    }
+}
+
+fn compilation(song: Song) -> Song {
+    Song {
+        album_artist: Some("Various Artists".to_string()),
+        ..song
+    }
+}
+
+fn on_disc(disc: u32, song: Song) -> Song {
+    Song {
+        disc: Some(disc),
+        ..song
     }
-    song
 }
 
 #[cfg(test)]
@@ -317,7 +370,7 @@ mod tests {
         let lib = realish_library();
         let pf_songs: Vec<_> = lib
             .iter()
-            .filter(|s| s.tags.iter().any(|(_, v)| v == "Pink Floyd"))
+            .filter(|s| s.artist.as_deref() == Some("Pink Floyd"))
             .collect();
         assert!(pf_songs.len() >= 10, "Should have Dark Side of Moon");
     }
@@ -328,9 +381,9 @@ mod tests {
         let now_songs: Vec<_> = lib
             .iter()
             .filter(|s| {
-                s.tags
-                    .iter()
-                    .any(|(_, v)| v.contains("Now That's What I Call Music"))
+                s.album
+                    .as_deref()
+                    .is_some_and(|a| a.contains("Now That's What I Call Music"))
             })
             .collect();
         assert!(now_songs.len() >= 2, "Should have NOW compilations");
@@ -341,13 +394,12 @@ mod tests {
         let lib = realish_library();
         let greatest_hits: Vec<_> = lib
             .iter()
-            .filter(|s| s.tags.iter().any(|(_, v)| v == "Greatest Hits"))
+            .filter(|s| s.album.as_deref() == Some("Greatest Hits"))
             .collect();
         // Should have 2 different artists
         let artists: std::collections::HashSet<_> = greatest_hits
             .iter()
-            .filter_map(|s| s.tags.iter().find(|(k, _)| k == "Artist"))
-            .map(|(_, v)| v.clone())
+            .filter_map(|s| s.artist.clone())
             .collect();
         assert!(
             artists.len() >= 2,
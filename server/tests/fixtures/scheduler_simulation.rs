@@ -1,4 +1,15 @@
-use mpd::Song;
+use jukectl_server::app_state::{AppState, Config};
+use jukectl_server::models::tags_data::TagsData;
+use jukectl_server::mpd_conn::mock_mpd::MockMpd;
+use jukectl_server::mpd_conn::mpd_pool::MpdPool;
+use jukectl_server::mpd_conn::traits::{MpdClient, Song};
+use jukectl_server::scheduler::clock::ManualClock;
+use jukectl_server::scheduler::scheduler_tick;
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+
+/// How far the simulated clock moves per tick, matching the real scheduler.
+pub const TICK: Duration = Duration::from_secs(3);
 
 #[derive(Debug, Clone)]
 pub struct SchedulerSnapshot {
@@ -14,26 +25,54 @@ pub enum SchedulerEvent {
     TagsChanged { from: Vec<String>, to: Vec<String> },
     QueueDrained { count: usize },
     Refill { song_count: usize },
+    Pushed { files: Vec<String> },
     CacheInvalidated,
 }
 
 #[derive(Debug, Clone)]
 pub struct SchedulerTimeline {
     pub snapshots: Vec<SchedulerSnapshot>,
-    pub events: Vec<SchedulerEvent>,
+    /// Events keyed by the tick they happen (scripted) or happened (recorded) on.
+    pub events: Vec<(u64, SchedulerEvent)>,
+}
+
+impl SchedulerTimeline {
+    pub fn refills(&self) -> Vec<(u64, usize)> {
+        self.events
+            .iter()
+            .filter_map(|(tick, e)| match e {
+                SchedulerEvent::Refill { song_count } => Some((*tick, *song_count)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn pushed_after(&self, after: u64) -> Vec<String> {
+        self.events
+            .iter()
+            .filter(|(tick, _)| *tick > after)
+            .filter_map(|(_, e)| match e {
+                SchedulerEvent::Pushed { files } => Some(files.clone()),
+                _ => None,
+            })
+            .flatten()
+            .collect()
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct TestScenario {
     pub initial_songs: Vec<Song>,
     pub initial_tags: Vec<String>,
+    pub playlists: Vec<(String, Vec<Song>)>,
     pub timeline: SchedulerTimeline,
 }
 
 pub struct ScenarioBuilder {
     initial_songs: Vec<Song>,
     initial_tags: Vec<String>,
-    timeline_events: Vec<SchedulerEvent>,
+    playlists: Vec<(String, Vec<Song>)>,
+    timeline_events: Vec<(u64, SchedulerEvent)>,
 }
 
 impl ScenarioBuilder {
@@ -41,10 +80,12 @@ impl ScenarioBuilder {
         ScenarioBuilder {
             initial_songs: Vec::new(),
             initial_tags: vec!["jukebox".to_string()],
+            playlists: Vec::new(),
             timeline_events: Vec::new(),
         }
     }
 
+    /// Songs tagged with every one of the initial tags.
     pub fn with_library(mut self, songs: Vec<Song>) -> Self {
         self.initial_songs = songs;
         self
@@ -55,17 +96,25 @@ impl ScenarioBuilder {
         self
     }
 
+    pub fn with_playlist(mut self, name: &str, songs: Vec<Song>) -> Self {
+        self.playlists.push((name.to_string(), songs));
+        self
+    }
+
     pub fn tag_change_at(mut self, tick: u64, new_tags: Vec<String>) -> Self {
-        self.timeline_events.push(SchedulerEvent::TagsChanged {
-            from: self.initial_tags.clone(),
-            to: new_tags,
-        });
+        self.timeline_events.push((
+            tick,
+            SchedulerEvent::TagsChanged {
+                from: self.initial_tags.clone(),
+                to: new_tags,
+            },
+        ));
         self
     }
 
     pub fn drain_at(mut self, tick: u64, count: usize) -> Self {
         self.timeline_events
-            .push(SchedulerEvent::QueueDrained { count });
+            .push((tick, SchedulerEvent::QueueDrained { count }));
         self
     }
 
@@ -73,6 +122,7 @@ impl ScenarioBuilder {
         TestScenario {
             initial_songs: self.initial_songs,
             initial_tags: self.initial_tags,
+            playlists: self.playlists,
             timeline: SchedulerTimeline {
                 snapshots: Vec::new(),
                 events: self.timeline_events,
@@ -81,6 +131,112 @@ impl ScenarioBuilder {
     }
 }
 
+/// Drives `scheduler_tick` against a `MockMpd` one tick at a time. Between
+/// ticks MPD "finishes" the song at the head of its queue, as consume mode would.
+pub struct Simulator {
+    pub state: AppState,
+    pub mock: MockMpd,
+    pub clock: ManualClock,
+    scenario: TestScenario,
+}
+
+impl Simulator {
+    pub fn new(scenario: TestScenario) -> Self {
+        let mock = MockMpd::new();
+        for tag in &scenario.initial_tags {
+            mock.add_playlist(tag, scenario.initial_songs.clone());
+        }
+        for (name, songs) in &scenario.playlists {
+            mock.add_playlist(name, songs.clone());
+        }
+
+        let state = AppState::new(
+            Arc::new(MpdPool::with_mock(mock.clone(), 1)),
+            Config::default(),
+            TagsData {
+                any: scenario.initial_tags.clone(),
+                not: vec![],
+                ..Default::default()
+            },
+        );
+
+        Simulator {
+            state,
+            mock,
+            clock: ManualClock::new(UNIX_EPOCH),
+            scenario,
+        }
+    }
+
+    pub async fn run(mut self, ticks: u64) -> SchedulerTimeline {
+        let mut timeline = SchedulerTimeline {
+            snapshots: Vec::new(),
+            events: Vec::new(),
+        };
+
+        for tick in 0..ticks {
+            timeline.events.push((tick, SchedulerEvent::Tick(tick)));
+            self.apply_scripted_events(tick, &mut timeline).await;
+
+            // playback: the current song finishes and consume mode drops it
+            if !MpdClient::queue(&mut self.mock).unwrap().is_empty() {
+                MpdClient::delete(&mut self.mock, 0).unwrap();
+            }
+
+            let outcome = scheduler_tick(&self.state, &mut self.mock, &self.clock)
+                .await
+                .expect("scheduler tick failed");
+            if outcome.refilled > 0 {
+                timeline.events.push((tick, SchedulerEvent::Refill { song_count: outcome.refilled }));
+            }
+            if !outcome.pushed.is_empty() {
+                let files = outcome.pushed.into_iter().map(|s| s.file).collect();
+                timeline.events.push((tick, SchedulerEvent::Pushed { files }));
+            }
+
+            timeline.snapshots.push(SchedulerSnapshot {
+                tick,
+                internal_queue_len: self.state.queue.lock().await.len(),
+                mpd_queue_len: MpdClient::queue(&mut self.mock).unwrap().len(),
+                current_tags: self.state.tags_data.read().await.any.clone(),
+            });
+
+            self.clock.advance(TICK);
+        }
+
+        timeline
+    }
+
+    async fn apply_scripted_events(&mut self, tick: u64, timeline: &mut SchedulerTimeline) {
+        let due: Vec<SchedulerEvent> = self
+            .scenario
+            .timeline
+            .events
+            .iter()
+            .filter(|(at, _)| *at == tick)
+            .map(|(_, e)| e.clone())
+            .collect();
+
+        for event in due {
+            match &event {
+                SchedulerEvent::TagsChanged { to, .. } => {
+                    let mut tags = self.state.tags_data.write().await;
+                    tags.any = to.clone();
+                }
+                SchedulerEvent::QueueDrained { count } => {
+                    for _ in 0..*count {
+                        if MpdClient::delete(&mut self.mock, 0).is_err() {
+                            break;
+                        }
+                    }
+                }
+                _ => {}
+            }
+            timeline.events.push((tick, event));
+        }
+    }
+}
+
 pub fn scenario_refill_on_empty() -> TestScenario {
     ScenarioBuilder::new()
         .with_tags(vec!["rock".to_string()])
@@ -92,6 +248,7 @@ pub fn scenario_tag_hot_swap() -> TestScenario {
     ScenarioBuilder::new()
         .with_tags(vec!["rock".to_string()])
         .with_library(sample_rock_library())
+        .with_playlist("jazz", sample_jazz_library())
         .tag_change_at(5, vec!["jazz".to_string()])
         .build()
 }
@@ -116,6 +273,7 @@ pub fn scenario_cache_invalidation() -> TestScenario {
     ScenarioBuilder::new()
         .with_tags(vec!["rock".to_string()])
         .with_library(sample_rock_library())
+        .with_playlist("pop", sample_pop_library())
         .tag_change_at(3, vec!["pop".to_string()])
         .tag_change_at(6, vec!["rock".to_string()])
         .build()
@@ -136,15 +294,28 @@ fn sample_rock_library() -> Vec<Song> {
     ]
 }
 
-fn mk_song(path: &str, artist: &str, album: &str, track: u32) -> Song {
-    let mut song = Song::default();
-    song.file = path.to_string();
-    song.tags = vec![
-        ("Artist".to_string(), artist.to_string()),
-        ("Album".to_string(), album.to_string()),
-        ("Track".to_string(), track.to_string()),
-    ];
-    song
+fn sample_jazz_library() -> Vec<Song> {
+    vec![
+        mk_song("jazz/artist5/album5/track1.mp3", "Artist5", "Album5", 1),
+        mk_song("jazz/artist5/album5/track2.mp3", "Artist5", "Album5", 2),
+        mk_song("jazz/artist6/album6/track1.mp3", "Artist6", "Album6", 1),
+    ]
+}
+
+fn sample_pop_library() -> Vec<Song> {
+    vec![
+        mk_song("pop/artist7/album7/track1.mp3", "Artist7", "Album7", 1),
+        mk_song("pop/artist7/album7/track2.mp3", "Artist7", "Album7", 2),
+    ]
+}
+
+fn mk_song(path: &str, artist: &str, album: &str, _track: u32) -> Song {
+    Song {
+        file: path.to_string(),
+        artist: Some(artist.to_string()),
+        album: Some(album.to_string()),
+        ..Default::default()
+    }
 }
 
 #[cfg(test)]
@@ -170,7 +341,7 @@ mod tests {
             .timeline
             .events
             .iter()
-            .any(|e| matches!(e, SchedulerEvent::TagsChanged { .. }));
+            .any(|(_, e)| matches!(e, SchedulerEvent::TagsChanged { .. }));
         assert!(has_tag_change);
     }
 }
@@ -0,0 +1,104 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+
+pub const GREETING: &str = "OK MPD 0.23.5\n";
+
+enum Step {
+    Reply { request: String, response: Vec<u8> },
+    Hangup { request: String },
+    Stall { request: String, for_: Duration },
+}
+
+/// A one-connection MPD stand-in that checks each request against a script
+/// and answers with canned bytes, so exact wire traffic can be asserted.
+pub struct Script {
+    steps: Vec<Step>,
+}
+
+impl Script {
+    pub fn new() -> Self {
+        Script { steps: Vec::new() }
+    }
+
+    /// Expect `request` (one or more newline-terminated lines) and answer with `response`.
+    pub fn reply(mut self, request: &str, response: impl Into<Vec<u8>>) -> Self {
+        self.steps.push(Step::Reply {
+            request: request.to_string(),
+            response: response.into(),
+        });
+        self
+    }
+
+    /// Expect `request`, then drop the connection without answering.
+    pub fn hangup_after(mut self, request: &str) -> Self {
+        self.steps.push(Step::Hangup {
+            request: request.to_string(),
+        });
+        self
+    }
+
+    /// Expect `request`, then keep the connection open but silent for `duration`.
+    pub fn stall_after(mut self, request: &str, duration: Duration) -> Self {
+        self.steps.push(Step::Stall {
+            request: request.to_string(),
+            for_: duration,
+        });
+        self
+    }
+
+    pub async fn serve(self) -> ScriptedMpd {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let handle = tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let mut socket = BufReader::new(socket);
+            socket.get_mut().write_all(GREETING.as_bytes()).await.unwrap();
+
+            for step in self.steps {
+                let request = match &step {
+                    Step::Reply { request, .. } | Step::Hangup { request } | Step::Stall { request, .. } => {
+                        request.clone()
+                    }
+                };
+
+                let mut received = String::new();
+                for _ in 0..request.lines().count() {
+                    if socket.read_line(&mut received).await.unwrap() == 0 {
+                        break;
+                    }
+                }
+                assert_eq!(received, request, "unexpected request from client");
+
+                match step {
+                    Step::Reply { response, .. } => socket.get_mut().write_all(&response).await.unwrap(),
+                    Step::Hangup { .. } => return,
+                    Step::Stall { for_, .. } => tokio::time::sleep(for_).await,
+                }
+            }
+        });
+
+        ScriptedMpd { addr, handle }
+    }
+}
+
+pub struct ScriptedMpd {
+    pub addr: SocketAddr,
+    handle: JoinHandle<()>,
+}
+
+impl ScriptedMpd {
+    pub fn port(&self) -> u16 {
+        self.addr.port()
+    }
+
+    /// Waits for the script to run to completion, re-raising any mismatch.
+    pub async fn finish(self) {
+        if let Err(e) = self.handle.await {
+            std::panic::resume_unwind(e.into_panic());
+        }
+    }
+}
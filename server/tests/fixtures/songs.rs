@@ -0,0 +1,60 @@
+use jukectl_server::mpd_conn::mock_mpd::MockMpd;
+use jukectl_server::mpd_conn::traits::Song;
+
+/// A song with only its file set. Tests add the tags they care about with
+/// `SongBuilder`, e.g. `song("a.mp3").by("Band").on("Record")`.
+pub fn song(path: &str) -> Song {
+    Song {
+        file: path.to_string(),
+        ..Default::default()
+    }
+}
+
+pub trait SongBuilder {
+    fn by(self, artist: &str) -> Self;
+    fn on(self, album: &str) -> Self;
+    fn titled(self, title: &str) -> Self;
+    fn track_no(self, track: u32) -> Self;
+    fn in_genre(self, genre: &str) -> Self;
+    fn dated(self, date: &str) -> Self;
+    fn lasting(self, secs: u32) -> Self;
+}
+
+impl SongBuilder for Song {
+    fn by(self, artist: &str) -> Self {
+        Song { artist: Some(artist.to_string()), ..self }
+    }
+
+    fn on(self, album: &str) -> Self {
+        Song { album: Some(album.to_string()), ..self }
+    }
+
+    fn titled(self, title: &str) -> Self {
+        Song { title: Some(title.to_string()), ..self }
+    }
+
+    fn track_no(self, track: u32) -> Self {
+        Song { track: Some(track), ..self }
+    }
+
+    fn in_genre(self, genre: &str) -> Self {
+        Song { genre: Some(genre.to_string()), ..self }
+    }
+
+    fn dated(self, date: &str) -> Self {
+        Song { date: Some(date.to_string()), ..self }
+    }
+
+    fn lasting(self, secs: u32) -> Self {
+        Song { duration: Some(secs), ..self }
+    }
+}
+
+/// A `MockMpd` holding `playlists`, in order.
+pub fn mock_library(playlists: Vec<(&str, Vec<Song>)>) -> MockMpd {
+    let mock = MockMpd::new();
+    for (name, songs) in playlists {
+        mock.add_playlist(name, songs);
+    }
+    mock
+}
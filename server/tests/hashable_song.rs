@@ -10,19 +10,13 @@ mod tests {
             file: "test.mp3".to_string(),
             title: Some("Title".to_string()),
             artist: Some("Artist".to_string()),
-            album: None,
-            duration: None,
-            pos: None,
-            id: None,
+            ..Default::default()
         };
         let song2 = Song {
             file: "test.mp3".to_string(),
             title: Some("Other Title".to_string()),
             artist: Some("Other Artist".to_string()),
-            album: None,
-            duration: None,
-            pos: None,
-            id: None,
+            ..Default::default()
         };
 
         let hs1 = HashableSong(song1);
@@ -36,12 +30,7 @@ mod tests {
         let mut set = HashSet::new();
         let song1 = Song {
             file: "test.mp3".to_string(),
-            title: None,
-            artist: None,
-            album: None,
-            duration: None,
-            pos: None,
-            id: None,
+            ..Default::default()
         };
         
         set.insert(HashableSong(song1.clone()));
@@ -0,0 +1,145 @@
+mod fixtures;
+
+use fixtures::{song, spawn_server, SongBuilder};
+use jukectl_server::models::history::History;
+use jukectl_server::mpd_conn::mock_mpd::MockMpd;
+use jukectl_server::mpd_conn::traits::{MpdClient, Song};
+use jukectl_server::scheduler::clock::SystemClock;
+use jukectl_server::scheduler::scheduler_tick;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// A 200 second song, so a play that runs to the next one lasts that long.
+fn tune(path: &str) -> Song {
+    song(path).titled(&format!("Title {}", path)).by("Artist").on("Album").lasting(200)
+}
+
+fn at(secs: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(secs)
+}
+
+#[test]
+fn test_records_a_play_when_the_head_changes() {
+    let mut history = History::new(10);
+
+    history.observe(Some(&tune("a.mp3")), at(100));
+    history.observe(Some(&tune("a.mp3")), at(150));
+    assert!(history.is_empty());
+    assert_eq!(history.now_playing().unwrap().file, "a.mp3");
+
+    history.observe(Some(&tune("b.mp3")), at(300));
+    history.observe(None, at(320));
+
+    let recent = history.recent(10, None);
+    let files: Vec<&str> = recent.iter().map(|e| e.file.as_str()).collect();
+    assert_eq!(files, vec!["b.mp3", "a.mp3"]);
+    assert_eq!((recent[1].started_at, recent[1].played_secs), (100, 200));
+    assert_eq!(recent[1].title.as_deref(), Some("Title a.mp3"));
+    assert_eq!(recent[0].played_secs, 20);
+    assert!(recent.iter().all(|e| !e.skipped));
+    assert!(history.now_playing().is_none());
+}
+
+#[test]
+fn test_skip_records_even_unobserved_songs() {
+    let mut history = History::new(10);
+
+    history.observe(Some(&tune("a.mp3")), at(100));
+    history.skip(&tune("a.mp3"), at(110));
+    // skipped before any scheduler pass saw it
+    history.skip(&tune("b.mp3"), at(110));
+
+    let recent = history.recent(10, None);
+    assert_eq!(recent.len(), 2);
+    assert!(recent.iter().all(|e| e.skipped));
+    assert_eq!(recent[1].played_secs, 10);
+    assert_eq!(recent[0].played_secs, 0);
+}
+
+#[test]
+fn test_bounded_and_filtered() {
+    let mut history = History::new(3);
+    for i in 0..6u64 {
+        history.observe(Some(&tune(&format!("{}.mp3", i))), at(i * 100));
+    }
+    history.observe(None, at(600));
+
+    assert_eq!(history.len(), 3);
+    let files: Vec<String> = history.recent(10, None).into_iter().map(|e| e.file).collect();
+    assert_eq!(files, vec!["5.mp3", "4.mp3", "3.mp3"]);
+    assert_eq!(history.recent(1, None)[0].file, "5.mp3");
+    assert_eq!(history.recent(10, Some(400)).len(), 2);
+}
+
+#[test]
+fn test_append_log_survives_restart() {
+    let path = std::env::temp_dir().join(format!("jukectl-history-{}.jsonl", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    {
+        let mut history = History::with_log(10, &path);
+        history.observe(Some(&tune("a.mp3")), at(100));
+        history.observe(Some(&tune("b.mp3")), at(200));
+        history.skip(&tune("b.mp3"), at(205));
+    }
+    std::fs::OpenOptions::new()
+        .append(true)
+        .open(&path)
+        .and_then(|mut f| std::io::Write::write_all(&mut f, b"not json\n"))
+        .unwrap();
+
+    let mut history = History::with_log(1, &path);
+    assert_eq!(history.len(), 1);
+    assert_eq!(history.recent(10, None)[0].file, "b.mp3");
+    // the log is cut back to what was loaded, dropping the bad line too
+    assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 1);
+
+    history.observe(Some(&tune("c.mp3")), at(300));
+    history.observe(None, at(400));
+    let files: Vec<String> = History::with_log(10, &path).recent(10, None).into_iter().map(|e| e.file).collect();
+    assert_eq!(files, vec!["c.mp3", "b.mp3"]);
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn test_scheduler_and_skip_feed_history_route() {
+    let mock = MockMpd::new();
+    mock.add_playlist("jukebox", vec![tune("a.mp3"), tune("b.mp3"), tune("c.mp3")]);
+    let (base, state) = spawn_server(mock.clone()).await;
+
+    let mut mpd = mock.clone();
+    scheduler_tick(&state, &mut mpd, &SystemClock).await.unwrap();
+    scheduler_tick(&state, &mut mpd, &SystemClock).await.unwrap();
+    let head = mpd.queue().unwrap()[0].file.clone();
+
+    let client = reqwest::Client::new();
+    let resp = client
+        .post(format!("{}/skip", base))
+        .header(reqwest::header::CONTENT_LENGTH, "0")
+        .send()
+        .await
+        .unwrap();
+    assert!(resp.status().is_success());
+
+    let body: serde_json::Value = client
+        .get(format!("{}/history?limit=5", base))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(body["total"], 1);
+    assert_eq!(body["entries"][0]["file"], head.as_str());
+    assert_eq!(body["entries"][0]["skipped"], true);
+
+    let body: serde_json::Value = client
+        .get(format!("{}/history?since=4000000000", base))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(body["entries"].as_array().unwrap().len(), 0);
+}
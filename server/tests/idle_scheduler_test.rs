@@ -0,0 +1,153 @@
+mod fixtures;
+
+use fixtures::{mock_library, song};
+use jukectl_server::app_state::{AppState, Config};
+use jukectl_server::models::tags_data::TagsData;
+use jukectl_server::mpd_conn::mock_mpd::MockMpd;
+use jukectl_server::mpd_conn::mpd_pool::MpdPool;
+use jukectl_server::mpd_conn::traits::MpdClient;
+use jukectl_server::models::library_index::RefreshScope;
+use jukectl_server::scheduler::clock::ManualClock;
+use jukectl_server::scheduler::{scheduler_tick, start_scheduler, EMPTY_REFILL_RETRY};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+fn library() -> MockMpd {
+    mock_library(vec![("jukebox", (0..10).map(|i| song(&format!("{}.mp3", i))).collect())])
+}
+
+fn state(mock: MockMpd, poll_interval: Duration) -> AppState {
+    AppState::new(
+        Arc::new(MpdPool::with_mock(mock, 2)),
+        Config {
+            poll_interval,
+            ..Config::default()
+        },
+        TagsData {
+            any: vec!["jukebox".to_string()],
+            not: vec![],
+            ..Default::default()
+        },
+    )
+}
+
+async fn wait_for_queue_len(mock: &MockMpd, len: usize, within: Duration) -> bool {
+    let deadline = Instant::now() + within;
+    while Instant::now() < deadline {
+        if MpdClient::queue(&mut mock.clone()).unwrap().len() == len {
+            return true;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    false
+}
+
+#[test]
+fn test_mock_idle_reports_each_change_once() {
+    let mut mpd = MockMpd::new();
+    let mut other = mpd.clone();
+
+    mpd.push("a.mp3").unwrap();
+    mpd.play().unwrap();
+
+    assert_eq!(mpd.idle(&["player", "playlist"]).unwrap(), vec!["player", "playlist"]);
+    assert!(mpd.idle_changes(&[]).unwrap().is_empty());
+    // every client has its own view of what it has already seen
+    assert_eq!(other.idle(&["playlist"]).unwrap(), vec!["playlist"]);
+    assert_eq!(other.idle_changes(&[]).unwrap(), vec!["player"]);
+}
+
+#[test]
+fn test_mock_idle_blocks_until_a_change() {
+    let mut mpd = MockMpd::new();
+    let writer = mpd.clone();
+
+    let waiter = std::thread::spawn(move || mpd.idle(&["playlist"]));
+    std::thread::sleep(Duration::from_millis(50));
+    assert!(!waiter.is_finished());
+
+    writer.add_playlist("unwatched", vec![]);
+    std::thread::sleep(Duration::from_millis(50));
+    assert!(!waiter.is_finished());
+
+    writer.clone().push("a.mp3").unwrap();
+    assert_eq!(waiter.join().unwrap().unwrap(), vec!["playlist"]);
+}
+
+#[test]
+fn test_mock_idle_unsupported() {
+    let mut mpd = MockMpd::new();
+    mpd.disable_idle();
+    assert!(mpd.idle(&["player"]).is_err());
+}
+
+#[tokio::test]
+async fn test_idle_events_drive_the_scheduler() {
+    let mock = library();
+    // polling alone would take a minute to push the second song
+    start_scheduler(state(mock.clone(), Duration::from_secs(60))).await;
+
+    assert!(wait_for_queue_len(&mock, 2, Duration::from_secs(2)).await);
+
+    mock.clone().delete(0).unwrap();
+    assert!(wait_for_queue_len(&mock, 2, Duration::from_secs(2)).await);
+}
+
+#[tokio::test]
+async fn test_polls_when_idle_is_unsupported() {
+    let mock = library();
+    mock.disable_idle();
+    start_scheduler(state(mock.clone(), Duration::from_millis(50))).await;
+
+    assert!(wait_for_queue_len(&mock, 2, Duration::from_secs(2)).await);
+
+    mock.clone().delete(0).unwrap();
+    assert!(wait_for_queue_len(&mock, 2, Duration::from_secs(2)).await);
+}
+
+#[tokio::test]
+async fn test_scheduler_picks_up_config_changes() {
+    let mock = library();
+    mock.disable_idle();
+    let app_state = state(mock.clone(), Duration::from_millis(50));
+    start_scheduler(app_state.clone()).await;
+    assert!(wait_for_queue_len(&mock, 2, Duration::from_secs(2)).await);
+
+    app_state.config.lock().await.low_water_mark = 4;
+    assert!(wait_for_queue_len(&mock, 4, Duration::from_secs(2)).await);
+}
+
+#[tokio::test]
+async fn test_empty_refill_waits_for_a_change() {
+    let mock = mock_library(vec![("other", vec![song("x.mp3")])]);
+    let app_state = state(mock.clone(), Duration::from_millis(50));
+    app_state.refresh_library(RefreshScope::Full).await.unwrap();
+    let clock = ManualClock::new(SystemTime::UNIX_EPOCH + Duration::from_secs(1000));
+    let mut mpd = mock.clone();
+
+    assert_eq!(scheduler_tick(&app_state, &mut mpd, &clock).await.unwrap().refilled, 0);
+
+    // the index hasn't seen the new playlist, so nothing is looked up again
+    mock.add_playlist("jukebox", vec![song("a.mp3"), song("b.mp3")]);
+    clock.advance(Duration::from_secs(3));
+    assert_eq!(scheduler_tick(&app_state, &mut mpd, &clock).await.unwrap().refilled, 0);
+
+    app_state.refresh_library(RefreshScope::Playlists).await.unwrap();
+    assert_eq!(scheduler_tick(&app_state, &mut mpd, &clock).await.unwrap().refilled, 2);
+}
+
+#[tokio::test]
+async fn test_empty_refill_is_retried_after_a_while() {
+    let empty = MockMpd::new();
+    let app_state = state(empty.clone(), Duration::from_millis(50));
+    let clock = ManualClock::new(SystemTime::UNIX_EPOCH + Duration::from_secs(1000));
+
+    assert_eq!(scheduler_tick(&app_state, &mut empty.clone(), &clock).await.unwrap().refilled, 0);
+
+    // without a built index reads go straight to MPD, so only time tells
+    let mut mpd = library();
+    clock.advance(EMPTY_REFILL_RETRY - Duration::from_secs(1));
+    assert_eq!(scheduler_tick(&app_state, &mut mpd, &clock).await.unwrap().refilled, 0);
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(scheduler_tick(&app_state, &mut mpd, &clock).await.unwrap().refilled, 10);
+}
@@ -13,6 +13,7 @@ async fn test_mock_mpd_listall() {
             duration: Some(180),
             pos: None,
             id: None,
+            ..Default::default()
         },
     ];
     mock.add_playlist("p1", songs);
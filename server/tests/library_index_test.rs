@@ -0,0 +1,186 @@
+mod fixtures;
+
+use fixtures::{get_json, mock_library, post_json, song, spawn_server, SongBuilder};
+use jukectl_server::models::library_index::{IndexedMpd, LibraryIndex, RefreshScope, LIBRARY_REFRESH_INTERVAL};
+use jukectl_server::mpd_conn::mock_mpd::MockMpd;
+use jukectl_server::mpd_conn::traits::{MpdClient, Song};
+use jukectl_server::scheduler::start_scheduler;
+use serde_json::{json, Value};
+use std::time::{Duration, Instant, SystemTime};
+
+fn library() -> MockMpd {
+    mock_library(vec![
+        (
+            "jukebox",
+            vec![
+                song("a.mp3").by("Alpha").on("First"),
+                song("b.mp3").by("Alpha").on("Second"),
+                song("c.mp3").by("Beta").on("First"),
+            ],
+        ),
+        ("chill", vec![song("c.mp3").by("Beta").on("First")]),
+    ])
+}
+
+fn files(songs: &[&Song]) -> Vec<String> {
+    songs.iter().map(|s| s.file.clone()).collect()
+}
+
+async fn stats(base: &str) -> Value {
+    get_json(format!("{}/library/stats", base)).await
+}
+
+#[test]
+fn test_index_lookups() {
+    let mut mock = library();
+    let index = LibraryIndex::load(&mut mock, SystemTime::UNIX_EPOCH + Duration::from_secs(1000)).unwrap();
+
+    assert_eq!(files(&index.by_artist("Alpha")), vec!["a.mp3", "b.mp3"]);
+    assert_eq!(files(&index.by_album("First")), vec!["a.mp3", "c.mp3"]);
+    assert!(index.by_artist("Nobody").is_empty());
+    assert_eq!(files(&index.by_artist_or_album("First")), vec!["a.mp3", "c.mp3"]);
+    assert_eq!(index.song("b.mp3").unwrap().album.as_deref(), Some("Second"));
+    assert_eq!(index.playlist("chill").unwrap().len(), 1);
+    assert_eq!(index.playlist_names().collect::<Vec<_>>(), vec!["chill", "jukebox"]);
+
+    let stats = index.stats(SystemTime::UNIX_EPOCH + Duration::from_secs(1060));
+    assert!(stats.ready);
+    assert_eq!((stats.songs, stats.artists, stats.albums), (3, 2, 2));
+    assert_eq!((stats.playlists, stats.playlist_entries), (2, 4));
+    assert_eq!(stats.built_at, Some(1000));
+    assert_eq!(stats.age_secs, Some(60));
+
+    let built = SystemTime::UNIX_EPOCH + Duration::from_secs(1000);
+    assert!(!index.is_stale(built + LIBRARY_REFRESH_INTERVAL - Duration::from_secs(1)));
+    assert!(index.is_stale(built + LIBRARY_REFRESH_INTERVAL));
+    assert!(!LibraryIndex::default().is_stale(built + LIBRARY_REFRESH_INTERVAL));
+}
+
+#[test]
+fn test_indexed_reads_skip_mpd_once_built() {
+    let mut mock = library();
+    let index = LibraryIndex::load(&mut mock, SystemTime::now()).unwrap();
+    mock.add_playlist("late", vec![song("d.mp3").by("Gamma").on("Night")]);
+
+    let mut other = mock.clone();
+    let mut indexed = IndexedMpd::new(&index, &mut other);
+    assert_eq!(indexed.listall().unwrap().len(), 3);
+    assert_eq!(indexed.playlists().unwrap().len(), 2);
+    assert!(indexed.playlist("late").is_err());
+    // everything else still goes to MPD
+    indexed.push("a.mp3").unwrap();
+    assert_eq!(mock.queue().unwrap().len(), 1);
+
+    // an index that was never built reads through
+    let empty = LibraryIndex::default();
+    let mut other = mock.clone();
+    let mut indexed = IndexedMpd::new(&empty, &mut other);
+    assert_eq!(indexed.listall().unwrap().len(), 4);
+    assert_eq!(indexed.playlist("late").unwrap().len(), 1);
+}
+
+#[test]
+fn test_playlist_refreshes() {
+    let mut mock = library();
+    let mut index = LibraryIndex::load(&mut mock, SystemTime::UNIX_EPOCH).unwrap();
+
+    mock.add_playlist("late", vec![song("c.mp3").by("Beta").on("First")]);
+    mock.pl_remove("chill").unwrap();
+    index.refresh_playlist("chill", &mut mock, SystemTime::UNIX_EPOCH);
+    assert_eq!(index.playlist_names().collect::<Vec<_>>(), vec!["jukebox"]);
+
+    index.refresh_playlists(&mut mock, SystemTime::UNIX_EPOCH + Duration::from_secs(5)).unwrap();
+    assert_eq!(index.playlist_names().collect::<Vec<_>>(), vec!["jukebox", "late"]);
+    let stats = index.stats(SystemTime::UNIX_EPOCH + Duration::from_secs(5));
+    assert_eq!(stats.built_at, Some(0));
+    assert_eq!(stats.playlists_refreshed_at, Some(5));
+
+    assert_eq!(RefreshScope::for_subsystem("database"), Some(RefreshScope::Full));
+    assert_eq!(RefreshScope::for_subsystem("stored_playlist"), Some(RefreshScope::Playlists));
+    assert_eq!(RefreshScope::for_subsystem("player"), None);
+}
+
+#[test]
+fn test_only_changed_playlists_are_reloaded() {
+    let mut mock = library();
+    let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1000);
+    let mut index = LibraryIndex::load(&mut mock, now).unwrap();
+    let reads = |mock: &MockMpd| (mock.playlist_reads("jukebox"), mock.playlist_reads("chill"));
+    assert_eq!(reads(&mock), (1, 1));
+
+    let revision = index.revision();
+    index.refresh_playlists(&mut mock, now).unwrap();
+    assert_eq!(reads(&mock), (1, 1));
+    assert_eq!(index.revision(), revision);
+
+    mock.pl_push("chill", "a.mp3").unwrap();
+    index.refresh_playlists(&mut mock, now).unwrap();
+    assert_eq!(reads(&mock), (1, 2));
+    assert_eq!(index.playlist("chill").unwrap().len(), 2);
+    assert!(index.revision() > revision);
+
+    // a database update relists the songs; kept playlists pick up new tags
+    let songs = vec![
+        song("a.mp3").by("Alpha").on("First"),
+        song("b.mp3").by("Alpha").on("Second"),
+        song("c.mp3").by("Beta").on("Remastered"),
+    ];
+    index.reload(songs, &mut mock, now).unwrap();
+    assert_eq!(reads(&mock), (1, 2));
+    let jukebox = index.playlist("jukebox").unwrap();
+    assert_eq!(jukebox.iter().find(|s| s.file == "c.mp3").unwrap().album.as_deref(), Some("Remastered"));
+    assert_eq!(files(&index.by_album("Remastered")), vec!["c.mp3"]);
+}
+
+#[tokio::test]
+async fn test_routes_read_from_the_index() {
+    let mock = library();
+    let (base, state) = spawn_server(mock.clone()).await;
+
+    let before = stats(&base).await;
+    assert_eq!(before["ready"], false);
+    assert_eq!(before["songs"], 0);
+
+    state.refresh_library(RefreshScope::Full).await.unwrap();
+    let after = stats(&base).await;
+    assert_eq!(after["ready"], true);
+    assert_eq!(after["songs"], 3);
+    assert_eq!(after["playlists"], 2);
+    assert_eq!(after["age_secs"], 0);
+
+    // changes MPD has not announced are not seen
+    mock.add_playlist("late", vec![song("d.mp3").by("Gamma").on("Night")]);
+    assert_eq!(get_json(format!("{}/song/all", base)).await.as_array().unwrap().len(), 3);
+    let alpha = get_json(format!("{}/tags/Alpha", base)).await;
+    assert_eq!(alpha[0]["file"], "a.mp3");
+    assert_eq!(alpha[1]["file"], "b.mp3");
+    assert_eq!(alpha.as_array().unwrap().len(), 2);
+
+    // but the server's own tagging updates the index right away
+    let body = json!({ "filename": "a.mp3", "add": ["chill"], "remove": [] });
+    let response = post_json(format!("{}/song/tags", base), body).await;
+    assert!(response.status().is_success());
+    assert_eq!(get_json(format!("{}/tags/chill", base)).await.as_array().unwrap().len(), 2);
+    assert_eq!(stats(&base).await["playlist_entries"], 5);
+}
+
+#[tokio::test]
+async fn test_idle_events_refresh_the_index() {
+    let mock = library();
+    let (base, state) = spawn_server(mock.clone()).await;
+    start_scheduler(state.clone()).await;
+
+    // the idle watcher coming up builds the index
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while stats(&base).await["ready"] != true {
+        assert!(Instant::now() < deadline, "index was never built");
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    mock.add_playlist("late", vec![song("d.mp3").by("Gamma").on("Night")]);
+    while stats(&base).await["playlists"] != 3 {
+        assert!(Instant::now() < deadline, "stored_playlist change never reached the index");
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert!(state.library.read().await.playlist("late").is_some());
+}
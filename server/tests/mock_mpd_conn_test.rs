@@ -35,6 +35,7 @@ async fn test_mock_mpd_playlist() {
             duration: Some(180),
             pos: None,
             id: None,
+            ..Default::default()
         },
     ];
     mock.add_playlist("test", songs.clone());
@@ -0,0 +1,307 @@
+#![cfg(feature = "native-mpd")]
+mod fixtures;
+
+use fixtures::scripted_mpd::Script;
+use jukectl_server::mpd_conn::native_client::{AsyncMpdClient, NativeMpdClient};
+use jukectl_server::mpd_conn::protocol::{AckError, Command};
+use jukectl_server::mpd_conn::traits::{FilterTerm, MpdClient, Query};
+use std::time::Duration;
+
+async fn connect(port: u16) -> AsyncMpdClient {
+    AsyncMpdClient::connect("127.0.0.1", port).await.unwrap()
+}
+
+#[tokio::test]
+async fn test_greeting_and_ping() {
+    let server = Script::new().reply("ping\n", "OK\n").serve().await;
+
+    let mut client = connect(server.port()).await;
+    assert_eq!(client.version(), "0.23.5");
+    client.ping().await.unwrap();
+
+    server.finish().await;
+}
+
+#[tokio::test]
+async fn test_queue_parses_songs() {
+    let server = Script::new()
+        .reply(
+            "playlistinfo\n",
+            "file: rock/a.flac\nTitle: A\nArtist: Band\nAlbum: Record\nTime: 200\nduration: 199.6\nPos: 0\nId: 11\n\
+             file: rock/b.flac\nTime: 185\nPos: 1\nId: 12\nOK\n",
+        )
+        .serve()
+        .await;
+
+    let mut client = connect(server.port()).await;
+    let queue = client.queue().await.unwrap();
+
+    assert_eq!(queue.len(), 2);
+    assert_eq!(queue[0].file, "rock/a.flac");
+    assert_eq!(queue[0].title.as_deref(), Some("A"));
+    assert_eq!(queue[0].artist.as_deref(), Some("Band"));
+    assert_eq!(queue[0].album.as_deref(), Some("Record"));
+    assert_eq!(queue[0].duration, Some(200));
+    assert_eq!((queue[0].pos, queue[0].id), (Some(0), Some(11)));
+    assert_eq!(queue[1].title, None);
+    assert_eq!(queue[1].duration, Some(185));
+
+    server.finish().await;
+}
+
+#[tokio::test]
+async fn test_songs_carry_album_metadata() {
+    let server = Script::new()
+        .reply(
+            "listallinfo\n",
+            "file: comp/03.flac\nArtist: Band\nAlbumArtist: Various Artists\nTrack: 3/12\nDisc: 2/2\n\
+             Date: 1999-04-01\nGenre: Rock\nGenre: Pop\nComposer: Someone\nOK\n",
+        )
+        .serve()
+        .await;
+
+    let mut client = connect(server.port()).await;
+    let song = client.listall().await.unwrap().remove(0);
+
+    assert_eq!(song.album_artist.as_deref(), Some("Various Artists"));
+    assert_eq!(song.album_identity_artist(), Some("Various Artists"));
+    assert_eq!((song.track, song.disc), (Some(3), Some(2)));
+    assert_eq!(song.date.as_deref(), Some("1999-04-01"));
+    assert_eq!(song.genre.as_deref(), Some("Rock"));
+    assert_eq!(song.composer.as_deref(), Some("Someone"));
+
+    server.finish().await;
+}
+
+#[tokio::test]
+async fn test_listall_skips_directories() {
+    let server = Script::new()
+        .reply(
+            "listallinfo\n",
+            "directory: rock\nLast-Modified: 2024-01-01T00:00:00Z\nfile: rock/a.flac\nArtist: Band\n\
+             directory: rock/live\nfile: rock/live/b.flac\nOK\n",
+        )
+        .serve()
+        .await;
+
+    let mut client = connect(server.port()).await;
+    let files: Vec<String> = client.listall().await.unwrap().into_iter().map(|s| s.file).collect();
+    assert_eq!(files, vec!["rock/a.flac", "rock/live/b.flac"]);
+
+    server.finish().await;
+}
+
+#[tokio::test]
+async fn test_arguments_are_quoted() {
+    let server = Script::new()
+        .reply("addid \"odd \\\"name\\\" \\\\ here.mp3\"\n", "Id: 42\nOK\n")
+        .reply(
+            "find \"artist\" \"AC/DC\" \"any\" \"live\" \"window\" \"0:10\"\n",
+            "OK\n",
+        )
+        .serve()
+        .await;
+
+    let mut client = connect(server.port()).await;
+    assert_eq!(client.push("odd \"name\" \\ here.mp3").await.unwrap(), 42);
+
+    let mut query = Query::new();
+    query
+        .and(FilterTerm::Tag("Artist".to_string(), "AC/DC".to_string()))
+        .and(FilterTerm::Any("live".to_string()));
+    assert!(client.search(&query, Some((0, 10))).await.unwrap().is_empty());
+
+    server.finish().await;
+}
+
+#[tokio::test]
+async fn test_line_breaks_in_arguments_are_refused() {
+    let server = Script::new().reply("ping\n", "OK\n").serve().await;
+
+    let mut client = connect(server.port()).await;
+    for file in ["a.mp3\nclear", "a.mp3\r\nclear", "a\0.mp3"] {
+        let err = client.push(file).await.unwrap_err();
+        assert!(err.to_string().contains("line break or NUL"), "{}", err);
+    }
+    let list = [Command::new("add").arg("ok.mp3"), Command::new("add").arg("x\nclear")];
+    assert!(client.execute_list(&list).await.is_err());
+
+    // nothing was sent, so the connection is still in step
+    assert!(!client.is_broken());
+    client.ping().await.unwrap();
+
+    server.finish().await;
+}
+
+#[tokio::test]
+async fn test_ack_is_parsed_and_connection_stays_usable() {
+    let server = Script::new()
+        .reply(
+            "listplaylistinfo \"missing\"\n",
+            "ACK [50@0] {listplaylistinfo} No such playlist\n",
+        )
+        .reply("ping\n", "OK\n")
+        .serve()
+        .await;
+
+    let mut client = connect(server.port()).await;
+    let err = client.playlist("missing").await.unwrap_err();
+    let ack = err.downcast_ref::<AckError>().expect("expected an ACK");
+    assert_eq!(ack.code, AckError::NO_EXIST);
+    assert_eq!(ack.index, 0);
+    assert_eq!(ack.command, "listplaylistinfo");
+    assert_eq!(ack.message, "No such playlist");
+
+    assert!(!client.is_broken());
+    client.ping().await.unwrap();
+
+    server.finish().await;
+}
+
+#[tokio::test]
+async fn test_command_list_splits_responses() {
+    let server = Script::new()
+        .reply(
+            "command_list_ok_begin\naddid \"a.mp3\"\naddid \"b.mp3\"\nplay\ncommand_list_end\n",
+            "Id: 1\nlist_OK\nId: 2\nlist_OK\nlist_OK\nOK\n",
+        )
+        .serve()
+        .await;
+
+    let mut client = connect(server.port()).await;
+    let responses = client
+        .execute_list(&[
+            Command::new("addid").arg("a.mp3"),
+            Command::new("addid").arg("b.mp3"),
+            Command::new("play"),
+        ])
+        .await
+        .unwrap();
+
+    assert_eq!(responses.len(), 3);
+    assert_eq!(responses[0].get("Id"), Some("1"));
+    assert_eq!(responses[1].get("Id"), Some("2"));
+    assert!(responses[2].pairs.is_empty());
+
+    server.finish().await;
+}
+
+#[tokio::test]
+async fn test_command_list_ack_reports_failing_index() {
+    let server = Script::new()
+        .reply(
+            "command_list_ok_begin\naddid \"a.mp3\"\naddid \"nope.mp3\"\ncommand_list_end\n",
+            "Id: 1\nlist_OK\nACK [50@1] {addid} No such song\n",
+        )
+        .serve()
+        .await;
+
+    let mut client = connect(server.port()).await;
+    let err = client
+        .execute_list(&[Command::new("addid").arg("a.mp3"), Command::new("addid").arg("nope.mp3")])
+        .await
+        .unwrap_err();
+
+    let ack = err.downcast_ref::<AckError>().unwrap();
+    assert_eq!((ack.code, ack.index), (AckError::NO_EXIST, 1));
+
+    server.finish().await;
+}
+
+#[tokio::test]
+async fn test_album_art_reads_binary_chunks() {
+    let mut first = b"size: 6\ntype: image/png\nbinary: 4\n".to_vec();
+    first.extend([0x89, b'P', b'\n', 0x00]);
+    first.extend(b"\nOK\n");
+    let mut second = b"size: 6\nbinary: 2\n".to_vec();
+    second.extend([0xff, 0xfe]);
+    second.extend(b"\nOK\n");
+
+    let server = Script::new()
+        .reply("albumart \"a/cover.flac\" \"0\"\n", first)
+        .reply("albumart \"a/cover.flac\" \"4\"\n", second)
+        .serve()
+        .await;
+
+    let mut client = connect(server.port()).await;
+    let art = client.album_art("a/cover.flac").await.unwrap();
+    assert_eq!(art, vec![0x89, b'P', b'\n', 0x00, 0xff, 0xfe]);
+
+    server.finish().await;
+}
+
+#[tokio::test]
+async fn test_dropped_connection_marks_client_broken() {
+    let server = Script::new().hangup_after("playlistinfo\n").serve().await;
+
+    let mut client = connect(server.port()).await;
+    assert!(client.queue().await.is_err());
+    assert!(client.is_broken());
+    assert!(client.ping().await.is_err());
+
+    server.finish().await;
+}
+
+#[tokio::test]
+async fn test_timeout_marks_client_broken() {
+    let server = Script::new()
+        .stall_after("ping\n", Duration::from_millis(300))
+        .serve()
+        .await;
+
+    let mut client = connect(server.port()).await;
+    client.set_timeout(Duration::from_millis(50));
+    let err = client.ping().await.unwrap_err();
+    assert!(err.to_string().contains("Timed out"));
+    assert!(client.is_broken());
+
+    server.finish().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_blocking_handle_implements_mpd_client() {
+    let server = Script::new()
+        .reply("consume \"1\"\n", "OK\n")
+        .reply("addid \"a.mp3\"\n", "Id: 7\nOK\n")
+        .reply("listplaylists\n", "playlist: jukebox\nLast-Modified: 2024-01-01T00:00:00Z\nplaylist: chill\nOK\n")
+        .reply("rm \"chill\"\n", "OK\n")
+        .serve()
+        .await;
+    let port = server.port();
+
+    tokio::task::spawn_blocking(move || {
+        let mut mpd = NativeMpdClient::connect("127.0.0.1", port).unwrap();
+        mpd.consume(true).unwrap();
+        assert_eq!(mpd.push("a.mp3").unwrap(), 7);
+
+        let playlists: Vec<(String, Option<u64>)> =
+            mpd.playlists().unwrap().into_iter().map(|p| (p.name, p.last_modified)).collect();
+        assert_eq!(playlists, vec![("jukebox".to_string(), Some(1_704_067_200)), ("chill".to_string(), None)]);
+        mpd.pl_remove("chill").unwrap();
+    })
+    .await
+    .unwrap();
+
+    server.finish().await;
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_sync_wrapper_does_not_stall_the_runtime() {
+    let server = Script::new().reply("ping\n", "OK\n").serve().await;
+    let port = server.port();
+    let mut mpd = tokio::task::spawn_blocking(move || NativeMpdClient::connect("127.0.0.1", port))
+        .await
+        .unwrap()
+        .unwrap();
+
+    // the script is served from the only worker, so holding that worker
+    // while waiting for the reply would never finish
+    let ping = tokio::spawn(async move { mpd.ping() });
+    tokio::time::timeout(Duration::from_secs(5), ping)
+        .await
+        .expect("the runtime stalled behind an MPD call")
+        .unwrap()
+        .unwrap();
+
+    server.finish().await;
+}
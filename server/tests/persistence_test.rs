@@ -0,0 +1,187 @@
+mod fixtures;
+
+use fixtures::{song, SongBuilder};
+use jukectl_server::app_state::{initialize_queue, AppState, Config};
+use jukectl_server::models::tags_data::TagsData;
+use jukectl_server::mpd_conn::mock_mpd::MockMpd;
+use jukectl_server::mpd_conn::mpd_pool::MpdPool;
+use jukectl_server::persistence::{self, StateStore, CONFIG_FILE, QUEUE_FILE, TAGS_FILE};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+fn data_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("jukectl-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+fn env_tags() -> TagsData {
+    TagsData {
+        any: vec!["jukebox".to_string()],
+        not: vec![],
+        ..Default::default()
+    }
+}
+
+fn state_with_store(dir: &PathBuf) -> AppState {
+    let mut state = AppState::new(Arc::new(MpdPool::with_mock(MockMpd::new(), 2)), Config::default(), env_tags());
+    state.state_store = Arc::new(StateStore::new(dir));
+    state
+}
+
+async fn queued_files(state: &AppState) -> Vec<String> {
+    let queue = state.queue.lock().await;
+    queue.head(Some(queue.len())).into_iter().map(|s| s.file).collect()
+}
+
+#[tokio::test]
+async fn test_save_and_restore_round_trip() {
+    let dir = data_dir("round-trip");
+    let state = state_with_store(&dir);
+    {
+        let mut queue = state.queue.lock().await;
+        queue.add(song("b.mp3").by("Artist").on("B"));
+        queue.add(song("a.mp3").by("Artist").on("A"));
+        queue.add(song("c.mp3").by("Artist").on("C"));
+    }
+    *state.tags_data.write().await = TagsData {
+        any: vec!["morning".to_string()],
+        not: vec!["explicit".to_string()],
+        ..Default::default()
+    };
+    state.config.lock().await.low_water_mark = 4;
+    state.song_stats.lock().await.rate("a.mp3", Some(5)).unwrap();
+    state.state_store.save(&state).await.unwrap();
+
+    let restarted = state_with_store(&dir);
+    persistence::restore(&restarted).await;
+
+    assert_eq!(restarted.tags_data.read().await.any, vec!["morning"]);
+    assert_eq!(restarted.config.lock().await.low_water_mark, 4);
+    assert_eq!(restarted.song_stats.lock().await.get("a.mp3").rating, Some(5));
+    assert_eq!(queued_files(&restarted).await, vec!["b.mp3", "a.mp3", "c.mp3"]);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_startup_settings_win_unless_changed_at_runtime() {
+    let dir = data_dir("startup-wins");
+    let state = state_with_store(&dir);
+    state.config.lock().await.refill_threshold = 7;
+    state.state_store.save(&state).await.unwrap();
+    assert!(!dir.join(TAGS_FILE).exists(), "unchanged tags are not saved");
+
+    // the next start resolves a new low water mark (say from a flag) and tags
+    let startup = Config {
+        low_water_mark: 9,
+        ..Config::default()
+    };
+    let mut restarted = AppState::new(Arc::new(MpdPool::with_mock(MockMpd::new(), 2)), startup, TagsData {
+        any: vec!["party".to_string()],
+        ..Default::default()
+    });
+    restarted.state_store = Arc::new(StateStore::new(&dir));
+    persistence::restore(&restarted).await;
+
+    let config = restarted.config.lock().await.clone();
+    assert_eq!(config.low_water_mark, 9);
+    assert_eq!(config.refill_threshold, 7);
+    assert_eq!(restarted.tags_data.read().await.any, vec!["party"]);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_album_aware_queue_keeps_its_seeds() {
+    let dir = data_dir("album-seeds");
+    let state = state_with_store(&dir);
+    state.config.lock().await.album_aware_shuffle = true;
+    {
+        let mut queue = state.queue.lock().await;
+        queue.set_album_aware(true);
+        queue.add(song("b1.mp3").by("Artist").on("B"));
+        queue.add(song("a1.mp3").by("Artist").on("A"));
+        queue.add(song("b2.mp3").by("Artist").on("B"));
+    }
+    state.state_store.save(&state).await.unwrap();
+
+    let restarted = state_with_store(&dir);
+    persistence::restore(&restarted).await;
+
+    let queue = restarted.queue.lock().await;
+    assert!(queue.is_album_aware());
+    let seeds: Vec<usize> = queue.albums().map(|seed| seed.songs.len()).collect();
+    assert_eq!(seeds, vec![2, 1]);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_corrupt_files_fall_back_to_defaults() {
+    let dir = data_dir("corrupt");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join(TAGS_FILE), "{\"any\": [").unwrap();
+    std::fs::write(dir.join(CONFIG_FILE), "{\"low_water_mark\": 0}").unwrap();
+    std::fs::write(dir.join(QUEUE_FILE), "not json").unwrap();
+
+    let state = state_with_store(&dir);
+    persistence::restore(&state).await;
+
+    assert_eq!(state.tags_data.read().await.any, vec!["jukebox"]);
+    assert_eq!(*state.config.lock().await, Config::default());
+    assert!(state.queue.lock().await.is_empty());
+
+    // fields missing from an older config file keep their defaults
+    std::fs::write(dir.join(CONFIG_FILE), "{\"album_aware\": true}").unwrap();
+    let config = StateStore::new(&dir).load_config(&Config::default()).unwrap();
+    assert!(config.album_aware_shuffle);
+    assert_eq!(config.low_water_mark, Config::default().low_water_mark);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_restored_queue_is_not_refilled() {
+    let dir = data_dir("no-refill");
+    let mock = MockMpd::new();
+    mock.add_playlist("jukebox", (0..5).map(|i| song(&format!("{}.mp3", i)).by("Artist").on("X")).collect());
+    let mut state = AppState::new(Arc::new(MpdPool::with_mock(mock, 2)), Config::default(), env_tags());
+    state.state_store = Arc::new(StateStore::new(&dir));
+    state.queue.lock().await.add(song("saved.mp3").by("Artist").on("S"));
+
+    initialize_queue(&state).await;
+    assert_eq!(queued_files(&state).await, vec!["saved.mp3"]);
+}
+
+#[tokio::test]
+async fn test_autosave_writes_after_changes() {
+    let dir = data_dir("autosave");
+    let state = state_with_store(&dir);
+    persistence::spawn_autosave(state.clone());
+
+    state.tags_data.write().await.any = vec!["evening".to_string()];
+    state.state_store.mark_dirty();
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let saved = loop {
+        if let Some(tags) = StateStore::new(&dir).load_tags() {
+            break tags;
+        }
+        assert!(Instant::now() < deadline, "state was never saved");
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    };
+    assert_eq!(saved.any, vec!["evening"]);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_disabled_store_does_nothing() {
+    let state = AppState::new(Arc::new(MpdPool::with_mock(MockMpd::new(), 2)), Config::default(), env_tags());
+    assert!(state.state_store.dir().is_none());
+    state.state_store.save(&state).await.unwrap();
+    persistence::restore(&state).await;
+    assert_eq!(state.tags_data.read().await.any, vec!["jukebox"]);
+}
@@ -0,0 +1,214 @@
+mod fixtures;
+
+use fixtures::{get_json, post_json, song, spawn_server, SongBuilder};
+use jukectl_server::mpd_conn::mock_mpd::MockMpd;
+use jukectl_server::mpd_conn::traits::{MpdClient, Song};
+use serde_json::{json, Value};
+
+fn album_track(path: &str, album: &str, track: u32) -> Song {
+    song(path).by("Artist").on(album).titled(&format!("Track {}", track)).track_no(track)
+}
+
+#[tokio::test]
+async fn test_upcoming_lists_the_internal_queue_with_metadata() {
+    let mut mock = MockMpd::new();
+    mock.push("playing.mp3").unwrap();
+    let (base, state) = spawn_server(mock).await;
+    {
+        let mut queue = state.queue.lock().await;
+        for i in 0..4 {
+            queue.add(album_track(&format!("song{}.mp3", i), "Album", i + 1));
+        }
+    }
+
+    let upcoming = get_json(format!("{}/queue/upcoming?count=2", base)).await;
+    assert_eq!(upcoming["length"], 4);
+    assert_eq!(upcoming["album_aware"], false);
+    let songs = upcoming["songs"].as_array().unwrap();
+    assert_eq!(songs.len(), 2);
+    assert_eq!(songs[0]["file"], "song0.mp3");
+    assert_eq!(songs[0]["title"], "Track 1");
+    assert_eq!(songs[1]["album"], "Album");
+    assert!(upcoming["albums"].as_array().unwrap().is_empty());
+
+    // the summary form and MPD's own queue stay separate
+    let summary = get_json(format!("{}/queue?count=1", base)).await;
+    assert_eq!(summary["head"], json!(["song0.mp3"]));
+    assert_eq!(summary["tail"], json!(["song3.mp3"]));
+    let mpd_queue = get_json(format!("{}/queue/all", base)).await;
+    assert_eq!(mpd_queue.as_array().unwrap().len(), 1);
+    assert_eq!(mpd_queue[0]["file"], "playing.mp3");
+
+    // the default is ten
+    for i in 4..15 {
+        state.queue.lock().await.add(album_track(&format!("song{}.mp3", i), "Album", i + 1));
+    }
+    let upcoming = get_json(format!("{}/queue/upcoming", base)).await;
+    assert_eq!(upcoming["songs"].as_array().unwrap().len(), 10);
+}
+
+#[tokio::test]
+async fn test_upcoming_groups_albums_when_album_aware() {
+    let (base, state) = spawn_server(MockMpd::new()).await;
+    {
+        let mut queue = state.queue.lock().await;
+        queue.set_album_aware(true);
+        queue.add(album_track("b2.mp3", "Second", 2));
+        queue.add(album_track("a1.mp3", "First", 1));
+        queue.add(album_track("b1.mp3", "Second", 1));
+        queue.add(Song {
+            album_artist: Some("Various".to_string()),
+            ..album_track("c1.mp3", "Third", 1)
+        });
+    }
+
+    let upcoming = get_json(format!("{}/queue/upcoming?count=2", base)).await;
+    assert_eq!(upcoming["length"], 3);
+    assert_eq!(upcoming["album_aware"], true);
+    assert!(upcoming["songs"].as_array().unwrap().is_empty());
+
+    let albums = upcoming["albums"].as_array().unwrap();
+    assert_eq!(albums.len(), 2);
+    assert_eq!(albums[0]["album"], "Second");
+    assert_eq!(albums[0]["artist"], "Artist");
+    let files: Vec<&str> = albums[0]["songs"].as_array().unwrap().iter().map(|s| s["file"].as_str().unwrap()).collect();
+    assert_eq!(files, vec!["b1.mp3", "b2.mp3"]);
+    assert_eq!(albums[1]["album"], "First");
+
+    let all = get_json(format!("{}/queue/upcoming?count=5", base)).await;
+    assert_eq!(all["albums"][2]["artist"], "Various");
+}
+
+fn party_library() -> MockMpd {
+    let mock = MockMpd::new();
+    mock.add_playlist(
+        "jukebox",
+        vec![
+            album_track("a1.mp3", "Party", 1),
+            album_track("a2.mp3", "Party", 2),
+            album_track("b1.mp3", "Other", 1),
+            Song {
+                album_artist: Some("Someone Else".to_string()),
+                ..album_track("p9.mp3", "Party", 9)
+            },
+        ],
+    );
+    mock.add_playlist("requests", vec![album_track("b1.mp3", "Other", 1)]);
+    mock
+}
+
+async fn post(base: &str, path: &str, body: Value) -> reqwest::Response {
+    post_json(format!("{}{}", base, path), body).await
+}
+
+fn queued_files(upcoming: &Value) -> Vec<String> {
+    upcoming["songs"].as_array().unwrap().iter().map(|s| s["file"].as_str().unwrap().to_string()).collect()
+}
+
+#[tokio::test]
+async fn test_play_next_and_enqueue_resolve_files_albums_and_tags() {
+    let (base, state) = spawn_server(party_library()).await;
+    state.queue.lock().await.add(album_track("queued.mp3", "Queued", 1));
+
+    let change: Value = post(&base, "/queue/next", json!({ "album": "Party", "artist": "Artist" }))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(change["length"], 3);
+    assert_eq!(change["songs"].as_array().unwrap().len(), 2);
+
+    let change: Value = post(&base, "/queue/enqueue", json!({ "tag": "requests" }))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(change["length"], 4);
+
+    // a file already queued moves instead of playing twice
+    post(&base, "/queue/next", json!({ "file": "b1.mp3" })).await;
+    let upcoming = get_json(format!("{}/queue/upcoming", base)).await;
+    assert_eq!(queued_files(&upcoming), vec!["b1.mp3", "a1.mp3", "a2.mp3", "queued.mp3"]);
+
+    for (body, status, detail) in [
+        (json!({ "file": "nope.mp3" }), 404, "no song `nope.mp3`"),
+        (json!({ "album": "Party", "artist": "Nobody" }), 404, "no album `Party` by `Nobody`"),
+        (json!({ "tag": "nope" }), 404, "no tag named `nope`"),
+        (
+            json!({ "file": "a1.mp3", "tag": "requests" }),
+            400,
+            "give exactly one of `file`, `album` or `tag` (`artist` only goes with `album`)",
+        ),
+    ] {
+        let response = post(&base, "/queue/next", body.clone()).await;
+        assert_eq!(response.status(), status, "{}", body);
+        let error: Value = response.json().await.unwrap();
+        assert_eq!(error["detail"], detail, "{}", body);
+    }
+}
+
+#[tokio::test]
+async fn test_remove_move_and_reshuffle_entries() {
+    let (base, state) = spawn_server(MockMpd::new()).await;
+    {
+        let mut queue = state.queue.lock().await;
+        for i in 0..4 {
+            queue.add(album_track(&format!("song{}.mp3", i), "Album", i + 1));
+        }
+    }
+    let client = reqwest::Client::new();
+
+    let change: Value = client.delete(format!("{}/queue/1", base)).send().await.unwrap().json().await.unwrap();
+    assert_eq!(change["songs"][0]["file"], "song1.mp3");
+    assert_eq!(change["length"], 3);
+    let response = client.delete(format!("{}/queue/3", base)).send().await.unwrap();
+    assert_eq!(response.status(), 404);
+
+    let change: Value = post(&base, "/queue/move", json!({ "from": 2, "to": 0 })).await.json().await.unwrap();
+    assert_eq!(change["songs"][0]["file"], "song3.mp3");
+    let upcoming = get_json(format!("{}/queue/upcoming", base)).await;
+    assert_eq!(queued_files(&upcoming), vec!["song3.mp3", "song0.mp3", "song2.mp3"]);
+
+    let response = post(&base, "/queue/move", json!({ "from": 0, "to": 3 })).await;
+    assert_eq!(response.status(), 400);
+    let error: Value = response.json().await.unwrap();
+    assert_eq!(error["detail"], "can't move 0 to 3, the queue has 3 entries");
+
+    let response = client.post(format!("{}/queue/reshuffle", base)).send().await.unwrap();
+    assert!(response.status().is_success());
+    let mut files = queued_files(&get_json(format!("{}/queue/upcoming", base)).await);
+    files.sort();
+    assert_eq!(files, vec!["song0.mp3", "song2.mp3", "song3.mp3"]);
+}
+
+#[tokio::test]
+async fn test_album_aware_play_next_queues_the_album_as_one_entry() {
+    let (base, state) = spawn_server(party_library()).await;
+    {
+        let mut queue = state.queue.lock().await;
+        queue.set_album_aware(true);
+        queue.add(album_track("b1.mp3", "Other", 1));
+    }
+
+    // both artists' "Party" albums, each its own entry
+    let change: Value = post(&base, "/queue/next", json!({ "album": "Party" })).await.json().await.unwrap();
+    assert_eq!(change["length"], 3);
+
+    let upcoming = get_json(format!("{}/queue/upcoming", base)).await;
+    let albums = upcoming["albums"].as_array().unwrap();
+    assert_eq!(albums[0]["artist"], "Artist");
+    assert_eq!(albums[0]["songs"].as_array().unwrap().len(), 2);
+    assert_eq!(albums[1]["artist"], "Someone Else");
+    assert_eq!(albums[2]["album"], "Other");
+
+    let change: Value = reqwest::Client::new()
+        .delete(format!("{}/queue/0", base))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(change["songs"].as_array().unwrap().len(), 2);
+    assert_eq!(change["length"], 2);
+}
@@ -0,0 +1,387 @@
+mod fixtures;
+
+use fixtures::{get_json, mock_library, song, spawn_server, SongBuilder};
+use jiff::civil::Weekday;
+use jiff::tz::TimeZone;
+use jiff::Timestamp;
+use jukectl_server::app_state::AppState;
+use jukectl_server::models::station::Station;
+use jukectl_server::models::tags_data::TagsData;
+use jukectl_server::mpd_conn::mock_mpd::MockMpd;
+use jukectl_server::persistence::{self, StateStore};
+use jukectl_server::scheduler::clock::ManualClock;
+use jukectl_server::scheduler::schedule::{schedule_tick, EngineState, Schedule, ScheduleRule, ScheduleTarget, When};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::SystemTime;
+use tokio::sync::Mutex;
+
+fn tags(any: &[&str]) -> TagsData {
+    TagsData {
+        any: any.iter().map(|t| t.to_string()).collect(),
+        not: vec![],
+        ..Default::default()
+    }
+}
+
+fn library() -> MockMpd {
+    mock_library(vec![
+        ("jukebox", vec![song("j1.mp3").by("Artist"), song("j2.mp3").by("Artist")]),
+        ("morning", vec![song("m1.mp3").by("Artist"), song("m2.mp3").by("Artist")]),
+        ("chill", vec![song("c1.mp3").by("Artist"), song("c2.mp3").by("Artist")]),
+    ])
+}
+
+/// 2026-10-12 is a Monday.
+fn at(time: &str) -> SystemTime {
+    SystemTime::from(format!("2026-10-12T{}Z", time).parse::<Timestamp>().unwrap())
+}
+
+fn rule(when: &str, target: ScheduleTarget, revert_after_minutes: Option<u32>) -> ScheduleRule {
+    let (station, tags) = match target {
+        ScheduleTarget::Station(name) => (Some(name), None),
+        ScheduleTarget::Tags(tags) => (None, Some(tags)),
+    };
+    ScheduleRule {
+        when: when.parse().unwrap(),
+        station,
+        tags,
+        revert_after_minutes,
+    }
+}
+
+/// A served state whose schedule reads times in UTC.
+async fn utc_server() -> (String, AppState) {
+    let (base, state) = spawn_server(library()).await;
+    *state.schedule.lock().await = Schedule::with_time_zone(TimeZone::UTC);
+    (base, state)
+}
+
+#[test]
+fn test_when_parses_day_lists_and_cron() {
+    let weekdays: When = "weekdays 07:00".parse().unwrap();
+    assert!(weekdays.runs_on(Weekday::Monday));
+    assert!(weekdays.runs_on(Weekday::Friday));
+    assert!(!weekdays.runs_on(Weekday::Saturday));
+    assert_eq!(weekdays.to_string(), "weekdays 07:00");
+
+    assert_eq!("Mon,Wed,Fri 9:05".parse::<When>().unwrap().to_string(), "mon,wed,fri 09:05");
+    assert_eq!("fri-mon 22:00".parse::<When>().unwrap().to_string(), "mon,fri,sat,sun 22:00");
+    assert_eq!("saturday,sunday 10:30".parse::<When>().unwrap().to_string(), "weekends 10:30");
+    assert_eq!("* 00:00".parse::<When>().unwrap().to_string(), "daily 00:00");
+
+    // cron: minute hour * * day-of-week, Sunday = 0 or 7
+    assert_eq!("0 7 * * 1-5".parse::<When>().unwrap(), weekdays);
+    assert_eq!("30 22 * * *".parse::<When>().unwrap().to_string(), "daily 22:30");
+    assert_eq!("0 9 * * 0,6".parse::<When>().unwrap().to_string(), "weekends 09:00");
+    assert_eq!("0 9 * * 7".parse::<When>().unwrap().to_string(), "sun 09:00");
+
+    for bad in ["weekdays", "daily 24:00", "daily 07:60", "someday 07:00", "0 7 1 * *", "0 7 * * 8", ""] {
+        assert!(bad.parse::<When>().is_err(), "{} should not parse", bad);
+    }
+}
+
+#[test]
+fn test_rule_needs_exactly_one_target() {
+    let both: Result<ScheduleRule, _> =
+        serde_json::from_value(json!({ "when": "daily 07:00", "station": "morning", "tags": { "any": ["x"] } }));
+    assert!(both.unwrap().target().is_err());
+
+    let neither: ScheduleRule = serde_json::from_value(json!({ "when": "daily 07:00" })).unwrap();
+    assert!(neither.target().is_err());
+
+    let blank: ScheduleRule = serde_json::from_value(json!({ "when": "daily 07:00", "tags": { "any": [" "] } })).unwrap();
+    assert!(blank.target().is_err());
+
+    let typo: Result<ScheduleRule, _> =
+        serde_json::from_value(json!({ "when": "daily 07:00", "station": "x", "revert_after": 5 }));
+    assert!(typo.is_err());
+}
+
+#[test]
+fn test_next_run_and_due() {
+    let mut schedule = Schedule::with_time_zone(TimeZone::UTC);
+    let morning = schedule.add(rule("weekdays 07:00", ScheduleTarget::Station("morning".into()), None)).unwrap();
+    let night = schedule.add(rule("daily 22:00", ScheduleTarget::Tags(tags(&["chill"])), None)).unwrap();
+
+    assert_eq!(schedule.next_run(&morning.rule.when, at("06:00:00")), Some(at("07:00:00")));
+    // Friday 07:00 is followed by Monday's
+    let friday = SystemTime::from("2026-10-16T07:00:00Z".parse::<Timestamp>().unwrap());
+    let monday = SystemTime::from("2026-10-19T07:00:00Z".parse::<Timestamp>().unwrap());
+    assert_eq!(schedule.next_run(&morning.rule.when, friday), Some(monday));
+
+    // the first check applies the latest rule from the last day
+    let (at_then, entry) = schedule.due(at("06:59:00")).unwrap();
+    assert_eq!(entry.id, night.id);
+    assert_eq!(at_then, SystemTime::from("2026-10-11T22:00:00Z".parse::<Timestamp>().unwrap()));
+    assert!(schedule.due(at("06:59:30")).is_none());
+
+    let (at_then, entry) = schedule.due(at("07:00:10")).unwrap();
+    assert_eq!(at_then, at("07:00:00"));
+    assert_eq!(entry.id, morning.id);
+    assert!(schedule.due(at("07:01:00")).is_none());
+
+    // a long gap applies only the last rule missed
+    let (_, entry) = schedule.due(SystemTime::from("2026-10-13T07:30:00Z".parse::<Timestamp>().unwrap())).unwrap();
+    assert_eq!(entry.id, morning.id);
+}
+
+#[test]
+fn test_first_check_skips_rules_older_than_a_day() {
+    let mut schedule = Schedule::with_time_zone(TimeZone::UTC);
+    // 2026-10-12 is a Monday; the last Saturday run is two days back
+    schedule.add(rule("sat 07:00", ScheduleTarget::Station("weekend".into()), None)).unwrap();
+    assert!(schedule.due(at("07:05:00")).is_none());
+}
+
+#[test]
+fn test_schedule_follows_its_time_zone() {
+    let tz = TimeZone::get("America/New_York").unwrap_or(TimeZone::fixed(jiff::tz::offset(-4)));
+    let schedule = Schedule::with_time_zone(tz);
+    let when: When = "daily 07:00".parse().unwrap();
+    // 07:00 in New York in October is 11:00 UTC
+    assert_eq!(schedule.next_run(&when, at("06:00:00")), Some(at("11:00:00")));
+}
+
+#[tokio::test]
+async fn test_tick_activates_station_and_tags() {
+    let (_base, state) = utc_server().await;
+    state
+        .stations
+        .write()
+        .await
+        .insert("morning", Station { volume: Some(30), ..Station::new(tags(&["morning"])) })
+        .unwrap();
+    {
+        let mut schedule = state.schedule.lock().await;
+        schedule.add(rule("weekdays 07:00", ScheduleTarget::Station("morning".into()), None)).unwrap();
+        schedule.add(rule("weekdays 22:00", ScheduleTarget::Tags(tags(&["chill"])), None)).unwrap();
+    }
+
+    let clock = ManualClock::new(at("06:59:50"));
+    assert!(schedule_tick(&state, &clock).await.fired.is_empty());
+
+    clock.set(at("07:00:05"));
+    assert_eq!(schedule_tick(&state, &clock).await.fired, vec![1]);
+    assert_eq!(*state.tags_data.read().await, tags(&["morning"]));
+    assert_eq!(state.stations.read().await.active().unwrap().name, "morning");
+
+    clock.set(at("21:59:00"));
+    assert!(schedule_tick(&state, &clock).await.fired.is_empty());
+    clock.set(at("22:00:00"));
+    assert_eq!(schedule_tick(&state, &clock).await.fired, vec![2]);
+    assert_eq!(*state.tags_data.read().await, tags(&["chill"]));
+    assert!(state.stations.read().await.active().is_none());
+}
+
+#[tokio::test]
+async fn test_first_tick_after_restart_applies_the_current_rule() {
+    let (_base, state) = utc_server().await;
+    state.stations.write().await.insert("morning", Station::new(tags(&["morning"]))).unwrap();
+    {
+        let mut schedule = state.schedule.lock().await;
+        schedule.add(rule("daily 06:00", ScheduleTarget::Tags(tags(&["early"])), None)).unwrap();
+        schedule.add(rule("daily 07:00", ScheduleTarget::Station("morning".into()), None)).unwrap();
+    }
+
+    let clock = ManualClock::new(at("07:05:00"));
+    assert_eq!(schedule_tick(&state, &clock).await.fired, vec![2]);
+    assert_eq!(state.stations.read().await.active().unwrap().name, "morning");
+    clock.set(at("07:05:15"));
+    assert!(schedule_tick(&state, &clock).await.fired.is_empty());
+}
+
+#[tokio::test]
+async fn test_tick_reverts_after_minutes() {
+    let (_base, state) = utc_server().await;
+    state.schedule.lock().await.add(rule("daily 09:00", ScheduleTarget::Tags(tags(&["morning"])), Some(30))).unwrap();
+
+    // yesterday's run has already been reverted, so a restart leaves it be
+    let clock = ManualClock::new(at("08:59:00"));
+    assert!(schedule_tick(&state, &clock).await.fired.is_empty());
+    clock.set(at("09:00:10"));
+    assert_eq!(schedule_tick(&state, &clock).await.fired, vec![1]);
+    assert_eq!(*state.tags_data.read().await, tags(&["morning"]));
+    assert_eq!(state.schedule.lock().await.pending_revert().unwrap().at, at("09:30:00"));
+
+    clock.set(at("09:29:59"));
+    assert!(!schedule_tick(&state, &clock).await.reverted);
+    clock.set(at("09:30:00"));
+    assert!(schedule_tick(&state, &clock).await.reverted);
+    assert_eq!(*state.tags_data.read().await, tags(&["jukebox"]));
+    assert!(state.schedule.lock().await.pending_revert().is_none());
+}
+
+#[tokio::test]
+async fn test_revert_is_dropped_when_playback_changed() {
+    let (_base, state) = utc_server().await;
+    state.schedule.lock().await.add(rule("daily 09:00", ScheduleTarget::Tags(tags(&["morning"])), Some(30))).unwrap();
+
+    let clock = ManualClock::new(at("08:59:00"));
+    schedule_tick(&state, &clock).await;
+    clock.set(at("09:00:00"));
+    schedule_tick(&state, &clock).await;
+
+    // someone picks something else by hand
+    state.switch_tags(tags(&["chill"])).await.unwrap();
+
+    clock.set(at("09:45:00"));
+    assert!(!schedule_tick(&state, &clock).await.reverted);
+    assert_eq!(*state.tags_data.read().await, tags(&["chill"]));
+    assert!(state.schedule.lock().await.pending_revert().is_none());
+}
+
+#[tokio::test]
+async fn test_schedule_api() {
+    let (base, state) = utc_server().await;
+    state.stations.write().await.insert("morning", Station::new(tags(&["morning"]))).unwrap();
+    let client = reqwest::Client::new();
+
+    let empty = get_json(format!("{}/schedule", base)).await;
+    assert_eq!(empty["rules"], json!([]));
+    assert_eq!(empty["time_zone"], "UTC");
+    assert_eq!(empty["pending_revert"], Value::Null);
+
+    let response = client
+        .post(format!("{}/schedule", base))
+        .json(&json!({ "when": "0 7 * * 1-5", "station": "morning", "revert_after_minutes": 120 }))
+        .send()
+        .await
+        .unwrap();
+    assert!(response.status().is_success());
+    let added: Value = response.json().await.unwrap();
+    assert_eq!(added["id"], 1);
+    assert_eq!(added["when"], "weekdays 07:00");
+    assert_eq!(added["revert_after_minutes"], 120);
+    let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs();
+    let next_run = added["next_run"].as_u64().unwrap();
+    assert!(next_run > now && next_run <= now + 4 * 24 * 60 * 60);
+
+    for bad in [
+        json!({ "when": "daily 25:00", "station": "morning" }),
+        json!({ "when": "daily 07:00", "station": "nope" }),
+        json!({ "when": "daily 07:00" }),
+        json!({ "when": "daily 07:00", "tags": { "any": ["x"] }, "volume": 3 }),
+    ] {
+        let response = client.post(format!("{}/schedule", base)).json(&bad).send().await.unwrap();
+        assert_eq!(response.status(), 400, "{}", bad);
+    }
+
+    let replaced: Value = client
+        .put(format!("{}/schedule", base))
+        .json(&json!([
+            { "when": "weekdays 09:00", "tags": { "any": ["jukebox"] } },
+            { "when": "daily 22:00", "tags": { "any": ["chill"] } },
+        ]))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let whens: Vec<&str> = replaced["rules"].as_array().unwrap().iter().map(|r| r["when"].as_str().unwrap()).collect();
+    assert_eq!(whens, vec!["weekdays 09:00", "daily 22:00"]);
+
+    // an invalid replacement leaves the list alone
+    let response = client
+        .put(format!("{}/schedule", base))
+        .json(&json!([{ "when": "daily 08:00", "station": "nope" }]))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+    assert_eq!(state.schedule.lock().await.rules().len(), 2);
+
+    let id = replaced["rules"][1]["id"].as_u64().unwrap();
+    let deleted = client.delete(format!("{}/schedule/{}", base, id)).send().await.unwrap();
+    assert!(deleted.status().is_success());
+    let deleted_again = client.delete(format!("{}/schedule/{}", base, id)).send().await.unwrap();
+    assert_eq!(deleted_again.status(), 404);
+    assert_eq!(state.schedule.lock().await.rules().len(), 1);
+}
+
+#[tokio::test]
+async fn test_schedule_survives_restart() {
+    let dir = std::env::temp_dir().join(format!("jukectl-schedule-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let (_base, state) = spawn_server(library()).await;
+    let state = AppState {
+        state_store: Arc::new(StateStore::new(&dir)),
+        ..state
+    };
+    {
+        let mut schedule = state.schedule.lock().await;
+        schedule.add(rule("weekdays 07:00", ScheduleTarget::Tags(tags(&["morning"])), Some(60))).unwrap();
+        schedule.add(rule("daily 22:00", ScheduleTarget::Station("late".into()), None)).unwrap();
+        schedule.remove(1);
+    }
+    state.state_store.save(&state).await.unwrap();
+
+    let (_base, restarted) = spawn_server(library()).await;
+    let restarted = AppState {
+        state_store: Arc::new(StateStore::new(&dir)),
+        schedule: Arc::new(Mutex::new(Schedule::with_time_zone(TimeZone::UTC))),
+        ..restarted
+    };
+    persistence::restore(&restarted).await;
+
+    let mut schedule = restarted.schedule.lock().await;
+    assert_eq!(schedule.rules(), state.schedule.lock().await.rules());
+    // ids keep counting from where they were
+    let added = schedule.add(rule("daily 12:00", ScheduleTarget::Tags(tags(&["jukebox"])), None)).unwrap();
+    assert_eq!(added.id, 3);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn test_restart_keeps_tags_set_by_hand_after_the_last_rule() {
+    let dir = std::env::temp_dir().join(format!("jukectl-schedule-manual-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let (_base, state) = utc_server().await;
+    let state = AppState {
+        state_store: Arc::new(StateStore::new(&dir)),
+        ..state
+    };
+    state.schedule.lock().await.add(rule("daily 07:00", ScheduleTarget::Tags(tags(&["morning"])), None)).unwrap();
+    let clock = ManualClock::new(at("07:00:00"));
+    assert_eq!(schedule_tick(&state, &clock).await.fired, vec![1]);
+
+    // someone picks something else by hand, then the server restarts
+    state.switch_tags(tags(&["chill"])).await.unwrap();
+    clock.set(at("08:00:00"));
+    schedule_tick(&state, &clock).await;
+    state.state_store.save(&state).await.unwrap();
+
+    let (_base, restarted) = spawn_server(library()).await;
+    let restarted = AppState {
+        state_store: Arc::new(StateStore::new(&dir)),
+        schedule: Arc::new(Mutex::new(Schedule::with_time_zone(TimeZone::UTC))),
+        ..restarted
+    };
+    persistence::restore(&restarted).await;
+    assert_eq!(*restarted.tags_data.read().await, tags(&["chill"]));
+
+    clock.set(at("09:00:00"));
+    assert!(schedule_tick(&restarted, &clock).await.fired.is_empty());
+    assert_eq!(*restarted.tags_data.read().await, tags(&["chill"]));
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_restored_engine_catches_up_only_on_downtime() {
+    let mut schedule = Schedule::with_time_zone(TimeZone::UTC);
+    schedule.add(rule("daily 07:00", ScheduleTarget::Tags(tags(&["morning"])), None)).unwrap();
+    schedule.add(rule("daily 08:30", ScheduleTarget::Tags(tags(&["jukebox"])), None)).unwrap();
+    schedule.restore_engine(EngineState {
+        last_checked: Some(at("08:00:00")),
+        pending_revert: None,
+    });
+
+    // 07:00 was handled before the restart; 08:30 came due while down
+    let (due_at, entry) = schedule.due(at("09:00:00")).unwrap();
+    assert_eq!((due_at, entry.id), (at("08:30:00"), 2));
+}
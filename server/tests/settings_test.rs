@@ -0,0 +1,406 @@
+use clap::Parser;
+use jukectl_server::models::song_queue::TagMatchMode;
+use jukectl_server::models::tags_data::TagsData;
+use jukectl_server::settings::{ServerArgs, Settings};
+use std::env;
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+// Settings::load reads the environment, so tests touching it take turns
+static ENV_MUTEX: Mutex<()> = Mutex::new(());
+
+const ENV_VARS: &[&str] = &[
+    "JUKECTL_CONFIG",
+    "ROCKET_ADDRESS",
+    "ROCKET_PORT",
+    "MPD_HOST",
+    "MPD_PORT",
+    "MPD_MAX_CONNECTIONS",
+    "MPD_BACKEND",
+    "JUKECTL_DEV_MODE",
+    "JUKECTL_PRESET",
+    "JUKECTL_DEFAULT_TAGS_B64",
+    "ALBUM_AWARE_SHUFFLE",
+    "TAG_MATCH_MODE",
+    "JUKECTL_LOW_WATER_MARK",
+    "JUKECTL_REFILL_THRESHOLD",
+    "JUKECTL_POLL_INTERVAL_MS",
+    "JUKECTL_ARTIST_SPACING",
+    "JUKECTL_TRACK_COOLDOWN_HOURS",
+    "JUKECTL_RATING_BOOST",
+    "JUKECTL_SKIP_PENALTY",
+    "JUKECTL_PLAY_PENALTY",
+    "JUKECTL_RECOVERY_HOURS",
+    "JUKECTL_DATA_DIR",
+    "JUKECTL_HISTORY_LOG",
+];
+
+fn clear_env() {
+    for var in ENV_VARS {
+        env::remove_var(var);
+    }
+}
+
+/// Settings loaded with only `var` set in the environment.
+fn load_with_env(var: &str, value: &str) -> Settings {
+    let _lock = ENV_MUTEX.lock().unwrap();
+    clear_env();
+    env::set_var(var, value);
+    let settings = Settings::load(&ServerArgs::default());
+    clear_env();
+    settings.unwrap()
+}
+
+fn config_file(name: &str, text: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("jukectl-{}-{}.toml", name, std::process::id()));
+    std::fs::write(&path, text).unwrap();
+    path
+}
+
+fn args(argv: &[&str]) -> ServerArgs {
+    ServerArgs::parse_from(std::iter::once("jukectl-server").chain(argv.iter().copied()))
+}
+
+fn tags(any: &[&str], not: &[&str]) -> TagsData {
+    TagsData {
+        any: any.iter().map(|t| t.to_string()).collect(),
+        not: not.iter().map(|t| t.to_string()).collect(),
+        ..Default::default()
+    }
+}
+
+const FULL_CONFIG: &str = r#"
+[server]
+address = "0.0.0.0"
+port = 4567
+
+[mpd]
+host = "mpd.local"
+port = 6601
+max_connections = 3
+dev_mode = true
+
+[tags]
+any = ["jukebox", "chill"]
+not = ["explicit"]
+
+[scheduler]
+album_aware = true
+tag_match_mode = "substring"
+low_water_mark = 4
+refill_threshold = 10
+poll_interval_ms = 500
+artist_spacing = 3
+track_cooldown_hours = 12
+rating_boost = 2.5
+recovery_hours = 0
+
+[presets.morning]
+any = ["morning"]
+
+[presets.deep-chill]
+any = ["deep-chill"]
+not = ["loud"]
+
+[storage]
+data_dir = "/var/lib/jukectl"
+"#;
+
+#[test]
+fn test_file_sets_every_section() {
+    let settings = Settings::from_toml(Path::new("jukectl.toml"), FULL_CONFIG).unwrap();
+
+    assert_eq!(settings.address, IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+    assert_eq!(settings.port, 4567);
+    assert_eq!(settings.mpd.host, "mpd.local");
+    assert_eq!(settings.mpd.port, 6601);
+    assert_eq!(settings.mpd.max_connections, 3);
+    assert!(settings.mpd.dev_mode);
+    assert_eq!(settings.default_tags, tags(&["jukebox", "chill"], &["explicit"]));
+    assert!(settings.config.album_aware_shuffle);
+    assert_eq!(settings.config.tag_match_mode, TagMatchMode::Substring);
+    assert_eq!(settings.config.low_water_mark, 4);
+    assert_eq!(settings.config.refill_threshold, 10);
+    assert_eq!(settings.config.poll_interval, Duration::from_millis(500));
+    assert_eq!(settings.config.artist_spacing, 3);
+    assert_eq!(settings.config.track_cooldown_hours, 12);
+    assert_eq!(settings.config.rating_boost, 2.5);
+    assert_eq!(settings.config.skip_penalty, 1.0);
+    assert_eq!(settings.config.recovery_hours, 0);
+    assert_eq!(settings.presets.len(), 2);
+    assert_eq!(settings.presets["deep-chill"].tags, tags(&["deep-chill"], &["loud"]));
+    assert_eq!(settings.data_dir, Some(PathBuf::from("/var/lib/jukectl")));
+    assert_eq!(settings.history_log, None);
+}
+
+#[test]
+fn test_example_config_parses() {
+    let text = include_str!("../../jukectl.toml.example");
+    let settings = Settings::from_toml(Path::new("jukectl.toml.example"), text).unwrap();
+    assert_eq!(settings.port, 4567);
+    assert!(settings.presets.contains_key("morning"));
+}
+
+#[test]
+fn test_empty_file_keeps_defaults() {
+    let settings = Settings::from_toml(Path::new("jukectl.toml"), "").unwrap();
+    assert_eq!(settings, Settings::default());
+}
+
+#[test]
+fn test_preset_selects_default_tags() {
+    let text = "[tags]\npreset = \"morning\"\n\n[presets.morning]\nany = [\"morning\"]\n";
+    let settings = Settings::from_toml(Path::new("jukectl.toml"), text).unwrap();
+    assert_eq!(settings.default_tags, tags(&["morning"], &[]));
+}
+
+#[test]
+fn test_presets_are_stations() {
+    let text = "[tags]\npreset = \"focus\"\n\n[presets.focus]\nany = [\"focus\"]\nalbum_aware = true\nvolume = 30\n";
+    let settings = Settings::from_toml(Path::new("jukectl.toml"), text).unwrap();
+    let focus = &settings.presets["focus"];
+    assert!(focus.album_aware);
+    assert_eq!(focus.volume, Some(30));
+    // starting on a preset also starts in its album mode
+    assert!(settings.config.album_aware_shuffle);
+    assert_eq!(settings.preset.as_deref(), Some("focus"));
+
+    let err = Settings::from_toml(Path::new("jukectl.toml"), "[presets.loud]\nany = [\"rock\"]\nvolume = 300\n").unwrap_err();
+    assert!(err.message.contains("preset `loud`"), "{}", err);
+    assert!(err.line.is_some());
+}
+
+#[test]
+fn test_syntax_error_reports_line() {
+    let text = "[mpd]\nhost = \"mpd\"\nport = \n";
+    let err = Settings::from_toml(Path::new("conf/jukectl.toml"), text).unwrap_err();
+    assert_eq!(err.origin, "conf/jukectl.toml");
+    assert_eq!(err.line, Some(3));
+    assert!(err.to_string().starts_with("conf/jukectl.toml:3: "), "{}", err);
+}
+
+#[test]
+fn test_unknown_key_reports_line() {
+    let text = "[mpd]\nhost = \"mpd\"\n\n[scheduler]\nalbum_awre = true\n";
+    let err = Settings::from_toml(Path::new("jukectl.toml"), text).unwrap_err();
+    assert_eq!(err.line, Some(5));
+    assert!(err.message.contains("album_awre"), "{}", err);
+}
+
+#[test]
+fn test_wrong_type_reports_line() {
+    let text = "[tags]\nany = \"jukebox\"\n";
+    let err = Settings::from_toml(Path::new("jukectl.toml"), text).unwrap_err();
+    assert_eq!(err.line, Some(2));
+}
+
+#[test]
+fn test_out_of_range_value_reports_line() {
+    let text = "[scheduler]\nalbum_aware = true\nlow_water_mark = 0\n";
+    let err = Settings::from_toml(Path::new("jukectl.toml"), text).unwrap_err();
+    assert_eq!(err.line, Some(3));
+    assert!(err.message.contains("low_water_mark"), "{}", err);
+
+    let err = Settings::from_toml(Path::new("jukectl.toml"), "[mpd]\nmax_connections = 0\n").unwrap_err();
+    assert_eq!(err.line, Some(2));
+}
+
+#[test]
+fn test_bad_enum_values_report_line() {
+    let err = Settings::from_toml(Path::new("jukectl.toml"), "[mpd]\nbackend = \"carrier-pigeon\"\n").unwrap_err();
+    assert_eq!(err.line, Some(2));
+    assert!(err.message.contains("carrier-pigeon"), "{}", err);
+
+    let err = Settings::from_toml(Path::new("jukectl.toml"), "[scheduler]\ntag_match_mode = \"fuzzy\"\n").unwrap_err();
+    assert_eq!(err.line, Some(2));
+}
+
+#[test]
+fn test_unknown_preset_reports_line() {
+    let text = "[presets.morning]\nany = [\"morning\"]\n\n[tags]\npreset = \"evening\"\n";
+    let err = Settings::from_toml(Path::new("jukectl.toml"), text).unwrap_err();
+    assert_eq!(err.line, Some(5));
+    assert!(err.message.contains("evening"), "{}", err);
+    assert!(err.message.contains("morning"), "{}", err);
+}
+
+#[test]
+fn test_env_overrides_file_and_flags_override_env() {
+    let _lock = ENV_MUTEX.lock().unwrap();
+    clear_env();
+    let path = config_file("precedence", FULL_CONFIG);
+
+    env::set_var("JUKECTL_CONFIG", &path);
+    env::set_var("MPD_HOST", "env-host");
+    env::set_var("MPD_PORT", "6602");
+    env::set_var("ROCKET_PORT", "9000");
+    env::set_var("ALBUM_AWARE_SHUFFLE", "0");
+    env::set_var("JUKECTL_LOW_WATER_MARK", "6");
+
+    let from_env = Settings::load(&ServerArgs::default());
+    let from_flags = Settings::load(&args(&["--mpd-host", "flag-host", "--low-water-mark", "8", "--album-aware"]));
+    clear_env();
+    let _ = std::fs::remove_file(&path);
+
+    let from_env = from_env.unwrap();
+    assert_eq!(from_env.mpd.host, "env-host");
+    assert_eq!(from_env.mpd.port, 6602);
+    assert_eq!(from_env.port, 9000);
+    assert!(!from_env.config.album_aware_shuffle);
+    assert_eq!(from_env.config.low_water_mark, 6);
+    // untouched by env, still from the file
+    assert_eq!(from_env.mpd.max_connections, 3);
+    assert_eq!(from_env.config.refill_threshold, 10);
+
+    let from_flags = from_flags.unwrap();
+    assert_eq!(from_flags.mpd.host, "flag-host");
+    assert_eq!(from_flags.mpd.port, 6602);
+    assert_eq!(from_flags.config.low_water_mark, 8);
+    assert!(from_flags.config.album_aware_shuffle);
+}
+
+#[test]
+fn test_env_sets_artist_spacing() {
+    assert_eq!(load_with_env("JUKECTL_ARTIST_SPACING", "3").config.artist_spacing, 3);
+}
+
+#[test]
+fn test_env_sets_track_cooldown_hours() {
+    assert_eq!(load_with_env("JUKECTL_TRACK_COOLDOWN_HOURS", "12").config.track_cooldown_hours, 12);
+}
+
+#[test]
+fn test_env_sets_rating_boost() {
+    assert_eq!(load_with_env("JUKECTL_RATING_BOOST", "2.5").config.rating_boost, 2.5);
+}
+
+#[test]
+fn test_env_sets_skip_penalty() {
+    assert_eq!(load_with_env("JUKECTL_SKIP_PENALTY", "0.5").config.skip_penalty, 0.5);
+}
+
+#[test]
+fn test_env_sets_play_penalty() {
+    assert_eq!(load_with_env("JUKECTL_PLAY_PENALTY", "0.25").config.play_penalty, 0.25);
+}
+
+#[test]
+fn test_env_sets_recovery_hours() {
+    assert_eq!(load_with_env("JUKECTL_RECOVERY_HOURS", "12").config.recovery_hours, 12);
+}
+
+#[test]
+fn test_config_flag_beats_env_path() {
+    let _lock = ENV_MUTEX.lock().unwrap();
+    clear_env();
+    let env_path = config_file("env-path", "[mpd]\nhost = \"from-env-file\"\n");
+    let flag_path = config_file("flag-path", "[mpd]\nhost = \"from-flag-file\"\n");
+
+    env::set_var("JUKECTL_CONFIG", &env_path);
+    let settings = Settings::load(&args(&["--config", flag_path.to_str().unwrap()]));
+    clear_env();
+    let _ = std::fs::remove_file(&env_path);
+    let _ = std::fs::remove_file(&flag_path);
+
+    assert_eq!(settings.unwrap().mpd.host, "from-flag-file");
+}
+
+#[test]
+fn test_missing_explicit_file_is_an_error() {
+    let _lock = ENV_MUTEX.lock().unwrap();
+    clear_env();
+    let err = Settings::load(&args(&["--config", "/nonexistent/jukectl.toml"])).unwrap_err();
+    assert_eq!(err.origin, "/nonexistent/jukectl.toml");
+}
+
+#[test]
+fn test_env_tags_override_file_preset() {
+    let _lock = ENV_MUTEX.lock().unwrap();
+    clear_env();
+    let path = config_file("env-tags", FULL_CONFIG);
+
+    env::set_var("JUKECTL_CONFIG", &path);
+    env::set_var("JUKECTL_PRESET", "deep-chill");
+    let with_preset = Settings::load(&ServerArgs::default());
+    let with_flag = Settings::load(&args(&["--preset", "morning"]));
+    env::set_var("JUKECTL_PRESET", "nope");
+    let unknown = Settings::load(&ServerArgs::default());
+    clear_env();
+    let _ = std::fs::remove_file(&path);
+
+    assert_eq!(with_preset.unwrap().default_tags, tags(&["deep-chill"], &["loud"]));
+    assert_eq!(with_flag.unwrap().default_tags, tags(&["morning"], &[]));
+    assert_eq!(unknown.unwrap_err().origin, "JUKECTL_PRESET");
+}
+
+#[test]
+fn test_invalid_env_values_are_errors() {
+    let _lock = ENV_MUTEX.lock().unwrap();
+    clear_env();
+
+    env::set_var("MPD_PORT", "sixty-six-hundred");
+    let bad_port = Settings::load(&ServerArgs::default());
+    clear_env();
+    env::set_var("JUKECTL_DEV_MODE", "maybe");
+    let bad_flag = Settings::load(&ServerArgs::default());
+    clear_env();
+    env::set_var("JUKECTL_POLL_INTERVAL_MS", "1");
+    let out_of_range = Settings::load(&ServerArgs::default());
+    clear_env();
+
+    let err = bad_port.unwrap_err();
+    assert_eq!(err.origin, "MPD_PORT");
+    assert!(err.to_string().starts_with("MPD_PORT: "), "{}", err);
+    assert_eq!(bad_flag.unwrap_err().origin, "JUKECTL_DEV_MODE");
+    assert!(out_of_range.unwrap_err().message.contains("poll_interval_ms"));
+}
+
+#[test]
+fn test_legacy_env_flags_still_work() {
+    let _lock = ENV_MUTEX.lock().unwrap();
+    clear_env();
+
+    env::set_var("JUKECTL_DEV_MODE", "1");
+    env::set_var("ALBUM_AWARE_SHUFFLE", "1");
+    env::set_var("TAG_MATCH_MODE", "substring");
+    let settings = Settings::load(&ServerArgs::default());
+    clear_env();
+
+    let settings = settings.unwrap();
+    assert!(settings.mpd.dev_mode);
+    assert!(settings.config.album_aware_shuffle);
+    assert_eq!(settings.config.tag_match_mode, TagMatchMode::Substring);
+}
+
+#[test]
+fn test_dev_mode_flag_overrides_env() {
+    let _lock = ENV_MUTEX.lock().unwrap();
+    clear_env();
+
+    env::set_var("JUKECTL_DEV_MODE", "1");
+    let from_env = Settings::load(&ServerArgs::default());
+    let from_flag = Settings::load(&args(&["--dev-mode=false"]));
+    let path = config_file("dev-mode", "[mpd]\ndev_mode = false\n");
+    env::set_var("JUKECTL_CONFIG", &path);
+    let over_file = Settings::load(&ServerArgs::default());
+    clear_env();
+    std::fs::remove_file(path).unwrap();
+
+    assert!(from_env.unwrap().mpd.dev_mode);
+    assert!(!from_flag.unwrap().mpd.dev_mode);
+    // the environment still beats the file
+    assert!(over_file.unwrap().mpd.dev_mode);
+}
+
+#[test]
+fn test_flags_are_validated() {
+    let _lock = ENV_MUTEX.lock().unwrap();
+    clear_env();
+
+    let err = Settings::load(&args(&["--refill-threshold", "5000"])).unwrap_err();
+    assert_eq!(err.origin, "command line");
+    assert!(ServerArgs::try_parse_from(["jukectl-server", "--tag-match-mode", "fuzzy"]).is_err());
+    assert!(ServerArgs::try_parse_from(["jukectl-server", "--port", "70000"]).is_err());
+}
@@ -0,0 +1,68 @@
+mod fixtures;
+
+use fixtures::*;
+
+#[tokio::test]
+async fn test_scenario_a_refills_when_internal_queue_runs_dry() {
+    let timeline = Simulator::new(scenario_refill_on_empty()).run(25).await;
+
+    // 10 songs in the library, one consumed per tick: must refill more than once
+    let refills = timeline.refills();
+    assert!(refills.len() >= 2, "expected repeated refills, got {:?}", refills);
+    assert!(refills.iter().all(|(_, count)| *count == 10));
+
+    // MPD is never left silent after the scheduler has run
+    for snapshot in &timeline.snapshots {
+        assert!(snapshot.mpd_queue_len >= 1, "MPD went silent at tick {}", snapshot.tick);
+    }
+}
+
+#[tokio::test]
+async fn test_scenario_b_next_refill_uses_new_tags() {
+    let timeline = Simulator::new(scenario_tag_hot_swap()).run(20).await;
+
+    let refill_after_swap = timeline
+        .refills()
+        .into_iter()
+        .find(|(tick, _)| *tick >= 5)
+        .expect("no refill after the tag change");
+    assert_eq!(refill_after_swap.1, 3, "refill should come from the jazz playlist");
+
+    let pushed = timeline.pushed_after(refill_after_swap.0 - 1);
+    assert!(!pushed.is_empty());
+    assert!(pushed.iter().all(|f| f.starts_with("jazz/")), "{:?}", pushed);
+}
+
+#[tokio::test]
+async fn test_rapid_drain_is_topped_up() {
+    let timeline = Simulator::new(scenario_rapid_drain()).run(8).await;
+
+    for snapshot in &timeline.snapshots {
+        assert!(snapshot.mpd_queue_len >= 1, "MPD went silent at tick {}", snapshot.tick);
+    }
+}
+
+#[tokio::test]
+async fn test_empty_library_stays_quiet() {
+    let timeline = Simulator::new(scenario_empty_library()).run(5).await;
+
+    assert!(timeline.pushed_after(0).is_empty());
+    assert!(timeline.snapshots.iter().all(|s| s.internal_queue_len == 0));
+}
+
+#[tokio::test]
+async fn test_tags_flip_back_and_forth() {
+    let timeline = Simulator::new(scenario_cache_invalidation()).run(40).await;
+
+    let last = timeline.snapshots.last().unwrap();
+    assert_eq!(last.current_tags, vec!["rock".to_string()]);
+
+    // every refill after the final switch comes from the rock playlist
+    let rock_refills: Vec<_> = timeline
+        .refills()
+        .into_iter()
+        .filter(|(tick, _)| *tick >= 6)
+        .collect();
+    assert!(!rock_refills.is_empty());
+    assert!(rock_refills.iter().all(|(_, count)| *count == 10));
+}
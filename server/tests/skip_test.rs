@@ -0,0 +1,111 @@
+mod fixtures;
+
+use fixtures::{get_json, send, song, spawn_server};
+use jukectl_server::models::skip_log::{SkipLog, SkipReason};
+use jukectl_server::mpd_conn::mock_mpd::MockMpd;
+use jukectl_server::mpd_conn::traits::MpdClient;
+use reqwest::Method;
+use std::time::{Duration, UNIX_EPOCH};
+
+async fn post_skip(base: &str, body: Option<serde_json::Value>) -> reqwest::Response {
+    send(Method::POST, format!("{}/skip", base), body).await
+}
+
+#[test]
+fn test_skip_log_is_bounded_and_counts_per_file() {
+    let mut log = SkipLog::new(3);
+    let at = UNIX_EPOCH + Duration::from_secs(100);
+
+    log.record("a.mp3", Some(SkipReason::Dislike), at);
+    log.record("b.mp3", None, at);
+    log.record("a.mp3", Some(SkipReason::BadRip), at);
+    log.record("a.mp3", Some(SkipReason::Dislike), at);
+
+    // the first skip fell off the end
+    assert_eq!(log.len(), 3);
+    assert_eq!(log.recent(None, 10)[0].file, "a.mp3");
+    assert_eq!(log.recent(None, 10)[2].file, "b.mp3");
+    assert_eq!(log.recent(None, 10)[0].at, 100);
+
+    let counts = log.counts(None);
+    assert_eq!(counts[0].file, "a.mp3");
+    assert_eq!(counts[0].count, 2);
+    assert_eq!(counts[0].reasons[&SkipReason::Dislike], 1);
+    assert_eq!(counts[0].reasons[&SkipReason::BadRip], 1);
+    assert_eq!(counts[1].file, "b.mp3");
+
+    let dislikes = log.counts(Some(SkipReason::Dislike));
+    assert_eq!(dislikes.len(), 1);
+    assert_eq!(dislikes[0].count, 1);
+}
+
+#[tokio::test]
+async fn test_skip_records_reason_and_lists_skips() {
+    let mut mock = MockMpd::new();
+    for file in ["a.mp3", "b.mp3", "c.mp3"] {
+        MpdClient::push(&mut mock, file).unwrap();
+    }
+    let (base, _state) = spawn_server(mock).await;
+
+    let json: serde_json::Value = post_skip(&base, Some(serde_json::json!({ "reason": "wrong-mood" })))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(json["skipped"], "a.mp3");
+    assert_eq!(json["new"], "b.mp3");
+    assert_eq!(json["reason"], "wrong-mood");
+
+    assert!(post_skip(&base, None).await.status().is_success());
+
+    let skips = get_json(format!("{}/skips", base)).await;
+    assert_eq!(skips["total"], 2);
+    assert_eq!(skips["recent"][0]["file"], "b.mp3");
+    assert_eq!(skips["recent"][0]["reason"], serde_json::Value::Null);
+    assert_eq!(skips["recent"][1]["reason"], "wrong-mood");
+
+    let filtered = get_json(format!("{}/skips?reason=wrong-mood", base)).await;
+    assert_eq!(filtered["recent"].as_array().unwrap().len(), 1);
+    assert_eq!(filtered["most_skipped"][0]["file"], "a.mp3");
+
+    // the limit caps both lists
+    let limited = get_json(format!("{}/skips?limit=1", base)).await;
+    assert_eq!(limited["total"], 2);
+    assert_eq!(limited["recent"].as_array().unwrap().len(), 1);
+    assert_eq!(limited["most_skipped"].as_array().unwrap().len(), 1);
+    assert_eq!(get_json(format!("{}/skips", base)).await["most_skipped"].as_array().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn test_skip_rejects_unknown_reason() {
+    let mut mock = MockMpd::new();
+    MpdClient::push(&mut mock, "a.mp3").unwrap();
+    let (base, state) = spawn_server(mock.clone()).await;
+
+    let response = post_skip(&base, Some(serde_json::json!({ "reason": "boring" }))).await;
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+
+    // nothing was skipped
+    assert_eq!(MpdClient::queue(&mut mock).unwrap().len(), 1);
+    assert!(state.skip_log.lock().await.is_empty());
+}
+
+#[tokio::test]
+async fn test_skip_tops_up_mpd_from_internal_queue() {
+    let mut mock = MockMpd::new();
+    MpdClient::push(&mut mock, "playing.mp3").unwrap();
+    let (base, state) = spawn_server(mock.clone()).await;
+    {
+        let mut queue = state.queue.lock().await;
+        queue.add(song("next1.mp3"));
+        queue.add(song("next2.mp3"));
+    }
+
+    let json: serde_json::Value = post_skip(&base, None).await.json().await.unwrap();
+    assert_eq!(json["skipped"], "playing.mp3");
+    assert_eq!(json["new"], "next1.mp3");
+
+    let mpd_queue = MpdClient::queue(&mut mock).unwrap();
+    assert_eq!(mpd_queue[0].file, "next1.mp3");
+    assert_eq!(state.queue.lock().await.len(), 1);
+}
@@ -1,17 +1,16 @@
 #[cfg(test)]
 mod tests {
-    use jukectl_server::models::song_queue::SongQueue;
+    use jukectl_server::models::cooldown::RecentPlays;
+    use jukectl_server::models::song_queue::{InsertAt, SongQueue, TagMatchMode};
+    use jukectl_server::models::tags_data::TagsData;
+    use jukectl_server::models::weighting::SongWeights;
+    use jukectl_server::mpd_conn::mock_mpd::MockMpd;
     use jukectl_server::mpd_conn::traits::Song;
 
     fn create_test_song(path: &str) -> Song {
         Song {
             file: path.to_string(),
-            title: None,
-            artist: None,
-            album: None,
-            duration: None,
-            pos: None,
-            id: None,
+            ..Default::default()
         }
     }
 
@@ -25,4 +24,176 @@ mod tests {
         queue.empty_queue();
         assert_eq!(queue.len(), 0);
     }
+
+    fn create_artist_song(path: &str, artist: &str) -> Song {
+        Song {
+            artist: Some(artist.to_string()),
+            ..create_test_song(path)
+        }
+    }
+
+    fn tags(any: &[&str], not: &[&str]) -> TagsData {
+        TagsData {
+            any: any.iter().map(|t| t.to_string()).collect(),
+            not: not.iter().map(|t| t.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    fn queued_files(queue: &SongQueue) -> Vec<String> {
+        let mut files: Vec<String> = queue.head(Some(queue.len())).into_iter().map(|s| s.file).collect();
+        files.sort();
+        files
+    }
+
+    fn overlapping_mock() -> MockMpd {
+        let mock = MockMpd::new();
+        mock.add_playlist("rock", vec![
+            create_test_song("a.mp3"),
+            create_test_song("b.mp3"),
+            create_test_song("c.mp3"),
+        ]);
+        mock.add_playlist("chill", vec![
+            create_test_song("c.mp3"),
+            create_test_song("d.mp3"),
+        ]);
+        mock.add_playlist("explicit", vec![
+            create_test_song("b.mp3"),
+            create_test_song("d.mp3"),
+        ]);
+        mock
+    }
+
+    #[test]
+    fn test_playlist_union_is_deduplicated() {
+        let mut mock = overlapping_mock();
+        let mut queue = SongQueue::new();
+        queue.shuffle_and_add(&tags(&["rock", "chill"], &[]), &mut mock);
+
+        assert_eq!(queued_files(&queue), vec!["a.mp3", "b.mp3", "c.mp3", "d.mp3"]);
+    }
+
+    #[test]
+    fn test_playlist_not_tags_are_subtracted() {
+        let mut mock = overlapping_mock();
+        let mut queue = SongQueue::new();
+        queue.shuffle_and_add(&tags(&["rock", "chill"], &["explicit"]), &mut mock);
+
+        assert_eq!(queued_files(&queue), vec!["a.mp3", "c.mp3"]);
+    }
+
+    #[test]
+    fn test_playlist_unknown_tag_matches_nothing() {
+        let mut mock = overlapping_mock();
+        let mut queue = SongQueue::new();
+        queue.shuffle_and_add(&tags(&["no-such-tag"], &[]), &mut mock);
+
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn test_playlist_mode_ignores_name_substrings() {
+        let mut mock = MockMpd::new();
+        mock.add_playlist("rock", vec![create_test_song("rock/a.mp3")]);
+        mock.add_playlist("other", vec![
+            create_artist_song("Rockabilly/b.mp3", "The Rockabillies"),
+            create_test_song("Bedrock.mp3"),
+        ]);
+
+        let mut queue = SongQueue::new();
+        queue.shuffle_and_add(&tags(&["rock"], &[]), &mut mock);
+
+        assert_eq!(queued_files(&queue), vec!["rock/a.mp3"]);
+    }
+
+    #[test]
+    fn test_substring_mode_is_opt_in() {
+        let mut mock = MockMpd::new();
+        mock.add_playlist("library", vec![
+            create_artist_song("x.mp3", "The Rockabillies"),
+            create_test_song("Bedrock.mp3"),
+            create_test_song("jazz.mp3"),
+        ]);
+
+        let mut queue = SongQueue::new();
+        queue.set_tag_match_mode(TagMatchMode::Substring);
+        queue.shuffle_and_add(&tags(&["rock"], &[]), &mut mock);
+
+        assert_eq!(queued_files(&queue), vec!["Bedrock.mp3"]);
+    }
+
+    fn create_album_song(path: &str, album: &str, track: u32) -> Song {
+        Song {
+            album: Some(album.to_string()),
+            artist: Some("Artist".to_string()),
+            track: Some(track),
+            ..create_test_song(path)
+        }
+    }
+
+    fn files(songs: Vec<Song>) -> Vec<String> {
+        songs.into_iter().map(|s| s.file).collect()
+    }
+
+    #[test]
+    fn test_insert_moves_songs_instead_of_duplicating() {
+        let mut queue = SongQueue::new();
+        for file in ["a.mp3", "b.mp3", "c.mp3"] {
+            queue.add(create_test_song(file));
+        }
+
+        let inserted = queue.insert(vec![create_test_song("c.mp3"), create_test_song("x.mp3")], InsertAt::Front);
+        assert_eq!(inserted, 2);
+        assert_eq!(files(queue.head(None)), vec!["c.mp3", "x.mp3", "a.mp3", "b.mp3"]);
+
+        queue.insert(vec![create_test_song("a.mp3")], InsertAt::Back);
+        assert_eq!(files(queue.head(None)), vec!["c.mp3", "x.mp3", "b.mp3", "a.mp3"]);
+    }
+
+    #[test]
+    fn test_remove_move_and_reshuffle_by_index() {
+        let mut queue = SongQueue::new();
+        for file in ["a.mp3", "b.mp3", "c.mp3", "d.mp3"] {
+            queue.add(create_test_song(file));
+        }
+
+        assert_eq!(files(queue.remove_at(1).unwrap()), vec!["b.mp3"]);
+        assert!(queue.remove_at(3).is_none());
+
+        assert_eq!(files(queue.move_entry(2, 0).unwrap()), vec!["d.mp3"]);
+        assert_eq!(files(queue.head(None)), vec!["d.mp3", "a.mp3", "c.mp3"]);
+        assert!(queue.move_entry(0, 3).is_none());
+
+        queue.reshuffle(&RecentPlays::default(), &SongWeights::uniform());
+        let mut reshuffled = files(queue.head(None));
+        reshuffled.sort();
+        assert_eq!(reshuffled, vec!["a.mp3", "c.mp3", "d.mp3"]);
+    }
+
+    #[test]
+    fn test_album_aware_edits_work_on_whole_albums() {
+        let mut queue = SongQueue::new();
+        queue.set_album_aware(true);
+        queue.add(create_album_song("a1.mp3", "First", 1));
+        queue.add(create_album_song("b2.mp3", "Second", 2));
+        queue.add(create_album_song("c1.mp3", "Third", 1));
+
+        // an album already queued is pulled forward with its tracks merged
+        let inserted = queue.insert(
+            vec![create_album_song("b1.mp3", "Second", 1), create_album_song("n1.mp3", "New", 1)],
+            InsertAt::Front,
+        );
+        assert_eq!(inserted, 2);
+        assert_eq!(queue.len(), 4);
+        let seeds: Vec<Vec<String>> = queue.albums().map(|seed| files(seed.songs.clone())).collect();
+        assert_eq!(
+            seeds,
+            vec![vec!["b1.mp3", "b2.mp3"], vec!["n1.mp3"], vec!["a1.mp3"], vec!["c1.mp3"]]
+        );
+        assert_eq!(queue.song_count(), 5);
+
+        assert_eq!(files(queue.move_entry(0, 3).unwrap()), vec!["b1.mp3", "b2.mp3"]);
+        assert_eq!(files(queue.remove_at(3).unwrap()), vec!["b1.mp3", "b2.mp3"]);
+        assert_eq!(files(queue.head(None)), vec!["n1.mp3", "a1.mp3", "c1.mp3"]);
+    }
 }
@@ -0,0 +1,253 @@
+mod fixtures;
+
+use fixtures::{get_json, mock_library, post_empty, put_json, song, spawn_server, SongBuilder};
+use jukectl_server::app_state::AppState;
+use jukectl_server::models::station::{Station, Stations};
+use jukectl_server::models::tags_data::TagsData;
+use jukectl_server::mpd_conn::mock_mpd::MockMpd;
+use jukectl_server::persistence::{self, StateStore};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+fn tags(any: &[&str], not: &[&str]) -> TagsData {
+    TagsData {
+        any: any.iter().map(|t| t.to_string()).collect(),
+        not: not.iter().map(|t| t.to_string()).collect(),
+        ..Default::default()
+    }
+}
+
+fn library() -> MockMpd {
+    let track = |path: &str, album: &str| song(path).by("Artist").on(album);
+    mock_library(vec![
+        ("jukebox", vec![track("j1.mp3", "J"), track("j2.mp3", "J")]),
+        ("morning", vec![track("m1.mp3", "M"), track("m2.mp3", "M"), track("m3.mp3", "M")]),
+    ])
+}
+
+async fn put(base: &str, name: &str, body: Value) -> reqwest::Response {
+    put_json(format!("{}/stations/{}", base, name), body).await
+}
+
+async fn post(base: &str, path: &str) -> reqwest::Response {
+    post_empty(format!("{}{}", base, path)).await
+}
+
+#[tokio::test]
+async fn test_station_crud() {
+    let (base, _state) = spawn_server(library()).await;
+
+    let list = get_json(format!("{}/stations", base)).await;
+    assert_eq!(list, json!([]));
+
+    let response = put(&base, "morning", json!({ "any": ["morning"], "volume": 40 })).await;
+    assert!(response.status().is_success());
+    let saved: Value = response.json().await.unwrap();
+    assert_eq!(
+        saved,
+        json!({ "name": "morning", "any": ["morning"], "not": [], "album_aware": false, "volume": 40 })
+    );
+
+    put(&base, "deep-chill", json!({ "any": ["chill"], "not": ["loud"], "album_aware": true })).await;
+    let list = get_json(format!("{}/stations", base)).await;
+    let names: Vec<&str> = list.as_array().unwrap().iter().map(|s| s["name"].as_str().unwrap()).collect();
+    assert_eq!(names, vec!["deep-chill", "morning"]);
+    assert!(list[0].get("volume").is_none());
+
+    // replacing keeps one entry
+    put(&base, "morning", json!({ "any": ["morning", "coffee"] })).await;
+    let one = get_json(format!("{}/stations/morning", base)).await;
+    assert_eq!(one["any"], json!(["morning", "coffee"]));
+    assert!(one.get("volume").is_none());
+
+    let deleted = reqwest::Client::new().delete(format!("{}/stations/morning", base)).send().await.unwrap();
+    assert!(deleted.status().is_success());
+    let missing = reqwest::get(format!("{}/stations/morning", base)).await.unwrap();
+    assert_eq!(missing.status(), 404);
+    let deleted_again = reqwest::Client::new().delete(format!("{}/stations/morning", base)).send().await.unwrap();
+    assert_eq!(deleted_again.status(), 404);
+}
+
+#[tokio::test]
+async fn test_invalid_stations_are_rejected() {
+    let (base, state) = spawn_server(library()).await;
+
+    assert_eq!(put(&base, "quiet", json!({ "any": [""], "not": ["loud"] })).await.status(), 400);
+    assert_eq!(put(&base, "loud", json!({ "any": ["rock"], "volume": 101 })).await.status(), 400);
+    assert_eq!(put(&base, "typo", json!({ "any": "rock" })).await.status(), 400);
+
+    let bad_json = reqwest::Client::new()
+        .put(format!("{}/stations/broken", base))
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body("{\"any\": [")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(bad_json.status(), 400);
+
+    assert!(state.stations.read().await.is_empty());
+}
+
+#[tokio::test]
+async fn test_activate_switches_tags_mode_and_volume() {
+    let mock = library();
+    let (base, state) = spawn_server(mock.clone()).await;
+    put(&base, "morning", json!({ "any": ["morning"], "album_aware": true, "volume": 35 })).await;
+
+    let response = post(&base, "/stations/morning/activate").await;
+    assert!(response.status().is_success());
+    let activated: Value = response.json().await.unwrap();
+    assert_eq!(activated["name"], "morning");
+
+    assert_eq!(*state.tags_data.read().await, tags(&["morning"], &[]));
+    assert!(state.config.lock().await.album_aware_shuffle);
+    let queue = state.queue.lock().await;
+    assert!(queue.is_album_aware());
+    // the three morning songs form one album seed
+    assert_eq!(queue.len(), 1);
+    drop(queue);
+    assert_eq!(mock.volume(), Some(35));
+
+    let active = get_json(format!("{}/stations/active", base)).await;
+    assert_eq!(active["name"], "morning");
+    assert_eq!(active["station"]["volume"], 35);
+
+    assert_eq!(post(&base, "/stations/nope/activate").await.status(), 404);
+}
+
+#[tokio::test]
+async fn test_activate_without_volume_leaves_volume_alone() {
+    let mock = library();
+    let (base, _state) = spawn_server(mock.clone()).await;
+    put(&base, "jukebox", json!({ "any": ["jukebox"] })).await;
+
+    assert!(post(&base, "/stations/jukebox/activate").await.status().is_success());
+    assert_eq!(mock.volume(), None);
+}
+
+#[tokio::test]
+async fn test_activate_with_mpd_down_changes_nothing() {
+    let mock = library();
+    let (base, state) = spawn_server(mock.clone()).await;
+    put(&base, "morning", json!({ "any": ["morning"], "album_aware": true, "volume": 35 })).await;
+    let before = state.tags_data.read().await.clone();
+
+    mock.simulate_disconnect();
+    assert_eq!(post(&base, "/stations/morning/activate").await.status(), 503);
+
+    assert_eq!(*state.tags_data.read().await, before);
+    assert!(!state.config.lock().await.album_aware_shuffle);
+    assert!(!state.queue.lock().await.is_album_aware());
+    assert!(state.stations.read().await.active().is_none());
+    mock.simulate_reconnect();
+    assert_eq!(mock.volume(), None);
+}
+
+#[tokio::test]
+async fn test_other_tag_changes_clear_active_station() {
+    let (base, state) = spawn_server(library()).await;
+    put(&base, "morning", json!({ "any": ["morning"] })).await;
+    post(&base, "/stations/morning/activate").await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/tags", base))
+        .json(&json!({ "any": ["jukebox"] }))
+        .send()
+        .await
+        .unwrap();
+    assert!(response.status().is_success());
+
+    let active = get_json(format!("{}/stations/active", base)).await;
+    assert_eq!(active, json!({ "name": null, "station": null }));
+
+    // deleting the active station clears it too
+    post(&base, "/stations/morning/activate").await;
+    assert!(state.stations.read().await.active().is_some());
+    reqwest::Client::new().delete(format!("{}/stations/morning", base)).send().await.unwrap();
+    assert!(state.stations.read().await.active().is_none());
+}
+
+#[tokio::test]
+async fn test_saved_stations_layer_over_config_presets() {
+    let dir = std::env::temp_dir().join(format!("jukectl-stations-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let (_base, state) = spawn_server(library()).await;
+    let state = AppState {
+        state_store: Arc::new(StateStore::new(&dir)),
+        ..state
+    };
+    {
+        let mut stations = state.stations.write().await;
+        stations.insert("morning", Station::new(tags(&["coffee"], &[]))).unwrap();
+        stations.insert("late", Station::new(tags(&["late"], &[]))).unwrap();
+        stations.set_active(Some("late"));
+    }
+    *state.tags_data.write().await = tags(&["late"], &[]);
+    state.state_store.save(&state).await.unwrap();
+
+    let (_base, restarted) = spawn_server(library()).await;
+    let presets = BTreeMap::from([
+        ("morning".to_string(), Station::new(tags(&["morning"], &[]))),
+        ("evening".to_string(), Station::new(tags(&["evening"], &[]))),
+    ]);
+    let restarted = AppState {
+        state_store: Arc::new(StateStore::new(&dir)),
+        stations: Arc::new(tokio::sync::RwLock::new(Stations::new(presets))),
+        ..restarted
+    };
+    persistence::restore(&restarted).await;
+
+    let stations = restarted.stations.read().await;
+    assert_eq!(stations.len(), 3);
+    assert_eq!(stations.get("morning").unwrap().tags, tags(&["coffee"], &[]));
+    assert!(stations.get("evening").is_some());
+    assert_eq!(stations.active().unwrap().name, "late");
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn test_deleted_presets_stay_deleted_after_restart() {
+    let dir = std::env::temp_dir().join(format!("jukectl-stations-removed-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let presets = || {
+        BTreeMap::from([
+            ("morning".to_string(), Station::new(tags(&["morning"], &[]))),
+            ("evening".to_string(), Station::new(tags(&["evening"], &[]))),
+        ])
+    };
+    let restart = |state: AppState| AppState {
+        state_store: Arc::new(StateStore::new(&dir)),
+        stations: Arc::new(tokio::sync::RwLock::new(Stations::new(presets()))),
+        ..state
+    };
+
+    let (_base, state) = spawn_server(library()).await;
+    let state = restart(state);
+    persistence::restore(&state).await;
+    state.stations.write().await.remove("evening").unwrap();
+    state.state_store.save(&state).await.unwrap();
+
+    let (_base, restarted) = spawn_server(library()).await;
+    let restarted = restart(restarted);
+    persistence::restore(&restarted).await;
+    assert!(restarted.stations.read().await.get("evening").is_none());
+    assert!(restarted.stations.read().await.get("morning").is_some());
+
+    // putting it back through the API undoes the deletion
+    restarted
+        .stations
+        .write()
+        .await
+        .insert("evening", Station::new(tags(&["late"], &[])))
+        .unwrap();
+    restarted.state_store.save(&restarted).await.unwrap();
+    let (_base, again) = spawn_server(library()).await;
+    let again = restart(again);
+    persistence::restore(&again).await;
+    assert_eq!(again.stations.read().await.get("evening").unwrap().tags, tags(&["late"], &[]));
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
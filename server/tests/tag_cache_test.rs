@@ -0,0 +1,111 @@
+mod fixtures;
+
+use fixtures::{get_json, song, spawn_server, SongBuilder};
+use jukectl_server::models::tag_cache::{TagCache, TagSnapshot};
+use jukectl_server::mpd_conn::mock_mpd::MockMpd;
+use jukectl_server::scheduler::start_scheduler;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+fn library() -> MockMpd {
+    let mock = MockMpd::new();
+    mock.add_playlist(
+        "jukebox",
+        vec![
+            song("a.mp3").by("Artist").in_genre("Jazz"),
+            song("b.mp3").by("Artist").in_genre("Jazz"),
+            song("c.mp3").by("Artist").in_genre("Rock"),
+        ],
+    );
+    mock.add_playlist("chill", vec![song("a.mp3").by("Artist").in_genre("Jazz"), song("d.mp3").by("Artist")]);
+    mock
+}
+
+async fn available(base: &str) -> Value {
+    get_json(format!("{}/tags/available", base)).await
+}
+
+#[tokio::test]
+async fn test_tags_report_genres_and_playlist_counts() {
+    let (base, _state) = spawn_server(library()).await;
+
+    let tags = get_json(format!("{}/tags", base)).await;
+    assert_eq!(
+        tags["genres"],
+        json!([{ "name": "Jazz", "count": 2 }, { "name": "Rock", "count": 1 }])
+    );
+    assert_eq!(
+        tags["playlists"],
+        json!([{ "name": "chill", "count": 2 }, { "name": "jukebox", "count": 3 }])
+    );
+    assert_eq!(tags["artists"], json!([{ "name": "Artist", "count": 4 }]));
+
+    assert_eq!(
+        available(&base).await,
+        json!([{ "name": "jukebox", "track_count": 3 }, { "name": "chill", "track_count": 2 }])
+    );
+}
+
+#[tokio::test]
+async fn test_counts_are_cached_until_tags_change_through_the_api() {
+    let mock = library();
+    let (base, _state) = spawn_server(mock.clone()).await;
+    assert_eq!(available(&base).await.as_array().unwrap().len(), 2);
+
+    // a change behind the server's back is not seen without the idle watcher
+    mock.add_playlist("late", vec![song("e.mp3").by("Artist")]);
+    assert_eq!(available(&base).await.as_array().unwrap().len(), 2);
+
+    let client = reqwest::Client::new();
+    client.put(format!("{}/tags/morning", base)).send().await.unwrap();
+    let names: Vec<String> = available(&base)
+        .await
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|t| t["name"].as_str().unwrap().to_string())
+        .collect();
+    assert_eq!(names, vec!["jukebox", "chill", "late", "morning"]);
+
+    let response = client
+        .post(format!("{}/song/tags", base))
+        .json(&json!({ "filename": "e.mp3", "add": ["morning"], "remove": [] }))
+        .send()
+        .await
+        .unwrap();
+    assert!(response.status().is_success());
+    let tags = get_json(format!("{}/tags", base)).await;
+    assert!(tags["playlists"].as_array().unwrap().contains(&json!({ "name": "morning", "count": 1 })));
+}
+
+#[tokio::test]
+async fn test_mpd_playlist_changes_invalidate_the_cache() {
+    let mock = library();
+    let (base, state) = spawn_server(mock.clone()).await;
+    start_scheduler(state.clone()).await;
+    assert_eq!(available(&base).await.as_array().unwrap().len(), 2);
+
+    mock.add_playlist("late", vec![song("e.mp3").by("Artist")]);
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while available(&base).await.as_array().unwrap().len() != 3 {
+        assert!(Instant::now() < deadline, "stored_playlist change never reached the cache");
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+}
+
+#[test]
+fn test_snapshot_computed_before_an_invalidation_is_dropped() {
+    let mut mock = library();
+    let snapshot = Arc::new(TagSnapshot::load(&mut mock).unwrap());
+
+    let mut cache = TagCache::default();
+    let generation = cache.generation();
+    cache.invalidate();
+    cache.store(generation, snapshot.clone());
+    assert!(cache.get().is_none());
+
+    cache.store(cache.generation(), snapshot);
+    assert_eq!(cache.get().unwrap().available.len(), 2);
+}
@@ -0,0 +1,224 @@
+mod fixtures;
+
+use fixtures::{song, spawn_server, SongBuilder};
+use jukectl_server::models::song_queue::{SongQueue, TagMatchMode};
+use jukectl_server::models::tag_expr::TagExpr;
+use jukectl_server::models::tags_data::TagsData;
+use jukectl_server::mpd_conn::mock_mpd::MockMpd;
+use serde_json::{json, Value};
+
+fn tags(any: &[&str], not: &[&str], expr: Option<&str>) -> TagsData {
+    TagsData {
+        any: any.iter().map(|t| t.to_string()).collect(),
+        not: not.iter().map(|t| t.to_string()).collect(),
+        expr: expr.map(str::to_string),
+    }
+}
+
+fn library() -> MockMpd {
+    let floyd = |path: &str, year: &str| song(path).by("Pink Floyd").in_genre("Rock").dated(year);
+    let jazz = |path: &str, year: &str| song(path).by("Miles Davis").in_genre("Jazz").dated(year);
+
+    let mock = MockMpd::new();
+    mock.add_playlist("chill", vec![song("chill-vocal.mp3"), song("chill-inst.mp3"), song("chill-explicit.mp3")]);
+    mock.add_playlist("instrumental", vec![song("chill-inst.mp3"), song("chill-explicit.mp3"), song("drums.mp3")]);
+    mock.add_playlist("ambient", vec![song("ambient.mp3"), song("ambient-explicit.mp3")]);
+    mock.add_playlist("explicit", vec![song("chill-explicit.mp3"), song("ambient-explicit.mp3")]);
+    mock.add_playlist(
+        "library",
+        vec![
+            floyd("floyd-1973.mp3", "1973"),
+            floyd("floyd-1994.mp3", "1994-03-28"),
+            jazz("miles-1959.mp3", "1959"),
+            jazz("miles-1991.mp3", "1991"),
+        ],
+    );
+    mock
+}
+
+fn queued(tags: &TagsData, mode: TagMatchMode, mock: &mut MockMpd) -> Vec<String> {
+    let mut queue = SongQueue::new();
+    queue.set_tag_match_mode(mode);
+    queue.shuffle_and_add(tags, mock);
+    let mut files: Vec<String> = queue.head(Some(queue.len())).into_iter().map(|s| s.file).collect();
+    files.sort();
+    files
+}
+
+fn matching(expr: &str) -> Vec<String> {
+    queued(&tags(&[], &[], Some(expr)), TagMatchMode::Playlist, &mut library())
+}
+
+#[test]
+fn test_precedence_and_display() {
+    let expr = TagExpr::parse("(chill AND instrumental) or ambient and not explicit").unwrap();
+    assert_eq!(expr.to_string(), "(chill and instrumental) or (ambient and not explicit)");
+
+    let expr = TagExpr::parse(r#"artist:"Pink Floyd" and year>=1990 or genre=jazz"#).unwrap();
+    assert_eq!(expr.to_string(), r#"(artist:"Pink Floyd" and year>=1990) or genre:jazz"#);
+    assert_eq!(TagExpr::parse(&expr.to_string()).unwrap(), expr);
+
+    // quoted names are always tags, even keywords or names with spaces
+    let expr = TagExpr::parse(r#""and" or "late night" or not not barber-beats"#).unwrap();
+    assert_eq!(expr.to_string(), r#""and" or "late night" or not not barber-beats"#);
+    assert_eq!(
+        expr.tag_names().into_iter().collect::<Vec<_>>(),
+        vec!["and", "barber-beats", "late night"]
+    );
+}
+
+#[test]
+fn test_parse_errors_point_at_the_problem() {
+    let cases = [
+        ("", "expression is empty"),
+        ("chill and", "column 10: expression ends early, expected a tag"),
+        ("(chill or ambient", "column 18: missing `)` for `(` at column 1"),
+        ("chill ambient", "column 7: unexpected `ambient`"),
+        ("chill or )", "column 10: expected a tag, found `)`"),
+        ("mood:happy", "column 1: unknown field `mood`"),
+        ("year>=nineties", "column 1: `year` needs a number, got `nineties`"),
+        ("artist>M", "column 1: `>` only works on year, track and disc"),
+        ("genre:", "column 7: expected a value for `genre`"),
+        ("\"late night", "column 1: unterminated quote"),
+        ("!chill", "column 1: expected `!=`, use `not` to negate"),
+    ];
+    for (input, expected) in cases {
+        let error = TagExpr::parse(input).unwrap_err();
+        assert!(error.starts_with(expected), "{:?}: got {:?}", input, error);
+    }
+}
+
+#[test]
+fn test_any_and_not_are_sugar() {
+    assert_eq!(tags(&[], &[], None).to_expr().unwrap(), TagExpr::All);
+    assert_eq!(
+        tags(&["chill", "ambient"], &["explicit"], None).to_expr().unwrap(),
+        TagExpr::parse("(chill or ambient) and not explicit").unwrap()
+    );
+    assert_eq!(
+        tags(&["chill"], &[], Some("year>=1990")).to_expr().unwrap(),
+        TagExpr::parse("year>=1990 and chill").unwrap()
+    );
+    assert!(tags(&["chill"], &[], Some("chill and")).to_expr().unwrap_err().starts_with("invalid expr: column 10"));
+
+    // the expression path selects the same songs as the list path
+    for (any, not) in [
+        (vec!["chill"], vec![]),
+        (vec!["chill", "ambient"], vec!["explicit"]),
+        (vec![], vec!["explicit"]),
+        (vec!["no-such-tag"], vec![]),
+    ] {
+        for mode in [TagMatchMode::Playlist, TagMatchMode::Substring] {
+            let lists = queued(&tags(&any, &not, None), mode, &mut library());
+            let via_expr = queued(&tags(&any, &not, Some("*")), mode, &mut library());
+            assert_eq!(lists, via_expr, "{:?} / {:?} in {:?}", any, not, mode);
+        }
+    }
+}
+
+#[test]
+fn test_expression_selects_by_membership() {
+    assert_eq!(
+        matching("(chill and instrumental) or ambient and not explicit"),
+        vec!["ambient.mp3", "chill-explicit.mp3", "chill-inst.mp3"]
+    );
+    assert_eq!(
+        matching("(chill and instrumental or ambient) and not explicit"),
+        vec!["ambient.mp3", "chill-inst.mp3"]
+    );
+    assert_eq!(matching("chill and not instrumental"), vec!["chill-vocal.mp3"]);
+    assert!(matching("chill and no-such-tag").is_empty());
+}
+
+#[test]
+fn test_field_predicates() {
+    assert_eq!(matching(r#"artist:"pink floyd""#), vec!["floyd-1973.mp3", "floyd-1994.mp3"]);
+    assert_eq!(matching("genre:jazz and year<1990"), vec!["miles-1959.mp3"]);
+    assert_eq!(matching("year>=1990"), vec!["floyd-1994.mp3", "miles-1991.mp3"]);
+    assert_eq!(
+        matching("genre!=jazz and library"),
+        vec!["floyd-1973.mp3", "floyd-1994.mp3"]
+    );
+    // songs without the field never match a comparison
+    assert!(!matching("year<3000").contains(&"chill-inst.mp3".to_string()));
+}
+
+#[test]
+fn test_expression_with_substring_tags() {
+    let files = queued(
+        &tags(&[], &[], Some("(chill or ambient) and not explicit")),
+        TagMatchMode::Substring,
+        &mut library(),
+    );
+    assert_eq!(files, vec!["ambient.mp3", "chill-inst.mp3", "chill-vocal.mp3"]);
+}
+
+#[tokio::test]
+async fn test_api_accepts_expressions_and_reports_errors() {
+    let (base, state) = spawn_server(library()).await;
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(format!("{}/tags", base))
+        .json(&json!({ "expr": "(chill and instrumental) or ambient and not explicit" }))
+        .send()
+        .await
+        .unwrap();
+    assert!(response.status().is_success());
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["expr"], "(chill and instrumental) or ambient and not explicit");
+    assert_eq!(state.queue.lock().await.len(), 3);
+
+    let response = client
+        .post(format!("{}/tags", base))
+        .json(&json!({ "any": ["chill"], "expr": "year>=nineties" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+    let detail = response.text().await.unwrap();
+    assert!(detail.contains("`year` needs a number"), "{}", detail);
+    // nothing changed
+    assert_eq!(state.tags_data.read().await.any, Vec::<String>::new());
+    assert_eq!(state.queue.lock().await.len(), 3);
+
+    // plain any/not still work and leave `expr` out of responses
+    let body: Value = client
+        .post(format!("{}/tags", base))
+        .json(&json!({ "any": ["chill"], "not": ["explicit"] }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert!(body.get("expr").is_none());
+
+    // a blank expression is dropped like blank tags, on both routes
+    for path in ["/tags", "/tags/active"] {
+        let response = client
+            .post(format!("{}{}", base, path))
+            .json(&json!({ "any": ["chill"], "expr": "  " }))
+            .send()
+            .await
+            .unwrap();
+        assert!(response.status().is_success(), "{}", path);
+        assert_eq!(state.tags_data.read().await.expr, None, "{}", path);
+    }
+
+    // a station may use an expression instead of `any`
+    let response = client
+        .put(format!("{}/stations/oldies", base))
+        .json(&json!({ "expr": "year<1980" }))
+        .send()
+        .await
+        .unwrap();
+    assert!(response.status().is_success());
+    let response = client
+        .put(format!("{}/stations/broken", base))
+        .json(&json!({ "expr": "chill or" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+}
@@ -0,0 +1,144 @@
+mod fixtures;
+
+use fixtures::{song, spawn_server};
+use jukectl_server::mpd_conn::mock_mpd::MockMpd;
+use jukectl_server::mpd_conn::traits::MpdClient;
+use jukectl_server::routes::error::ErrorBody;
+use jukectl_server::tagging;
+
+fn playlist_files(mock: &mut MockMpd, name: &str) -> Vec<String> {
+    MpdClient::playlist(mock, name)
+        .unwrap()
+        .into_iter()
+        .map(|s| s.file)
+        .collect()
+}
+
+#[test]
+fn test_tag_song_is_idempotent() {
+    let mut mock = MockMpd::new();
+
+    assert!(tagging::tag_song(&mut mock, "favorites", "a.mp3").unwrap());
+    assert!(!tagging::tag_song(&mut mock, "favorites", "a.mp3").unwrap());
+
+    assert_eq!(playlist_files(&mut mock, "favorites"), vec!["a.mp3"]);
+}
+
+#[test]
+fn test_untag_song_removes_every_entry() {
+    let mut mock = MockMpd::new();
+    mock.add_playlist("jukebox", vec![song("a.mp3"), song("b.mp3"), song("a.mp3"), song("c.mp3")]);
+
+    assert_eq!(tagging::untag_song(&mut mock, "jukebox", "a.mp3").unwrap(), 2);
+    assert_eq!(playlist_files(&mut mock, "jukebox"), vec!["b.mp3", "c.mp3"]);
+
+    assert_eq!(tagging::untag_song(&mut mock, "jukebox", "a.mp3").unwrap(), 0);
+    assert_eq!(tagging::untag_song(&mut mock, "missing", "a.mp3").unwrap(), 0);
+}
+
+#[test]
+fn test_create_and_delete_tag() {
+    let mut mock = MockMpd::new();
+    mock.add_playlist("jukebox", vec![song("a.mp3")]);
+
+    assert!(tagging::create_tag(&mut mock, "morning").unwrap());
+    assert!(playlist_files(&mut mock, "morning").is_empty());
+
+    // creating an existing tag must not wipe it
+    assert!(!tagging::create_tag(&mut mock, "jukebox").unwrap());
+    assert_eq!(playlist_files(&mut mock, "jukebox"), vec!["a.mp3"]);
+
+    assert_eq!(tagging::delete_tag(&mut mock, "jukebox").unwrap(), Some(1));
+    assert_eq!(tagging::delete_tag(&mut mock, "jukebox").unwrap(), None);
+    assert!(!tagging::tag_exists(&mut mock, "jukebox").unwrap());
+}
+
+#[tokio::test]
+async fn test_put_and_delete_tag_routes() {
+    let mut mock = MockMpd::new();
+    mock.add_playlist("jukebox", vec![song("a.mp3"), song("b.mp3")]);
+    let (base, _state) = spawn_server(mock.clone()).await;
+    let client = reqwest::Client::new();
+
+    let created: serde_json::Value = client
+        .put(format!("{}/tags/morning", base))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(created["name"], "morning");
+    assert_eq!(created["track_count"], 0);
+    assert!(tagging::tag_exists(&mut mock, "morning").unwrap());
+
+    let deleted: serde_json::Value = client
+        .delete(format!("{}/tags/jukebox", base))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(deleted["track_count"], 2);
+    assert!(!tagging::tag_exists(&mut mock, "jukebox").unwrap());
+
+    let missing = client
+        .delete(format!("{}/tags/jukebox", base))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(missing.status(), reqwest::StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_song_tags_defaults_to_now_playing() {
+    let mut mock = MockMpd::new();
+    MpdClient::push(&mut mock, "now.mp3").unwrap();
+    let (base, _state) = spawn_server(mock.clone()).await;
+
+    let response: serde_json::Value = reqwest::Client::new()
+        .post(format!("{}/song/tags", base))
+        .json(&serde_json::json!({ "add": ["favorites"] }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    assert_eq!(response["filename"], "now.mp3");
+    assert_eq!(response["added"], serde_json::json!(["favorites"]));
+    assert_eq!(playlist_files(&mut mock, "favorites"), vec!["now.mp3"]);
+}
+
+#[tokio::test]
+async fn test_song_tags_reports_failed_edits() {
+    let mut mock = MockMpd::new();
+    mock.add_playlist("jukebox", vec![song("a.mp3")]);
+    let (base, _state) = spawn_server(mock.clone()).await;
+    let client = reqwest::Client::new();
+
+    mock.fail_playlist_edits(true);
+    let response = client
+        .post(format!("{}/song/tags", base))
+        .json(&serde_json::json!({ "filename": "a.mp3", "add": ["favorites"] }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 500);
+    let body: ErrorBody = response.json().await.unwrap();
+    assert_eq!(body.error, "mpd_error");
+    assert!(body.detail.contains("Read-only"), "{}", body.detail);
+    assert!(!tagging::tag_exists(&mut mock, "favorites").unwrap());
+
+    mock.fail_playlist_edits(false);
+    let response = client
+        .post(format!("{}/song/tags", base))
+        .json(&serde_json::json!({ "filename": "nope.mp3", "add": ["favorites"] }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 404);
+    assert!(!tagging::tag_exists(&mut mock, "favorites").unwrap());
+}
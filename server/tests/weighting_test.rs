@@ -0,0 +1,162 @@
+mod fixtures;
+
+use fixtures::{get_json, post_empty, put_json, song, spawn_server};
+use jukectl_server::models::cooldown::RecentPlays;
+use jukectl_server::models::history::HistoryEntry;
+use jukectl_server::models::song_queue::SongQueue;
+use jukectl_server::models::song_stats::{PlayStats, SongStats};
+use jukectl_server::models::weighting::{weighted_order, SongWeights, Weighting};
+use jukectl_server::mpd_conn::mock_mpd::MockMpd;
+use jukectl_server::mpd_conn::traits::MpdClient;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use serde_json::{json, Value};
+use std::time::{Duration, UNIX_EPOCH};
+
+fn play(file: &str, started_at: u64, skipped: bool) -> HistoryEntry {
+    HistoryEntry {
+        file: file.to_string(),
+        title: None,
+        artist: None,
+        album: None,
+        started_at,
+        played_secs: 0,
+        skipped,
+    }
+}
+
+fn close(a: f64, b: f64) -> bool {
+    (a - b).abs() < 1e-9
+}
+
+#[test]
+fn test_factors_follow_the_knobs() {
+    let weighting = Weighting::default();
+    let hour = 3600;
+
+    let neutral = weighting.factors(&SongStats::default(), 0);
+    assert!(close(neutral.weight(), 1.0));
+    let three_stars = SongStats { rating: Some(3), ..SongStats::default() };
+    assert!(close(weighting.factors(&three_stars, 0).weight(), 1.0));
+
+    let loved = SongStats { rating: Some(5), ..SongStats::default() };
+    assert!(close(weighting.factors(&loved, 0).rating, 2.25));
+    let disliked = SongStats { rating: Some(1), ..SongStats::default() };
+    assert!(close(weighting.factors(&disliked, 0).rating, 1.0 / 2.25));
+
+    // always skipped weighs half at a skip penalty of 1
+    let skipped = SongStats { skips: 4, ..SongStats::default() };
+    assert!(close(weighting.factors(&skipped, 0).skips, 0.5));
+
+    let played = SongStats { plays: 3, last_played: Some(100 * hour), ..SongStats::default() };
+    let factors = weighting.factors(&played, 124 * hour);
+    assert!(close(factors.plays, 1.0 / (1.0 + 0.1 * 4f64.ln())));
+    assert!(close(factors.recency, 0.5));
+    assert!(close(weighting.factors(&played, 100 * hour).recency, 0.05));
+    assert!(close(weighting.factors(&played, 200 * hour).recency, 1.0));
+
+    let flat = Weighting { rating_boost: 1.0, skip_penalty: 0.0, play_penalty: 0.0, recovery_hours: 0 };
+    let everything = SongStats { rating: Some(5), plays: 9, skips: 9, last_played: Some(0) };
+    assert!(close(flat.factors(&everything, 0).weight(), 1.0));
+}
+
+#[test]
+fn test_stats_count_plays_and_keep_ratings_in_range() {
+    let mut stats = PlayStats::default();
+    stats.record(&play("a.mp3", 100, false));
+    stats.record(&play("a.mp3", 50, true));
+    assert_eq!(
+        stats.get("a.mp3"),
+        SongStats { rating: None, plays: 1, skips: 1, last_played: Some(100) }
+    );
+
+    assert_eq!(stats.rate("a.mp3", Some(4)).unwrap().rating, Some(4));
+    assert_eq!(stats.rate("a.mp3", Some(6)).unwrap_err(), "rating must be between 1 and 5, not 6");
+    assert_eq!(stats.get("a.mp3").rating, Some(4));
+
+    // clearing the only thing known about a song forgets it
+    stats.rate("b.mp3", Some(2)).unwrap();
+    stats.rate("b.mp3", None).unwrap();
+    assert_eq!(stats.len(), 1);
+}
+
+#[test]
+fn test_weighted_order_favours_heavy_items_and_is_seeded() {
+    let items = vec!["light", "heavy", "never"];
+    let weight = |item: &&str| match *item {
+        "heavy" => 20.0,
+        "light" => 1.0,
+        _ => 0.0,
+    };
+
+    let mut heavy_first = 0;
+    for seed in 0..200 {
+        let order = weighted_order(items.clone(), weight, &mut StdRng::seed_from_u64(seed));
+        assert_eq!(order, weighted_order(items.clone(), weight, &mut StdRng::seed_from_u64(seed)));
+        assert_eq!(order[2], "never");
+        if order[0] == "heavy" {
+            heavy_first += 1;
+        }
+    }
+    // 20 to 1 odds
+    assert!(heavy_first > 170, "{}", heavy_first);
+}
+
+#[test]
+fn test_queue_puts_loved_songs_ahead() {
+    let mut stats = PlayStats::default();
+    stats.rate("loved.mp3", Some(5)).unwrap();
+    stats.rate("hated.mp3", Some(1)).unwrap();
+    let weighting = Weighting { rating_boost: 4.0, ..Weighting::default() };
+    let weights = SongWeights::new(&stats, weighting, UNIX_EPOCH + Duration::from_secs(1_000_000));
+
+    let mut loved_first = 0;
+    let mut hated_last = 0;
+    for seed in 0..100 {
+        let mut queue = SongQueue::new();
+        queue.seed_rng(seed);
+        let songs = ["loved.mp3", "plain.mp3", "hated.mp3"].map(song).to_vec();
+        queue.add_songs_after(songs, &RecentPlays::default(), &weights);
+        let files: Vec<String> = queue.head(None).into_iter().map(|s| s.file).collect();
+        loved_first += usize::from(files[0] == "loved.mp3");
+        hated_last += usize::from(files[2] == "hated.mp3");
+    }
+    assert!(loved_first > 80, "{}", loved_first);
+    assert!(hated_last > 80, "{}", hated_last);
+}
+
+#[tokio::test]
+async fn test_score_and_rating_routes() {
+    let mut mock = MockMpd::new();
+    mock.add_playlist("jukebox", vec![song("dir/a.mp3"), song("b.mp3")]);
+    mock.push("b.mp3").unwrap();
+    let (base, _state) = spawn_server(mock).await;
+
+    let score = get_json(format!("{}/song/dir%2Fa.mp3/score", base)).await;
+    assert_eq!(score["file"], "dir/a.mp3");
+    assert_eq!(score["weight"], 1.0);
+    assert_eq!(score["rating"], Value::Null);
+    assert_eq!(score["plays"], 0);
+
+    let rated: Value = put_json(format!("{}/song/dir%2Fa.mp3/rating", base), json!({ "rating": 5 }))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(rated["rating"], 5);
+    assert_eq!(rated["factors"]["rating"], 2.25);
+    assert_eq!(rated["weight"], 2.25);
+
+    let response = put_json(format!("{}/song/dir%2Fa.mp3/rating", base), json!({ "rating": 0 })).await;
+    assert_eq!(response.status(), 400);
+    let response = reqwest::get(format!("{}/song/nope.mp3/score", base)).await.unwrap();
+    assert_eq!(response.status(), 404);
+    let error: Value = response.json().await.unwrap();
+    assert_eq!(error["detail"], "no song `nope.mp3`");
+
+    // a skip through the API shows up in the counts
+    post_empty(format!("{}/skip", base)).await;
+    let score = get_json(format!("{}/song/b.mp3/score", base)).await;
+    assert_eq!(score["skips"], 1);
+    assert!(score["weight"].as_f64().unwrap() < 0.5);
+}